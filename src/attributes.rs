@@ -0,0 +1,298 @@
+//! Which `.gitattributes` rule puts a file under git-crypt's filter, so
+//! `status --files` can name the pattern behind each encrypted file.
+//!
+//! Whether a file is filtered at all is still decided by libgit2; this module
+//! only explains the decision. It follows gitattributes matching closely
+//! enough for that: patterns without a slash match the file name at any depth,
+//! patterns with one are anchored at the directory of their attributes file,
+//! and `*`, `?`, `[...]` and `**` work as in git. Macros are not expanded.
+
+/// One attributes file: the directory it applies to (`""` for the root or
+/// `.git/info/attributes`, otherwise `/`-separated without a trailing slash)
+/// and its contents
+pub struct AttributesFile<'a> {
+    pub dir: &'a str,
+    pub contents: &'a str,
+}
+
+/// The pattern of the last rule setting `filter` for `path`, if that rule
+/// sets it to `driver` (`git-crypt`, or `git-crypt-<name>` for a named key).
+///
+/// `files` must be ordered from lowest to highest precedence: the root
+/// `.gitattributes`, nested ones from shallow to deep, then
+/// `.git/info/attributes`.
+pub fn filter_pattern(files: &[AttributesFile<'_>], path: &str, driver: &str) -> Option<String> {
+    let mut last = None;
+    for file in files {
+        let relative = if file.dir.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(file.dir)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(relative) => relative,
+                None => continue,
+            }
+        };
+
+        for line in file.contents.lines() {
+            let Some((pattern, attributes)) = split_rule(line) else {
+                continue;
+            };
+            let Some(value) = attributes
+                .split_whitespace()
+                .filter_map(filter_value)
+                .next_back()
+            else {
+                continue;
+            };
+            if pattern_matches(&pattern, relative) {
+                last = Some((pattern, value));
+            }
+        }
+    }
+
+    match last {
+        Some((pattern, Some(value))) if value == driver => Some(pattern),
+        _ => None,
+    }
+}
+
+/// Split a rule into its (unquoted) pattern and the attribute list, skipping
+/// blank lines, comments and `[attr]` macro definitions
+fn split_rule(line: &str) -> Option<(String, &str)> {
+    let line = line.trim_start();
+    if line.is_empty() || line.starts_with('#') || line.starts_with("[attr]") {
+        return None;
+    }
+
+    if let Some(quoted) = line.strip_prefix('"') {
+        let mut pattern = String::new();
+        let mut chars = quoted.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => return Some((pattern, &quoted[i + 1..])),
+                '\\' => pattern.push(chars.next()?.1),
+                c => pattern.push(c),
+            }
+        }
+        return None;
+    }
+
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    Some((line[..end].to_string(), &line[end..]))
+}
+
+/// The effect of one attribute on `filter`: `Some(Some(value))` for
+/// `filter=value`, `Some(None)` for unsetting it, `None` if unrelated
+fn filter_value(attribute: &str) -> Option<Option<String>> {
+    match attribute {
+        "-filter" | "!filter" | "filter" => Some(None),
+        _ => attribute
+            .strip_prefix("filter=")
+            .map(|value| Some(value.to_string())),
+    }
+}
+
+/// Whether a pattern from an attributes file matches `path`, relative to
+/// that file's directory
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    // Directory patterns never match in .gitattributes
+    if pattern.is_empty() || pattern.ends_with('/') {
+        return false;
+    }
+    match pattern.strip_prefix('/') {
+        Some(anchored) => wildmatch(anchored.as_bytes(), path.as_bytes()),
+        None if pattern.contains('/') => wildmatch(pattern.as_bytes(), path.as_bytes()),
+        None => {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            wildmatch(pattern.as_bytes(), name.as_bytes())
+        }
+    }
+}
+
+/// Match `text` against a glob where `*` and `?` stop at `/` and `**`
+/// crosses directories (`**/` also matches no directory at all)
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    Matcher {
+        pattern,
+        text,
+        memo: vec![None; (pattern.len() + 1) * (text.len() + 1)],
+    }
+    .matches(0, 0)
+}
+
+/// [`wildmatch`] state. Every (pattern index, text index) pair is decided at
+/// most once, so wildcards cost polynomial time instead of retrying every
+/// suffix for each `*`.
+struct Matcher<'a> {
+    pattern: &'a [u8],
+    text: &'a [u8],
+    memo: Vec<Option<bool>>,
+}
+
+impl Matcher<'_> {
+    /// Whether `pattern[p..]` matches `text[t..]`
+    fn matches(&mut self, p: usize, t: usize) -> bool {
+        let slot = p * (self.text.len() + 1) + t;
+        if let Some(matched) = self.memo[slot] {
+            return matched;
+        }
+        let matched = self.step(p, t);
+        self.memo[slot] = Some(matched);
+        matched
+    }
+
+    fn step(&mut self, p: usize, t: usize) -> bool {
+        let (pattern, text) = (self.pattern, self.text);
+        match &pattern[p..] {
+            [] => t == text.len(),
+            [b'*', b'*', rest @ ..] => {
+                if rest.first() == Some(&b'/') && self.matches(p + 3, t) {
+                    return true;
+                }
+                (t..=text.len()).any(|i| self.matches(p + 2, i))
+            }
+            [b'*', ..] => {
+                for i in t..=text.len() {
+                    if self.matches(p + 1, i) {
+                        return true;
+                    }
+                    if text.get(i) == Some(&b'/') {
+                        break;
+                    }
+                }
+                false
+            }
+            [b'?', ..] => text.get(t).is_some_and(|&c| c != b'/') && self.matches(p + 1, t + 1),
+            [b'[', class @ ..] => match match_class(class, text.get(t).copied()) {
+                Some((true, len)) => self.matches(p + 1 + len, t + 1),
+                Some((false, _)) => false,
+                // No closing bracket: a literal `[`
+                None => self.literal(b'[', p + 1, t),
+            },
+            [b'\\', c, ..] => self.literal(*c, p + 2, t),
+            [c, ..] => self.literal(*c, p + 1, t),
+        }
+    }
+
+    fn literal(&mut self, c: u8, p: usize, t: usize) -> bool {
+        self.text.get(t) == Some(&c) && self.matches(p, t + 1)
+    }
+}
+
+/// Match `c` against a bracket expression whose opening `[` is already
+/// consumed; returns whether it matched and the length up to and including
+/// the closing `]`, or `None` if the class is never closed
+fn match_class(class: &[u8], c: Option<u8>) -> Option<(bool, usize)> {
+    let (negated, mut i) = match class.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+    let start = i;
+    let mut matched = false;
+    while i < class.len() {
+        let first = class[i];
+        if first == b']' && i > start {
+            let matched = c.is_some_and(|c| c != b'/') && matched != negated;
+            return Some((matched, i + 1));
+        }
+        if class.get(i + 1) == Some(&b'-') && class.get(i + 2).is_some_and(|&end| end != b']') {
+            let last = class[i + 2];
+            matched |= c.is_some_and(|c| (first..=last).contains(&c));
+            i += 3;
+        } else {
+            matched |= c == Some(first);
+            i += 1;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildmatch_follows_gitattributes_globs() {
+        let matches = |pattern: &str, text: &str| wildmatch(pattern.as_bytes(), text.as_bytes());
+        assert!(matches("*.secret", "a.secret"));
+        assert!(!matches("*.secret", "dir/a.secret"));
+        assert!(matches("secrets/**", "secrets/a/b.txt"));
+        assert!(matches("**/db.yml", "db.yml"));
+        assert!(matches("**/db.yml", "config/prod/db.yml"));
+        assert!(matches("config/**/db.yml", "config/db.yml"));
+        assert!(matches("key?.pem", "key1.pem"));
+        assert!(!matches("key?.pem", "key/.pem"));
+        assert!(matches("key[0-9].pem", "key7.pem"));
+        assert!(!matches("key[!0-9].pem", "key7.pem"));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches("\\*.txt", "*.txt"));
+        assert!(!matches("\\*.txt", "a.txt"));
+
+        // Many stars against a long near-miss must not backtrack exponentially
+        let stars = "*a".repeat(30) + "b";
+        assert!(!matches(&stars, &"a".repeat(200)));
+        let globstars = "**/x".repeat(12) + "/y";
+        assert!(!matches(&globstars, &"x/".repeat(100)));
+    }
+
+    #[test]
+    fn filter_pattern_reports_the_deciding_rule() {
+        let root = AttributesFile {
+            dir: "",
+            contents: "# secrets\n*.secret filter=git-crypt diff=git-crypt\n\
+                       /config/db.yml filter=git-crypt\n\
+                       \"my file\" filter=git-crypt\n\
+                       public.secret !filter !diff\n",
+        };
+        let nested = AttributesFile {
+            dir: "keys",
+            contents: "*.pem filter=git-crypt\nroot.secret -filter\n",
+        };
+        let files = [root, nested];
+
+        assert_eq!(
+            filter_pattern(&files, "a.secret", "git-crypt").as_deref(),
+            Some("*.secret")
+        );
+        assert_eq!(
+            filter_pattern(&files, "deep/b.secret", "git-crypt").as_deref(),
+            Some("*.secret")
+        );
+        assert_eq!(
+            filter_pattern(&files, "config/db.yml", "git-crypt").as_deref(),
+            Some("/config/db.yml")
+        );
+        assert_eq!(
+            filter_pattern(&files, "my file", "git-crypt").as_deref(),
+            Some("my file")
+        );
+        assert_eq!(
+            filter_pattern(&files, "keys/id.pem", "git-crypt").as_deref(),
+            Some("*.pem")
+        );
+        assert_eq!(filter_pattern(&files, "id.pem", "git-crypt"), None);
+        assert_eq!(filter_pattern(&files, "public.secret", "git-crypt"), None);
+        assert_eq!(
+            filter_pattern(&files, "keys/root.secret", "git-crypt"),
+            None
+        );
+    }
+
+    #[test]
+    fn filter_pattern_matches_the_given_driver() {
+        let files = [AttributesFile {
+            dir: "",
+            contents: "*.secret filter=git-crypt\n*.team filter=git-crypt-team\n",
+        }];
+
+        assert_eq!(
+            filter_pattern(&files, "a.team", "git-crypt-team").as_deref(),
+            Some("*.team")
+        );
+        assert_eq!(filter_pattern(&files, "a.team", "git-crypt"), None);
+        assert_eq!(filter_pattern(&files, "a.secret", "git-crypt-team"), None);
+    }
+}
@@ -0,0 +1,245 @@
+//! # Smudge Cache
+//!
+//! Optional content-addressed cache of decrypted blobs, so checking out the
+//! same ciphertext again skips the AEAD work.
+//!
+//! ## Layout
+//!
+//! ```text
+//! .git/git-crypt/cache/<key fingerprint prefix>/<sha256 of ciphertext>
+//! ```
+//!
+//! Entries live under a directory named after the key, so a new key never
+//! sees plaintext produced by an old one; directories for other keys are
+//! removed when the cache is opened. Once the entries exceed the size cap,
+//! the least recently used ones are evicted.
+//!
+//! The cache holds plaintext, so it is only used while the repository is
+//! unlocked: `lock` and `purge` delete it.
+//!
+//! ## Enabling
+//!
+//! ```bash
+//! git config filter.git-crypt.smudgecache 64m   # size cap; 0 or unset disables
+//! ```
+
+use crate::crypto::CryptoKey;
+use crate::error::Result;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Cache directory name under `.git/git-crypt`
+pub const CACHE_DIR: &str = "cache";
+
+// Length of the key fingerprint prefix naming a key's cache directory
+const KEY_DIR_LEN: usize = 16;
+
+/// Decrypted blobs for one key, capped at `max_bytes`
+#[derive(Debug)]
+pub struct SmudgeCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl SmudgeCache {
+    /// Open the cache for `key` below `git_crypt_dir`, dropping entries made
+    /// with any other key
+    pub fn open(git_crypt_dir: &Path, key: &CryptoKey, max_bytes: u64) -> Result<Self> {
+        let root = git_crypt_dir.join(CACHE_DIR);
+        let key_dir = key.fingerprint()[..KEY_DIR_LEN].to_string();
+
+        if root.exists() {
+            for entry in fs::read_dir(&root)? {
+                let entry = entry?;
+                if entry.file_name() != key_dir.as_str() {
+                    fs::remove_dir_all(entry.path())?;
+                }
+            }
+        }
+
+        let dir = root.join(key_dir);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// Delete every cached entry below `git_crypt_dir`
+    pub fn clear(git_crypt_dir: &Path) -> Result<()> {
+        let root = git_crypt_dir.join(CACHE_DIR);
+        if root.exists() {
+            fs::remove_dir_all(root)?;
+        }
+        Ok(())
+    }
+
+    /// Cached plaintext for `ciphertext`, if any
+    pub fn get(&self, ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let path = self.entry_path(ciphertext);
+        let plaintext = fs::read(&path).ok()?;
+
+        // Mark as recently used; eviction order is best effort
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(plaintext)
+    }
+
+    /// Store `plaintext` for `ciphertext`, then evict down to the size cap.
+    ///
+    /// Entries larger than the whole cap are not stored.
+    pub fn put(&self, ciphertext: &[u8], plaintext: &[u8]) -> Result<()> {
+        if plaintext.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.entry_path(ciphertext);
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = create_private(&tmp)?;
+            file.write_all(plaintext)?;
+        }
+        fs::rename(&tmp, &path)?;
+
+        self.evict()
+    }
+
+    fn entry_path(&self, ciphertext: &[u8]) -> PathBuf {
+        self.dir.join(hex::encode(Sha256::digest(ciphertext)))
+    }
+
+    /// Remove least recently used entries until the total size fits the cap
+    fn evict(&self) -> Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            total += meta.len();
+            entries.push((meta.modified()?, meta.len(), entry.path()));
+        }
+
+        entries.sort();
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn create_private(path: &Path) -> Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    Ok(File::options()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> Result<File> {
+    Ok(File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    #[test]
+    fn miss_then_hit() {
+        let temp = TempDir::new().unwrap();
+        let key = CryptoKey::generate();
+        let cache = SmudgeCache::open(temp.path(), &key, 1024).unwrap();
+
+        let ciphertext = key.encrypt(b"secret").unwrap();
+        assert!(cache.get(&ciphertext).is_none());
+
+        cache.put(&ciphertext, b"secret").unwrap();
+        assert_eq!(cache.get(&ciphertext).unwrap(), b"secret");
+
+        // Entries are addressed by ciphertext, not plaintext
+        let other = key.encrypt(b"secret").unwrap();
+        assert!(cache.get(&other).is_none());
+    }
+
+    #[test]
+    fn key_change_invalidates_entries() {
+        let temp = TempDir::new().unwrap();
+        let old_key = CryptoKey::generate();
+        let ciphertext = old_key.encrypt(b"secret").unwrap();
+        SmudgeCache::open(temp.path(), &old_key, 1024)
+            .unwrap()
+            .put(&ciphertext, b"secret")
+            .unwrap();
+
+        let new_key = CryptoKey::generate();
+        let cache = SmudgeCache::open(temp.path(), &new_key, 1024).unwrap();
+        assert!(cache.get(&ciphertext).is_none());
+
+        // The old key's entries are gone from disk, not just hidden
+        let dirs: Vec<_> = fs::read_dir(temp.path().join(CACHE_DIR)).unwrap().collect();
+        assert_eq!(dirs.len(), 1);
+        let reopened = SmudgeCache::open(temp.path(), &old_key, 1024).unwrap();
+        assert!(reopened.get(&ciphertext).is_none());
+    }
+
+    #[test]
+    fn evicts_least_recently_used_over_cap() {
+        let temp = TempDir::new().unwrap();
+        let key = CryptoKey::generate();
+        let cache = SmudgeCache::open(temp.path(), &key, 10).unwrap();
+
+        let backdate = |ciphertext: &[u8], secs: u64| {
+            File::options()
+                .write(true)
+                .open(cache.entry_path(ciphertext))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(secs))
+                .unwrap();
+        };
+
+        cache.put(b"a", b"11111").unwrap();
+        backdate(b"a", 20);
+        cache.put(b"b", b"22222").unwrap();
+        backdate(b"b", 10);
+
+        // Using "a" makes "b" the least recently used
+        assert!(cache.get(b"a").is_some());
+        cache.put(b"c", b"33333").unwrap();
+
+        assert!(cache.get(b"a").is_some());
+        assert!(cache.get(b"b").is_none());
+        assert!(cache.get(b"c").is_some());
+    }
+
+    #[test]
+    fn oversized_entries_are_not_stored() {
+        let temp = TempDir::new().unwrap();
+        let key = CryptoKey::generate();
+        let cache = SmudgeCache::open(temp.path(), &key, 4).unwrap();
+
+        cache.put(b"big", b"too large").unwrap();
+        assert!(cache.get(b"big").is_none());
+    }
+
+    #[test]
+    fn clear_removes_everything() {
+        let temp = TempDir::new().unwrap();
+        let key = CryptoKey::generate();
+        let cache = SmudgeCache::open(temp.path(), &key, 1024).unwrap();
+        cache.put(b"a", b"plaintext").unwrap();
+
+        SmudgeCache::clear(temp.path()).unwrap();
+        assert!(!temp.path().join(CACHE_DIR).exists());
+    }
+}
@@ -2,10 +2,16 @@ use crate::error::{GitCryptError, Result};
 use crate::git::GitRepo;
 use crate::gpg::GpgManager;
 use crate::key::KeyManager;
+use crate::recipients::{BlobManifest, BlobRecord, Recipient, RecipientKind};
 use std::fs;
 
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+use git_crypt::sync;
+
 /// Add a GPG user who can unlock the repository
-pub fn add_gpg_user(gpg_id: &str) -> Result<()> {
+///
+/// With `all_subkeys`, the key is encrypted to every encryption subkey of the user.
+pub fn add_gpg_user(gpg_id: &str, all_subkeys: bool) -> Result<()> {
     println!("Adding GPG user: {}", gpg_id);
 
     // Open repository
@@ -23,17 +29,41 @@ pub fn add_gpg_user(gpg_id: &str) -> Result<()> {
     let key = key_manager.load_key()?;
 
     // Encrypt the key for this GPG user
-    let encrypted_key = GpgManager::encrypt_key_for_recipient(&key, gpg_id)?;
+    let encrypted_key = GpgManager::encrypt_key_for_recipient(&key, gpg_id, all_subkeys)?;
 
     // Save the encrypted key
-    let gpg_keys_dir = key_manager.git_crypt_dir().join("keys").join("gpg");
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+    let gpg_keys_dir = keys_dir.join("gpg");
     fs::create_dir_all(&gpg_keys_dir)?;
 
     let key_file = gpg_keys_dir.join(format!("{}.key", gpg_id));
     fs::write(&key_file, encrypted_key)?;
 
+    // rewrap can only regenerate blobs it can describe: a single-token ID and
+    // the default subkey selection
+    let mut manifest = BlobManifest::load(&keys_dir)?;
+    if all_subkeys || gpg_id.contains(char::is_whitespace) {
+        manifest.remove(RecipientKind::Gpg, gpg_id);
+    } else {
+        manifest.record(BlobRecord::new(
+            &key,
+            Recipient {
+                kind: RecipientKind::Gpg,
+                name: gpg_id.to_string(),
+                recipient: gpg_id.to_string(),
+            },
+        ));
+    }
+    manifest.save(&keys_dir)?;
+
     println!("Successfully added GPG user: {}", gpg_id);
     println!("Encrypted key saved to: {}", key_file.display());
 
+    // Best-effort, like add-ssh-user: the local blob is already written
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    if let Err(err) = sync::maybe_sync_gpg_key(git_dir, &key_file, gpg_id, false) {
+        eprintln!("Warning: Failed to sync GPG key: {err}");
+    }
+
     Ok(())
 }
@@ -1,19 +1,39 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use sha2::{Digest, Sha256};
 
+use super::summary::BatchSummary;
+use crate::crypto::CryptoKey;
 use crate::error::{GitCryptError, Result};
 use crate::git::GitRepo;
 use crate::key::KeyManager;
 use crate::rage::RageManager;
+use crate::recipients::{BlobManifest, BlobRecord, Recipient, RecipientKind};
 
-#[cfg(feature = "sync-s3")]
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
 use git_crypt::sync;
 
-/// Add an SSH recipient using age/rage encryption.
-pub fn add_ssh_user(ssh_key_path: &Path, alias: Option<&str>) -> Result<()> {
-    println!("Adding SSH (age) user from: {}", ssh_key_path.display());
+/// Add SSH recipients using age/rage encryption.
+///
+/// Each key is granted independently: recipients that already have a blob are
+/// skipped unless `force` is set, and a failing key doesn't stop the rest.
+/// `force_upload` re-uploads blobs to the sync backend even if the remote copy is identical.
+#[cfg_attr(
+    not(any(feature = "sync-s3", feature = "sync-gcs")),
+    allow(unused_variables, unused_mut)
+)]
+pub fn add_ssh_user(
+    ssh_key_paths: &[PathBuf],
+    alias: Option<&str>,
+    force: bool,
+    force_upload: bool,
+) -> Result<()> {
+    if alias.is_some() && ssh_key_paths.len() > 1 {
+        return Err(GitCryptError::Other(
+            "--alias can only be used with a single --ssh-key".into(),
+        ));
+    }
 
     let repo = GitRepo::open(".")?;
     let key_manager = KeyManager::new(repo.git_dir());
@@ -23,28 +43,119 @@ pub fn add_ssh_user(ssh_key_path: &Path, alias: Option<&str>) -> Result<()> {
     }
 
     let key = key_manager.load_key()?;
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+    let age_dir = keys_dir.join("age");
+    fs::create_dir_all(&age_dir)?;
+    let mut manifest = BlobManifest::load(&keys_dir)?;
+
+    let mut summary = BatchSummary::default();
+    for ssh_key_path in ssh_key_paths {
+        println!("Adding SSH (age) user from: {}", ssh_key_path.display());
+
+        match grant(&key, ssh_key_path, alias, &age_dir, force) {
+            Ok(Grant::Written {
+                name,
+                key_file,
+                recipient,
+            }) => {
+                summary.granted += 1;
+                match recipient {
+                    Some(recipient) => manifest.record(BlobRecord::new(
+                        &key,
+                        Recipient {
+                            kind: RecipientKind::Ssh,
+                            name: name.clone(),
+                            recipient,
+                        },
+                    )),
+                    None => manifest.remove(RecipientKind::Ssh, &name),
+                }
+                println!("Encrypted key saved to {}", key_file.display());
+
+                #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+                summary.record_sync(sync::maybe_sync_age_key(
+                    repo.git_dir(),
+                    &key_file,
+                    &name,
+                    force_upload,
+                ));
+            }
+            Ok(Grant::AlreadyPresent { name }) => {
+                summary.skipped += 1;
+                println!("Skipped {name}: already has a key blob (use --force to replace it)");
+            }
+            Err(err) => {
+                summary.failed += 1;
+                eprintln!("Failed to add {}: {err}", ssh_key_path.display());
+            }
+        }
+    }
+
+    manifest.save(&keys_dir)?;
+
+    println!("\n{}", summary.grant_line());
+    if let Some(line) = summary.sync_line() {
+        println!("{line}");
+    }
+    if summary.granted > 0 {
+        println!("Share the .age files with the SSH users; they can decrypt them with rage/age.");
+    }
+
+    if summary.failed > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{} of {} SSH key(s) could not be granted",
+            summary.failed,
+            ssh_key_paths.len()
+        )));
+    }
+
+    Ok(())
+}
+
+enum Grant {
+    Written {
+        name: String,
+        #[cfg_attr(not(any(feature = "sync-s3", feature = "sync-gcs")), allow(dead_code))]
+        key_file: PathBuf,
+        /// The recipient as a single line, when the key file held exactly one
+        recipient: Option<String>,
+    },
+    AlreadyPresent {
+        name: String,
+    },
+}
+
+fn grant(
+    key: &CryptoKey,
+    ssh_key_path: &Path,
+    alias: Option<&str>,
+    age_dir: &Path,
+    force: bool,
+) -> Result<Grant> {
     let ssh_key = fs::read_to_string(ssh_key_path)?;
-    let encrypted_key = RageManager::encrypt_key_for_ssh_recipient(&key, &ssh_key)?;
 
     let name = alias
         .map(sanitize_label)
         .filter(|s| !s.is_empty())
         .unwrap_or_else(|| derive_recipient_name(&ssh_key, ssh_key_path));
 
-    let age_dir = key_manager.git_crypt_dir().join("keys").join("age");
-    fs::create_dir_all(&age_dir)?;
     let key_file = age_dir.join(format!("{name}.age"));
-    fs::write(&key_file, encrypted_key)?;
-
-    println!("Encrypted key saved to {}", key_file.display());
-    println!("Share this file with the SSH user; they can decrypt it with rage/age.");
-
-    #[cfg(feature = "sync-s3")]
-    if let Err(err) = sync::maybe_sync_age_key(repo.git_dir(), &key_file, &name) {
-        eprintln!("Warning: Failed to sync age key to S3: {err}");
+    if key_file.exists() && !force {
+        return Ok(Grant::AlreadyPresent { name });
     }
 
-    Ok(())
+    let encrypted_key = RageManager::encrypt_key_for_ssh_recipient(key, &ssh_key)?;
+    fs::write(&key_file, encrypted_key)?;
+
+    let recipient = match ssh_key.trim() {
+        line if !line.contains('\n') => Some(line.to_string()),
+        _ => None,
+    };
+    Ok(Grant::Written {
+        name,
+        key_file,
+        recipient,
+    })
 }
 
 fn derive_recipient_name(ssh_key: &str, ssh_key_path: &Path) -> String {
@@ -63,7 +174,7 @@ fn derive_recipient_name(ssh_key: &str, ssh_key_path: &Path) -> String {
         .unwrap_or_else(|| fallback_fingerprint(ssh_key))
 }
 
-fn sanitize_label(input: &str) -> String {
+pub(super) fn sanitize_label(input: &str) -> String {
     input
         .chars()
         .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
@@ -0,0 +1,54 @@
+use std::fs;
+
+use super::add_ssh_user::sanitize_label;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+use crate::rage::{self, RageManager};
+
+/// Key blob directory for passphrase recipients, under `.git/git-crypt/keys`
+pub const PASSPHRASE_DIR: &str = "passphrase";
+
+/// Share the repository key under a passphrase instead of a public key.
+///
+/// The key is wrapped with age's scrypt recipient and stored as
+/// `keys/passphrase/<label>.age`; anyone with the passphrase can import it
+/// with `import-age-key --passphrase`.
+pub fn add_passphrase_user(label: &str, force: bool) -> Result<()> {
+    let name = sanitize_label(label);
+    if name.is_empty() || name != label {
+        return Err(GitCryptError::Other(format!(
+            "Invalid label '{label}': use only letters, digits, '-', '_' and '.'"
+        )));
+    }
+
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let dir = key_manager
+        .git_crypt_dir()
+        .join("keys")
+        .join(PASSPHRASE_DIR);
+    let key_file = dir.join(format!("{name}.age"));
+    if key_file.exists() && !force {
+        return Err(GitCryptError::Other(format!(
+            "{} already exists (use --force to replace it)",
+            key_file.display()
+        )));
+    }
+
+    let key = key_manager.load_key()?;
+    let passphrase = rage::read_passphrase("Passphrase for the shared key", true)?;
+    let encrypted_key = RageManager::encrypt_key_with_passphrase(&key, passphrase)?;
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&key_file, encrypted_key)?;
+
+    println!("Encrypted key saved to {}", key_file.display());
+    println!("Share the file and the passphrase separately; import it with `import-age-key --passphrase`.");
+    Ok(())
+}
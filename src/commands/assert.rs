@@ -0,0 +1,46 @@
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::output::{self, Style};
+use std::path::Path;
+
+/// Check that the stored blob of `path` is encrypted (or, with
+/// `expect_encrypted` false, plaintext), failing with a message otherwise.
+///
+/// The blob is read from HEAD, or from the index with `index`.
+pub fn assert_stored(path: &Path, expect_encrypted: bool, index: bool) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let repo_path = repo.repo_relative(path)?;
+
+    let (blob, source) = if index {
+        let blob = match repo.index_blob(&repo_path)? {
+            Some(oid) => Some(repo.read_blob(oid)?),
+            None => None,
+        };
+        (blob, "the index")
+    } else {
+        (repo.head_blob_for_path(&repo_path)?, "HEAD")
+    };
+    let Some(blob) = blob else {
+        return Err(GitCryptError::Other(format!(
+            "{repo_path} is not in {source}"
+        )));
+    };
+
+    let encrypted = CryptoKey::is_encrypted(&blob);
+    let state = |encrypted| if encrypted { "encrypted" } else { "plaintext" };
+    if encrypted != expect_encrypted {
+        return Err(GitCryptError::Other(format!(
+            "{repo_path} is stored {} in {source} (expected {})",
+            state(encrypted),
+            state(expect_encrypted)
+        )));
+    }
+
+    println!(
+        "{}  {repo_path} is stored {} in {source}",
+        output::stdout("OK", Style::Ok),
+        state(encrypted)
+    );
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::output::{self, Style};
+use std::collections::HashSet;
+
+/// Scan the history of HEAD for files the attributes mark for encryption that
+/// were committed in plaintext.
+///
+/// Each blob is checked once, at the newest commit that contains it. With
+/// `fail_fast` the scan stops at the first plaintext file; `max_commits`
+/// limits how far back it goes.
+pub fn audit(fail_fast: bool, max_commits: Option<usize>) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+
+    let history = repo.head_history()?;
+    if history.is_empty() {
+        println!("No commits to audit");
+        return Ok(());
+    }
+    let limit = max_commits.unwrap_or(history.len()).min(history.len());
+
+    let mut seen = HashSet::new();
+    let mut scanned = 0;
+    let mut found = 0;
+    'commits: for &commit in &history[..limit] {
+        scanned += 1;
+        for entry in repo.encrypted_commit_entries(commit)? {
+            if !seen.insert(entry.oid) {
+                continue;
+            }
+            if CryptoKey::is_encrypted(&repo.read_blob(entry.oid)?) {
+                continue;
+            }
+
+            found += 1;
+            println!(
+                "{}  {} {}",
+                output::stdout("PLAINTEXT", Style::Fail),
+                &commit.to_string()[..7],
+                entry.path
+            );
+            if fail_fast {
+                break 'commits;
+            }
+        }
+    }
+
+    println!("\n{scanned} commit(s) scanned, {found} plaintext file(s) found");
+    if fail_fast && found > 0 {
+        println!("Stopped at the first plaintext file (--fail-fast)");
+    } else if limit < history.len() {
+        println!(
+            "Stopped after {limit} of {} commits (--max-commits)",
+            history.len()
+        );
+    }
+
+    if found > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{found} plaintext file(s) found in history"
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::output::{self, Style};
+
+/// Check the tree of HEAD for files the attributes mark for encryption that
+/// were committed in plaintext, failing if any are found.
+///
+/// With `all_commits`, every commit reachable from HEAD is checked instead
+/// (see [`super::audit`]).
+pub fn check(all_commits: bool) -> Result<()> {
+    if all_commits {
+        return super::audit(false, None);
+    }
+
+    let repo = GitRepo::open(".")?;
+    let entries = repo.encrypted_head_entries()?;
+
+    let mut leaked = 0;
+    for entry in &entries {
+        if CryptoKey::is_encrypted(&repo.read_blob(entry.oid)?) {
+            continue;
+        }
+        leaked += 1;
+        println!("{}  {}", output::stdout("LEAKED", Style::Fail), entry.path);
+    }
+
+    println!(
+        "\n{} file(s) marked for encryption in HEAD, {leaked} stored in plaintext",
+        entries.len()
+    );
+    if leaked > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{leaked} file(s) committed in plaintext; re-stage them through the filter \
+             and run 'git-crypt audit' to find them in older commits"
+        )));
+    }
+    Ok(())
+}
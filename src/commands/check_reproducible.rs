@@ -0,0 +1,60 @@
+use std::fs;
+
+use crate::error::{GitCryptError, Result};
+use crate::git::{clean_content, GitRepo};
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+
+/// Check that re-staging unchanged encrypted files reproduces the same blobs.
+///
+/// Each attributed file in the working tree is run through the clean filter
+/// twice; the file is stable only if both outputs are identical.
+pub fn check_reproducible() -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let key = key_manager.load_key()?;
+    let options = repo.clean_options()?;
+    let workdir = repo.workdir()?;
+    let entries = repo.encrypted_head_entries()?;
+
+    let mut checked = 0;
+    let mut unstable = 0;
+    for entry in &entries {
+        let path = workdir.join(&entry.path);
+        let Ok(content) = fs::read(&path) else {
+            // Deleted from the working tree; nothing to re-stage
+            continue;
+        };
+        checked += 1;
+
+        if clean_content(&key, &options, &content)? == clean_content(&key, &options, &content)? {
+            println!("{}  {}", output::stdout("STABLE  ", Style::Ok), entry.path);
+        } else {
+            unstable += 1;
+            println!(
+                "{}  {}",
+                output::stdout("UNSTABLE", Style::Fail),
+                entry.path
+            );
+        }
+    }
+
+    println!("\n{checked} files checked, {unstable} not reproducible");
+
+    if unstable > 0 {
+        println!("Clean output uses a random nonce, so every re-stage stores a new blob.");
+        println!(
+            "Set 'git config filter.git-crypt.deterministic true' to derive it from the content."
+        );
+        return Err(GitCryptError::Other(format!(
+            "{unstable} encrypted file(s) are not reproducible"
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::output::{self, Style};
+
+/// Fail if a staged file marked for encryption is stored in plaintext.
+///
+/// This is what the pre-commit hook from `init --install-hook` runs; it is
+/// silent when everything staged is encrypted.
+pub fn check_staged() -> Result<()> {
+    let repo = GitRepo::open(".")?;
+
+    let mut plaintext = 0;
+    for (path, oid) in repo.index_entries()? {
+        // git-crypt's own metadata is never encrypted
+        if path.starts_with(".git-crypt/") || !repo.is_filtered(&path)? {
+            continue;
+        }
+        if !CryptoKey::is_encrypted(&repo.read_blob(oid)?) {
+            plaintext += 1;
+            eprintln!(
+                "{}  {path}: staged without encryption",
+                output::stderr("PLAINTEXT", Style::Fail)
+            );
+        }
+    }
+
+    if plaintext > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{plaintext} staged file(s) marked for encryption are not encrypted; \
+             run 'git-crypt unlock' and stage them again"
+        )));
+    }
+    Ok(())
+}
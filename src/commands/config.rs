@@ -0,0 +1,111 @@
+use git_crypt::settings::{layered, Setting, Source};
+use git_crypt::sync;
+
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::{self, KeyManager};
+use crate::output;
+
+/// Print every effective setting and the layer it came from
+pub fn config_list() -> Result<()> {
+    for setting in settings()? {
+        println!("{}={} ({})", setting.name, setting.value, setting.source);
+    }
+    Ok(())
+}
+
+/// Effective settings of the current repository and this invocation
+fn settings() -> Result<Vec<Setting>> {
+    let repo = GitRepo::open(".")?;
+    let git_source = |name: &str| -> Result<Source> {
+        Ok(if repo.config_has(name)? {
+            Source::GitConfig
+        } else {
+            Source::Default
+        })
+    };
+
+    let options = repo.clean_options()?;
+    let format = match (options.wrapped, options.padding) {
+        (true, _) => "wrapped",
+        (false, Some(_)) => "padded",
+        (false, None) => "basic",
+    };
+    let format_source = git_source(&repo.filter_setting("padding"))?
+        .max(git_source(&repo.filter_setting("wrapped"))?);
+    let padding = options
+        .padding
+        .map_or("none".to_string(), |size| size.to_string());
+    let smudge_cache = repo
+        .smudge_cache_size()?
+        .map_or("off".to_string(), |size| size.to_string());
+
+    let mut settings = vec![
+        Setting::new(
+            "cipher",
+            options.cipher,
+            git_source(&repo.filter_setting("cipher"))?,
+        ),
+        Setting::new("format", format, format_source),
+        Setting::new(
+            "padding",
+            padding,
+            git_source(&repo.filter_setting("padding"))?,
+        ),
+        Setting::new(
+            "smudgecache",
+            smudge_cache,
+            git_source(&repo.filter_setting("smudgecache"))?,
+        ),
+    ];
+    if cfg!(feature = "notify") {
+        settings.push(Setting::new(
+            "notify",
+            repo.notify_enabled()?,
+            git_source("git-crypt.notify")?,
+        ));
+    }
+
+    let key_path = KeyManager::new(repo.git_dir()).default_key_path();
+    let key_path_source = match key::key_path_override() {
+        Some(_) => Source::Cli,
+        None => Source::Default,
+    };
+    settings.push(Setting::new(
+        "key_path",
+        key_path.display(),
+        key_path_source,
+    ));
+    let key_dir_env = std::env::var_os(key::KEY_DIR_ENV).is_some_and(|v| !v.is_empty());
+    let key_dir_source = match (key::key_dir_flag(), key_dir_env) {
+        (Some(_), _) => Source::Cli,
+        (None, true) => Source::Env,
+        (None, false) => Source::Default,
+    };
+    settings.push(Setting::new(
+        "keydir",
+        KeyManager::new(repo.git_dir()).git_crypt_dir().display(),
+        key_dir_source,
+    ));
+    let follow = key::follows_key_symlink();
+    settings.push(Setting::new(
+        "follow_key_symlink",
+        follow,
+        if follow { Source::Cli } else { Source::Default },
+    ));
+
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let (color, color_source) = layered(
+        "auto",
+        [
+            (Source::Env, no_color_env.then_some("off")),
+            (Source::Cli, output::no_color_flag().then_some("off")),
+        ],
+    );
+    settings.push(Setting::new("color", color, color_source));
+
+    let sync_settings = sync::settings(repo.workdir()?)
+        .map_err(|e| GitCryptError::Other(format!("Invalid sync configuration: {e}")))?;
+    settings.extend(sync_settings);
+    Ok(settings)
+}
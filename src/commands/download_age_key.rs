@@ -0,0 +1,47 @@
+use std::fs;
+use std::path::Path;
+
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+
+use git_crypt::sync;
+
+/// Download the age key blob for `alias` from the sync backend.
+///
+/// The blob is written to `output`, or next to locally granted blobs in
+/// `.git/git-crypt/keys/age/<alias>.age`, ready for `import-age-key`.
+pub fn download_age_key(alias: &str, output: Option<&Path>) -> Result<()> {
+    if alias.is_empty()
+        || alias == "."
+        || alias == ".."
+        || !alias
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(GitCryptError::Other(format!(
+            "Invalid alias '{alias}': use only letters, digits, '-', '_' and '.'"
+        )));
+    }
+
+    let repo = GitRepo::open(".")?;
+    let bytes = sync::download_age_key(repo.git_dir(), alias)
+        .map_err(|e| GitCryptError::Other(e.to_string()))?;
+
+    let output = match output {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let age_dir = KeyManager::new(repo.git_dir()).keys_dir().join("age");
+            fs::create_dir_all(&age_dir)?;
+            age_dir.join(format!("{alias}.age"))
+        }
+    };
+    fs::write(&output, &bytes)?;
+
+    println!("Downloaded age key for {alias} to {}", output.display());
+    println!(
+        "Import it with: git-crypt import-age-key --input {} --identity <SSH_KEY>",
+        output.display()
+    );
+    Ok(())
+}
@@ -1,11 +1,56 @@
 use crate::error::{GitCryptError, Result};
 use crate::git::GitRepo;
-use crate::key::KeyManager;
-use std::path::Path;
+use crate::gpg::GpgManager;
+use crate::key::{self, KeyManager};
+use crate::output::{self, Style};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 
-/// Export the symmetric key to a file
-pub fn export_key(output_path: &Path) -> Result<()> {
-    println!("Exporting key to: {}", output_path.display());
+/// Export the symmetric key to a file, or to stdout when `output_path` is `-`
+///
+/// Refuses to write a passphrase-protected key in the clear unless `allow_raw`
+/// is set, since the exported bytes bypass the passphrase entirely.
+///
+/// In `raw` mode stdout carries nothing but the 32 key bytes (no trailing
+/// newline) and every message goes to stderr, so the key can be piped into
+/// `age`, `gpg` or a secret store. Exporting to stdout implies `raw`.
+///
+/// With `armor`, the file holds the key as base64 text between armor lines,
+/// which survives copy-paste.
+///
+/// With `split` as `(shares, threshold)`, `output_path` is a directory that
+/// receives one Shamir share file per holder instead of the whole key.
+///
+/// With `gpg_recipient`, the file holds the key as an armored GPG message for
+/// that recipient, to hand over directly instead of through `add-gpg-user`.
+pub fn export_key(
+    output_path: &Path,
+    raw: bool,
+    armor: bool,
+    allow_raw: bool,
+    split: Option<(u8, u8)>,
+    gpg_recipient: Option<&str>,
+) -> Result<()> {
+    let to_stdout = output_path == Path::new("-");
+    if to_stdout && split.is_some() {
+        return Err(GitCryptError::Other(
+            "Key shares are written to a directory, not stdout".into(),
+        ));
+    }
+    if to_stdout && (armor || gpg_recipient.is_some()) {
+        return Err(GitCryptError::Other(
+            "Only the raw key can be exported to stdout".into(),
+        ));
+    }
+    let raw = raw || to_stdout;
+
+    let target = if to_stdout {
+        "stdout".to_string()
+    } else {
+        output_path.display().to_string()
+    };
+    say(raw, &format!("Exporting key to: {target}"));
 
     // Open repository
     let repo = GitRepo::open(".")?;
@@ -18,19 +63,121 @@ pub fn export_key(output_path: &Path) -> Result<()> {
         return Err(GitCryptError::NotInitialized);
     }
 
+    if let Some(recipient) = gpg_recipient {
+        let key = key_manager.load_key()?;
+        let encrypted = GpgManager::encrypt_key_for_recipient_armored(&key, recipient, false)?;
+        fs::write(output_path, encrypted)?;
+        println!("Key encrypted for GPG recipient: {recipient}");
+        println!(
+            "They can install it with 'gpg --decrypt {} | git-crypt import-key -'.",
+            output_path.display()
+        );
+        return Ok(());
+    }
+
+    if key_manager.is_passphrase_protected() {
+        if !allow_raw {
+            return Err(GitCryptError::Other(
+                "Repository key is protected by a passphrase; a plain export bypasses that \
+                 protection. Use a passphrase-wrapped export, or pass --allow-raw to export \
+                 the raw key anyway"
+                    .into(),
+            ));
+        }
+        say(
+            raw,
+            &warning(
+                raw,
+                "WARNING: Exporting the raw key bypasses its passphrase protection!",
+            ),
+        );
+    }
+
+    if let Some((shares, threshold)) = split {
+        let paths = key_manager.export_key_shares(output_path, shares, threshold)?;
+        for path in &paths {
+            println!("Wrote share {}", path.display());
+        }
+        println!(
+            "Key split into {shares} shares; any {threshold} of them rebuild it \
+             with 'git-crypt import-key --combine'."
+        );
+        println!(
+            "\n{}",
+            output::stdout(
+                "WARNING: Give each share to a different holder!",
+                Style::Warn
+            )
+        );
+        return Ok(());
+    }
+
     // Export the key
-    key_manager.export_key(output_path)?;
+    if to_stdout {
+        let mut stdout = io::stdout().lock();
+        key_manager.export_key_to_writer(&mut stdout)?;
+        stdout.flush()?;
+    } else if armor {
+        key_manager.export_key_armored(output_path)?;
+    } else {
+        key_manager.export_key(output_path)?;
+    }
+
+    say(raw, "Key exported successfully!");
+    say(
+        raw,
+        &format!("\n{}", warning(raw, "WARNING: Keep this key file secure!")),
+    );
+    say(
+        raw,
+        "Anyone with this key can decrypt your encrypted files.",
+    );
+
+    Ok(())
+}
+
+/// Print a human-facing line; in raw mode stdout is reserved for key bytes
+fn say(raw: bool, line: &str) {
+    if raw {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+fn warning(raw: bool, text: &str) -> String {
+    if raw {
+        output::stderr(text, Style::Warn)
+    } else {
+        output::stdout(text, Style::Warn)
+    }
+}
+
+/// Rebuild the symmetric key from Shamir share files and install it
+pub fn import_key_shares(share_paths: &[PathBuf]) -> Result<()> {
+    println!("Combining {} key share(s)", share_paths.len());
+
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    key_manager.import_key_shares(share_paths)?;
 
-    println!("Key exported successfully!");
-    println!("\nWARNING: Keep this key file secure!");
-    println!("Anyone with this key can decrypt your encrypted files.");
+    println!("Key imported successfully!");
 
     Ok(())
 }
 
-/// Import a symmetric key from a file
+/// Import a symmetric key from a file, or from stdin when `input_path` is `-`
 pub fn import_key(input_path: &Path) -> Result<()> {
-    println!("Importing key from: {}", input_path.display());
+    if input_path == Path::new(key::STDIN_KEY_PATH) {
+        println!("Importing key from stdin");
+    } else {
+        println!("Importing key from: {}", input_path.display());
+    }
 
     // Open repository
     let repo = GitRepo::open(".")?;
@@ -47,6 +194,20 @@ pub fn import_key(input_path: &Path) -> Result<()> {
     key_manager.import_key(input_path)?;
 
     println!("Key imported successfully!");
+    let key = key_manager.load_key()?;
+    if key.is_low_entropy() {
+        eprintln!(
+            "{} the imported key has very few distinct bytes and may not be random; \
+             check it is the key you meant to import",
+            output::stderr("WARNING:", Style::Warn)
+        );
+    }
+    if key.is_upstream() {
+        println!(
+            "This is an upstream git-crypt key: files it encrypted can be checked out, and \
+             'git add --renormalize .' re-encrypts them in git-crypt-rs format."
+        );
+    }
 
     Ok(())
 }
@@ -1,38 +1,414 @@
+use crate::cache::SmudgeCache;
+use crate::crypto::CryptoKey;
 use crate::error::{GitCryptError, Result};
-use crate::git::{clean_filter, diff_filter, smudge_filter, GitRepo};
-use crate::key::KeyManager;
+use crate::git::{
+    clean_filter, clean_stream, diff_filter, ignore_broken_pipe, smudge_filter,
+    smudge_stream_with_keys, CleanOptions, GitRepo, STREAM_THRESHOLD,
+};
+use crate::key::{self, KeyManager};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
-/// Clean filter implementation (called by git during add/commit)
-pub fn clean() -> Result<()> {
-    let repo =
-        GitRepo::open(".").map_err(|_| GitCryptError::Other("Not in a git repository".into()))?;
+/// Environment variable that turns on filter progress, like `--progress`
+pub const PROGRESS_ENV: &str = "GIT_CRYPT_PROGRESS";
 
-    let key_manager = KeyManager::new(repo.git_dir());
+/// How often progress is reported
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
 
-    if !key_manager.is_initialized() {
-        return Err(GitCryptError::NotInitialized);
+/// Bytes of input enough to tell an encrypted blob by its magic
+const MAGIC_PEEK: u64 = 16;
+
+/// Clean filter implementation (called by git during add/commit)
+///
+/// With `key_file`, runs standalone: the key is read from that file and the
+/// filter works outside any repository.
+///
+/// `path` is the file being filtered (git's `%f`), named in any error. When
+/// it lies inside a submodule that has its own git-crypt setup, that
+/// submodule's key is used instead of this repository's.
+///
+/// `input` and `output` replace stdin and stdout with files, for encrypting a
+/// single file by hand; git itself always uses stdio.
+///
+/// With `progress` (or `GIT_CRYPT_PROGRESS=1`), inputs over
+/// [`STREAM_THRESHOLD`] report how much has been read to stderr every
+/// second, when stderr is a terminal.
+///
+/// A `path` that is a symlink (or another special file) in the working tree
+/// passes through unencrypted with a warning: its content is a link target,
+/// and an encrypted target would leave a broken link on checkout.
+pub fn clean(
+    key_file: Option<&Path>,
+    path: Option<&Path>,
+    input: Option<&Path>,
+    output: Option<&Path>,
+    progress: bool,
+) -> Result<()> {
+    if let Some(path) = path.filter(|path| is_special_file(path)) {
+        eprintln!(
+            "Warning: {} is not a regular file; storing it unencrypted. \
+             Narrow the .gitattributes pattern that matches it.",
+            path.display()
+        );
+        check_distinct(input, output)?;
+        let (mut reader, mut writer) = (open_input(input)?, open_output(output)?);
+        return ignore_broken_pipe(
+            io::copy(&mut reader, &mut writer)
+                .and_then(|_| writer.flush())
+                .map_err(GitCryptError::from),
+        );
     }
 
-    let key = key_manager.load_key()?;
-    clean_filter(&key)
+    let (keys, repo) = filter_keys(key_file, path, false)?;
+    let key = &keys[0];
+    let options = match repo {
+        Some(repo) => repo.clean_options()?,
+        None => CleanOptions::default(),
+    };
+    let progress = Progress::start(progress, "encrypting", path);
+    let result = match &progress {
+        None if input.is_none() && output.is_none() => clean_filter(key, &options),
+        _ => {
+            check_distinct(input, output)?;
+            let reader = Progress::track(progress.as_ref(), open_input(input)?);
+            ignore_broken_pipe(clean_stream(key, &options, reader, open_output(output)?))
+        }
+    };
+    drop(progress);
+    result.map_err(|e| name_file(e, "encrypt", path))
 }
 
 /// Smudge filter implementation (called by git during checkout)
-pub fn smudge() -> Result<()> {
-    let repo =
-        GitRepo::open(".").map_err(|_| GitCryptError::Other("Not in a git repository".into()))?;
+///
+/// With `key_file`, `path`, `input`, `output` or `progress`, behaves like
+/// [`clean`].
+///
+/// Without a key (the repository was never unlocked or has no key file),
+/// plaintext still passes through but an encrypted blob fails with
+/// [`GitCryptError::EncryptedWithoutKey`], so checkout stops instead of
+/// writing ciphertext into the working tree.
+///
+/// Blobs the current key can't open are tried with the repository's retired
+/// and named keys (see [`KeyManager::load_all_keys`]), so history from before
+/// a `rotate-key` still checks out.
+pub fn smudge(
+    key_file: Option<&Path>,
+    path: Option<&Path>,
+    input: Option<&Path>,
+    output: Option<&Path>,
+    progress: bool,
+) -> Result<()> {
+    let (keys, repo) = match filter_keys(key_file, path, true) {
+        Ok(found) => found,
+        Err(GitCryptError::NotInitialized | GitCryptError::KeyNotFound(_)) => {
+            check_distinct(input, output)?;
+            return ignore_broken_pipe(smudge_without_key(
+                open_input(input)?,
+                open_output(output)?,
+                path,
+            ));
+        }
+        Err(err) => return Err(err),
+    };
 
-    let key_manager = KeyManager::new(repo.git_dir());
+    let cache = match &repo {
+        Some(repo) => match repo.smudge_cache_size()? {
+            Some(max_bytes) => {
+                let git_crypt_dir = KeyManager::new(repo.git_dir()).git_crypt_dir();
+                // A cache that can't be opened only costs speed
+                SmudgeCache::open(&git_crypt_dir, &keys[0], max_bytes).ok()
+            }
+            None => None,
+        },
+        None => None,
+    };
+    let progress = Progress::start(progress, "decrypting", path);
+    let result = match &progress {
+        None if input.is_none() && output.is_none() => smudge_filter(&keys, cache.as_ref()),
+        _ => {
+            check_distinct(input, output)?;
+            let reader = Progress::track(progress.as_ref(), open_input(input)?);
+            ignore_broken_pipe(smudge_stream_with_keys(
+                &keys,
+                cache.as_ref(),
+                reader,
+                open_output(output)?,
+            ))
+        }
+    };
+    drop(progress);
+    result.map_err(|e| name_file(e, "decrypt", path))
+}
 
-    if !key_manager.is_initialized() {
-        return Err(GitCryptError::NotInitialized);
+/// Copy plaintext from `reader` to `writer`, refusing encrypted input
+fn smudge_without_key(
+    mut reader: impl Read,
+    mut writer: impl Write,
+    path: Option<&Path>,
+) -> Result<()> {
+    let mut head = Vec::new();
+    reader.by_ref().take(MAGIC_PEEK).read_to_end(&mut head)?;
+    if CryptoKey::is_encrypted(&head) {
+        return Err(GitCryptError::EncryptedWithoutKey(
+            path.map_or("input".to_string(), |path| path.display().to_string()),
+        ));
     }
+    writer.write_all(&head)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
 
-    let key = key_manager.load_key()?;
-    smudge_filter(&key)
+/// Whether `path` exists in the working tree as something other than a
+/// regular file or directory: a symlink, FIFO, socket or device
+fn is_special_file(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|meta| {
+        let file_type = meta.file_type();
+        !file_type.is_file() && !file_type.is_dir()
+    })
 }
 
 /// Diff filter implementation (called by git during diff)
 pub fn diff() -> Result<()> {
     diff_filter()
 }
+
+/// Prefix a filter error with the file git was filtering, when it said
+fn name_file(err: GitCryptError, action: &str, path: Option<&Path>) -> GitCryptError {
+    match path {
+        Some(path) => GitCryptError::Other(format!("Failed to {action} {}: {err}", path.display())),
+        None => err,
+    }
+}
+
+/// Output is written while input is still being read, so the two must not
+/// be the same file
+fn check_distinct(input: Option<&Path>, output: Option<&Path>) -> Result<()> {
+    if let (Some(input), Some(output)) = (input, output) {
+        let same = fs::canonicalize(input)
+            .and_then(|input| Ok(input == fs::canonicalize(output)?))
+            .unwrap_or(false);
+        if same {
+            return Err(GitCryptError::Other(format!(
+                "--input and --output are the same file ({}); write to another file",
+                output.display()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Filter input from `input`, or stdin when not given
+fn open_input(input: Option<&Path>) -> Result<Box<dyn Read>> {
+    Ok(match input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(io::stdin().lock()),
+    })
+}
+
+/// Filter output to `output`, or stdout when not given
+fn open_output(output: Option<&Path>) -> Result<Box<dyn Write>> {
+    Ok(match output {
+        Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    })
+}
+
+/// Whether to report progress: asked for by flag or a `GIT_CRYPT_PROGRESS`
+/// of `1`, and only ever on a terminal so logs and pipes stay clean
+fn progress_enabled(flag: bool, env: Option<&str>, is_terminal: bool) -> bool {
+    (flag || env == Some("1")) && is_terminal
+}
+
+/// A heartbeat on stderr counting the bytes a filter has read.
+///
+/// A background thread prints the count every [`PROGRESS_INTERVAL`] once it
+/// passes [`STREAM_THRESHOLD`], so small files stay silent. Dropping it
+/// stops the thread and ends the line. Stdout is never touched: it carries
+/// the filtered content.
+struct Progress {
+    read: Arc<AtomicU64>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    /// Start reporting, or `None` when progress is off
+    fn start(flag: bool, action: &'static str, path: Option<&Path>) -> Option<Self> {
+        let env = std::env::var(PROGRESS_ENV).ok();
+        if !progress_enabled(flag, env.as_deref(), io::stderr().is_terminal()) {
+            return None;
+        }
+
+        let read = Arc::new(AtomicU64::new(0));
+        let (stop, stopped) = mpsc::channel();
+        let name = path.map_or("stdin".to_string(), |path| path.display().to_string());
+        let counter = Arc::clone(&read);
+        let thread = thread::spawn(move || {
+            let mut shown = false;
+            loop {
+                let done = !matches!(
+                    stopped.recv_timeout(PROGRESS_INTERVAL),
+                    Err(mpsc::RecvTimeoutError::Timeout)
+                );
+                let bytes = counter.load(Ordering::Relaxed);
+                if bytes > STREAM_THRESHOLD as u64 {
+                    let mib = bytes as f64 / (1024.0 * 1024.0);
+                    eprint!("\rgit-crypt: {action} {name}: {mib:.1} MiB");
+                    shown = true;
+                }
+                if done {
+                    if shown {
+                        eprintln!();
+                    }
+                    return;
+                }
+            }
+        });
+        Some(Progress {
+            read,
+            stop: Some(stop),
+            thread: Some(thread),
+        })
+    }
+
+    /// `reader`, counting into `progress` when there is one
+    fn track<'a>(progress: Option<&Self>, reader: Box<dyn Read + 'a>) -> Box<dyn Read + 'a> {
+        match progress {
+            Some(progress) => Box::new(CountingReader {
+                inner: reader,
+                read: Arc::clone(&progress.read),
+            }),
+            None => reader,
+        }
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Adds the bytes read through it to a shared counter
+struct CountingReader<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// Load the filter key from `key_file`, or from the repository that owns
+/// `path`: the enclosing repository, or a submodule with its own git-crypt.
+///
+/// The current key always comes first. With `all_keys`, the repository's
+/// other keys follow it ([`KeyManager::load_all_keys`]); a `key_file` is
+/// used alone.
+///
+/// The repository is returned when there is one so callers can read its
+/// filter settings.
+fn filter_keys(
+    key_file: Option<&Path>,
+    path: Option<&Path>,
+    all_keys: bool,
+) -> Result<(Vec<CryptoKey>, Option<GitRepo>)> {
+    if let Some(key_path) = key_file {
+        let key = key::read_key_file(key_path)?;
+        return Ok((vec![key], GitRepo::open(".").ok()));
+    }
+    let load = |key_manager: &KeyManager| match all_keys {
+        true => key_manager.load_all_keys(),
+        false => key_manager.load_key().map(|key| vec![key]),
+    };
+
+    let repo = GitRepo::open(".").map_err(|_| {
+        GitCryptError::Other(
+            "Not in a git repository (to use git-crypt outside a repository, pass --key-file)"
+                .into(),
+        )
+    })?;
+
+    if let Some(path) = path {
+        if let Some(submodule) = repo.submodule_containing(path)? {
+            let submodule_keys = KeyManager::new(submodule.git_dir());
+            if submodule_keys.is_initialized() {
+                // Never fall back to the parent's key for the submodule's files
+                let keys = load(&submodule_keys).map_err(|e| {
+                    GitCryptError::Other(format!(
+                        "{} belongs to a submodule with its own git-crypt key ({e}); \
+                         unlock the submodule instead of filtering it with this repository's key",
+                        path.display()
+                    ))
+                })?;
+                return Ok((keys, Some(submodule)));
+            }
+        }
+    }
+
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    Ok((load(&key_manager)?, Some(repo)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_requires_request_and_terminal() {
+        assert!(progress_enabled(true, None, true));
+        assert!(progress_enabled(false, Some("1"), true));
+        assert!(!progress_enabled(false, Some("0"), true));
+        assert!(!progress_enabled(false, None, true));
+        assert!(!progress_enabled(true, Some("1"), false));
+    }
+
+    #[test]
+    fn counting_reader_counts_bytes() {
+        let read = Arc::new(AtomicU64::new(0));
+        let mut reader = CountingReader {
+            inner: &b"twelve bytes"[..],
+            read: Arc::clone(&read),
+        };
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        assert_eq!(read.load(Ordering::Relaxed), 12);
+    }
+
+    #[test]
+    fn smudge_without_key_passes_plaintext_and_refuses_ciphertext() {
+        let plaintext = b"plain file, longer than the peeked magic";
+        let mut out = Vec::new();
+        smudge_without_key(&plaintext[..], &mut out, None).unwrap();
+        assert_eq!(out, plaintext);
+
+        let blob = CryptoKey::generate().encrypt(b"secret").unwrap();
+        let mut out = Vec::new();
+        let err = smudge_without_key(&blob[..], &mut out, Some(Path::new("a.secret"))).unwrap_err();
+        assert!(matches!(err, GitCryptError::EncryptedWithoutKey(_)));
+        assert_eq!(
+            err.to_string(),
+            "a.secret is encrypted but no git-crypt key is available; run 'git-crypt unlock'"
+        );
+        assert!(out.is_empty());
+    }
+}
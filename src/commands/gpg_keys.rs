@@ -0,0 +1,20 @@
+use crate::error::Result;
+use crate::gpg::GpgManager;
+
+/// List GPG public keys that can be passed to `add-gpg-user`
+pub fn gpg_keys() -> Result<()> {
+    let keys = GpgManager::list_keys()?;
+
+    if keys.is_empty() {
+        println!("No GPG public keys found in your keyring");
+        return Ok(());
+    }
+
+    for key in &keys {
+        println!("{}  {}", key.fingerprint, key.uid);
+    }
+
+    println!("\nUse 'git-crypt add-gpg-user <FINGERPRINT>' to grant access");
+
+    Ok(())
+}
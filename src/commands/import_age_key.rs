@@ -4,10 +4,45 @@ use std::path::Path;
 use crate::error::{GitCryptError, Result};
 use crate::git::GitRepo;
 use crate::key::KeyManager;
-use crate::rage::RageManager;
+use crate::output::{self, Style};
+use crate::rage::{self, RageManager};
 
 /// Import an age/rage-encrypted key using an SSH identity.
-pub fn import_age_key(encrypted_path: &Path, identity_path: &Path) -> Result<()> {
+///
+/// With `verify_only`, only checks that the identity decrypts the blob and
+/// reports the key's fingerprint; nothing is installed and no repository is
+/// needed.
+pub fn import_age_key(
+    encrypted_path: &Path,
+    identity_path: &Path,
+    verify_only: bool,
+) -> Result<()> {
+    let encrypted = fs::read(encrypted_path)?;
+    let identity = fs::read_to_string(identity_path)?;
+    let identity_label = identity_path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("ssh identity");
+
+    if verify_only {
+        let key =
+            RageManager::decrypt_key_with_ssh_identity(&encrypted, &identity, identity_label)?;
+        println!(
+            "{}",
+            output::stdout(
+                &format!(
+                    "{} decrypts {}",
+                    identity_path.display(),
+                    encrypted_path.display()
+                ),
+                Style::Ok
+            )
+        );
+        println!("Key fingerprint: {}", key.fingerprint());
+        println!("Nothing was installed (--verify-only).");
+        return Ok(());
+    }
+
     println!(
         "Importing age key from {} using identity {}",
         encrypted_path.display(),
@@ -21,17 +56,36 @@ pub fn import_age_key(encrypted_path: &Path, identity_path: &Path) -> Result<()>
         return Err(GitCryptError::NotInitialized);
     }
 
-    let encrypted = fs::read(encrypted_path)?;
-    let identity = fs::read_to_string(identity_path)?;
-    let identity_label = identity_path
-        .file_name()
-        .and_then(|s| s.to_str())
-        .unwrap_or("ssh identity");
-
     let key = RageManager::decrypt_key_with_ssh_identity(&encrypted, &identity, identity_label)?;
 
     key_manager.save_key(&key)?;
+    key_manager.record_import(&format!("age:{}", encrypted_path.display()), &key)?;
 
     println!("Repository key imported successfully using SSH identity.");
     Ok(())
 }
+
+/// Import a passphrase-wrapped key written by `add-user --passphrase`.
+pub fn import_age_key_with_passphrase(encrypted_path: &Path) -> Result<()> {
+    println!(
+        "Importing passphrase-protected age key from {}",
+        encrypted_path.display()
+    );
+
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let encrypted = fs::read(encrypted_path)?;
+    let passphrase = rage::read_passphrase("Passphrase", false)?;
+    let key = RageManager::decrypt_key_with_passphrase(&encrypted, passphrase)?;
+
+    key_manager.save_key(&key)?;
+    key_manager.record_import(&format!("age:{}", encrypted_path.display()), &key)?;
+
+    println!("Repository key imported successfully using passphrase.");
+    Ok(())
+}
@@ -1,41 +1,275 @@
-use crate::error::Result;
-use crate::git::GitRepo;
-use crate::key::KeyManager;
+use crate::crypto::{Cipher, CryptoKey};
+use crate::error::{GitCryptError, Result};
+use crate::git::{filter_driver, shell_quote, GitRepo, METADATA_ATTRIBUTES_FILE};
+use crate::key::{self, KeyManager, LockState};
+use crate::output::{self, Style};
+use zeroize::Zeroizing;
+
+// File names that usually hold secrets, matched exactly, by prefix or by suffix
+const SECRET_NAMES: &[&str] = &[
+    ".env",
+    "id_rsa",
+    "id_ecdsa",
+    "id_ed25519",
+    "credentials.json",
+];
+const SECRET_PREFIXES: &[&str] = &[".env."];
+const SECRET_SUFFIXES: &[&str] = &[".pem", ".key", ".p12", ".pfx", ".jks", ".keystore"];
+
+/// A tracked file that is stored in plaintext but should not be
+struct PlaintextFile {
+    path: String,
+    /// Whether `.gitattributes` already marks it for encryption, so
+    /// re-staging is enough to encrypt it
+    filtered: bool,
+}
 
 /// Initialize git-crypt in the repository
-pub fn init() -> Result<()> {
+///
+/// With `strict`, refuse to initialize while tracked files that are marked for
+/// encryption or look like secrets are stored in plaintext. `restage`
+/// re-adds the marked ones through the filter instead of refusing.
+///
+/// In an already initialized repository, filter settings that have gone
+/// missing from the git config are added back unless the repository is locked.
+///
+/// `install_hook` adds a pre-commit hook that blocks commits of plaintext
+/// files marked for encryption, also in an already initialized repository.
+///
+/// `cipher` sets the AEAD new blobs are sealed with (`filter.git-crypt.cipher`);
+/// blobs record their cipher, so existing ones stay readable either way.
+///
+/// With `passphrase`, the key file is wrapped under a passphrase asked for
+/// twice (see [`KeyManager::save_key_encrypted`]); an existing unprotected
+/// key is wrapped in place.
+pub fn init(
+    strict: bool,
+    restage: bool,
+    install_hook: bool,
+    cipher: Option<Cipher>,
+    passphrase: bool,
+) -> Result<()> {
     println!("Initializing git-crypt...");
 
     // Open repository
     let repo = GitRepo::open(".")?;
+    if repo.is_bare() {
+        return Err(GitCryptError::BareRepository);
+    }
     let git_dir = repo.git_dir();
 
     // Initialize key manager
     let key_manager = KeyManager::new(git_dir);
 
-    // Check if already initialized
-    if key_manager.is_initialized() {
+    // A key file means initialized; a git-crypt directory without one is a
+    // partial init that still needs a key
+    if key_manager.has_key() {
         println!("Repository already initialized for git-crypt");
+        // lock removes the filters on purpose; unlock puts them back
+        if key_manager.lock_state()? == Some(LockState::Locked) {
+            println!("Repository is locked; run 'git-crypt unlock' to restore the filters");
+        } else if repo.repair_filters()? {
+            println!("Repaired git filters");
+        }
+        mark_binary(&repo)?;
+        if passphrase && !key_manager.is_passphrase_protected() {
+            let key = key_manager.load_key()?;
+            key_manager.save_key_encrypted(&key, &new_passphrase()?)?;
+            let _ = key_manager.cache_session_key(&key);
+            println!("Protected the key with a passphrase");
+        }
+        if let Some(cipher) = cipher {
+            set_cipher(&repo, cipher)?;
+            println!("New files will be encrypted with {cipher}; existing blobs are unchanged");
+        }
+        if install_hook {
+            install_pre_commit_hook(&repo)?;
+        }
         return Ok(());
     }
 
+    let mut to_restage = Vec::new();
+    if strict {
+        let plaintext = scan_plaintext(&repo)?;
+        report_plaintext(&plaintext);
+
+        let refuse = plaintext.iter().any(|f| !f.filtered || !restage);
+        if refuse {
+            return Err(GitCryptError::Other(format!(
+                "Refusing to initialize: {} tracked file(s) would stay in plaintext",
+                plaintext.len()
+            )));
+        }
+        to_restage = plaintext.into_iter().map(|f| f.path).collect();
+    }
+
     // Create directory structure
-    key_manager.init_dirs()?;
+    if !key_manager.is_initialized() {
+        key_manager.init_dirs()?;
+    }
 
-    // Generate and save key
-    let _key = key_manager.generate_key()?;
-    println!("Generated new encryption key");
+    // Generate and save key, leaving one written concurrently in place
+    let generated = match passphrase {
+        true => new_passphrase().and_then(|passphrase| {
+            let key = key_manager.generate_key_encrypted(&passphrase)?;
+            let _ = key_manager.cache_session_key(&key);
+            Ok(key)
+        }),
+        false => key_manager.generate_key(),
+    };
+    match generated {
+        Ok(_) if passphrase => println!("Generated new encryption key, protected by a passphrase"),
+        Ok(_) => println!("Generated new encryption key"),
+        Err(GitCryptError::AlreadyInitialized) => {
+            println!("Repository already initialized for git-crypt");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    }
 
     // Configure git filters
     repo.configure_filters()?;
+    key_manager.set_lock_state(LockState::Unlocked)?;
     println!("Configured git filters");
+    if let Some(cipher) = cipher {
+        set_cipher(&repo, cipher)?;
+        println!("New files will be encrypted with {cipher}");
+    }
 
-    println!("\nInitialization complete!");
+    if repo.write_metadata_attributes()? {
+        println!("Excluded git-crypt metadata from encryption in {METADATA_ATTRIBUTES_FILE}");
+    }
+    mark_binary(&repo)?;
+
+    if install_hook {
+        install_pre_commit_hook(&repo)?;
+    }
+
+    if !to_restage.is_empty() {
+        repo.restage(&to_restage)?;
+        println!(
+            "Re-staged {} plaintext file(s) through the filter; commit them to store them encrypted",
+            to_restage.len()
+        );
+    }
+
+    println!(
+        "\n{}",
+        output::stdout("Initialization complete!", Style::Ok)
+    );
     println!("\nNext steps:");
     println!("1. Create a .gitattributes file to specify which files to encrypt");
-    println!("   Example: echo 'secretfile filter=git-crypt diff=git-crypt' >> .gitattributes");
-    println!("2. Commit the .gitattributes file");
+    let driver = filter_driver(key_manager.key_name());
+    println!("   Example: echo 'secretfile filter={driver} diff={driver}' >> .gitattributes");
+    println!("2. Commit the .gitattributes file (and {METADATA_ATTRIBUTES_FILE})");
     println!("3. Use 'git-crypt add-gpg-user' to grant access to other users");
 
     Ok(())
 }
+
+/// Record the cipher new blobs are sealed with (`filter.git-crypt.cipher`)
+fn set_cipher(repo: &GitRepo, cipher: Cipher) -> Result<()> {
+    let mut snapshot = repo.config_snapshot()?;
+    snapshot.set("cipher", cipher.as_str());
+    repo.write_config_snapshot(&snapshot)?;
+    Ok(())
+}
+
+/// Keep git from converting line endings of encrypted blobs (see
+/// [`GitRepo::ensure_binary_attribute`])
+fn mark_binary(repo: &GitRepo) -> Result<()> {
+    let patterns = repo.ensure_binary_attribute()?;
+    if !patterns.is_empty() {
+        println!(
+            "Added -text to {} in .gitattributes so git never converts line endings of encrypted files; commit it",
+            patterns.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Install the pre-commit hook, pointing it at this executable
+fn install_pre_commit_hook(repo: &GitRepo) -> Result<()> {
+    let exe = std::env::current_exe()
+        .map(|path| shell_quote(&path.display().to_string()))
+        .unwrap_or_else(|_| "git-crypt".into());
+    if repo.install_pre_commit_hook(&format!("{exe} check-staged"))? {
+        println!("Installed pre-commit hook that blocks plaintext secrets");
+    } else {
+        println!("Pre-commit hook already installed");
+    }
+    Ok(())
+}
+
+/// Find tracked files stored in plaintext that are marked for encryption or
+/// look like secrets
+fn scan_plaintext(repo: &GitRepo) -> Result<Vec<PlaintextFile>> {
+    let mut found = Vec::new();
+    for (path, oid) in repo.index_entries()? {
+        // git-crypt's own metadata is never encrypted
+        if path.starts_with(".git-crypt/") {
+            continue;
+        }
+
+        let filtered = repo.is_filtered(&path)?;
+        if !filtered && !looks_like_secret(&path) {
+            continue;
+        }
+        if !CryptoKey::is_encrypted(&repo.read_blob(oid)?) {
+            found.push(PlaintextFile { path, filtered });
+        }
+    }
+    Ok(found)
+}
+
+fn report_plaintext(files: &[PlaintextFile]) {
+    for file in files {
+        let reason = if file.filtered {
+            "committed in plaintext; re-add it (or pass --restage)"
+        } else {
+            "looks like a secret but is not marked for encryption in .gitattributes"
+        };
+        eprintln!(
+            "{}  {}: {reason}",
+            output::stderr("PLAINTEXT", Style::Warn),
+            file.path
+        );
+    }
+}
+
+fn looks_like_secret(path: &str) -> bool {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    SECRET_NAMES.contains(&name)
+        || SECRET_PREFIXES.iter().any(|p| name.starts_with(p))
+        || SECRET_SUFFIXES.iter().any(|s| name.ends_with(s))
+}
+
+/// Passphrase for a newly protected key, typed twice
+fn new_passphrase() -> Result<Zeroizing<String>> {
+    key::read_passphrase("Passphrase for the git-crypt key", true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_like_secret_matches_common_names() {
+        for path in [
+            ".env",
+            "config/.env.production",
+            "certs/server.pem",
+            "id_ed25519",
+        ] {
+            assert!(looks_like_secret(path), "{path}");
+        }
+        for path in [
+            "README.md",
+            "src/keys.rs",
+            "environment.txt",
+            "id_ed25519.pub",
+        ] {
+            assert!(!looks_like_secret(path), "{path}");
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+
+/// Hex digits of the fingerprint shown per key
+const FINGERPRINT_LEN: usize = 16;
+
+/// List the repository key and any named keys, with their fingerprint,
+/// cipher, generation and whether their filters are configured
+pub fn keys_list() -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let names = key_manager.key_names()?;
+    if names.is_empty() {
+        println!("No keys in {}", key_manager.keys_dir().display());
+        return Ok(());
+    }
+
+    let mut rows =
+        vec![["NAME", "FINGERPRINT", "CIPHER", "GENERATION", "FILTERS"].map(String::from)];
+    for name in names {
+        let named = key_manager.named_key(&name);
+        let fingerprint = match named.load_key() {
+            Ok(key) => key.fingerprint()[..FINGERPRINT_LEN].to_string(),
+            Err(_) => "(unreadable)".to_string(),
+        };
+        let generation = named
+            .key_generation()?
+            .map_or("-".to_string(), |generation| generation.to_string());
+        let filters = if repo.key_filters_configured(&name)? {
+            "configured"
+        } else {
+            "not configured"
+        };
+        rows.push([
+            name.clone(),
+            fingerprint,
+            repo.key_cipher(&name)?.to_string(),
+            generation,
+            filters.to_string(),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..4)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    for row in &rows {
+        println!(
+            "{:w0$}  {:w1$}  {:w2$}  {:w3$}  {}",
+            row[0],
+            row[1],
+            row[2],
+            row[3],
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w3 = widths[3]
+        );
+    }
+    Ok(())
+}
+
+/// Print the fingerprint of the repository key (or the `--key-name` key) in
+/// groups of four hex digits, for teammates to compare out of band
+pub fn key_fingerprint() -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let key = key_manager.load_key()?;
+    println!("{}", group_hex(&key.fingerprint()[..FINGERPRINT_LEN]));
+    Ok(())
+}
+
+/// `hex` split into space-separated groups of four digits
+fn group_hex(hex: &str) -> String {
+    hex.as_bytes()
+        .chunks(4)
+        .map(|chunk| String::from_utf8_lossy(chunk))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_hex_splits_into_fours() {
+        assert_eq!(group_hex("0123456789abcdef"), "0123 4567 89ab cdef");
+        assert_eq!(group_hex("abcdef"), "abcd ef");
+    }
+}
@@ -0,0 +1,169 @@
+use std::fs;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+
+use super::status::json_string;
+
+/// A collaborator with a key blob under `.git/git-crypt/keys`
+struct User {
+    /// `gpg` or `age`
+    kind: &'static str,
+    /// GPG id or SSH alias, taken from the blob's file name
+    name: String,
+    /// Seconds since the Unix epoch the blob was last written
+    modified: Option<u64>,
+}
+
+/// List everyone who was granted access with `add-gpg-user` or
+/// `add-ssh-user`, with the time their key blob was last written
+pub fn list_users(json: bool) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+    let mut users = collect_users(&keys_dir.join("gpg"), "key", "gpg")?;
+    users.extend(collect_users(&keys_dir.join("age"), "age", "age")?);
+
+    if json {
+        println!("{}", to_json(&users));
+    } else if users.is_empty() {
+        println!("No users in {}", keys_dir.display());
+    } else {
+        print!("{}", to_table(&users));
+    }
+    Ok(())
+}
+
+/// Users with a `*.extension` blob in `dir`, sorted by name; none if `dir`
+/// doesn't exist
+fn collect_users(dir: &Path, extension: &str, kind: &'static str) -> Result<Vec<User>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut users = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().is_none_or(|ext| ext != extension) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        let modified = fs::metadata(&path)?
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|age| age.as_secs());
+        users.push(User {
+            kind,
+            name: name.to_string(),
+            modified,
+        });
+    }
+    users.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(users)
+}
+
+fn to_table(users: &[User]) -> String {
+    let mut rows = vec![["TYPE", "USER", "MODIFIED"].map(String::from)];
+    for user in users {
+        rows.push([
+            user.kind.to_string(),
+            user.name.clone(),
+            user.modified.map_or("-".to_string(), format_utc),
+        ]);
+    }
+
+    let widths: Vec<usize> = (0..2)
+        .map(|column| rows.iter().map(|row| row[column].len()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            format!(
+                "{:w0$}  {:w1$}  {}\n",
+                row[0],
+                row[1],
+                row[2],
+                w0 = widths[0],
+                w1 = widths[1]
+            )
+        })
+        .collect()
+}
+
+fn to_json(users: &[User]) -> String {
+    let entries: Vec<String> = users
+        .iter()
+        .map(|user| {
+            let modified = user
+                .modified
+                .map_or("null".into(), |secs| json_string(&format_utc(secs)));
+            format!(
+                "{{\"type\":{},\"user\":{},\"modified\":{}}}",
+                json_string(user.kind),
+                json_string(&user.name),
+                modified
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+/// `secs` since the Unix epoch as an RFC 3339 UTC timestamp
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_utc_renders_calendar_dates() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn json_escapes_names() {
+        let users = [User {
+            kind: "gpg",
+            name: "a\"b".to_string(),
+            modified: None,
+        }];
+        assert_eq!(
+            to_json(&users),
+            r#"[{"type":"gpg","user":"a\"b","modified":null}]"#
+        );
+    }
+}
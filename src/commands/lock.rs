@@ -1,13 +1,25 @@
+use crate::cache::SmudgeCache;
 use crate::error::{GitCryptError, Result};
 use crate::git::GitRepo;
-use crate::key::KeyManager;
+use crate::key::{KeyManager, LockState};
+use crate::notify;
+use crate::output::{self, Style};
+use crate::parallel;
 
 /// Lock the repository (remove filters and show encrypted content)
-pub fn lock() -> Result<()> {
+///
+/// Removing the filters leaves decrypted files in the working tree. With
+/// `reset_working_tree`, every encrypted file is replaced by its ciphertext
+/// from HEAD as well; this refuses to run if any of them has uncommitted
+/// changes, which would be lost.
+pub fn lock(force: bool, reset_working_tree: bool) -> Result<()> {
     println!("Locking repository...");
 
     // Open repository
     let repo = GitRepo::open(".")?;
+    if repo.is_bare() {
+        return Err(GitCryptError::BareRepository);
+    }
     let git_dir = repo.git_dir();
 
     let key_manager = KeyManager::new(git_dir);
@@ -17,12 +29,65 @@ pub fn lock() -> Result<()> {
         return Err(GitCryptError::NotInitialized);
     }
 
+    if !force
+        && key_manager.lock_state()? == Some(LockState::Locked)
+        && !repo.filters_configured()?
+    {
+        println!("Repository is already locked (use --force to lock again)");
+        return Ok(());
+    }
+
+    let reset_paths = if reset_working_tree {
+        encrypted_paths_to_reset(&repo)?
+    } else {
+        Vec::new()
+    };
+
     // Remove git filters
     repo.remove_filters()?;
+    SmudgeCache::clear(&key_manager.git_crypt_dir())?;
+    key_manager.forget_session_key()?;
+    key_manager.set_lock_state(LockState::Locked)?;
+    notify::lock_state_changed(&repo, LockState::Locked);
+
+    repo.checkout_stored(&reset_paths)?;
 
-    println!("Repository locked!");
-    println!("\nEncrypted files will now show their encrypted content.");
+    println!("{}", output::stdout("Repository locked!", Style::Ok));
+    if reset_working_tree {
+        println!(
+            "\nReset {} encrypted file(s) in the working tree:",
+            reset_paths.len()
+        );
+        for path in &reset_paths {
+            println!("  {path}");
+        }
+    } else {
+        println!("\nEncrypted files will now show their encrypted content.");
+    }
     println!("Run 'git-crypt unlock' to restore access.");
 
     Ok(())
 }
+
+/// Encrypted files in HEAD, after checking that none has uncommitted changes
+fn encrypted_paths_to_reset(repo: &GitRepo) -> Result<Vec<String>> {
+    let paths: Vec<String> = repo
+        .encrypted_head_entries()?
+        .into_iter()
+        .map(|entry| entry.path)
+        .collect();
+    let key = repo.load_key()?;
+    let changed: Vec<String> =
+        super::rotate_key::uncommitted_changes(repo, &key, parallel::default_threads())?
+            .into_iter()
+            .filter(|path| paths.contains(path))
+            .collect();
+    if let Some(first) = changed.first() {
+        return Err(GitCryptError::Other(format!(
+            "{} encrypted file(s) have uncommitted changes (e.g. {first}); commit or stash \
+             them before resetting the working tree",
+            changed.len()
+        )));
+    }
+    Ok(paths)
+}
@@ -1,21 +1,68 @@
 pub mod add_gpg_user;
 #[cfg(feature = "ssh")]
 pub mod add_ssh_user;
+#[cfg(feature = "ssh")]
+pub mod add_user;
+pub mod assert;
+pub mod audit;
+pub mod check;
+pub mod check_reproducible;
+pub mod check_staged;
+pub mod config;
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+pub mod download_age_key;
 pub mod export_key;
 pub mod filters;
+pub mod gpg_keys;
 #[cfg(feature = "ssh")]
 pub mod import_age_key;
 pub mod init;
+pub mod keys;
+pub mod list_users;
 pub mod lock;
+pub mod purge;
+pub mod remove_user;
+pub mod rewrap;
+pub mod rotate_key;
+pub mod sidecar;
+pub mod status;
+pub mod summary;
+pub mod sync_recipients;
+pub mod toggle_path;
 pub mod unlock;
+pub mod verify;
+pub mod watch;
 
 pub use add_gpg_user::add_gpg_user;
 #[cfg(feature = "ssh")]
 pub use add_ssh_user::add_ssh_user;
-pub use export_key::{export_key, import_key};
+#[cfg(feature = "ssh")]
+pub use add_user::add_passphrase_user;
+pub use assert::assert_stored;
+pub use audit::audit;
+pub use check::check;
+pub use check_reproducible::check_reproducible;
+pub use check_staged::check_staged;
+pub use config::config_list;
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+pub use download_age_key::download_age_key;
+pub use export_key::{export_key, import_key, import_key_shares};
 pub use filters::{clean, diff, smudge};
+pub use gpg_keys::gpg_keys;
 #[cfg(feature = "ssh")]
-pub use import_age_key::import_age_key;
+pub use import_age_key::{import_age_key, import_age_key_with_passphrase};
 pub use init::init;
+pub use keys::{key_fingerprint, keys_list};
+pub use list_users::list_users;
 pub use lock::lock;
-pub use unlock::unlock;
+pub use purge::purge;
+pub use remove_user::{remove_user, User as RemovedUser};
+pub use rewrap::rewrap;
+pub use rotate_key::rotate_key;
+pub use sidecar::{decrypt_file, encrypt_file, sync_sidecars};
+pub use status::{status, status_files};
+pub use sync_recipients::sync_recipients;
+pub use toggle_path::{decrypt_path, encrypt_path};
+pub use unlock::{unlock, Method as UnlockMethod};
+pub use verify::verify;
+pub use watch::watch;
@@ -0,0 +1,62 @@
+use std::io::{self, BufRead, Write};
+
+use crate::error::{GitCryptError, Result};
+use crate::git::{ConfigSnapshot, GitRepo};
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+
+use super::lock;
+
+/// Remove the key, key blobs and filter configuration from this clone
+pub fn purge(relock: bool, yes: bool) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    if !yes && !confirm()? {
+        println!("Purge cancelled");
+        return Ok(());
+    }
+
+    // Swap plaintext for ciphertext while the key can still decrypt HEAD;
+    // refuses, before anything is erased, if that would lose edits
+    if relock {
+        lock(true, true)?;
+    }
+
+    // Drops every filter.git-crypt.* setting, including format options
+    repo.write_config_snapshot(&ConfigSnapshot::default())?;
+    println!("Removed git-crypt filter configuration");
+
+    if repo.remove_pre_commit_hook()? {
+        println!("Removed git-crypt pre-commit hook");
+    }
+
+    let erased = key_manager.purge()?;
+    println!("Erased {erased} key file(s)");
+
+    println!(
+        "\n{}",
+        output::stdout("git-crypt data purged from this repository", Style::Ok)
+    );
+    if !relock {
+        println!("Files still checked out in plaintext remain readable in the working tree.");
+    }
+
+    Ok(())
+}
+
+fn confirm() -> Result<bool> {
+    print!(
+        "{} This deletes the repository key and all key blobs. Continue? [y/N] ",
+        output::stdout("WARNING:", Style::Warn)
+    );
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "YES"))
+}
@@ -0,0 +1,80 @@
+use std::fs;
+
+use super::sync_recipients::blob_path;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+use crate::recipients::{self, BlobManifest, RecipientKind};
+
+/// Which collaborator to remove
+pub enum User<'a> {
+    /// An SSH or age user added with `add-ssh-user --alias`
+    Alias(&'a str),
+    /// A GPG user added with `add-gpg-user`
+    GpgId(&'a str),
+}
+
+/// Delete a collaborator's key blob and its manifest record.
+///
+/// Removing the blob only stops new clones from unlocking through it; the
+/// user still holds the symmetric key, so with `rotate` the key is rotated
+/// straight after (see [`super::rotate_key`]).
+pub fn remove_user(user: User, rotate: bool) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let (kinds, name) = match user {
+        User::Alias(alias) => (&[RecipientKind::Ssh, RecipientKind::Age][..], alias),
+        User::GpgId(gpg_id) => (&[RecipientKind::Gpg][..], gpg_id),
+    };
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(GitCryptError::Other(format!("Invalid user name '{name}'")));
+    }
+
+    // rewrap and sync-recipients regrant everyone in the recipients file
+    let recipients_file = repo.workdir()?.join(recipients::RECIPIENTS_FILE);
+    if recipients_file.exists() {
+        let listed = recipients::load_recipients(repo.workdir()?)?
+            .iter()
+            .any(|r| kinds.contains(&r.kind) && r.name == name);
+        if listed {
+            return Err(GitCryptError::Other(format!(
+                "'{name}' is listed in {}; remove them there and run sync-recipients instead",
+                recipients::RECIPIENTS_FILE
+            )));
+        }
+    }
+
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+    let path = blob_path(&keys_dir, kinds[0], name);
+    if !path.is_file() {
+        return Err(GitCryptError::KeyNotFound(path.display().to_string()));
+    }
+    fs::remove_file(&path)?;
+
+    let mut manifest = BlobManifest::load(&keys_dir)?;
+    for &kind in kinds {
+        manifest.remove(kind, name);
+    }
+    manifest.save(&keys_dir)?;
+
+    println!(
+        "{} {}",
+        output::stdout("Revoked access:", Style::Warn),
+        path.display()
+    );
+    if rotate {
+        println!();
+        return super::rotate_key(None, false, None);
+    }
+    println!(
+        "{} '{name}' may still hold the current key; run 'git-crypt rotate-key' to revoke it.",
+        output::stdout("WARNING:", Style::Warn)
+    );
+    Ok(())
+}
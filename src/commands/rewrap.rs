@@ -0,0 +1,108 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::sync_recipients::{blob_path, encrypt_for};
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+use crate::recipients::{self, BlobManifest, BlobRecord, Recipient, RecipientKind};
+
+/// Regenerate recipient blobs that were not written in the current format for
+/// the current key.
+///
+/// Recipients come from the blob manifest, with `.git-crypt/recipients`
+/// taking precedence when it exists. Blobs whose recipient is unknown are
+/// left alone and reported.
+pub fn rewrap() -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let key = key_manager.load_key()?;
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+    let mut manifest = BlobManifest::load(&keys_dir)?;
+
+    let mut known: Vec<Recipient> = Vec::new();
+    if repo.workdir()?.join(recipients::RECIPIENTS_FILE).exists() {
+        known = recipients::load_recipients(repo.workdir()?)?;
+    }
+    for record in manifest.records() {
+        let r = &record.recipient;
+        let listed = known
+            .iter()
+            .any(|k| k.kind.key_dir() == r.kind.key_dir() && k.name == r.name);
+        if !listed {
+            known.push(r.clone());
+        }
+    }
+
+    let mut covered = HashSet::new();
+    let (mut rewrapped, mut current) = (0, 0);
+    for recipient in &known {
+        let path = blob_path(&keys_dir, recipient.kind, &recipient.name);
+        covered.insert(path.clone());
+
+        let is_current = path.exists()
+            && manifest
+                .get(recipient.kind, &recipient.name)
+                .is_some_and(|record| record.is_current(&key, recipient));
+        if is_current {
+            current += 1;
+            println!(
+                "{}  {}",
+                output::stdout("Current  ", Style::Ok),
+                recipient.name
+            );
+            continue;
+        }
+
+        let blob = encrypt_for(&key, recipient)?;
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, blob)?;
+        manifest.record(BlobRecord::new(&key, recipient.clone()));
+        rewrapped += 1;
+        println!(
+            "{}  {} ({})",
+            output::stdout("Rewrapped", Style::Warn),
+            recipient.name,
+            path.display()
+        );
+    }
+    manifest.save(&keys_dir)?;
+
+    let skipped = report_unknown_blobs(&keys_dir, &covered)?;
+
+    println!("\n{rewrapped} rewrapped, {current} already current, {skipped} skipped");
+    Ok(())
+}
+
+/// Print blobs that no known recipient accounts for, returning how many there are
+fn report_unknown_blobs(keys_dir: &Path, covered: &HashSet<PathBuf>) -> Result<usize> {
+    let mut skipped = 0;
+    for kind in [RecipientKind::Age, RecipientKind::Gpg] {
+        let dir = keys_dir.join(kind.key_dir());
+        if !dir.exists() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let is_blob = path.extension().and_then(|e| e.to_str()) == Some(kind.extension());
+            if is_blob && !covered.contains(&path) {
+                skipped += 1;
+                println!(
+                    "{}  {}: recipient unknown (list it in {})",
+                    output::stdout("Skipped  ", Style::Warn),
+                    path.display(),
+                    recipients::RECIPIENTS_FILE
+                );
+            }
+        }
+    }
+    Ok(skipped)
+}
@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::Path;
+
+use git2::Oid;
+
+use crate::crypto::{Cipher, CryptoKey};
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+use crate::parallel;
+
+/// Replace the repository key with a new one of the next generation,
+/// re-stage every encrypted file under it and rewrap recipient blobs.
+///
+/// Files stored in the wrapped-key format keep their content ciphertext:
+/// only the content key in their header is rewrapped under the new key.
+///
+/// With `cipher`, the repository's default cipher (`filter.git-crypt.cipher`)
+/// changes too, so the re-staged files are sealed with it. The previous key
+/// is kept next to the new one so older history stays readable.
+///
+/// Re-staging would sweep unrelated edits into the rotation, so this refuses
+/// to run with uncommitted changes unless `force` is set.
+///
+/// Files are checked and re-encrypted on `threads` workers (defaults to the
+/// available parallelism).
+pub fn rotate_key(cipher: Option<Cipher>, force: bool, threads: Option<usize>) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+    if key_manager.is_passphrase_protected() {
+        return Err(GitCryptError::Other(
+            "Cannot rotate a passphrase-protected key".into(),
+        ));
+    }
+    // Re-staging needs the plaintext checked out and the filters in place
+    if !repo.filters_configured()? {
+        return Err(GitCryptError::Other(
+            "Repository is locked; unlock it before rotating the key".into(),
+        ));
+    }
+    let threads = threads.unwrap_or_else(parallel::default_threads);
+    let previous = key_manager.load_key()?;
+    if !force {
+        let changed = uncommitted_changes(&repo, &previous, threads)?;
+        if let Some(first) = changed.first() {
+            return Err(GitCryptError::Other(format!(
+                "{} file(s) have uncommitted changes (e.g. {first}); commit or stash them, \
+                 or pass --force to rotate anyway",
+                changed.len()
+            )));
+        }
+    }
+
+    let previous_generation = previous.generation().unwrap_or(1);
+    let generation = previous_generation
+        .checked_add(1)
+        .ok_or_else(|| GitCryptError::Other("Key generation overflow".into()))?;
+
+    let retired = key_manager.retire_key(&previous, previous_generation)?;
+    let key = CryptoKey::generate().with_generation(generation);
+    key_manager.save_key(&key)?;
+    key_manager.set_key_generation(generation)?;
+    key_manager.record_key_created()?;
+
+    // The clean filter reads the cipher from config when files are re-staged
+    if let Some(cipher) = cipher {
+        let mut snapshot = repo.config_snapshot()?;
+        snapshot.set("cipher", cipher.as_str());
+        repo.write_config_snapshot(&snapshot)?;
+    }
+    let cipher = repo.clean_options()?.cipher;
+
+    // Wrapped blobs only need their key header rewritten. With --force the
+    // working tree may hold changes that belong in the index, so re-encrypt
+    let entries = repo.encrypted_head_entries()?;
+    let reencrypt = if force {
+        entries.iter().map(|entry| entry.path.clone()).collect()
+    } else {
+        repo.rewrap_with_key(&entries, &previous, &key, threads)?
+    };
+    repo.restage_with_key(&reencrypt, &key, threads)?;
+
+    println!(
+        "{}",
+        output::stdout(
+            &format!("Rotated repository key to generation {generation} ({cipher})"),
+            Style::Ok
+        )
+    );
+    println!(
+        "Previous key (generation {previous_generation}) kept at {}",
+        retired.display()
+    );
+    println!(
+        "Re-staged {} encrypted file(s); commit them to finish the rotation.",
+        entries.len()
+    );
+    let rewrapped = entries.len() - reencrypt.len();
+    if rewrapped > 0 {
+        println!("{rewrapped} of them only had their content key rewrapped.");
+    }
+
+    println!("\nRewrapping recipient blobs for the new key...");
+    super::rewrap()
+}
+
+/// Tracked paths that are staged, or whose working-tree content differs from
+/// the staged blob (decrypted with `key` if encrypted).
+///
+/// `git status` can't tell: the clean filter seals with a random nonce, so a
+/// racily clean file never matches its staged ciphertext.
+pub(super) fn uncommitted_changes(
+    repo: &GitRepo,
+    key: &CryptoKey,
+    threads: usize,
+) -> Result<Vec<String>> {
+    let workdir = repo.workdir()?;
+    let git_dir = repo.git_dir();
+    let entries = repo.index_entries()?;
+
+    // git2 repositories can't be shared across threads, so each worker opens its own
+    let results = parallel::map_bounded(
+        &entries,
+        threads,
+        || GitRepo::open(git_dir),
+        |worker_repo, (path, oid)| -> Result<bool> {
+            let worker_repo = worker_repo
+                .as_ref()
+                .map_err(|e| GitCryptError::Other(e.to_string()))?;
+            is_changed(worker_repo, key, &workdir.join(path), *oid)
+        },
+    );
+
+    let mut changed = repo.staged_paths()?;
+    for ((path, _), result) in entries.into_iter().zip(results) {
+        if result? {
+            changed.push(path);
+        }
+    }
+    changed.sort();
+    changed.dedup();
+    Ok(changed)
+}
+
+/// Whether `file` differs from the staged blob `oid`
+fn is_changed(repo: &GitRepo, key: &CryptoKey, file: &Path, oid: Oid) -> Result<bool> {
+    let working = match fs::symlink_metadata(file) {
+        Ok(meta) if meta.is_file() => fs::read(file)?,
+        // Symlinks and submodules don't go through the filter
+        Ok(_) => return Ok(false),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => return Err(e.into()),
+    };
+
+    let stored = repo.read_blob(oid)?;
+    let expected = if CryptoKey::is_encrypted(&stored) {
+        // Blobs under a retired key can't be compared; leave them be
+        match key.decrypt(&stored) {
+            Ok(plaintext) => plaintext,
+            Err(_) => return Ok(false),
+        }
+    } else {
+        stored
+    };
+    Ok(working != expected)
+}
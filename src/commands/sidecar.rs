@@ -0,0 +1,247 @@
+//! Sidecar files: `file.secret` kept in plaintext (and gitignored) next to a
+//! committed `file.secret.enc`, for workflows that can't use git filters.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::{clean_content, CleanOptions, GitRepo};
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+
+/// Sidecar pairs processed by `sync-sidecars`, relative to the repository root
+pub const SIDECARS_FILE: &str = ".git-crypt/sidecars";
+
+/// Extension appended to a plaintext file to name its encrypted sidecar
+pub const SIDECAR_EXTENSION: &str = "enc";
+
+/// Encrypt `path` into `path.enc`, leaving the sidecar alone if it already
+/// holds the same plaintext
+pub fn encrypt_file(path: &Path) -> Result<()> {
+    let (repo, key) = open()?;
+    let options = repo.clean_options()?;
+    let sidecar = sidecar_path(path);
+
+    if encrypt_pair(&key, &options, path, &sidecar)? {
+        println!("Encrypted {} -> {}", path.display(), sidecar.display());
+    } else {
+        println!("{} is up to date", sidecar.display());
+    }
+    warn_if_not_ignored(&repo, path)?;
+    Ok(())
+}
+
+/// Decrypt `path` (ending in `.enc`) into the plaintext file next to it
+pub fn decrypt_file(path: &Path) -> Result<()> {
+    let (repo, key) = open()?;
+    let plain = plaintext_path(path)?;
+
+    if decrypt_pair(&key, path, &plain)? {
+        println!("Decrypted {} -> {}", path.display(), plain.display());
+    } else {
+        println!("{} is up to date", plain.display());
+    }
+    warn_if_not_ignored(&repo, &plain)?;
+    Ok(())
+}
+
+/// Bring every pair listed in `.git-crypt/sidecars` up to date.
+///
+/// When both files exist and differ, the more recently modified one wins.
+pub fn sync_sidecars() -> Result<()> {
+    let (repo, key) = open()?;
+    let options = repo.clean_options()?;
+    let workdir = repo.workdir()?;
+    let listed = load_sidecars(workdir)?;
+
+    let (mut encrypted, mut decrypted, mut current, mut missing) = (0, 0, 0, 0);
+    for entry in &listed {
+        let plain = workdir.join(entry);
+        let sidecar = sidecar_path(&plain);
+        let encrypt = match (plain.exists(), sidecar.exists()) {
+            (false, false) => {
+                missing += 1;
+                println!(
+                    "{}  {entry}: neither it nor its sidecar exists",
+                    output::stdout("Missing  ", Style::Warn)
+                );
+                continue;
+            }
+            (true, false) => true,
+            (false, true) => false,
+            (true, true) => modified(&plain)? > modified(&sidecar)?,
+        };
+
+        let changed = if encrypt {
+            encrypt_pair(&key, &options, &plain, &sidecar)?
+        } else {
+            decrypt_pair(&key, &sidecar, &plain)?
+        };
+        match (changed, encrypt) {
+            (false, _) => {
+                current += 1;
+                println!("{}  {entry}", output::stdout("Current  ", Style::Ok));
+            }
+            (true, true) => {
+                encrypted += 1;
+                println!("{}  {entry}", output::stdout("Encrypted", Style::Ok));
+            }
+            (true, false) => {
+                decrypted += 1;
+                println!("{}  {entry}", output::stdout("Decrypted", Style::Ok));
+            }
+        }
+        if plain.exists() {
+            warn_if_not_ignored(&repo, &plain)?;
+        }
+    }
+
+    println!(
+        "\n{encrypted} encrypted, {decrypted} decrypted, {current} up to date, {missing} missing"
+    );
+    Ok(())
+}
+
+fn open() -> Result<(GitRepo, CryptoKey)> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let key = key_manager.load_key()?;
+    Ok((repo, key))
+}
+
+/// Write the encryption of `plain` to `sidecar` unless it already decrypts
+/// to the same content. Returns whether `sidecar` was written.
+fn encrypt_pair(
+    key: &CryptoKey,
+    options: &CleanOptions,
+    plain: &Path,
+    sidecar: &Path,
+) -> Result<bool> {
+    let plaintext = fs::read(plain)?;
+    if CryptoKey::is_encrypted(&plaintext) {
+        return Err(GitCryptError::Other(format!(
+            "{} is already encrypted",
+            plain.display()
+        )));
+    }
+
+    // Re-encrypting uses a fresh nonce; keep the committed sidecar stable
+    let unchanged = fs::read(sidecar)
+        .ok()
+        .and_then(|existing| key.decrypt(&existing).ok())
+        .is_some_and(|existing| existing == plaintext);
+    if unchanged {
+        return Ok(false);
+    }
+
+    write_keeping_mode(sidecar, &clean_content(key, options, &plaintext)?, plain)?;
+    Ok(true)
+}
+
+/// Write the decryption of `sidecar` to `plain` unless it already holds it.
+/// Returns whether `plain` was written.
+fn decrypt_pair(key: &CryptoKey, sidecar: &Path, plain: &Path) -> Result<bool> {
+    let ciphertext = fs::read(sidecar)?;
+    if !CryptoKey::is_encrypted(&ciphertext) {
+        return Err(GitCryptError::Other(format!(
+            "{} is not encrypted",
+            sidecar.display()
+        )));
+    }
+
+    let plaintext = key.decrypt(&ciphertext)?;
+    if fs::read(plain).is_ok_and(|existing| existing == plaintext) {
+        return Ok(false);
+    }
+
+    write_keeping_mode(plain, &plaintext, sidecar)?;
+    Ok(true)
+}
+
+/// Write `contents` to `path`, keeping its permissions, or when it doesn't
+/// exist yet, copying those of `counterpart` (the other half of the pair) so
+/// an executable script stays executable across encrypt and decrypt
+fn write_keeping_mode(path: &Path, contents: &[u8], counterpart: &Path) -> Result<()> {
+    let permissions = fs::metadata(path)
+        .or_else(|_| fs::metadata(counterpart))?
+        .permissions();
+    fs::write(path, contents)?;
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+fn sidecar_path(plain: &Path) -> PathBuf {
+    let mut name = plain.as_os_str().to_owned();
+    name.push(format!(".{SIDECAR_EXTENSION}"));
+    PathBuf::from(name)
+}
+
+fn plaintext_path(sidecar: &Path) -> Result<PathBuf> {
+    if sidecar.extension().and_then(|e| e.to_str()) != Some(SIDECAR_EXTENSION) {
+        return Err(GitCryptError::Other(format!(
+            "{} is not a sidecar (expected a .{SIDECAR_EXTENSION} file)",
+            sidecar.display()
+        )));
+    }
+    Ok(sidecar.with_extension(""))
+}
+
+fn modified(path: &Path) -> Result<std::time::SystemTime> {
+    Ok(fs::metadata(path)?.modified()?)
+}
+
+/// Paths listed in the sidecars file: one per line, `#` comments allowed
+fn load_sidecars(workdir: &Path) -> Result<Vec<String>> {
+    let path = workdir.join(SIDECARS_FILE);
+    let text = fs::read_to_string(&path)
+        .map_err(|e| GitCryptError::Other(format!("Cannot read {SIDECARS_FILE}: {e}")))?;
+    Ok(parse_sidecars(&text))
+}
+
+fn parse_sidecars(text: &str) -> Vec<String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The plaintext half of a pair must never be committed
+fn warn_if_not_ignored(repo: &GitRepo, plain: &Path) -> Result<()> {
+    if !repo.is_ignored(plain)? {
+        eprintln!(
+            "{} {} is not ignored by git; add it to .gitignore so the plaintext is never committed",
+            output::stderr("WARNING:", Style::Warn),
+            plain.display()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_paths_round_trip() {
+        let sidecar = sidecar_path(Path::new("config/db.secret"));
+        assert_eq!(sidecar, Path::new("config/db.secret.enc"));
+        assert_eq!(
+            plaintext_path(&sidecar).unwrap(),
+            Path::new("config/db.secret")
+        );
+        assert!(plaintext_path(Path::new("config/db.secret")).is_err());
+    }
+
+    #[test]
+    fn parse_sidecars_skips_comments_and_blanks() {
+        let text = "# pairs\nconfig/db.secret\n\n  .env  \n";
+        assert_eq!(parse_sidecars(text), ["config/db.secret", ".env"]);
+    }
+}
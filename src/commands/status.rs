@@ -0,0 +1,463 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git_crypt::attributes::{self, AttributesFile};
+
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::{self, GitRepo};
+use crate::key::{KeyManager, LockState};
+
+/// Whether a repository's filters are usable, as far as `status` can tell
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    Locked,
+    Unlocked,
+    /// Initialized, but the key, filters and recorded lock state disagree
+    Misconfigured(String),
+}
+
+/// Where the configured clean/smudge filters point
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Filters {
+    /// Not configured (expected while locked)
+    Missing,
+    /// This git-crypt binary
+    Current,
+    /// Some other program, as written in the config
+    Other(String),
+}
+
+/// Status of one git-crypt repository
+struct RepoStatus {
+    path: PathBuf,
+    state: State,
+    filters: Filters,
+}
+
+/// Stored state of one tracked file marked for encryption
+struct FileStatus {
+    path: String,
+    /// Name of the key whose filter the attributes give it (`default` for
+    /// `filter=git-crypt`)
+    key: String,
+    /// The `.gitattributes` pattern that marks it, if it can be told
+    pattern: Option<String>,
+    /// Whether the staged blob is encrypted
+    encrypted: bool,
+    /// Whether the staged blob differs from HEAD
+    staged: bool,
+}
+
+/// Show the lock state and filter setup of the current repository, or with
+/// `scan`, of every git-crypt repository found under that directory
+pub fn status(scan: Option<&Path>, json: bool) -> Result<()> {
+    let statuses = match scan {
+        Some(dir) => scan_repos(dir)?,
+        None => {
+            let repo = GitRepo::open(".")?;
+            let status = repo_status(&repo)?.ok_or(GitCryptError::NotInitialized)?;
+            vec![status]
+        }
+    };
+
+    if json {
+        println!("{}", to_json(&statuses));
+    } else if statuses.is_empty() {
+        println!("No git-crypt repositories found");
+    } else {
+        print!("{}", to_table(&statuses));
+    }
+    Ok(())
+}
+
+/// Show every tracked file marked for encryption and whether its staged blob
+/// is encrypted. `encrypted` keeps only encrypted (`Some(true)`) or plaintext
+/// (`Some(false)`) files; with `exit_code`, fail if any file is in plaintext.
+pub fn status_files(json: bool, encrypted: Option<bool>, exit_code: bool) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    if !KeyManager::new(repo.git_dir()).is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let files = file_statuses(&repo)?;
+    let plaintext = files.iter().filter(|file| !file.encrypted).count();
+    let shown: Vec<&FileStatus> = files
+        .iter()
+        .filter(|file| encrypted.is_none_or(|encrypted| file.encrypted == encrypted))
+        .collect();
+
+    if json {
+        println!("{}", files_to_json(&shown));
+    } else if shown.is_empty() {
+        println!("No matching files");
+    } else {
+        print!("{}", files_to_table(&shown));
+    }
+
+    if exit_code && plaintext > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{plaintext} file(s) marked for encryption are stored in plaintext"
+        )));
+    }
+    Ok(())
+}
+
+/// Status of every tracked file the attributes route through one of
+/// git-crypt's keys, in index order
+fn file_statuses(repo: &GitRepo) -> Result<Vec<FileStatus>> {
+    let workdir = repo.workdir()?;
+    let mut statuses = Vec::new();
+    for (path, key) in repo.classify_index()? {
+        // git-crypt's own metadata is never encrypted
+        let Some(key) = key.filter(|_| !path.starts_with(".git-crypt/")) else {
+            continue;
+        };
+        let Some(oid) = repo.index_blob(&path)? else {
+            continue;
+        };
+
+        let blob = repo.read_blob(oid)?;
+        let staged = repo.head_blob_for_path(&path)?.as_ref() != Some(&blob);
+        statuses.push(FileStatus {
+            pattern: filter_pattern(workdir, repo.git_dir(), &path, &git::filter_driver(&key)),
+            encrypted: CryptoKey::is_encrypted(&blob),
+            staged,
+            path,
+            key,
+        });
+    }
+    Ok(statuses)
+}
+
+/// Pattern marking `path` for encryption with filter `driver`, read from the
+/// working tree's attributes files and `.git/info/attributes`
+fn filter_pattern(workdir: &Path, git_dir: &Path, path: &str, driver: &str) -> Option<String> {
+    let mut sources = vec![(String::new(), workdir.join(".gitattributes"))];
+    let mut dir = String::new();
+    for component in path
+        .split('/')
+        .rev()
+        .skip(1)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        if !dir.is_empty() {
+            dir.push('/');
+        }
+        dir.push_str(component);
+        sources.push((dir.clone(), workdir.join(&dir).join(".gitattributes")));
+    }
+    sources.push((String::new(), git_dir.join("info").join("attributes")));
+
+    let contents: Vec<(String, String)> = sources
+        .into_iter()
+        .filter_map(|(dir, file)| fs::read_to_string(file).ok().map(|text| (dir, text)))
+        .collect();
+    let files: Vec<AttributesFile<'_>> = contents
+        .iter()
+        .map(|(dir, contents)| AttributesFile { dir, contents })
+        .collect();
+    attributes::filter_pattern(&files, path, driver)
+}
+
+/// Status of every initialized git-crypt repository under `dir`, sorted by
+/// path relative to `dir`
+fn scan_repos(dir: &Path) -> Result<Vec<RepoStatus>> {
+    let mut repos = Vec::new();
+    find_repos(dir, &mut repos)?;
+    repos.sort();
+
+    let mut statuses = Vec::new();
+    for path in repos {
+        let repo = GitRepo::open(&path)?;
+        if let Some(mut status) = repo_status(&repo)? {
+            status.path = path.strip_prefix(dir).unwrap_or(&path).to_path_buf();
+            if status.path.as_os_str().is_empty() {
+                status.path = PathBuf::from(".");
+            }
+            statuses.push(status);
+        }
+    }
+    Ok(statuses)
+}
+
+/// Collect working trees under `dir` (any directory with a `.git`), without
+/// descending into them or following symlinks
+fn find_repos(dir: &Path, repos: &mut Vec<PathBuf>) -> Result<()> {
+    if dir.join(".git").exists() {
+        repos.push(dir.to_path_buf());
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            find_repos(&entry.path(), repos)?;
+        }
+    }
+    Ok(())
+}
+
+/// Status of `repo`, or `None` if git-crypt was never initialized there
+fn repo_status(repo: &GitRepo) -> Result<Option<RepoStatus>> {
+    let key_manager = KeyManager::new(repo.git_dir());
+    if !key_manager.is_initialized() {
+        return Ok(None);
+    }
+
+    let configured = repo.filters_configured()?;
+    let state = if !key_manager.has_key() {
+        State::Misconfigured("key missing".into())
+    } else {
+        match (configured, key_manager.lock_state()?) {
+            (true, Some(LockState::Locked)) => {
+                State::Misconfigured("filters configured but recorded as locked".into())
+            }
+            (false, Some(LockState::Unlocked)) => {
+                State::Misconfigured("recorded as unlocked but filters are missing".into())
+            }
+            (true, _) => State::Unlocked,
+            (false, _) => State::Locked,
+        }
+    };
+
+    let filters = if configured {
+        let snapshot = repo.config_snapshot()?;
+        let workdir = repo.workdir()?;
+        let current = std::env::current_exe()
+            .and_then(|exe| exe.canonicalize())
+            .ok();
+        let other = ["clean", "smudge"]
+            .into_iter()
+            .filter_map(|name| snapshot.get(name))
+            .filter_map(filter_program)
+            .find(|program| current.is_none() || resolve_program(program, workdir) != current);
+        match other {
+            Some(program) => Filters::Other(program),
+            None => Filters::Current,
+        }
+    } else {
+        Filters::Missing
+    };
+
+    Ok(Some(RepoStatus {
+        path: repo.workdir()?.to_path_buf(),
+        state,
+        filters,
+    }))
+}
+
+/// Program a filter command runs: its first word, unquoting `'...'`/`"..."`
+fn filter_program(command: &str) -> Option<String> {
+    let command = command.trim_start();
+    match command.chars().next()? {
+        quote @ ('\'' | '"') => {
+            let rest = &command[1..];
+            rest.find(quote).map(|end| rest[..end].to_string())
+        }
+        _ => command.split_whitespace().next().map(str::to_string),
+    }
+}
+
+/// Canonical path of `program` as git would run it from `workdir`: relative
+/// to the working tree when it contains a slash, otherwise found on `PATH`
+fn resolve_program(program: &str, workdir: &Path) -> Option<PathBuf> {
+    if program.contains('/') {
+        return workdir.join(program).canonicalize().ok();
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+        .and_then(|found| found.canonicalize().ok())
+}
+
+impl State {
+    fn as_str(&self) -> &str {
+        match self {
+            State::Locked => "locked",
+            State::Unlocked => "unlocked",
+            State::Misconfigured(_) => "misconfigured",
+        }
+    }
+
+    fn problem(&self) -> Option<&str> {
+        match self {
+            State::Misconfigured(problem) => Some(problem),
+            _ => None,
+        }
+    }
+}
+
+impl Filters {
+    fn as_str(&self) -> &str {
+        match self {
+            Filters::Missing => "none",
+            Filters::Current => "current",
+            Filters::Other(_) => "other",
+        }
+    }
+}
+
+fn to_table(statuses: &[RepoStatus]) -> String {
+    let rows: Vec<[String; 3]> = statuses
+        .iter()
+        .map(|status| {
+            let state = match status.state.problem() {
+                Some(problem) => format!("{} ({problem})", status.state.as_str()),
+                None => status.state.as_str().to_string(),
+            };
+            let filters = match &status.filters {
+                Filters::Other(program) => format!("other ({program})"),
+                filters => filters.as_str().to_string(),
+            };
+            [status.path.display().to_string(), state, filters]
+        })
+        .collect();
+
+    let header = ["REPOSITORY", "STATE", "FILTERS"].map(String::from);
+    let width = |column: usize| {
+        rows.iter()
+            .chain([&header])
+            .map(|row| row[column].len())
+            .max()
+            .unwrap_or(0)
+    };
+    let (path_width, state_width) = (width(0), width(1));
+
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        table.push_str(&format!(
+            "{:path_width$}  {:state_width$}  {}\n",
+            row[0], row[1], row[2]
+        ));
+    }
+    table
+}
+
+fn to_json(statuses: &[RepoStatus]) -> String {
+    let entries: Vec<String> = statuses
+        .iter()
+        .map(|status| {
+            let optional = |value: Option<&str>| value.map_or("null".into(), json_string);
+            let program = match &status.filters {
+                Filters::Other(program) => Some(program.as_str()),
+                _ => None,
+            };
+            format!(
+                "{{\"path\":{},\"state\":{},\"problem\":{},\"filters\":{},\"filter_program\":{}}}",
+                json_string(&status.path.display().to_string()),
+                json_string(status.state.as_str()),
+                optional(status.state.problem()),
+                json_string(status.filters.as_str()),
+                optional(program)
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+fn files_to_table(files: &[&FileStatus]) -> String {
+    let rows: Vec<[String; 5]> = files
+        .iter()
+        .map(|file| {
+            [
+                file.path.clone(),
+                file.key.clone(),
+                file.pattern.clone().unwrap_or_else(|| "-".into()),
+                if file.encrypted {
+                    "encrypted"
+                } else {
+                    "plaintext"
+                }
+                .into(),
+                if file.staged { "yes" } else { "no" }.into(),
+            ]
+        })
+        .collect();
+
+    let header = ["PATH", "KEY", "PATTERN", "STORED", "STAGED"].map(String::from);
+    let width = |column: usize| {
+        rows.iter()
+            .chain([&header])
+            .map(|row| row[column].len())
+            .max()
+            .unwrap_or(0)
+    };
+    let (path_width, key_width, pattern_width, stored_width) =
+        (width(0), width(1), width(2), width(3));
+
+    let mut table = String::new();
+    for row in [&header].into_iter().chain(&rows) {
+        table.push_str(&format!(
+            "{:path_width$}  {:key_width$}  {:pattern_width$}  {:stored_width$}  {}\n",
+            row[0], row[1], row[2], row[3], row[4]
+        ));
+    }
+    table
+}
+
+fn files_to_json(files: &[&FileStatus]) -> String {
+    let entries: Vec<String> = files
+        .iter()
+        .map(|file| {
+            format!(
+                "{{\"path\":{},\"key\":{},\"pattern\":{},\"encrypted\":{},\"staged\":{}}}",
+                json_string(&file.path),
+                json_string(&file.key),
+                file.pattern.as_deref().map_or("null".into(), json_string),
+                file.encrypted,
+                file.staged
+            )
+        })
+        .collect();
+    format!("[{}]", entries.join(","))
+}
+
+pub(super) fn json_string(value: &str) -> String {
+    let mut out = String::with_capacity(value.len() + 2);
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_program_handles_quoting() {
+        assert_eq!(
+            filter_program("git-crypt clean").as_deref(),
+            Some("git-crypt")
+        );
+        assert_eq!(
+            filter_program("'/opt/my tools/git-crypt' smudge").as_deref(),
+            Some("/opt/my tools/git-crypt")
+        );
+        assert_eq!(
+            filter_program("\"/bin/gc\" clean").as_deref(),
+            Some("/bin/gc")
+        );
+        assert_eq!(filter_program("'unterminated clean"), None);
+        assert_eq!(filter_program("   "), None);
+    }
+
+    #[test]
+    fn json_string_escapes_control_characters() {
+        assert_eq!(json_string("a\"b\\c"), r#""a\"b\\c""#);
+        assert_eq!(json_string("line\nnext\u{1}"), r#""line\nnext\u0001""#);
+    }
+}
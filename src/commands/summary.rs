@@ -0,0 +1,95 @@
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+use git_crypt::sync::SyncOutcome;
+
+/// Counts accumulated over a batch of key grants and their sync uploads
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BatchSummary {
+    pub granted: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub uploaded: usize,
+    pub unchanged: usize,
+    pub upload_failed: usize,
+}
+
+impl BatchSummary {
+    /// Count the result of handing one blob to the sync backend, warning on failure
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    pub fn record_sync(&mut self, result: Result<SyncOutcome, impl std::fmt::Display>) {
+        match result {
+            Ok(SyncOutcome::Disabled) => {}
+            Ok(SyncOutcome::Uploaded) => self.uploaded += 1,
+            Ok(SyncOutcome::Unchanged) => self.unchanged += 1,
+            Err(err) => {
+                self.upload_failed += 1;
+                eprintln!("Warning: Failed to sync key blob: {err}");
+            }
+        }
+    }
+
+    /// e.g. `granted 12 recipients, 1 skipped (already present), 0 failed`
+    #[cfg_attr(not(feature = "ssh"), allow(dead_code))]
+    pub fn grant_line(&self) -> String {
+        format!(
+            "granted {} recipient{}, {} skipped (already present), {} failed",
+            self.granted,
+            if self.granted == 1 { "" } else { "s" },
+            self.skipped,
+            self.failed
+        )
+    }
+
+    /// Upload counts, or `None` if nothing was handed to the sync backend
+    pub fn sync_line(&self) -> Option<String> {
+        let total = self.uploaded + self.unchanged + self.upload_failed;
+        (total > 0).then(|| {
+            format!(
+                "synced: {} uploaded, {} unchanged, {} failed",
+                self.uploaded, self.unchanged, self.upload_failed
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grant_line_reports_all_counts() {
+        let summary = BatchSummary {
+            granted: 12,
+            skipped: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.grant_line(),
+            "granted 12 recipients, 1 skipped (already present), 0 failed"
+        );
+
+        let single = BatchSummary {
+            granted: 1,
+            failed: 2,
+            ..Default::default()
+        };
+        assert_eq!(
+            single.grant_line(),
+            "granted 1 recipient, 0 skipped (already present), 2 failed"
+        );
+    }
+
+    #[test]
+    fn sync_line_only_when_something_was_synced() {
+        assert_eq!(BatchSummary::default().sync_line(), None);
+
+        let summary = BatchSummary {
+            uploaded: 2,
+            unchanged: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary.sync_line().as_deref(),
+            Some("synced: 2 uploaded, 1 unchanged, 0 failed")
+        );
+    }
+}
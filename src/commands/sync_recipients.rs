@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::summary::BatchSummary;
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::gpg::GpgManager;
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+#[cfg(feature = "ssh")]
+use crate::rage::RageManager;
+use crate::recipients::{
+    self, BlobManifest, BlobRecord, Recipient, RecipientKind, RECIPIENTS_FILE,
+};
+
+#[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+use git_crypt::sync;
+
+/// Regenerate encrypted key blobs for exactly the recipients in `.git-crypt/recipients`
+///
+/// `force_upload` re-uploads blobs to the sync backend even if the remote copy is identical.
+#[cfg_attr(
+    not(any(feature = "sync-s3", feature = "sync-gcs")),
+    allow(unused_variables, unused_mut)
+)]
+pub fn sync_recipients(force_upload: bool) -> Result<()> {
+    println!("Syncing recipients from {RECIPIENTS_FILE}...");
+
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    let recipients = recipients::load_recipients(repo.workdir()?)?;
+    let key = key_manager.load_key()?;
+    let keys_dir = key_manager.git_crypt_dir().join("keys");
+
+    // Encrypt for everyone up front so a bad entry leaves existing blobs untouched
+    let blobs = recipients
+        .iter()
+        .map(|r| encrypt_for(&key, r).map(|blob| (r, blob)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut manifest = BlobManifest::load(&keys_dir)?;
+    let mut wanted = HashSet::new();
+    let mut uploads = BatchSummary::default();
+    for (recipient, blob) in &blobs {
+        let path = blob_path(&keys_dir, recipient.kind, &recipient.name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, blob)?;
+        manifest.record(BlobRecord::new(&key, (*recipient).clone()));
+        println!(
+            "{} {} ({})",
+            output::stdout("Granted access:", Style::Ok),
+            recipient.name,
+            path.display()
+        );
+
+        #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+        uploads.record_sync(match recipient.kind {
+            RecipientKind::Ssh | RecipientKind::Age => {
+                sync::maybe_sync_age_key(repo.git_dir(), &path, &recipient.name, force_upload)
+            }
+            RecipientKind::Gpg => {
+                sync::maybe_sync_gpg_key(repo.git_dir(), &path, &recipient.name, force_upload)
+            }
+        });
+
+        wanted.insert(path);
+    }
+
+    let mut removed = 0;
+    for kind in [RecipientKind::Age, RecipientKind::Gpg] {
+        let dir = keys_dir.join(kind.key_dir());
+        if !dir.exists() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let is_blob = path.extension().and_then(|e| e.to_str()) == Some(kind.extension());
+            if is_blob && !wanted.contains(&path) {
+                fs::remove_file(&path)?;
+                if let Some(name) = path.file_stem().and_then(|s| s.to_str()) {
+                    manifest.remove(kind, name);
+                }
+                println!(
+                    "{} {}",
+                    output::stdout("Revoked access:", Style::Warn),
+                    path.display()
+                );
+                removed += 1;
+            }
+        }
+    }
+
+    manifest.save(&keys_dir)?;
+
+    println!(
+        "\nRecipients synced: {} granted, {} revoked",
+        blobs.len(),
+        removed
+    );
+    if let Some(line) = uploads.sync_line() {
+        println!("{line}");
+    }
+    if removed > 0 {
+        println!("Revoked users may still hold the current key; consider rotating it.");
+    }
+
+    Ok(())
+}
+
+pub(super) fn blob_path(keys_dir: &Path, kind: RecipientKind, name: &str) -> PathBuf {
+    keys_dir
+        .join(kind.key_dir())
+        .join(format!("{name}.{}", kind.extension()))
+}
+
+pub(super) fn encrypt_for(key: &CryptoKey, recipient: &Recipient) -> Result<Vec<u8>> {
+    match recipient.kind {
+        #[cfg(feature = "ssh")]
+        RecipientKind::Ssh => RageManager::encrypt_key_for_ssh_recipient(key, &recipient.recipient),
+        #[cfg(feature = "ssh")]
+        RecipientKind::Age => RageManager::encrypt_key_for_age_recipient(key, &recipient.recipient),
+        #[cfg(not(feature = "ssh"))]
+        RecipientKind::Ssh | RecipientKind::Age => Err(GitCryptError::Other(format!(
+            "Recipient '{}' needs SSH/age support. Rebuild with --features ssh",
+            recipient.name
+        ))),
+        RecipientKind::Gpg => {
+            GpgManager::encrypt_key_for_recipient(key, &recipient.recipient, false)
+        }
+    }
+}
@@ -0,0 +1,245 @@
+//! `encrypt-path` / `decrypt-path`: switch single files in or out of
+//! encryption by editing the root `.gitattributes` and re-staging them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+
+/// Attributes file edited by both commands, relative to the repository root
+const ROOT_ATTRIBUTES: &str = ".gitattributes";
+
+/// Start encrypting `paths`: mark them `filter=git-crypt` and stage them
+/// through the clean filter, so the next commit stores them encrypted
+pub fn encrypt_path(paths: &[PathBuf]) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    if !KeyManager::new(repo.git_dir()).is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+    // Staging through the clean filter needs the filters in place
+    if !repo.filters_configured()? {
+        return Err(GitCryptError::Other(
+            "Repository is locked; unlock it before encrypting paths".into(),
+        ));
+    }
+    let workdir = repo.workdir()?.to_path_buf();
+
+    let (mut tracked, mut untracked) = (Vec::new(), Vec::new());
+    for path in paths {
+        let repo_path = repo.repo_relative(path)?;
+        if !workdir.join(&repo_path).is_file() {
+            return Err(GitCryptError::Other(format!("{repo_path} is not a file")));
+        }
+        if repo.is_filtered(&repo_path)? {
+            println!("{repo_path} is already encrypted");
+            continue;
+        }
+
+        set_encrypted(&workdir, &repo_path, true)?;
+        println!("{repo_path} will be committed encrypted");
+        if repo.index_blob(&repo_path)?.is_some() {
+            tracked.push(repo_path);
+        } else {
+            untracked.push(repo_path);
+        }
+    }
+    if tracked.is_empty() && untracked.is_empty() {
+        return Ok(());
+    }
+
+    repo.restage(&tracked)?;
+    repo.stage(&untracked)?;
+    if !tracked.is_empty() {
+        println!(
+            "{} earlier commits still hold the plaintext of {} file(s)",
+            output::stdout("Note:", Style::Warn),
+            tracked.len()
+        );
+    }
+    Ok(())
+}
+
+/// Stop encrypting `paths`: exclude them from `filter=git-crypt`, decrypt
+/// them in the working tree if needed, and stage the plaintext
+pub fn decrypt_path(paths: &[PathBuf]) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+    let key = key_manager.load_key()?;
+    let workdir = repo.workdir()?.to_path_buf();
+
+    let mut changed = Vec::new();
+    for path in paths {
+        let repo_path = repo.repo_relative(path)?;
+        if repo.index_blob(&repo_path)?.is_none() {
+            return Err(GitCryptError::Other(format!("{repo_path} is not tracked")));
+        }
+        if !repo.is_filtered(&repo_path)? {
+            println!("{repo_path} is already stored in plaintext");
+            continue;
+        }
+
+        // A locked checkout holds the ciphertext
+        let file = workdir.join(&repo_path);
+        let content = fs::read(&file)?;
+        if CryptoKey::is_encrypted(&content) {
+            fs::write(&file, key.decrypt(&content)?)?;
+        }
+
+        set_encrypted(&workdir, &repo_path, false)?;
+        println!("{repo_path} will be committed in plaintext");
+        changed.push(repo_path);
+    }
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    repo.restage(&changed)
+}
+
+/// Make the root `.gitattributes` route `path` through git-crypt or not.
+///
+/// Rules for exactly this path are dropped first; an explicit rule is only
+/// appended if a broader pattern still decides otherwise. Fails if a nested
+/// `.gitattributes` overrides the root one.
+fn set_encrypted(workdir: &Path, path: &str, encrypted: bool) -> Result<()> {
+    let attributes = workdir.join(ROOT_ATTRIBUTES);
+    let existing = match fs::read_to_string(&attributes) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // Reopen for every check: attributes read earlier may be cached
+    let is_filtered = || GitRepo::open(workdir)?.is_filtered(path);
+
+    let mut updated = without_path_rules(&existing, path);
+    write_if_changed(workdir, &existing, &updated)?;
+    if is_filtered()? != encrypted {
+        let rule = if encrypted {
+            "filter=git-crypt diff=git-crypt -text"
+        } else {
+            "!filter !diff"
+        };
+        let written = updated.clone();
+        if !updated.is_empty() && !updated.ends_with('\n') {
+            updated.push('\n');
+        }
+        updated.push_str(&format!("{} {rule}\n", attribute_pattern(path)));
+        write_if_changed(workdir, &written, &updated)?;
+    }
+
+    if is_filtered()? != encrypted {
+        return Err(GitCryptError::Other(format!(
+            "{path}: a .gitattributes file below the root overrides {ROOT_ATTRIBUTES}; edit it by hand"
+        )));
+    }
+    Ok(())
+}
+
+/// Write and stage the attributes file; the staged copy counts for attribute
+/// lookups too, so it must not lag behind
+fn write_if_changed(workdir: &Path, previous: &str, updated: &str) -> Result<()> {
+    if previous != updated {
+        fs::write(workdir.join(ROOT_ATTRIBUTES), updated)?;
+        GitRepo::open(workdir)?.stage(&[ROOT_ATTRIBUTES.to_string()])?;
+    }
+    Ok(())
+}
+
+/// `.gitattributes` pattern matching exactly `path` from the root, quoted
+/// when it contains whitespace, quotes or backslashes
+fn attribute_pattern(path: &str) -> String {
+    let pattern = format!("/{path}");
+    if pattern.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", pattern.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        pattern
+    }
+}
+
+/// `attributes` without the `filter` and `diff` settings (and the `-text`
+/// that `encrypt-path` adds) of rules whose pattern names exactly `path`;
+/// rules left with no attributes are dropped
+fn without_path_rules(attributes: &str, path: &str) -> String {
+    let ours = attribute_pattern(path);
+    let mut kept = Vec::new();
+    for line in attributes.lines() {
+        let mut fields = line.split_whitespace();
+        let pattern = fields.next();
+        let exact = pattern == Some(ours.as_str()) || (path.contains('/') && pattern == Some(path));
+        if !exact {
+            kept.push(line.to_string());
+            continue;
+        }
+
+        let others: Vec<&str> = fields
+            .filter(|attr| {
+                if *attr == "-text" {
+                    return false;
+                }
+                let name = attr.trim_start_matches(['!', '-']);
+                let name = name.split('=').next().unwrap_or(name);
+                name != "filter" && name != "diff"
+            })
+            .collect();
+        if !others.is_empty() {
+            kept.push(format!(
+                "{} {}",
+                pattern.unwrap_or_default(),
+                others.join(" ")
+            ));
+        }
+    }
+
+    let mut result = kept.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_path_rules_drops_only_exact_git_crypt_settings() {
+        let attributes = "*.secret filter=git-crypt diff=git-crypt\n\
+                          /a.secret !filter !diff\n\
+                          /b.txt filter=git-crypt diff=git-crypt eol=lf\n\
+                          config/db.yml filter=git-crypt diff=git-crypt -text\n";
+        assert_eq!(
+            without_path_rules(attributes, "a.secret"),
+            "*.secret filter=git-crypt diff=git-crypt\n\
+             /b.txt filter=git-crypt diff=git-crypt eol=lf\n\
+             config/db.yml filter=git-crypt diff=git-crypt -text\n"
+        );
+        assert_eq!(
+            without_path_rules(attributes, "b.txt"),
+            "*.secret filter=git-crypt diff=git-crypt\n\
+             /a.secret !filter !diff\n\
+             /b.txt eol=lf\n\
+             config/db.yml filter=git-crypt diff=git-crypt -text\n"
+        );
+        assert_eq!(
+            without_path_rules(attributes, "config/db.yml"),
+            "*.secret filter=git-crypt diff=git-crypt\n\
+             /a.secret !filter !diff\n\
+             /b.txt filter=git-crypt diff=git-crypt eol=lf\n"
+        );
+        assert_eq!(without_path_rules("", "a"), "");
+    }
+
+    #[test]
+    fn attribute_pattern_quotes_when_needed() {
+        assert_eq!(attribute_pattern("dir/a.secret"), "/dir/a.secret");
+        assert_eq!(attribute_pattern("my file"), "\"/my file\"");
+        assert_eq!(attribute_pattern("a\"b"), "\"/a\\\"b\"");
+    }
+}
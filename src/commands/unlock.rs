@@ -1,14 +1,70 @@
+use crate::crypto::CryptoKey;
 use crate::error::{GitCryptError, Result};
-use crate::git::GitRepo;
-use crate::key::KeyManager;
-use std::path::Path;
+use crate::git::{shell_quote, ConfigSnapshot, GitRepo};
+use crate::gpg::GpgManager;
+use crate::key::{self, KeyManager, LockState};
+use crate::notify;
+use crate::output::{self, Style};
+#[cfg(feature = "ssh")]
+use crate::rage::RageManager;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Command that decrypts the working tree once the filters are configured
+const CHECKOUT_COMMAND: &str = "git checkout HEAD -- .";
+
+/// Where `unlock` gets the repository key from
+pub enum Method {
+    /// The installed key; without one, the GPG key blobs if there are any
+    Auto,
+    /// A key file, imported as the repository key
+    KeyFile(PathBuf),
+    /// The first GPG key blob in `keys/gpg` a local secret key decrypts
+    Gpg,
+    /// The first age key blob in `keys/age` this SSH identity decrypts
+    #[cfg(feature = "ssh")]
+    Age(PathBuf),
+}
+
+/// A key to install before unlocking
+enum Import {
+    File(PathBuf),
+    /// Read from stdin (`--key-file -`), which can only be read once
+    Stdin(CryptoKey),
+    /// Decrypted from a key blob in the repository
+    Blob {
+        key: CryptoKey,
+        path: PathBuf,
+        /// Who decrypted it, for the user
+        identity: String,
+        /// Import log source, e.g. `gpg:<path>`
+        source: String,
+    },
+}
+
+/// Everything `unlock` changes, worked out before anything is changed
+struct UnlockPlan {
+    /// Key to install as the repository key
+    import: Option<Import>,
+    /// `filter.git-crypt.*` settings to write
+    filters: ConfigSnapshot,
+    /// Settings in `filters` that differ from the current config
+    changes: Vec<(String, String)>,
+}
 
 /// Unlock the repository (make encrypted files readable)
-pub fn unlock(key_file: Option<&Path>) -> Result<()> {
-    println!("Unlocking repository...");
+///
+/// With `print_commands`, print what would change and exit without changing it.
+pub fn unlock(method: Method, force: bool, print_commands: bool) -> Result<()> {
+    if !print_commands {
+        println!("Unlocking repository...");
+    }
 
     // Open repository
     let repo = GitRepo::open(".")?;
+    if repo.is_bare() {
+        return Err(GitCryptError::BareRepository);
+    }
     let git_dir = repo.git_dir();
 
     let key_manager = KeyManager::new(git_dir);
@@ -18,20 +74,214 @@ pub fn unlock(key_file: Option<&Path>) -> Result<()> {
         return Err(GitCryptError::NotInitialized);
     }
 
-    // If key file provided, import it
-    if let Some(key_path) = key_file {
-        println!("Importing key from: {}", key_path.display());
-        key_manager.import_key(key_path)?;
+    // Nothing to do unless a new key is being imported
+    if !force
+        && matches!(method, Method::Auto)
+        && key_manager.has_key()
+        && key_manager.lock_state()? == Some(LockState::Unlocked)
+        && repo.filters_configured()?
+    {
+        println!("Repository is already unlocked (use --force to unlock again)");
+        return Ok(());
     }
 
-    // Try to load the key to verify it exists
-    let _key = key_manager.load_key()?;
+    let plan = plan(&repo, &key_manager, method)?;
+    if print_commands {
+        print_plan(&plan, &key_manager);
+        return Ok(());
+    }
+
+    match &plan.import {
+        Some(Import::File(key_path)) => {
+            println!("Importing key from: {}", key_path.display());
+            key_manager.import_key(key_path)?;
+        }
+        Some(Import::Stdin(key)) => {
+            println!("Importing key from stdin");
+            key_manager.save_key(key)?;
+            key_manager.record_import("stdin", key)?;
+        }
+        Some(Import::Blob {
+            key,
+            path,
+            identity,
+            source,
+        }) => {
+            println!("Decrypted {} with {identity}", path.display());
+            key_manager.save_key(key)?;
+            key_manager.record_import(source, key)?;
+        }
+        None => {}
+    }
 
     // Configure filters
-    repo.configure_filters()?;
+    repo.write_config_snapshot(&plan.filters)?;
+    key_manager.set_lock_state(LockState::Unlocked)?;
+    notify::lock_state_changed(&repo, LockState::Unlocked);
 
-    println!("Repository unlocked successfully!");
-    println!("\nRun 'git checkout HEAD -- .' to decrypt all tracked files");
+    println!(
+        "{}",
+        output::stdout("Repository unlocked successfully!", Style::Ok)
+    );
+    println!("\nRun '{CHECKOUT_COMMAND}' to decrypt all tracked files");
 
     Ok(())
 }
+
+/// Check that a usable key will be in place and work out the config changes
+fn plan(repo: &GitRepo, key_manager: &KeyManager, method: Method) -> Result<UnlockPlan> {
+    let import = match method {
+        Method::KeyFile(key_path) if key_path == Path::new(key::STDIN_KEY_PATH) => {
+            Some(Import::Stdin(key::read_key_input(&key_path)?))
+        }
+        Method::KeyFile(key_path) => {
+            key::read_key_file(&key_path)?;
+            Some(Import::File(key_path))
+        }
+        Method::Gpg => Some(gpg_import(key_manager)?),
+        #[cfg(feature = "ssh")]
+        Method::Age(identity) => Some(age_import(key_manager, &identity)?),
+        // A fresh clone of a repository shared over GPG has blobs but no key
+        Method::Auto
+            if !key_manager.has_key() && !key_blobs(&gpg_dir(key_manager), "key")?.is_empty() =>
+        {
+            Some(gpg_import(key_manager)?)
+        }
+        Method::Auto => {
+            key_manager.load_key()?;
+            None
+        }
+    };
+
+    let current = repo.config_snapshot()?;
+    let filters = repo.configured_snapshot()?;
+    let changes = filters
+        .iter()
+        .filter(|(name, value)| current.get(name) != Some(value))
+        .map(|(name, value)| (repo.filter_setting(name), value.to_string()))
+        .collect();
+
+    Ok(UnlockPlan {
+        import,
+        filters,
+        changes,
+    })
+}
+
+fn gpg_dir(key_manager: &KeyManager) -> PathBuf {
+    key_manager.keys_dir().join("gpg")
+}
+
+/// Files in `dir` with `extension`, sorted; none if `dir` doesn't exist
+fn key_blobs(dir: &Path, extension: &str) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut blobs = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == extension) {
+            blobs.push(path);
+        }
+    }
+    blobs.sort();
+    Ok(blobs)
+}
+
+/// Try every blob in turn with `decrypt`, returning the first key it yields
+/// and the blob it came from
+fn first_decrypting<T>(
+    blobs: &[PathBuf],
+    dir: &Path,
+    mut decrypt: impl FnMut(&[u8]) -> Result<T>,
+) -> Result<(T, PathBuf)> {
+    let mut last_error = None;
+    for path in blobs {
+        match decrypt(&fs::read(path)?) {
+            Ok(decrypted) => return Ok((decrypted, path.clone())),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(match last_error {
+        Some(e) => GitCryptError::Other(format!(
+            "None of the {} key blob(s) in {} could be decrypted (last error: {e})",
+            blobs.len(),
+            dir.display()
+        )),
+        None => GitCryptError::Other(format!("No key blobs in {}", dir.display())),
+    })
+}
+
+fn gpg_import(key_manager: &KeyManager) -> Result<Import> {
+    let dir = gpg_dir(key_manager);
+    let blobs = key_blobs(&dir, "key")?;
+    let (decrypted, path) = first_decrypting(&blobs, &dir, GpgManager::decrypt_key)?;
+
+    let identity = match decrypted.fingerprint {
+        Some(fingerprint) => {
+            let uid = GpgManager::list_keys()
+                .ok()
+                .and_then(|keys| keys.into_iter().find(|k| k.fingerprint == fingerprint))
+                .map(|k| k.uid);
+            match uid {
+                Some(uid) => format!("GPG key {uid} ({fingerprint})"),
+                None => format!("GPG key {fingerprint}"),
+            }
+        }
+        None => "a GPG secret key".to_string(),
+    };
+    Ok(Import::Blob {
+        key: decrypted.key,
+        source: format!("gpg:{}", path.display()),
+        path,
+        identity,
+    })
+}
+
+#[cfg(feature = "ssh")]
+fn age_import(key_manager: &KeyManager, identity_path: &Path) -> Result<Import> {
+    let dir = key_manager.keys_dir().join("age");
+    let blobs = key_blobs(&dir, "age")?;
+    let identity = fs::read_to_string(identity_path)?;
+    let label = identity_path
+        .file_name()
+        .and_then(|s| s.to_str())
+        .unwrap_or("ssh identity");
+    let (key, path) = first_decrypting(&blobs, &dir, |blob| {
+        RageManager::decrypt_key_with_ssh_identity(blob, &identity, label)
+    })?;
+
+    Ok(Import::Blob {
+        key,
+        source: format!("age:{}", path.display()),
+        path,
+        identity: format!("SSH identity {}", identity_path.display()),
+    })
+}
+
+fn print_plan(plan: &UnlockPlan, key_manager: &KeyManager) {
+    println!("# unlock would run the equivalent of (nothing has been changed):");
+    match &plan.import {
+        Some(Import::File(key_path)) => println!(
+            "git-crypt import-key {}",
+            shell_quote(&key_path.display().to_string())
+        ),
+        Some(Import::Stdin(_)) => println!("git-crypt import-key -  # key read from stdin"),
+        Some(Import::Blob { path, identity, .. }) => println!(
+            "# install the key decrypted from {} with {identity}",
+            path.display()
+        ),
+        None => {}
+    }
+    for (name, value) in &plan.changes {
+        println!("git config {name} {}", shell_quote(value));
+    }
+    println!(
+        "# record 'unlocked' in {}",
+        key_manager.state_path().display()
+    );
+    println!("# then decrypt the working tree with:");
+    println!("{CHECKOUT_COMMAND}");
+}
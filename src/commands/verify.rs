@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+
+use crate::crypto::{Cipher, CryptoKey};
+use crate::error::{GitCryptError, Result};
+use crate::git::{EncryptedEntry, GitRepo};
+use crate::key::KeyManager;
+use crate::output::{self, Style};
+use crate::parallel;
+
+/// What [`check_entry`] found out about one committed file
+struct Checked {
+    /// Key generation tagged in the header
+    generation: Option<u32>,
+    /// Format version read from the header, for encrypted blobs
+    format: Option<u8>,
+    /// Cipher read from the header, for encrypted blobs
+    cipher: Option<Cipher>,
+    result: std::result::Result<(), String>,
+}
+
+/// Check that every file the attributes mark for encryption is committed
+/// encrypted and decrypts with the repository key
+///
+/// With `format_check`, also report each file's format version and cipher;
+/// files below `min_format` are flagged and fail the check. With
+/// `max_key_age` (in days), an older repository key fails the check too.
+pub fn verify(
+    threads: Option<usize>,
+    format_check: bool,
+    min_format: Option<u8>,
+    max_key_age: Option<u64>,
+) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+
+    if let Some(max_days) = max_key_age {
+        check_key_age(&key_manager, max_days)?;
+    }
+
+    let key = key_manager.load_key()?;
+    let entries = repo.encrypted_head_entries()?;
+    if entries.is_empty() {
+        println!("No encrypted files in HEAD to verify");
+        return Ok(());
+    }
+
+    let threads = threads.unwrap_or_else(parallel::default_threads);
+    let git_dir = repo.git_dir();
+
+    // git2 repositories can't be shared across threads, so each worker opens its own
+    let results = parallel::map_bounded(
+        &entries,
+        threads,
+        || GitRepo::open(git_dir),
+        |worker_repo, entry| match worker_repo {
+            Ok(worker_repo) => check_entry(worker_repo, &key, entry),
+            Err(e) => Checked {
+                generation: None,
+                format: None,
+                cipher: None,
+                result: Err(e.to_string()),
+            },
+        },
+    );
+
+    let (mut failed, mut outdated) = (0, 0);
+    let mut generations = BTreeMap::new();
+    let mut formats = BTreeMap::new();
+    for (entry, checked) in entries.iter().zip(&results) {
+        *generations.entry(checked.generation).or_insert(0) += 1;
+        let format = match checked.format.zip(checked.cipher) {
+            Some((version, cipher)) if format_check => {
+                *formats.entry(version).or_insert(0) += 1;
+                format!(" (format v{version}, {cipher})")
+            }
+            _ => String::new(),
+        };
+        let below_minimum = checked
+            .format
+            .zip(min_format)
+            .filter(|(version, minimum)| version < minimum);
+
+        match (&checked.result, below_minimum) {
+            (Err(reason), _) => {
+                failed += 1;
+                println!(
+                    "{}  {}: {reason}{format}",
+                    output::stdout("FAIL", Style::Fail),
+                    entry.path
+                );
+            }
+            (Ok(()), Some((_, minimum))) => {
+                outdated += 1;
+                println!(
+                    "{}  {}{format}: below minimum format v{minimum}",
+                    output::stdout("OLD ", Style::Warn),
+                    entry.path
+                );
+            }
+            (Ok(()), None) => println!(
+                "{}  {}{format}",
+                output::stdout("OK  ", Style::Ok),
+                entry.path
+            ),
+        }
+    }
+
+    println!("\n{} files verified, {failed} failed", entries.len());
+    if format_check {
+        let counts: Vec<String> = formats
+            .iter()
+            .map(|(version, count)| format!("{count} at v{version}"))
+            .collect();
+        println!("Formats: {}", counts.join(", "));
+        if let Some(minimum) = min_format {
+            println!("{outdated} file(s) below minimum format v{minimum}");
+        }
+    }
+    if let Some(coverage) = generation_coverage(&generations, key.generation()) {
+        println!("{coverage}");
+    }
+
+    if failed > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{failed} encrypted file(s) failed verification"
+        )));
+    }
+    if outdated > 0 {
+        return Err(GitCryptError::Other(format!(
+            "{outdated} encrypted file(s) below minimum format v{}",
+            min_format.unwrap_or_default()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Fail if the repository key is older than `max_days`.
+///
+/// An unknown age (no creation record, or one written by a wrong clock) is
+/// reported but doesn't fail the check.
+fn check_key_age(key_manager: &KeyManager, max_days: u64) -> Result<()> {
+    let key_age = key_manager.key_age()?;
+    if let Some(warning) = &key_age.warning {
+        eprintln!("{} {warning}", output::stderr("Warning:", Style::Warn));
+    }
+
+    match key_age.days() {
+        Some(days) if days > max_days => Err(GitCryptError::Other(format!(
+            "Repository key is {days} day(s) old (maximum {max_days}); run `git-crypt rotate-key`"
+        ))),
+        Some(days) => {
+            println!("Key age: {days} day(s) (maximum {max_days})");
+            Ok(())
+        }
+        None => {
+            println!("Key age: unknown (no creation time recorded)");
+            Ok(())
+        }
+    }
+}
+
+/// Check one entry, also reading the format and key generation of its blob
+fn check_entry(repo: &GitRepo, key: &CryptoKey, entry: &EncryptedEntry) -> Checked {
+    let data = match repo.read_blob(entry.oid) {
+        Ok(data) => data,
+        Err(e) => {
+            return Checked {
+                generation: None,
+                format: None,
+                cipher: None,
+                result: Err(e.to_string()),
+            }
+        }
+    };
+
+    let result = if CryptoKey::is_encrypted(&data) {
+        key.decrypt(&data)
+            .map(|_| ())
+            .map_err(|e| format!("decryption failed ({e})"))
+    } else {
+        Err("committed in plaintext".into())
+    };
+    Checked {
+        generation: CryptoKey::generation_of(&data),
+        format: CryptoKey::format_of(&data),
+        cipher: CryptoKey::cipher_of(&data),
+        result,
+    }
+}
+
+/// Summarize how many files are at each key generation, or `None` while no
+/// file carries one (the key was never rotated)
+fn generation_coverage(
+    generations: &BTreeMap<Option<u32>, usize>,
+    current: Option<u32>,
+) -> Option<String> {
+    if generations.keys().all(Option::is_none) {
+        return None;
+    }
+
+    let counts: Vec<String> = generations
+        .iter()
+        .map(|(generation, count)| match generation {
+            Some(generation) => format!("{count} at generation {generation}"),
+            None => format!("{count} untagged"),
+        })
+        .collect();
+    let mut coverage = format!("Key generations: {}", counts.join(", "));
+
+    if let Some(current) = current {
+        let behind: usize = generations
+            .iter()
+            .filter(|(generation, _)| **generation != Some(current))
+            .map(|(_, count)| count)
+            .sum();
+        if behind > 0 {
+            coverage.push_str(&format!(
+                "\n{behind} file(s) not yet re-encrypted at key generation {current}"
+            ));
+        }
+    }
+    Some(coverage)
+}
@@ -0,0 +1,168 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::lock::lock;
+use crate::error::{GitCryptError, Result};
+use crate::git::GitRepo;
+use crate::key::{KeyManager, LockState};
+use crate::output::{self, Style};
+
+// Longest sleep between activity checks
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Stay in the foreground and lock the repository once neither the index nor
+/// any encrypted file in the working tree has changed for `idle`.
+///
+/// Locking replaces decrypted files with their ciphertext from HEAD, unless
+/// some have uncommitted changes; those are left as they are. Exits after
+/// locking, or without locking on SIGINT.
+pub fn watch(idle: Duration) -> Result<()> {
+    let repo = GitRepo::open(".")?;
+    let key_manager = KeyManager::new(repo.git_dir());
+
+    if !key_manager.is_initialized() {
+        return Err(GitCryptError::NotInitialized);
+    }
+    if key_manager.lock_state()? == Some(LockState::Locked) && !repo.filters_configured()? {
+        println!("Repository is already locked; nothing to watch");
+        return Ok(());
+    }
+
+    // Activity is any write to the index or to a file that git-crypt encrypts
+    let workdir = repo.workdir()?;
+    let mut watched: Vec<PathBuf> = vec![repo.git_dir().join("index")];
+    watched.extend(
+        repo.encrypted_head_entries()?
+            .into_iter()
+            .map(|entry| workdir.join(entry.path)),
+    );
+
+    install_interrupt_handler();
+    println!(
+        "Watching {} path(s); locking after {} of inactivity (Ctrl-C to stop)",
+        watched.len(),
+        format_duration(idle)
+    );
+
+    let mut last_change = latest_mtime(&watched);
+    let mut idle_since = Instant::now();
+    loop {
+        if INTERRUPTED.load(Ordering::SeqCst) {
+            println!("Stopped watching; repository left unlocked");
+            return Ok(());
+        }
+
+        let elapsed = idle_since.elapsed();
+        if elapsed >= idle {
+            break;
+        }
+        thread::sleep(POLL_INTERVAL.min(idle - elapsed));
+
+        let change = latest_mtime(&watched);
+        if change != last_change {
+            last_change = change;
+            idle_since = Instant::now();
+        }
+    }
+
+    println!("No activity for {}", format_duration(idle));
+    // An unattended lock still goes ahead when resetting would lose edits
+    lock(false, true).or_else(|err| {
+        eprintln!(
+            "{} {err}; locking without resetting the working tree",
+            output::stderr("WARNING:", Style::Warn)
+        );
+        lock(false, false)
+    })
+}
+
+/// Newest modification time among `paths` that exist
+fn latest_mtime(paths: &[PathBuf]) -> Option<SystemTime> {
+    paths
+        .iter()
+        .filter_map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+/// Parse a duration such as `90`, `45s`, `30m` or `2h` (bare numbers are seconds)
+pub fn parse_duration(text: &str) -> std::result::Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let value: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration '{text}'"))?;
+    let seconds = match unit {
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 60 * 60,
+        _ => return Err(format!("unknown unit '{unit}' (use s, m or h)")),
+    };
+    if seconds == 0 {
+        return Err("duration must be greater than zero".into());
+    }
+    Ok(Duration::from_secs(seconds))
+}
+
+fn format_duration(duration: Duration) -> String {
+    match duration.as_secs() {
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+#[cfg(unix)]
+fn install_interrupt_handler() {
+    extern "C" fn on_interrupt(_: libc::c_int) {
+        INTERRUPTED.store(true, Ordering::SeqCst);
+    }
+
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+}
+
+// Elsewhere Ctrl-C ends the process; nothing is left half-written while idle
+#[cfg(not(unix))]
+fn install_interrupt_handler() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_accepts_units() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("45s"), Ok(Duration::from_secs(45)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+    }
+
+    #[test]
+    fn parse_duration_rejects_bad_input() {
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("0m").is_err());
+        assert!(parse_duration("5d").is_err());
+        assert!(parse_duration("m").is_err());
+    }
+
+    #[test]
+    fn format_duration_uses_largest_whole_unit() {
+        assert_eq!(format_duration(Duration::from_secs(1800)), "30m");
+        assert_eq!(format_duration(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_duration(Duration::from_secs(90)), "90s");
+    }
+}
@@ -1,13 +1,15 @@
 //! # Cryptographic Operations
 //!
-//! This module provides core encryption and decryption functionality using AES-256-GCM.
+//! This module provides core encryption and decryption functionality using AES-256-GCM,
+//! or optionally ChaCha20-Poly1305.
 //!
 //! ## Algorithm
 //!
-//! - **Cipher**: AES-256-GCM (Galois/Counter Mode)
+//! - **Cipher**: AES-256-GCM (Galois/Counter Mode) by default; ChaCha20-Poly1305
+//!   for hardware without AES instructions
 //! - **Key size**: 256 bits (32 bytes)
 //! - **Nonce size**: 96 bits (12 bytes)
-//! - **Authentication**: Built into GCM mode (16-byte tag)
+//! - **Authentication**: Built into both AEADs (16-byte tag)
 //!
 //! ## Encrypted Data Format
 //!
@@ -15,9 +17,101 @@
 //! [GITCRYPT][12-byte nonce][variable-length ciphertext + 16-byte GCM tag]
 //! ```
 //!
+//! The nonce is random, except with [`CryptoKey::encrypt_deterministic`]
+//! (`filter.git-crypt.deterministic`), which derives it from the plaintext so
+//! unchanged files re-encrypt to the same blob.
+//!
 //! The magic header ensures reliable detection of encrypted data and provides
 //! versioning capability for future format changes.
 //!
+//! Versioned blobs follow the magic with a zero marker byte and a format version:
+//!
+//! ```text
+//! [GITCRYPT][0x00][version][12-byte nonce][ciphertext + 16-byte GCM tag]
+//! ```
+//!
+//! - **Version 1 (padded)**: the plaintext is padded inside the AEAD to a multiple
+//!   of a block size (ISO/IEC 7816-4: a `0x80` byte followed by zeros), so small
+//!   and empty files are indistinguishable by size.
+//!
+//! - **Version 2 (wrapped key)**: each blob is encrypted under its own random
+//!   content key, and the content key is encrypted ("wrapped") under the
+//!   repository key and stored in the header:
+//!
+//! ```text
+//! [GITCRYPT][0x00][0x02][wrapped content key][12-byte nonce][ciphertext + 16-byte tag]
+//!
+//! wrapped content key = [12-byte nonce][32-byte content key + 16-byte tag]  (under the repo key)
+//! ```
+//!
+//!   Rotating the repository key only needs [`CryptoKey::rewrap`] to rewrite the
+//!   60-byte key header; the file ciphertext stays as it is.
+//!
+//! - **Version 3 (stream)**: written and read incrementally by
+//!   [`CryptoKey::encrypt_stream`] and [`CryptoKey::decrypt_stream`] for inputs
+//!   too large to buffer. The plaintext is cut into 64 KiB frames, each sealed
+//!   under the stream's random 8-byte nonce prefix followed by the 4-byte
+//!   big-endian frame index. A zero length ends the frames, and an
+//!   HMAC-SHA256 trailer covers the header, the prefix, every frame tag and the
+//!   frame count, so dropped, reordered or truncated frames fail even though
+//!   each frame authenticates on its own. The MAC key is derived from the
+//!   repository key with HKDF-SHA256, salted with the prefix:
+//!
+//! ```text
+//! [GITCRYPT][0x00][0x03][8-byte nonce prefix]
+//!   ([4-byte frame length][ciphertext + 16-byte tag])...
+//!   [0x00000000][32-byte HMAC-SHA256]
+//! ```
+//!
+//! - **Key generation tag**: setting the high bit (`0x80`) of the format
+//!   version adds a 4-byte big-endian key generation after it, counting the
+//!   `rotate-key` runs that produced the encrypting key. The tagged header is
+//!   passed to AES-GCM as associated data, so the generation can't be altered
+//!   without failing decryption. Version `0x80` tags a body in the original
+//!   format:
+//!
+//! ```text
+//! [GITCRYPT][0x00][version | 0x80][4-byte generation][body of that version]
+//! ```
+//!
+//! - **Cipher flag**: bit `0x40` of the format version marks a blob sealed with
+//!   ChaCha20-Poly1305 instead of AES-256-GCM. Nonce and tag sizes are the same,
+//!   so the body layout of every version is unchanged. Flagged headers are
+//!   authenticated as associated data like tagged ones.
+//!
+//! Blobs without a recognised version are decrypted as the original format.
+//!
+//! ### Upstream git-crypt Blobs
+//!
+//! Blobs written by upstream (C++) git-crypt are read, never written:
+//!
+//! ```text
+//! [\0GITCRYPT\0][12-byte nonce][AES-256-CTR ciphertext]
+//!
+//! nonce = first 12 bytes of HMAC-SHA1(hmac key, plaintext)
+//! ```
+//!
+//! The CTR counter block is the nonce followed by a 4-byte big-endian block
+//! index starting at zero. Decryption recomputes the HMAC to authenticate the
+//! plaintext. Both keys come from an upstream key file, loaded with
+//! [`CryptoKey::from_git_crypt_key_file`]; its AES key doubles as the key for
+//! new blobs, so re-staging files migrates them to this format.
+//!
+//! ### Header Layout
+//!
+//! All header reading and writing goes through one `Header` type:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 8    | magic `GITCRYPT`                       |
+//! | 8      | 1    | version marker `0x00` (versioned only) |
+//! | 9      | 1    | format version (versioned only)        |
+//! | 10     | 4    | key generation (tagged only)           |
+//!
+//! Multi-byte integer fields added to the header are encoded big-endian
+//! (`to_be_bytes`/`from_be_bytes`), so blobs read the same on every
+//! architecture.
+//!
 //! ## Security Properties
 //!
 //! - **Confidentiality**: AES-256 provides strong encryption
@@ -38,44 +132,401 @@
 //! - Binary data with all byte values
 //! - Unicode content
 //! - Key uniqueness and nonce randomness
+//! - Key bytes wiped on drop
+//! - Deterministic key generation from a seeded RNG
 //! - Authentication with wrong keys
 //! - Tamper detection on corrupted data
 //! - Invalid key size rejection
+//! - Padded format round-trips and size normalization
+//! - Wrapped-key round-trips and header-only rewrapping
+//! - Exact byte layout of headers and blobs, and known-answer ciphertext under a fixed nonce
+//! - Key generation tags and their authentication
+//! - Stream round-trips, and detection of dropped, reordered or truncated frames
 
 use crate::error::{GitCryptError, Result};
+use aes::cipher::{KeyIvInit, StreamCipher};
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
-    Aes256Gcm, Nonce,
+    aead::{Aead, KeyInit, OsRng, Payload},
+    Aes256Gcm,
 };
-use rand::RngCore;
+use chacha20poly1305::ChaCha20Poly1305;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::{CryptoRng, RngCore};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Write};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
 pub const KEY_SIZE: usize = 32; // 256 bits
 pub const NONCE_SIZE: usize = 12; // 96 bits for GCM
 
+/// Distinct byte values below which [`CryptoKey::is_low_entropy`] flags a key;
+/// a random 32-byte key has fewer with probability around 2^-120
+pub const MIN_DISTINCT_KEY_BYTES: usize = 8;
+
 // Magic header to identify encrypted data
 const MAGIC_HEADER: &[u8] = b"GITCRYPT";
 
+// Marker byte following the magic in versioned blobs
+const VERSION_MARKER: u8 = 0x00;
+
+/// Format version for a generation-tagged body in the original format
+pub const FORMAT_BASIC: u8 = 0;
+
+/// Format version for plaintext padded to a block size before encryption
+pub const FORMAT_PADDED: u8 = 1;
+
+/// Format version for content encrypted under a per-file key wrapped by the repo key
+pub const FORMAT_WRAPPED: u8 = 2;
+
+/// Format version for a stream of independently sealed frames
+pub const FORMAT_STREAM: u8 = 3;
+
+// Format version bit marking a key generation in the header
+const GENERATION_FLAG: u8 = 0x80;
+
+// Format version bit marking a ChaCha20-Poly1305 blob
+const CHACHA_FLAG: u8 = 0x40;
+
+const TAG_SIZE: usize = 16; // GCM authentication tag
+
+// Size of a wrapped content key: nonce + encrypted key + tag
+const WRAPPED_KEY_LEN: usize = NONCE_SIZE + KEY_SIZE + TAG_SIZE;
+
+// Plaintext bytes per frame of a stream (format version 3)
+const STREAM_FRAME_SIZE: usize = 64 * 1024;
+
+// Random per-stream prefix of every frame nonce; the frame index fills the rest
+const STREAM_PREFIX_SIZE: usize = NONCE_SIZE - 4;
+
+// Size of the HMAC-SHA256 stream trailer
+const STREAM_MAC_SIZE: usize = 32;
+
+// HKDF info deriving the stream MAC key from the repository key
+const STREAM_MAC_INFO: &[u8] = b"git-crypt stream mac";
+
+// ISO/IEC 7816-4 padding delimiter
+const PADDING_DELIMITER: u8 = 0x80;
+
+/// Header of key files written by upstream (C++) git-crypt, followed by a
+/// 4-byte big-endian format version
+pub const UPSTREAM_KEY_MAGIC: &[u8] = b"\0GITCRYPTKEY";
+
+/// The only upstream key file format version with fields (2); versions 0
+/// and 1 were headerless
+const UPSTREAM_KEY_FORMAT: u32 = 2;
+
+/// Header of blobs written by upstream git-crypt
+const UPSTREAM_MAGIC: &[u8] = b"\0GITCRYPT\0";
+
+/// Size of the HMAC-derived nonce in upstream blobs
+const UPSTREAM_NONCE_SIZE: usize = 12;
+
+/// Size of the HMAC-SHA1 key in upstream key files
+const UPSTREAM_HMAC_KEY_SIZE: usize = 64;
+
+/// Upstream key file field ids. Odd ids are critical: a reader that doesn't
+/// know one must refuse the file.
+const UPSTREAM_FIELD_END: u32 = 0;
+const UPSTREAM_FIELD_KEY_NAME: u32 = 1;
+const UPSTREAM_FIELD_VERSION: u32 = 1;
+const UPSTREAM_FIELD_AES_KEY: u32 = 3;
+const UPSTREAM_FIELD_HMAC_KEY: u32 = 5;
+
+/// Longest field upstream git-crypt accepts
+const UPSTREAM_MAX_FIELD_LEN: u32 = 1 << 20;
+
+/// Constant MACed under the key to get its check value
+const KEY_CHECK_LABEL: &[u8] = b"git-crypt key check value";
+
+/// Bytes of the MAC kept as the key check value
+const KEY_CHECK_SIZE: usize = 8;
+
+/// AEAD that seals a blob
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Cipher {
+    #[default]
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+        }
+    }
+}
+
+impl std::fmt::Display for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Cipher {
+    type Err = GitCryptError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "aes" | "aes-256-gcm" => Ok(Cipher::Aes256Gcm),
+            "chacha20" | "chacha20-poly1305" => Ok(Cipher::ChaCha20Poly1305),
+            other => Err(GitCryptError::Crypto(format!(
+                "Unknown cipher '{other}' (expected aes-256-gcm or chacha20-poly1305)"
+            ))),
+        }
+    }
+}
+
+/// A 256-bit symmetric repository key.
+///
+/// With the optional `serde` feature, keys serialize as a base64 string.
+/// Serializing a key writes the secret itself: never log serialized keys or
+/// store them anywhere less protected than `.git/git-crypt/keys`.
+///
+/// A key may carry its generation (see [`CryptoKey::with_generation`]); blobs
+/// it encrypts are then tagged with it. It also has a [`Cipher`] for new
+/// blobs; decryption follows each blob's header. Neither is serialized.
+///
+/// The key bytes are wiped from memory when the key is dropped.
 #[derive(Clone)]
 pub struct CryptoKey {
     key: [u8; KEY_SIZE],
+    generation: Option<u32>,
+    cipher: Cipher,
+    /// HMAC key of an upstream git-crypt key file, for reading its blobs
+    upstream_hmac: Option<Box<[u8; UPSTREAM_HMAC_KEY_SIZE]>>,
+}
+
+impl Drop for CryptoKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        if let Some(hmac_key) = &mut self.upstream_hmac {
+            hmac_key.zeroize();
+        }
+    }
+}
+
+impl ZeroizeOnDrop for CryptoKey {}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CryptoKey {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use base64::Engine;
+        serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(self.key))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CryptoKey {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        use base64::Engine;
+        use serde::de::Error;
+
+        let encoded = Zeroizing::new(String::deserialize(deserializer)?);
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(encoded.as_bytes())
+            .map(Zeroizing::new)
+            .map_err(|e| D::Error::custom(format!("invalid base64 key: {e}")))?;
+        CryptoKey::from_bytes(&bytes).map_err(|e| match e {
+            GitCryptError::ZeroKey => D::Error::custom("invalid key: all bytes are zero"),
+            _ => D::Error::custom(format!(
+                "invalid key length: expected {KEY_SIZE} bytes, got {}",
+                bytes.len()
+            )),
+        })
+    }
+}
+
+/// Where [`CryptoKey::encrypt`] gets its nonces.
+///
+/// Any cryptographic RNG is one; the default is `OsRng`. Tests and fuzzers
+/// can supply their own to pin the ciphertext.
+pub(crate) trait NonceSource {
+    fn next_nonce(&mut self) -> [u8; NONCE_SIZE];
+}
+
+impl<R: RngCore + CryptoRng> NonceSource for R {
+    fn next_nonce(&mut self) -> [u8; NONCE_SIZE] {
+        let mut nonce = [0u8; NONCE_SIZE];
+        self.fill_bytes(&mut nonce);
+        nonce
+    }
 }
 
 impl CryptoKey {
-    /// Generate a new random key
+    /// Generate a new random key from the operating system's RNG
     pub fn generate() -> Self {
-        let mut key = [0u8; KEY_SIZE];
-        OsRng.fill_bytes(&mut key);
-        Self { key }
+        Self::generate_with_rng(&mut OsRng)
+    }
+
+    /// Generate a new key from `rng`.
+    ///
+    /// Lets tests use a seeded RNG and deployments plug in a vetted DRBG;
+    /// anything else should call [`CryptoKey::generate`].
+    pub fn generate_with_rng<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut key = Self {
+            key: [0u8; KEY_SIZE],
+            generation: None,
+            cipher: Cipher::default(),
+            upstream_hmac: None,
+        };
+        rng.fill_bytes(&mut key.key);
+        key
     }
 
     /// Create a key from existing bytes
+    ///
+    /// The all-zero key is rejected: it is what a truncated or zero-filled
+    /// key file holds, never a key [`Self::generate`] produced.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
         if bytes.len() != KEY_SIZE {
             return Err(GitCryptError::InvalidKeyFormat);
         }
-        let mut key = [0u8; KEY_SIZE];
-        key.copy_from_slice(bytes);
-        Ok(Self { key })
+        if bytes.iter().fold(0u8, |acc, &b| acc | b) == 0 {
+            return Err(GitCryptError::ZeroKey);
+        }
+        let mut key = Self {
+            key: [0u8; KEY_SIZE],
+            generation: None,
+            cipher: Cipher::default(),
+            upstream_hmac: None,
+        };
+        key.key.copy_from_slice(bytes);
+        Ok(key)
+    }
+
+    /// Load the key from a key file written by upstream (C++) git-crypt.
+    ///
+    /// Its AES key becomes this key, and its HMAC key is kept so blobs
+    /// encrypted by upstream git-crypt can be decrypted. Only the key entry
+    /// of version 0 is used, as upstream git-crypt itself does when
+    /// decrypting.
+    pub fn from_git_crypt_key_file(bytes: &[u8]) -> Result<Self> {
+        let malformed = |reason: &str| {
+            GitCryptError::Crypto(format!("Malformed upstream git-crypt key file: {reason}"))
+        };
+
+        let mut fields = FieldReader(bytes);
+        if fields.take(UPSTREAM_KEY_MAGIC.len()) != Some(UPSTREAM_KEY_MAGIC) {
+            return Err(malformed("missing header"));
+        }
+        match fields.be32() {
+            Some(UPSTREAM_KEY_FORMAT) => {}
+            Some(version) => {
+                return Err(GitCryptError::Crypto(format!(
+                    "Upstream git-crypt key file format {version} is not supported"
+                )))
+            }
+            None => return Err(malformed("truncated header")),
+        }
+
+        // Header fields; the key name is the only one defined
+        loop {
+            let (id, value) = fields
+                .field()
+                .ok_or_else(|| malformed("truncated header"))?;
+            match id {
+                UPSTREAM_FIELD_END => break,
+                UPSTREAM_FIELD_KEY_NAME => {}
+                id if id & 1 == 1 => {
+                    return Err(malformed(&format!("unknown critical field {id}")))
+                }
+                _ => {}
+            }
+            value.ok_or_else(|| malformed("truncated header"))?;
+        }
+
+        let mut entry = None;
+        while !fields.0.is_empty() {
+            let (mut version, mut aes_key, mut hmac_key) = (0, None, None);
+            loop {
+                let (id, value) = fields.field().ok_or_else(|| malformed("truncated entry"))?;
+                if id == UPSTREAM_FIELD_END {
+                    break;
+                }
+                let value = value.ok_or_else(|| malformed("truncated entry"))?;
+                match id {
+                    UPSTREAM_FIELD_VERSION => {
+                        let bytes: [u8; 4] = value
+                            .try_into()
+                            .map_err(|_| malformed("bad version field"))?;
+                        version = u32::from_be_bytes(bytes);
+                    }
+                    UPSTREAM_FIELD_AES_KEY if value.len() == KEY_SIZE => aes_key = Some(value),
+                    UPSTREAM_FIELD_HMAC_KEY if value.len() == UPSTREAM_HMAC_KEY_SIZE => {
+                        hmac_key = Some(value)
+                    }
+                    UPSTREAM_FIELD_AES_KEY | UPSTREAM_FIELD_HMAC_KEY => {
+                        return Err(malformed("bad key length"))
+                    }
+                    id if id & 1 == 1 => {
+                        return Err(malformed(&format!("unknown critical field {id}")))
+                    }
+                    _ => {}
+                }
+            }
+            let (Some(aes_key), Some(hmac_key)) = (aes_key, hmac_key) else {
+                return Err(malformed("entry without both keys"));
+            };
+            if version == 0 {
+                entry = Some((aes_key, hmac_key));
+            }
+        }
+
+        let (aes_key, hmac_key) = entry.ok_or_else(|| malformed("no key of version 0"))?;
+        let mut key = Self::from_bytes(aes_key)?;
+        let mut upstream_hmac = Box::new([0u8; UPSTREAM_HMAC_KEY_SIZE]);
+        upstream_hmac.copy_from_slice(hmac_key);
+        key.upstream_hmac = Some(upstream_hmac);
+        Ok(key)
+    }
+
+    /// Whether the key bytes look hand-made rather than random: fewer than
+    /// [`MIN_DISTINCT_KEY_BYTES`] distinct values, which a random key has
+    /// with negligible probability
+    pub fn is_low_entropy(&self) -> bool {
+        let mut seen = [false; 256];
+        for &b in &self.key {
+            seen[usize::from(b)] = true;
+        }
+        seen.iter().filter(|&&s| s).count() < MIN_DISTINCT_KEY_BYTES
+    }
+
+    /// Whether this key came from an upstream git-crypt key file
+    pub fn is_upstream(&self) -> bool {
+        self.upstream_hmac.is_some()
+    }
+
+    /// The key file contents that load back into this key: the raw key, or
+    /// for a key from [`Self::from_git_crypt_key_file`], an upstream key
+    /// file so its blobs stay readable
+    pub fn to_key_file(&self) -> Zeroizing<Vec<u8>> {
+        let Some(hmac_key) = &self.upstream_hmac else {
+            return Zeroizing::new(self.key.to_vec());
+        };
+        let mut out = Zeroizing::new(Vec::with_capacity(148));
+        out.extend_from_slice(UPSTREAM_KEY_MAGIC);
+        out.extend_from_slice(&UPSTREAM_KEY_FORMAT.to_be_bytes());
+        out.extend_from_slice(&UPSTREAM_FIELD_END.to_be_bytes());
+        for (id, value) in [
+            (UPSTREAM_FIELD_VERSION, &0u32.to_be_bytes()[..]),
+            (UPSTREAM_FIELD_AES_KEY, &self.key[..]),
+            (UPSTREAM_FIELD_HMAC_KEY, &hmac_key[..]),
+        ] {
+            out.extend_from_slice(&id.to_be_bytes());
+            out.extend_from_slice(&(value.len() as u32).to_be_bytes());
+            out.extend_from_slice(value);
+        }
+        out.extend_from_slice(&UPSTREAM_FIELD_END.to_be_bytes());
+        out
     }
 
     /// Get the key as bytes
@@ -83,69 +534,1122 @@ impl CryptoKey {
         &self.key
     }
 
+    /// Tag everything this key encrypts with key `generation`
+    pub fn with_generation(mut self, generation: u32) -> Self {
+        self.generation = Some(generation);
+        self
+    }
+
+    /// Key generation this key tags blobs with, if any
+    pub fn generation(&self) -> Option<u32> {
+        self.generation
+    }
+
+    /// Seal everything this key encrypts with `cipher`
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Hex SHA-256 of the key, safe to log or display in place of the key
+    pub fn fingerprint(&self) -> String {
+        hex::encode(Sha256::digest(self.key))
+    }
+
+    /// Key check value: the first 8 bytes of
+    /// `HMAC-SHA256(key, "git-crypt key check value")` in hex.
+    ///
+    /// Stored next to the key file so a corrupted or swapped key is caught on
+    /// load; reveals nothing about the key beyond telling keys apart.
+    pub fn check_value(&self) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(KEY_CHECK_LABEL);
+        hex::encode(&mac.finalize().into_bytes()[..KEY_CHECK_SIZE])
+    }
+
     /// Encrypt data
     pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
-        let cipher = Aes256Gcm::new_from_slice(&self.key)
-            .map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+        self.encrypt_with_nonce_source(plaintext, &mut OsRng)
+    }
 
-        // Generate random nonce
-        let mut nonce_bytes = [0u8; NONCE_SIZE];
-        OsRng.fill_bytes(&mut nonce_bytes);
-        let nonce = Nonce::from_slice(&nonce_bytes);
+    /// [`Self::encrypt`] with the nonce taken from `nonces`
+    pub(crate) fn encrypt_with_nonce_source(
+        &self,
+        plaintext: &[u8],
+        nonces: &mut impl NonceSource,
+    ) -> Result<Vec<u8>> {
+        self.encrypt_with_nonce(plaintext, nonces.next_nonce())
+    }
 
-        // Encrypt
-        let ciphertext = cipher
-            .encrypt(nonce, plaintext)
-            .map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+    /// [`Self::encrypt`] under a fixed nonce, for known-answer tests. The
+    /// nonce must never seal different plaintext under this key.
+    pub(crate) fn encrypt_with_nonce(
+        &self,
+        plaintext: &[u8],
+        nonce: [u8; NONCE_SIZE],
+    ) -> Result<Vec<u8>> {
+        let header = self.header(Header::LEGACY);
+        let sealed = self.seal_with_nonce(self.cipher, nonce, plaintext, &header.aad())?;
+        Ok(frame(header, &[&sealed]))
+    }
+
+    /// Encrypt data like [`Self::encrypt`], but under a nonce derived from the
+    /// plaintext (`HMAC-SHA256(key, plaintext)`, first 12 bytes), so equal
+    /// content always yields the same blob, as in upstream git-crypt.
+    ///
+    /// This reveals which blobs hold identical plaintext; the nonce is never
+    /// reused for different content. Decrypts with [`Self::decrypt`] as usual.
+    pub fn encrypt_deterministic(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(plaintext);
+        let mut nonce = [0u8; NONCE_SIZE];
+        nonce.copy_from_slice(&mac.finalize().into_bytes()[..NONCE_SIZE]);
 
-        // Format: MAGIC_HEADER + nonce + ciphertext
-        let mut result = Vec::with_capacity(MAGIC_HEADER.len() + NONCE_SIZE + ciphertext.len());
-        result.extend_from_slice(MAGIC_HEADER);
-        result.extend_from_slice(&nonce_bytes);
-        result.extend_from_slice(&ciphertext);
+        let header = self.header(Header::LEGACY);
+        let sealed = self.seal_with_nonce(self.cipher, nonce, plaintext, &header.aad())?;
+        Ok(frame(header, &[&sealed]))
+    }
+
+    /// Encrypt data padded to a multiple of `block_size` (format version 1)
+    pub fn encrypt_padded(&self, plaintext: &[u8], block_size: usize) -> Result<Vec<u8>> {
+        if block_size == 0 {
+            return Err(GitCryptError::Crypto(
+                "Padding block size must be greater than zero".into(),
+            ));
+        }
+
+        let padded = pad(plaintext, block_size);
+        let header = self.header(Header::versioned(FORMAT_PADDED));
+        Ok(frame(
+            header,
+            &[&self.seal(self.cipher, &padded, &header.aad())?],
+        ))
+    }
+
+    /// Encrypt data under a fresh content key wrapped by this key (format version 2)
+    pub fn encrypt_wrapped(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let header = self.header(Header::versioned(FORMAT_WRAPPED));
+        let content_key = CryptoKey::generate();
+        let wrapped_key = self.seal(self.cipher, content_key.as_bytes(), &header.aad())?;
+        let body = content_key.seal(self.cipher, plaintext, &[])?;
+        Ok(frame(header, &[&wrapped_key, &body]))
+    }
+
+    /// Encrypt everything read from `reader` to `writer` as a stream of
+    /// frames (format version 3), never holding more than one frame in memory.
+    ///
+    /// Suited to inputs too large to buffer, or beyond the ~64 GiB a single
+    /// GCM nonce may seal.
+    pub fn encrypt_stream(&self, mut reader: impl Read, mut writer: impl Write) -> Result<()> {
+        let header = self.header(Header::versioned(FORMAT_STREAM));
+        let mut prefix = [0u8; STREAM_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut prefix);
+
+        let mut header_bytes = Vec::new();
+        header.write_to(&mut header_bytes);
+        writer.write_all(&header_bytes)?;
+        writer.write_all(&prefix)?;
+
+        let mut mac = self.stream_mac(&header_bytes, &prefix);
+        let mut buffer = vec![0u8; STREAM_FRAME_SIZE];
+        let mut frames: u32 = 0;
+        loop {
+            let filled = read_full(&mut reader, &mut buffer)?;
+            if filled == 0 {
+                break;
+            }
+
+            let payload = Payload {
+                msg: &buffer[..filled],
+                aad: &header.aad(),
+            };
+            let nonce = stream_nonce(&prefix, frames);
+            let sealed = match header.cipher {
+                Cipher::Aes256Gcm => aead_encrypt::<Aes256Gcm>(&self.key, &nonce, payload),
+                Cipher::ChaCha20Poly1305 => {
+                    aead_encrypt::<ChaCha20Poly1305>(&self.key, &nonce, payload)
+                }
+            }?;
+            writer.write_all(&(sealed.len() as u32).to_be_bytes())?;
+            writer.write_all(&sealed)?;
+            mac.update(&sealed[sealed.len() - TAG_SIZE..]);
+
+            frames = frames
+                .checked_add(1)
+                .ok_or_else(|| GitCryptError::Crypto("Stream has too many frames".into()))?;
+            if filled < buffer.len() {
+                break;
+            }
+        }
+
+        // A zero length can't be a frame (every frame has a tag), so it ends them
+        mac.update(&u64::from(frames).to_be_bytes());
+        writer.write_all(&0u32.to_be_bytes())?;
+        writer.write_all(&mac.finalize().into_bytes())?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Re-wrap the content key of a version 2 blob under `new_key`.
+    ///
+    /// Only the small key header is rewritten; the file ciphertext is carried
+    /// over unchanged, which is what makes master-key rotation cheap. The
+    /// header takes `new_key`'s generation but keeps the blob's cipher, which
+    /// only re-encryption can change.
+    pub fn rewrap(&self, ciphertext: &[u8], new_key: &CryptoKey) -> Result<Vec<u8>> {
+        let not_wrapped = || GitCryptError::Crypto("Data is not in wrapped-key format".into());
+
+        let (header, body) = match Header::parse(ciphertext) {
+            Some((header, body)) if header.version == Some(FORMAT_WRAPPED) => (header, body),
+            _ => return Err(not_wrapped()),
+        };
+        if body.len() < WRAPPED_KEY_LEN {
+            return Err(not_wrapped());
+        }
 
-        Ok(result)
+        let (wrapped_key, content) = body.split_at(WRAPPED_KEY_LEN);
+        let content_key = self
+            .open(header.cipher, wrapped_key, &header.aad())
+            .map_err(|_| not_wrapped())?;
+        let new_header = Header::versioned(FORMAT_WRAPPED)
+            .tagged(new_key.generation)
+            .with_cipher(header.cipher);
+        let rewrapped_key = new_key.seal(header.cipher, &content_key, &new_header.aad())?;
+        Ok(frame(new_header, &[&rewrapped_key, content]))
     }
 
     /// Decrypt data
     pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.starts_with(UPSTREAM_MAGIC) {
+            return self.decrypt_upstream(&ciphertext[UPSTREAM_MAGIC.len()..]);
+        }
+
         let min_size = MAGIC_HEADER.len() + NONCE_SIZE;
         if ciphertext.len() < min_size {
             return Err(GitCryptError::Crypto("Ciphertext too short".into()));
         }
 
-        // Check magic header
-        if &ciphertext[..MAGIC_HEADER.len()] != MAGIC_HEADER {
+        let Some((header, body)) = Header::parse(ciphertext) else {
             return Err(GitCryptError::Crypto(
                 "Invalid encrypted data format".into(),
             ));
+        };
+
+        match header.version {
+            Some(_) => {
+                let versioned = self.decrypt_versioned(header, body);
+                // A legacy nonce can begin with the same two bytes; fall back to
+                // it before reporting the versioned failure.
+                let legacy = &ciphertext[Header::LEGACY.len()..];
+                versioned.or_else(|err| self.open(Cipher::Aes256Gcm, legacy, &[]).map_err(|_| err))
+            }
+            None => self.open(Cipher::Aes256Gcm, body, &[]),
+        }
+    }
+
+    /// Decrypt a stream written by [`Self::encrypt_stream`] from `reader` to
+    /// `writer`.
+    ///
+    /// Each frame is authenticated on its own, and the trailer MAC over every
+    /// frame tag and the frame count is checked at the end, so dropped,
+    /// reordered or truncated frames are detected. Plaintext is written as
+    /// frames are read: on error, discard whatever reached `writer`.
+    pub fn decrypt_stream(&self, mut reader: impl Read, writer: impl Write) -> Result<()> {
+        let not_stream = || GitCryptError::Crypto("Data is not in stream format".into());
+
+        let mut header_bytes = vec![0u8; Header::versioned(FORMAT_STREAM).len()];
+        reader
+            .read_exact(&mut header_bytes)
+            .map_err(|_| not_stream())?;
+        if header_bytes[header_bytes.len() - 1] & GENERATION_FLAG != 0 {
+            let mut generation = [0u8; 4];
+            reader
+                .read_exact(&mut generation)
+                .map_err(|_| not_stream())?;
+            header_bytes.extend_from_slice(&generation);
+        }
+        match Header::parse(&header_bytes) {
+            Some((header, [])) if header.version == Some(FORMAT_STREAM) => {
+                self.decrypt_stream_body(header, reader, writer)
+            }
+            _ => Err(not_stream()),
         }
+    }
 
-        let cipher = Aes256Gcm::new_from_slice(&self.key)
-            .map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+    /// Decrypt the frames and trailer that follow a stream `header`
+    fn decrypt_stream_body(
+        &self,
+        header: Header,
+        mut reader: impl Read,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        let truncated = || GitCryptError::Crypto("Stream is truncated".into());
+        let read = |reader: &mut dyn Read, buf: &mut [u8]| {
+            reader.read_exact(buf).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => truncated(),
+                _ => e.into(),
+            })
+        };
 
-        // Extract nonce and ciphertext (skip magic header)
-        let data = &ciphertext[MAGIC_HEADER.len()..];
-        let (nonce_bytes, encrypted_data) = data.split_at(NONCE_SIZE);
-        let nonce = Nonce::from_slice(nonce_bytes);
+        let mut header_bytes = Vec::new();
+        header.write_to(&mut header_bytes);
+        let mut prefix = [0u8; STREAM_PREFIX_SIZE];
+        read(&mut reader, &mut prefix)?;
+
+        let mut mac = self.stream_mac(&header_bytes, &prefix);
+        let mut frames: u32 = 0;
+        loop {
+            let mut len = [0u8; 4];
+            read(&mut reader, &mut len)?;
+            let len = u32::from_be_bytes(len) as usize;
+            if len == 0 {
+                break;
+            }
+            if !(TAG_SIZE..=STREAM_FRAME_SIZE + TAG_SIZE).contains(&len) {
+                return Err(GitCryptError::Crypto(format!(
+                    "Invalid stream frame length: {len}"
+                )));
+            }
 
-        // Decrypt
-        let plaintext = cipher
-            .decrypt(nonce, encrypted_data)
+            let mut sealed = vec![0u8; len];
+            read(&mut reader, &mut sealed)?;
+            let payload = Payload {
+                msg: &sealed,
+                aad: &header.aad(),
+            };
+            let nonce = stream_nonce(&prefix, frames);
+            let plaintext = match header.cipher {
+                Cipher::Aes256Gcm => aead_decrypt::<Aes256Gcm>(&self.key, &nonce, payload),
+                Cipher::ChaCha20Poly1305 => {
+                    aead_decrypt::<ChaCha20Poly1305>(&self.key, &nonce, payload)
+                }
+            }
+            .map_err(|_| {
+                GitCryptError::Crypto(format!(
+                    "Stream frame {frames} failed authentication (tampered, reordered or dropped frames)"
+                ))
+            })?;
+            writer.write_all(&plaintext)?;
+            mac.update(&sealed[len - TAG_SIZE..]);
+
+            frames = frames
+                .checked_add(1)
+                .ok_or_else(|| GitCryptError::Crypto("Stream has too many frames".into()))?;
+        }
+
+        let mut trailer = [0u8; STREAM_MAC_SIZE];
+        read(&mut reader, &mut trailer)?;
+        mac.update(&u64::from(frames).to_be_bytes());
+        mac.verify_slice(&trailer).map_err(|_| {
+            GitCryptError::Crypto("Stream MAC mismatch (frames dropped or reordered)".into())
+        })?;
+        if reader.read(&mut [0u8; 1])? != 0 {
+            return Err(GitCryptError::Crypto(
+                "Unexpected data after stream trailer".into(),
+            ));
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// HMAC-SHA256 over a stream, keyed from this key and the stream's nonce
+    /// prefix, started with the header and prefix
+    fn stream_mac(&self, header_bytes: &[u8], prefix: &[u8]) -> Hmac<Sha256> {
+        let mut mac_key = [0u8; KEY_SIZE];
+        Hkdf::<Sha256>::new(Some(prefix), &self.key)
+            .expand(STREAM_MAC_INFO, &mut mac_key)
+            .expect("32 bytes is a valid HKDF-SHA256 output length");
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&mac_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(header_bytes);
+        mac.update(prefix);
+        mac
+    }
+
+    /// Decrypt the body of a versioned blob
+    fn decrypt_versioned(&self, header: Header, body: &[u8]) -> Result<Vec<u8>> {
+        let (aad, cipher) = (header.aad(), header.cipher);
+        match header.version.unwrap_or(FORMAT_BASIC) {
+            FORMAT_BASIC => self.open(cipher, body, &aad),
+            FORMAT_PADDED => self
+                .open(cipher, body, &aad)
+                .and_then(|padded| unpad(&padded)),
+            FORMAT_WRAPPED => {
+                if body.len() < WRAPPED_KEY_LEN {
+                    return Err(GitCryptError::Crypto("Ciphertext too short".into()));
+                }
+                let (wrapped_key, content) = body.split_at(WRAPPED_KEY_LEN);
+                let content_key = CryptoKey::from_bytes(&Zeroizing::new(self.open(
+                    cipher,
+                    wrapped_key,
+                    &aad,
+                )?))?;
+                content_key.open(cipher, content, &[])
+            }
+            FORMAT_STREAM => {
+                let mut plaintext = Vec::new();
+                self.decrypt_stream_body(header, body, &mut plaintext)?;
+                Ok(plaintext)
+            }
+            other => Err(GitCryptError::Crypto(format!(
+                "Unsupported format version: {other}"
+            ))),
+        }
+    }
+
+    /// Header `base` with this key's generation and cipher
+    fn header(&self, base: Header) -> Header {
+        base.tagged(self.generation).with_cipher(self.cipher)
+    }
+
+    /// Encrypt `plaintext` with `cipher` under a random nonce, returning
+    /// `nonce + ciphertext`.
+    ///
+    /// `aad` is authenticated but not stored; it is empty except for flagged headers.
+    fn seal(&self, cipher: Cipher, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        self.seal_with_nonce(cipher, OsRng.next_nonce(), plaintext, aad)
+    }
+
+    /// [`Self::seal`] under the given nonce, which must never seal different
+    /// plaintext under this key
+    fn seal_with_nonce(
+        &self,
+        cipher: Cipher,
+        nonce_bytes: [u8; NONCE_SIZE],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        let payload = Payload {
+            msg: plaintext,
+            aad,
+        };
+        let ciphertext = match cipher {
+            Cipher::Aes256Gcm => aead_encrypt::<Aes256Gcm>(&self.key, &nonce_bytes, payload),
+            Cipher::ChaCha20Poly1305 => {
+                aead_encrypt::<ChaCha20Poly1305>(&self.key, &nonce_bytes, payload)
+            }
+        }?;
+
+        let mut sealed = Vec::with_capacity(NONCE_SIZE + ciphertext.len());
+        sealed.extend_from_slice(&nonce_bytes);
+        sealed.extend_from_slice(&ciphertext);
+
+        Ok(sealed)
+    }
+
+    /// Decrypt the body of a blob written by upstream git-crypt and check
+    /// its HMAC-derived nonce
+    fn decrypt_upstream(&self, body: &[u8]) -> Result<Vec<u8>> {
+        let Some(hmac_key) = &self.upstream_hmac else {
+            return Err(GitCryptError::Crypto(
+                "Blob was encrypted by upstream git-crypt; unlock with its key file to read it"
+                    .into(),
+            ));
+        };
+        if body.len() < UPSTREAM_NONCE_SIZE {
+            return Err(GitCryptError::Crypto("Ciphertext too short".into()));
+        }
+
+        let (nonce, encrypted) = body.split_at(UPSTREAM_NONCE_SIZE);
+        let mut counter = [0u8; 16];
+        counter[..UPSTREAM_NONCE_SIZE].copy_from_slice(nonce);
+        let mut plaintext = Zeroizing::new(encrypted.to_vec());
+        ctr::Ctr32BE::<aes::Aes256>::new(&self.key.into(), &counter.into())
+            .apply_keystream(&mut plaintext);
+
+        let mut mac = <Hmac<Sha1> as Mac>::new_from_slice(&hmac_key[..])
             .map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+        mac.update(&plaintext);
+        mac.verify_truncated_left(nonce).map_err(|_| {
+            GitCryptError::Crypto("Decryption failed: upstream blob failed its HMAC check".into())
+        })?;
+        Ok(std::mem::take(&mut *plaintext))
+    }
 
-        Ok(plaintext)
+    /// Decrypt a `nonce + ciphertext` body sealed with `cipher` and `aad`
+    fn open(&self, cipher: Cipher, data: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        if data.len() < NONCE_SIZE {
+            return Err(GitCryptError::Crypto("Ciphertext too short".into()));
+        }
+
+        let (nonce_bytes, encrypted_data) = data.split_at(NONCE_SIZE);
+        let payload = Payload {
+            msg: encrypted_data,
+            aad,
+        };
+        match cipher {
+            Cipher::Aes256Gcm => aead_decrypt::<Aes256Gcm>(&self.key, nonce_bytes, payload),
+            Cipher::ChaCha20Poly1305 => {
+                aead_decrypt::<ChaCha20Poly1305>(&self.key, nonce_bytes, payload)
+            }
+        }
     }
 
-    /// Check if data has our magic header
+    /// Check if data has our magic header, or that of upstream git-crypt
     pub fn is_encrypted(data: &[u8]) -> bool {
-        data.len() >= MAGIC_HEADER.len() && &data[..MAGIC_HEADER.len()] == MAGIC_HEADER
+        data.starts_with(MAGIC_HEADER) || data.starts_with(UPSTREAM_MAGIC)
+    }
+
+    /// Format version in the header of encrypted `data` (`0` for the original
+    /// format), or `None` if `data` is not encrypted.
+    ///
+    /// Read from the header alone: a legacy blob whose nonce happens to look
+    /// like a version marker reports that version.
+    pub fn format_of(data: &[u8]) -> Option<u8> {
+        Header::parse(data).map(|(header, _)| header.version.unwrap_or(FORMAT_BASIC))
+    }
+
+    /// Cipher named in the header of encrypted `data`, or `None` if `data`
+    /// is not encrypted
+    pub fn cipher_of(data: &[u8]) -> Option<Cipher> {
+        Header::parse(data).map(|(header, _)| header.cipher)
+    }
+
+    /// Key generation tagged in the header of encrypted `data`, if any.
+    ///
+    /// Read without a key, so it is only authenticated once the blob decrypts.
+    pub fn generation_of(data: &[u8]) -> Option<u32> {
+        Header::parse(data).and_then(|(header, _)| header.generation)
+    }
+}
+
+/// Reads the big-endian fields of an upstream git-crypt key file
+struct FieldReader<'a>(&'a [u8]);
+
+impl<'a> FieldReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.0.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(taken)
+    }
+
+    fn be32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// The next field id, and its value unless the field is truncated or
+    /// too long. The end marker has no length or value.
+    fn field(&mut self) -> Option<(u32, Option<&'a [u8]>)> {
+        let id = self.be32()?;
+        if id == UPSTREAM_FIELD_END {
+            return Some((id, Some(&[])));
+        }
+        let len = self.be32()?;
+        if len > UPSTREAM_MAX_FIELD_LEN {
+            return Some((id, None));
+        }
+        Some((id, self.take(len as usize)))
+    }
+}
+
+/// Header at the start of every encrypted blob; see the module docs for the layout
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    /// Format version, or `None` for the original unversioned format
+    version: Option<u8>,
+    /// Key generation; always `None` for the unversioned format
+    generation: Option<u32>,
+    /// Always AES-256-GCM for the unversioned format
+    cipher: Cipher,
+}
+
+impl Header {
+    const LEGACY: Header = Header {
+        version: None,
+        generation: None,
+        cipher: Cipher::Aes256Gcm,
+    };
+
+    const fn versioned(version: u8) -> Self {
+        Header {
+            version: Some(version),
+            generation: None,
+            cipher: Cipher::Aes256Gcm,
+        }
+    }
+
+    /// This header tagged with `generation`; a legacy header becomes
+    /// [`FORMAT_BASIC`], which has the same body
+    fn tagged(self, generation: Option<u32>) -> Self {
+        match generation {
+            Some(_) => Header {
+                version: Some(self.version.unwrap_or(FORMAT_BASIC)),
+                generation,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// This header for a blob sealed with `cipher`; like [`Self::tagged`], a
+    /// legacy header becomes [`FORMAT_BASIC`] for anything but AES-256-GCM
+    fn with_cipher(self, cipher: Cipher) -> Self {
+        match cipher {
+            Cipher::Aes256Gcm => Header { cipher, ..self },
+            Cipher::ChaCha20Poly1305 => Header {
+                version: Some(self.version.unwrap_or(FORMAT_BASIC)),
+                cipher,
+                ..self
+            },
+        }
+    }
+
+    /// Whether the version byte carries flags, which makes the header
+    /// associated data
+    fn is_flagged(&self) -> bool {
+        self.generation.is_some() || self.cipher != Cipher::Aes256Gcm
+    }
+
+    /// Encoded size in bytes
+    fn len(&self) -> usize {
+        match (self.version, self.generation) {
+            (Some(_), Some(_)) => MAGIC_HEADER.len() + 6,
+            (Some(_), None) => MAGIC_HEADER.len() + 2,
+            (None, _) => MAGIC_HEADER.len(),
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(MAGIC_HEADER);
+        let Some(mut version) = self.version else {
+            return;
+        };
+        if self.generation.is_some() {
+            version |= GENERATION_FLAG;
+        }
+        if self.cipher == Cipher::ChaCha20Poly1305 {
+            version |= CHACHA_FLAG;
+        }
+        out.extend_from_slice(&[VERSION_MARKER, version]);
+        if let Some(generation) = self.generation {
+            out.extend_from_slice(&generation.to_be_bytes());
+        }
+    }
+
+    /// Associated data binding a flagged header to the ciphertext (empty
+    /// otherwise, which keeps unflagged blobs in their original form)
+    fn aad(&self) -> Vec<u8> {
+        let mut aad = Vec::new();
+        if self.is_flagged() {
+            self.write_to(&mut aad);
+        }
+        aad
+    }
+
+    /// Split `data` into its header and body, or `None` without the magic.
+    ///
+    /// Only known format versions are recognised; anything else after the
+    /// magic is treated as a legacy body.
+    fn parse(data: &[u8]) -> Option<(Header, &[u8])> {
+        let rest = data.strip_prefix(MAGIC_HEADER)?;
+        let legacy = Some((Header::LEGACY, rest));
+
+        let [VERSION_MARKER, version, body @ ..] = rest else {
+            return legacy;
+        };
+        let format = version & !(GENERATION_FLAG | CHACHA_FLAG);
+        // Version 0 is only written with a flag; bare, it's a legacy nonce
+        let known = match format {
+            FORMAT_PADDED | FORMAT_WRAPPED | FORMAT_STREAM => true,
+            FORMAT_BASIC => format != *version,
+            _ => false,
+        };
+        if !known {
+            return legacy;
+        }
+
+        let (generation, body) = if version & GENERATION_FLAG != 0 {
+            let [a, b, c, d, body @ ..] = body else {
+                return legacy;
+            };
+            (Some(u32::from_be_bytes([*a, *b, *c, *d])), body)
+        } else {
+            (None, body)
+        };
+        let cipher = if version & CHACHA_FLAG != 0 {
+            Cipher::ChaCha20Poly1305
+        } else {
+            Cipher::Aes256Gcm
+        };
+
+        let header = Header::versioned(format)
+            .tagged(generation)
+            .with_cipher(cipher);
+        Some((header, body))
+    }
+}
+
+/// Encrypt `payload` with AEAD `A` under `key` and `nonce`
+fn aead_encrypt<A: Aead + KeyInit>(key: &[u8], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+    let cipher = A::new_from_slice(key).map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+    cipher
+        .encrypt(aes_gcm::aead::Nonce::<A>::from_slice(nonce), payload)
+        .map_err(|e| GitCryptError::Crypto(e.to_string()))
+}
+
+/// Decrypt `payload` with AEAD `A` under `key` and `nonce`
+fn aead_decrypt<A: Aead + KeyInit>(key: &[u8], nonce: &[u8], payload: Payload) -> Result<Vec<u8>> {
+    let cipher = A::new_from_slice(key).map_err(|e| GitCryptError::Crypto(e.to_string()))?;
+    cipher
+        .decrypt(aes_gcm::aead::Nonce::<A>::from_slice(nonce), payload)
+        .map_err(|e| GitCryptError::Crypto(e.to_string()))
+}
+
+/// Nonce of stream frame `index`: the stream's random prefix, then the
+/// big-endian index, so frames can't be reordered undetected
+fn stream_nonce(prefix: &[u8; STREAM_PREFIX_SIZE], index: u32) -> [u8; NONCE_SIZE] {
+    let mut nonce = [0u8; NONCE_SIZE];
+    nonce[..STREAM_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[STREAM_PREFIX_SIZE..].copy_from_slice(&index.to_be_bytes());
+    nonce
+}
+
+/// Fill `buf` from `reader` until it is full or the input ends, returning
+/// how many bytes were read
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(filled)
+}
+
+/// Assemble a blob: `header`, then `parts`
+fn frame(header: Header, parts: &[&[u8]]) -> Vec<u8> {
+    let parts_len: usize = parts.iter().map(|p| p.len()).sum();
+
+    let mut result = Vec::with_capacity(header.len() + parts_len);
+    header.write_to(&mut result);
+    for part in parts {
+        result.extend_from_slice(part);
+    }
+    result
+}
+
+/// Pad to the next multiple of `block_size`, always adding at least one byte
+fn pad(plaintext: &[u8], block_size: usize) -> Vec<u8> {
+    let padded_len = (plaintext.len() / block_size + 1) * block_size;
+    let mut padded = Vec::with_capacity(padded_len);
+    padded.extend_from_slice(plaintext);
+    padded.push(PADDING_DELIMITER);
+    padded.resize(padded_len, 0);
+    padded
+}
+
+/// Strip padding added by [`pad`]
+fn unpad(padded: &[u8]) -> Result<Vec<u8>> {
+    match padded.iter().rposition(|&b| b != 0) {
+        Some(end) if padded[end] == PADDING_DELIMITER => Ok(padded[..end].to_vec()),
+        _ => Err(GitCryptError::Crypto("Invalid padding".into())),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aes_gcm::Nonce;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// Key with AES key bytes 0..32 and HMAC key bytes 64..128
+    fn upstream_key() -> CryptoKey {
+        let mut key =
+            CryptoKey::from_bytes(&std::array::from_fn::<u8, 32, _>(|i| i as u8)).unwrap();
+        key.upstream_hmac = Some(Box::new(std::array::from_fn(|i| i as u8 + 64)));
+        key
+    }
+
+    /// `hello from upstream git-crypt\n` encrypted by upstream git-crypt
+    /// with [`upstream_key`]
+    fn upstream_blob() -> Vec<u8> {
+        let mut blob = UPSTREAM_MAGIC.to_vec();
+        blob.extend(hex::decode("c5e82db533e03f4ba70fc9f5").unwrap());
+        blob.extend(
+            hex::decode("6250b099abbb9863b6cca6d9f71517403735ed744d627dd3fb143d9fc119").unwrap(),
+        );
+        blob
+    }
+
+    #[test]
+    fn test_decrypt_upstream_blob() {
+        let blob = upstream_blob();
+        assert!(CryptoKey::is_encrypted(&blob));
+        assert_eq!(
+            upstream_key().decrypt(&blob).unwrap(),
+            b"hello from upstream git-crypt\n"
+        );
+    }
+
+    #[test]
+    fn test_upstream_blob_rejects_tampering_and_missing_hmac_key() {
+        let mut blob = upstream_blob();
+        *blob.last_mut().unwrap() ^= 1;
+        let err = upstream_key().decrypt(&blob).unwrap_err().to_string();
+        assert!(err.contains("HMAC check"), "{err}");
+
+        let aes_only = CryptoKey::from_bytes(upstream_key().as_bytes()).unwrap();
+        let err = aes_only.decrypt(&upstream_blob()).unwrap_err().to_string();
+        assert!(err.contains("upstream git-crypt"), "{err}");
+    }
+
+    #[test]
+    fn test_upstream_key_file_round_trip() {
+        let key = upstream_key();
+        let file = key.to_key_file();
+        assert_eq!(file.len(), 148);
+        let loaded = CryptoKey::from_git_crypt_key_file(&file).unwrap();
+        assert_eq!(loaded.as_bytes(), key.as_bytes());
+        assert_eq!(loaded.upstream_hmac, key.upstream_hmac);
+
+        // New blobs use our format, readable with the same key
+        let blob = loaded.encrypt(b"data").unwrap();
+        assert!(blob.starts_with(MAGIC_HEADER));
+        assert_eq!(loaded.decrypt(&blob).unwrap(), b"data");
+
+        // Unknown critical fields and other key file versions are refused
+        let mut critical = file.to_vec();
+        critical.splice(16..16, [0, 0, 0, 7, 0, 0, 0, 0]);
+        assert!(CryptoKey::from_git_crypt_key_file(&critical).is_err());
+        let mut optional = file.to_vec();
+        optional.splice(16..16, [0, 0, 0, 8, 0, 0, 0, 1, 0xaa]);
+        assert!(CryptoKey::from_git_crypt_key_file(&optional).is_ok());
+        let mut version = file.to_vec();
+        version[15] = 1;
+        assert!(CryptoKey::from_git_crypt_key_file(&version).is_err());
+        assert!(CryptoKey::from_git_crypt_key_file(&file[..100]).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_matches_aes_gcm_known_answer() {
+        // GCM spec test case 14: zero key, zero IV, one zero block. from_bytes
+        // refuses the zero key, so set it directly
+        let mut key = CryptoKey::generate();
+        key.key = [0u8; KEY_SIZE];
+        let blob = key
+            .encrypt_with_nonce(&[0u8; 16], [0u8; NONCE_SIZE])
+            .unwrap();
+
+        let mut expected = b"GITCRYPT".to_vec();
+        expected.extend_from_slice(&[0u8; NONCE_SIZE]);
+        expected.extend_from_slice(&hex::decode("cea7403d4d606b6e074ec5d3baf39d18").unwrap());
+        expected.extend_from_slice(&hex::decode("d0d1c8a799996bf0265b98b5d48ab919").unwrap());
+        assert_eq!(blob, expected);
+        assert_eq!(key.decrypt(&blob).unwrap(), [0u8; 16]);
+    }
+
+    #[test]
+    fn test_seeded_nonce_source_is_reproducible() {
+        let key = CryptoKey::generate();
+        let seal = |seed| {
+            key.encrypt_with_nonce_source(b"same input", &mut StdRng::seed_from_u64(seed))
+                .unwrap()
+        };
+        assert_eq!(seal(7), seal(7));
+        assert_ne!(seal(7), seal(8));
+    }
+
+    #[test]
+    fn test_nonce_is_bound_to_its_ciphertext() {
+        let key = CryptoKey::generate();
+        let mut nonces = StdRng::seed_from_u64(1);
+        let first = key
+            .encrypt_with_nonce_source(b"first", &mut nonces)
+            .unwrap();
+        let second = key
+            .encrypt_with_nonce_source(b"second", &mut nonces)
+            .unwrap();
+
+        // Swapping in another blob's nonce fails authentication
+        let nonce_range = MAGIC_HEADER.len()..MAGIC_HEADER.len() + NONCE_SIZE;
+        let mut swapped = first.clone();
+        swapped[nonce_range.clone()].copy_from_slice(&second[nonce_range]);
+        assert!(key.decrypt(&swapped).is_err());
+        assert_eq!(key.decrypt(&first).unwrap(), b"first");
+    }
+
+    #[test]
+    fn test_header_byte_layout_is_pinned() {
+        let mut legacy = Vec::new();
+        Header::LEGACY.write_to(&mut legacy);
+        assert_eq!(legacy, b"GITCRYPT");
+        assert_eq!(Header::LEGACY.len(), 8);
+
+        let mut padded = Vec::new();
+        Header::versioned(FORMAT_PADDED).write_to(&mut padded);
+        assert_eq!(padded, b"GITCRYPT\x00\x01");
+
+        let mut wrapped = Vec::new();
+        Header::versioned(FORMAT_WRAPPED).write_to(&mut wrapped);
+        assert_eq!(wrapped, b"GITCRYPT\x00\x02");
+        assert_eq!(Header::versioned(FORMAT_WRAPPED).len(), 10);
+    }
+
+    #[test]
+    fn test_header_parse_round_trip() {
+        for header in [
+            Header::LEGACY,
+            Header::versioned(FORMAT_PADDED),
+            Header::versioned(FORMAT_WRAPPED),
+        ] {
+            let blob = frame(header, &[b"body"]);
+            assert_eq!(Header::parse(&blob), Some((header, &b"body"[..])));
+        }
+
+        // Unknown versions and missing magic
+        assert_eq!(
+            Header::parse(b"GITCRYPT\x00\x7fbody"),
+            Some((Header::LEGACY, &b"\x00\x7fbody"[..]))
+        );
+        assert_eq!(Header::parse(b"NOTCRYPT"), None);
+    }
+
+    #[test]
+    fn test_blob_byte_layout_is_pinned() {
+        let key = CryptoKey::generate();
+
+        let legacy = key.encrypt(b"abc").unwrap();
+        assert_eq!(&legacy[..8], b"GITCRYPT");
+        assert_eq!(legacy.len(), 8 + NONCE_SIZE + 3 + TAG_SIZE);
+
+        let padded = key.encrypt_padded(b"abc", 16).unwrap();
+        assert_eq!(&padded[..10], b"GITCRYPT\x00\x01");
+        assert_eq!(padded.len(), 10 + NONCE_SIZE + 16 + TAG_SIZE);
+
+        // Wrapped: header, then a 60-byte wrapped key, then the content
+        let wrapped = key.encrypt_wrapped(b"abc").unwrap();
+        assert_eq!(&wrapped[..10], b"GITCRYPT\x00\x02");
+        assert_eq!(WRAPPED_KEY_LEN, 60);
+        assert_eq!(wrapped.len(), 10 + 60 + NONCE_SIZE + 3 + TAG_SIZE);
+        let content_key = key.open(Cipher::Aes256Gcm, &wrapped[10..70], &[]).unwrap();
+        let content_key = CryptoKey::from_bytes(&content_key).unwrap();
+        assert_eq!(
+            content_key
+                .open(Cipher::Aes256Gcm, &wrapped[70..], &[])
+                .unwrap(),
+            b"abc"
+        );
+    }
+
+    #[test]
+    fn test_generation_tag_byte_layout_is_pinned() {
+        let key = CryptoKey::generate().with_generation(3);
+
+        let basic = key.encrypt(b"abc").unwrap();
+        assert_eq!(&basic[..14], b"GITCRYPT\x00\x80\x00\x00\x00\x03");
+        assert_eq!(basic.len(), 14 + NONCE_SIZE + 3 + TAG_SIZE);
+
+        let padded = key.encrypt_padded(b"abc", 16).unwrap();
+        assert_eq!(&padded[..14], b"GITCRYPT\x00\x81\x00\x00\x00\x03");
+
+        let wrapped = key.encrypt_wrapped(b"abc").unwrap();
+        assert_eq!(&wrapped[..14], b"GITCRYPT\x00\x82\x00\x00\x00\x03");
+        assert_eq!(wrapped.len(), 14 + 60 + NONCE_SIZE + 3 + TAG_SIZE);
+    }
+
+    #[test]
+    fn test_generation_tagged_round_trip() {
+        let key = CryptoKey::generate().with_generation(7);
+        let untagged = CryptoKey::from_bytes(key.as_bytes()).unwrap();
+
+        for blob in [
+            key.encrypt(b"secret").unwrap(),
+            key.encrypt_padded(b"secret", 64).unwrap(),
+            key.encrypt_wrapped(b"secret").unwrap(),
+        ] {
+            assert_eq!(CryptoKey::generation_of(&blob), Some(7));
+            // The generation only affects encryption
+            assert_eq!(untagged.decrypt(&blob).unwrap(), b"secret");
+        }
+
+        assert_eq!(
+            CryptoKey::generation_of(&untagged.encrypt(b"x").unwrap()),
+            None
+        );
+        assert_eq!(CryptoKey::generation_of(b"plaintext"), None);
+    }
+
+    #[test]
+    fn test_encrypt_deterministic_round_trip() {
+        let key = CryptoKey::generate();
+        for plaintext in [&b""[..], b"secret", &[0xAB; 100_000]] {
+            let blob = key.encrypt_deterministic(plaintext).unwrap();
+            assert!(CryptoKey::is_encrypted(&blob));
+            assert_eq!(key.decrypt(&blob).unwrap(), plaintext);
+        }
+
+        let chacha = key.clone().with_cipher(Cipher::ChaCha20Poly1305);
+        let blob = chacha.encrypt_deterministic(b"secret").unwrap();
+        assert_eq!(CryptoKey::cipher_of(&blob), Some(Cipher::ChaCha20Poly1305));
+        assert_eq!(key.decrypt(&blob).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_encrypt_deterministic_is_reproducible() {
+        let key = CryptoKey::generate();
+        let first = key.encrypt_deterministic(b"same content").unwrap();
+        assert_eq!(first, key.encrypt_deterministic(b"same content").unwrap());
+        assert_ne!(first, key.encrypt_deterministic(b"other content").unwrap());
+        // The nonce depends on the key, not just the content
+        assert_ne!(
+            first,
+            CryptoKey::generate()
+                .encrypt_deterministic(b"same content")
+                .unwrap()
+        );
+        assert_ne!(key.encrypt(b"same content").unwrap(), first);
+    }
+
+    #[test]
+    fn test_generation_tag_is_authenticated() {
+        let key = CryptoKey::generate().with_generation(2);
+
+        for mut blob in [
+            key.encrypt(b"secret").unwrap(),
+            key.encrypt_wrapped(b"secret").unwrap(),
+        ] {
+            blob[13] = 3;
+            assert_eq!(CryptoKey::generation_of(&blob), Some(3));
+            assert!(key.decrypt(&blob).is_err());
+        }
+    }
+
+    #[test]
+    fn test_format_of_reads_header_version() {
+        let key = CryptoKey::generate();
+        // A legacy nonce may itself look like a version header; skip those
+        let legacy = std::iter::repeat_with(|| key.encrypt(b"x").unwrap())
+            .find(|blob| blob[MAGIC_HEADER.len()] != VERSION_MARKER)
+            .unwrap();
+        assert_eq!(CryptoKey::format_of(&legacy), Some(0));
+        assert_eq!(
+            CryptoKey::format_of(&key.encrypt_padded(b"x", 16).unwrap()),
+            Some(FORMAT_PADDED)
+        );
+        assert_eq!(
+            CryptoKey::format_of(&key.encrypt_wrapped(b"x").unwrap()),
+            Some(FORMAT_WRAPPED)
+        );
+
+        let tagged = key.with_generation(4);
+        assert_eq!(
+            CryptoKey::format_of(&tagged.encrypt_wrapped(b"x").unwrap()),
+            Some(FORMAT_WRAPPED)
+        );
+        assert_eq!(CryptoKey::format_of(b"plaintext"), None);
+    }
+
+    #[test]
+    fn test_chacha_byte_layout_is_pinned() {
+        let key = CryptoKey::generate().with_cipher(Cipher::ChaCha20Poly1305);
+
+        let basic = key.encrypt(b"abc").unwrap();
+        assert_eq!(&basic[..10], b"GITCRYPT\x00\x40");
+        assert_eq!(basic.len(), 10 + NONCE_SIZE + 3 + TAG_SIZE);
+
+        let wrapped = key.encrypt_wrapped(b"abc").unwrap();
+        assert_eq!(&wrapped[..10], b"GITCRYPT\x00\x42");
+
+        let tagged = key.with_generation(5).encrypt_padded(b"abc", 16).unwrap();
+        assert_eq!(&tagged[..14], b"GITCRYPT\x00\xc1\x00\x00\x00\x05");
+    }
+
+    #[test]
+    fn test_chacha_round_trip_in_every_format() {
+        let key = CryptoKey::generate();
+        let chacha = key.clone().with_cipher(Cipher::ChaCha20Poly1305);
+
+        for blob in [
+            chacha.encrypt(b"secret").unwrap(),
+            chacha.encrypt_padded(b"secret", 64).unwrap(),
+            chacha.encrypt_wrapped(b"secret").unwrap(),
+            chacha
+                .clone()
+                .with_generation(2)
+                .encrypt(b"secret")
+                .unwrap(),
+        ] {
+            assert_eq!(CryptoKey::cipher_of(&blob), Some(Cipher::ChaCha20Poly1305));
+            // The header picks the cipher, not the decrypting key
+            assert_eq!(key.decrypt(&blob).unwrap(), b"secret");
+        }
+
+        let aes = key.encrypt(b"secret").unwrap();
+        assert_eq!(CryptoKey::cipher_of(&aes), Some(Cipher::Aes256Gcm));
+        assert_eq!(CryptoKey::cipher_of(b"plaintext"), None);
+    }
+
+    #[test]
+    fn test_cipher_flag_is_authenticated() {
+        let key = CryptoKey::generate().with_cipher(Cipher::ChaCha20Poly1305);
+        let mut blob = key.encrypt_padded(b"secret", 16).unwrap();
+
+        // Clearing the flag claims AES-256-GCM
+        blob[9] &= !CHACHA_FLAG;
+        assert!(key.decrypt(&blob).is_err());
+    }
+
+    #[test]
+    fn test_cipher_names_parse() {
+        for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+            assert_eq!(cipher.as_str().parse::<Cipher>().unwrap(), cipher);
+        }
+        assert_eq!(
+            "chacha20".parse::<Cipher>().unwrap(),
+            Cipher::ChaCha20Poly1305
+        );
+        assert!("des".parse::<Cipher>().is_err());
+    }
+
+    #[test]
+    fn test_rewrap_keeps_blob_cipher() {
+        let old_key = CryptoKey::generate().with_cipher(Cipher::ChaCha20Poly1305);
+        let new_key = CryptoKey::generate();
+
+        let blob = old_key.encrypt_wrapped(b"secret").unwrap();
+        let rewrapped = old_key.rewrap(&blob, &new_key).unwrap();
+        assert_eq!(
+            CryptoKey::cipher_of(&rewrapped),
+            Some(Cipher::ChaCha20Poly1305)
+        );
+        assert_eq!(new_key.decrypt(&rewrapped).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_rewrap_takes_new_key_generation() {
+        let old_key = CryptoKey::generate().with_generation(1);
+        let new_key = CryptoKey::generate().with_generation(2);
+
+        let blob = old_key.encrypt_wrapped(b"secret").unwrap();
+        let rewrapped = old_key.rewrap(&blob, &new_key).unwrap();
+        assert_eq!(CryptoKey::generation_of(&rewrapped), Some(2));
+        assert_eq!(new_key.decrypt(&rewrapped).unwrap(), b"secret");
+    }
+
+    #[test]
+    fn test_seeded_rng_yields_stable_key() {
+        let first = CryptoKey::generate_with_rng(&mut StdRng::seed_from_u64(42));
+        let second = CryptoKey::generate_with_rng(&mut StdRng::seed_from_u64(42));
+        let other = CryptoKey::generate_with_rng(&mut StdRng::seed_from_u64(43));
+
+        assert_eq!(first.as_bytes(), second.as_bytes());
+        assert_ne!(first.as_bytes(), other.as_bytes());
+
+        // The key comes straight from the RNG's output
+        let mut expected = [0u8; KEY_SIZE];
+        StdRng::seed_from_u64(42).fill_bytes(&mut expected);
+        assert_eq!(first.as_bytes(), expected);
+    }
 
     #[test]
     fn test_encrypt_decrypt() {
@@ -279,6 +1783,29 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_key_from_zero_bytes_is_rejected() {
+        let result = CryptoKey::from_bytes(&[0u8; KEY_SIZE]);
+        assert!(matches!(result, Err(GitCryptError::ZeroKey)));
+
+        let mut one_bit = [0u8; KEY_SIZE];
+        one_bit[KEY_SIZE - 1] = 1;
+        assert!(CryptoKey::from_bytes(&one_bit).is_ok());
+    }
+
+    #[test]
+    fn test_low_entropy_key_detection() {
+        assert!(!CryptoKey::generate().is_low_entropy());
+        assert!(CryptoKey::from_bytes(&[0x42u8; KEY_SIZE])
+            .unwrap()
+            .is_low_entropy());
+
+        let counting: Vec<u8> = (0..KEY_SIZE as u8).map(|i| i % 8).collect();
+        assert!(!CryptoKey::from_bytes(&counting).unwrap().is_low_entropy());
+        let counting: Vec<u8> = (0..KEY_SIZE as u8).map(|i| i % 7).collect();
+        assert!(CryptoKey::from_bytes(&counting).unwrap().is_low_entropy());
+    }
+
     #[test]
     fn test_key_roundtrip() {
         let key1 = CryptoKey::generate();
@@ -292,6 +1819,42 @@ mod tests {
         assert_eq!(plaintext.as_slice(), &decrypted[..]);
     }
 
+    #[test]
+    fn test_fingerprint_is_stable_and_not_the_key() {
+        let key = CryptoKey::from_bytes(&[0x42u8; KEY_SIZE]).unwrap();
+        let fingerprint = key.fingerprint();
+
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(fingerprint, key.clone().fingerprint());
+        assert_ne!(fingerprint, hex::encode(key.as_bytes()));
+        assert_ne!(fingerprint, CryptoKey::generate().fingerprint());
+    }
+
+    #[test]
+    fn test_check_value_is_short_and_per_key() {
+        let key = CryptoKey::from_bytes(&[0x42u8; KEY_SIZE]).unwrap();
+        let check_value = key.check_value();
+
+        assert_eq!(check_value.len(), 2 * KEY_CHECK_SIZE);
+        assert_eq!(check_value, key.clone().check_value());
+        assert!(!key.fingerprint().starts_with(&check_value));
+        assert_ne!(check_value, CryptoKey::generate().check_value());
+    }
+
+    #[test]
+    fn test_key_is_zeroed_on_drop() {
+        let mut key =
+            std::mem::ManuallyDrop::new(CryptoKey::from_bytes(&[0x42u8; KEY_SIZE]).unwrap());
+        let bytes = std::ptr::addr_of!(key.key);
+
+        // SAFETY: ManuallyDrop keeps the storage alive after the destructor
+        // runs, and every bit pattern is a valid [u8; KEY_SIZE]
+        unsafe {
+            std::mem::ManuallyDrop::drop(&mut key);
+            assert_eq!(std::ptr::read_volatile(bytes), [0u8; KEY_SIZE]);
+        }
+    }
+
     #[test]
     fn test_unicode_data() {
         let key = CryptoKey::generate();
@@ -316,4 +1879,314 @@ mod tests {
         // First NONCE_SIZE bytes should be the nonce
         assert_eq!(&ciphertext[..NONCE_SIZE].len(), &NONCE_SIZE);
     }
+
+    #[test]
+    fn test_padded_round_trip() {
+        let key = CryptoKey::generate();
+
+        for plaintext in [&b""[..], b"a", b"secret", &[0u8; 300][..], &[0x80u8; 3][..]] {
+            let ciphertext = key.encrypt_padded(plaintext, 256).unwrap();
+            assert!(CryptoKey::is_encrypted(&ciphertext));
+            assert_eq!(key.decrypt(&ciphertext).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_padded_sizes_are_normalized() {
+        let key = CryptoKey::generate();
+
+        let empty = key.encrypt_padded(b"", 64).unwrap();
+        let small = key.encrypt_padded(b"hunter2", 64).unwrap();
+        let block_minus_one = key.encrypt_padded(&[0x42; 63], 64).unwrap();
+        assert_eq!(empty.len(), small.len());
+        assert_eq!(empty.len(), block_minus_one.len());
+
+        // A full block needs another block for the delimiter
+        let full_block = key.encrypt_padded(&[0x42; 64], 64).unwrap();
+        assert_eq!(full_block.len(), empty.len() + 64);
+    }
+
+    #[test]
+    fn test_padded_header_is_versioned() {
+        let key = CryptoKey::generate();
+        let ciphertext = key.encrypt_padded(b"data", 16).unwrap();
+
+        assert_eq!(&ciphertext[..MAGIC_HEADER.len()], MAGIC_HEADER);
+        assert_eq!(
+            &ciphertext[MAGIC_HEADER.len()..MAGIC_HEADER.len() + 2],
+            &[VERSION_MARKER, FORMAT_PADDED]
+        );
+    }
+
+    #[test]
+    fn test_padded_zero_block_size_rejected() {
+        let key = CryptoKey::generate();
+        assert!(key.encrypt_padded(b"data", 0).is_err());
+    }
+
+    #[test]
+    fn test_unpad_rejects_missing_delimiter() {
+        assert!(unpad(&[0x41, 0x00, 0x00]).is_err());
+        assert!(unpad(&[0x00; 4]).is_err());
+        assert_eq!(unpad(&[0x41, 0x80, 0x00]).unwrap(), b"A");
+    }
+
+    #[test]
+    fn test_legacy_nonce_resembling_version_marker() {
+        let key = CryptoKey::generate();
+        let cipher = Aes256Gcm::new_from_slice(key.as_bytes()).unwrap();
+
+        // Legacy blob whose random nonce happens to start with the version marker
+        let mut nonce_bytes = [0x11u8; NONCE_SIZE];
+        nonce_bytes[0] = VERSION_MARKER;
+        nonce_bytes[1] = FORMAT_PADDED;
+        let sealed = cipher
+            .encrypt(Nonce::from_slice(&nonce_bytes), b"legacy".as_slice())
+            .unwrap();
+
+        let mut blob = MAGIC_HEADER.to_vec();
+        blob.extend_from_slice(&nonce_bytes);
+        blob.extend_from_slice(&sealed);
+
+        assert_eq!(key.decrypt(&blob).unwrap(), b"legacy");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip_as_base64() {
+        let key = CryptoKey::from_bytes(&[0x42u8; KEY_SIZE]).unwrap();
+
+        let json = serde_json::to_string(&key).unwrap();
+        assert_eq!(json, "\"QkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkJCQkI=\"");
+
+        let decoded: CryptoKey = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.as_bytes(), key.as_bytes());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_wrong_length() {
+        // 16 bytes of base64-encoded data
+        match serde_json::from_str::<CryptoKey>("\"QkJCQkJCQkJCQkJCQkJCQg==\"") {
+            Err(err) => assert!(err.to_string().contains("invalid key length")),
+            Ok(_) => panic!("16-byte key should be rejected"),
+        }
+
+        assert!(serde_json::from_str::<CryptoKey>("\"not base64!\"").is_err());
+        assert!(serde_json::from_str::<CryptoKey>("[66, 66]").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_rejects_zero_key() {
+        // 32 zero bytes, base64-encoded
+        match serde_json::from_str::<CryptoKey>("\"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=\"")
+        {
+            Err(err) => {
+                assert!(err.to_string().contains("all bytes are zero"), "{err}");
+                assert!(!err.to_string().contains("invalid key length"), "{err}");
+            }
+            Ok(_) => panic!("all-zero key should be rejected"),
+        }
+    }
+
+    #[test]
+    fn test_wrapped_round_trip() {
+        let key = CryptoKey::generate();
+
+        for plaintext in [&b""[..], b"secret", &[0xA5u8; 100_000][..]] {
+            let ciphertext = key.encrypt_wrapped(plaintext).unwrap();
+            assert!(CryptoKey::is_encrypted(&ciphertext));
+            assert_eq!(
+                &ciphertext[MAGIC_HEADER.len()..MAGIC_HEADER.len() + 2],
+                &[VERSION_MARKER, FORMAT_WRAPPED]
+            );
+            assert_eq!(
+                ciphertext.len(),
+                MAGIC_HEADER.len() + 2 + WRAPPED_KEY_LEN + NONCE_SIZE + plaintext.len() + TAG_SIZE
+            );
+            assert_eq!(key.decrypt(&ciphertext).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_wrapped_wrong_key_fails() {
+        let key = CryptoKey::generate();
+        let other = CryptoKey::generate();
+
+        let ciphertext = key.encrypt_wrapped(b"secret").unwrap();
+        assert!(other.decrypt(&ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_rewrap_only_rewrites_header() {
+        let old_key = CryptoKey::generate();
+        let new_key = CryptoKey::generate();
+        let plaintext = b"large file contents";
+
+        let ciphertext = old_key.encrypt_wrapped(plaintext).unwrap();
+        let rewrapped = old_key.rewrap(&ciphertext, &new_key).unwrap();
+
+        // Content ciphertext after the key header is untouched
+        let header_len = MAGIC_HEADER.len() + 2 + WRAPPED_KEY_LEN;
+        assert_eq!(ciphertext.len(), rewrapped.len());
+        assert_eq!(&ciphertext[header_len..], &rewrapped[header_len..]);
+        assert_ne!(&ciphertext[..header_len], &rewrapped[..header_len]);
+
+        assert_eq!(new_key.decrypt(&rewrapped).unwrap(), plaintext);
+        assert!(old_key.decrypt(&rewrapped).is_err());
+    }
+
+    #[test]
+    fn test_rewrap_rejects_other_formats_and_keys() {
+        let key = CryptoKey::generate();
+        let new_key = CryptoKey::generate();
+
+        let legacy = key.encrypt(b"legacy").unwrap();
+        assert!(key.rewrap(&legacy, &new_key).is_err());
+
+        let wrapped = key.encrypt_wrapped(b"wrapped").unwrap();
+        assert!(new_key.rewrap(&wrapped, &key).is_err());
+    }
+
+    #[test]
+    fn test_wrapped_tampered_key_header_fails() {
+        let key = CryptoKey::generate();
+        let mut ciphertext = key.encrypt_wrapped(b"secret").unwrap();
+
+        let wrapped_key_byte = MAGIC_HEADER.len() + 2 + NONCE_SIZE;
+        ciphertext[wrapped_key_byte] ^= 0xFF;
+        assert!(key.decrypt(&ciphertext).is_err());
+    }
+
+    fn encrypt_stream_to_vec(key: &CryptoKey, plaintext: &[u8]) -> Vec<u8> {
+        let mut blob = Vec::new();
+        key.encrypt_stream(plaintext, &mut blob).unwrap();
+        blob
+    }
+
+    fn decrypt_stream_to_vec(key: &CryptoKey, blob: &[u8]) -> Result<Vec<u8>> {
+        let mut plaintext = Vec::new();
+        key.decrypt_stream(blob, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    /// Split a stream blob into header + prefix, length-prefixed frames, and
+    /// terminator + trailer
+    fn split_stream(blob: &[u8]) -> (Vec<u8>, Vec<Vec<u8>>, Vec<u8>) {
+        let start = Header::versioned(FORMAT_STREAM).len() + STREAM_PREFIX_SIZE;
+        let mut frames = Vec::new();
+        let mut pos = start;
+        loop {
+            let len = u32::from_be_bytes(blob[pos..pos + 4].try_into().unwrap()) as usize;
+            if len == 0 {
+                break;
+            }
+            frames.push(blob[pos..pos + 4 + len].to_vec());
+            pos += 4 + len;
+        }
+        (blob[..start].to_vec(), frames, blob[pos..].to_vec())
+    }
+
+    #[test]
+    fn test_stream_layout() {
+        let key = CryptoKey::generate();
+        let plaintext = vec![7u8; STREAM_FRAME_SIZE + 10];
+        let blob = encrypt_stream_to_vec(&key, &plaintext);
+
+        assert!(blob.starts_with(b"GITCRYPT\x00\x03"));
+        let (head, frames, tail) = split_stream(&blob);
+        assert_eq!(head.len(), 10 + STREAM_PREFIX_SIZE);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].len(), 4 + STREAM_FRAME_SIZE + TAG_SIZE);
+        assert_eq!(frames[1].len(), 4 + 10 + TAG_SIZE);
+        assert_eq!(tail.len(), 4 + STREAM_MAC_SIZE);
+        assert_eq!(CryptoKey::format_of(&blob), Some(FORMAT_STREAM));
+    }
+
+    #[test]
+    fn test_stream_round_trips() {
+        let key = CryptoKey::generate();
+        let chacha = key
+            .clone()
+            .with_generation(4)
+            .with_cipher(Cipher::ChaCha20Poly1305);
+        for size in [0, 1, STREAM_FRAME_SIZE, 3 * STREAM_FRAME_SIZE + 5] {
+            let plaintext: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+            for key in [&key, &chacha] {
+                let blob = encrypt_stream_to_vec(key, &plaintext);
+                assert_eq!(decrypt_stream_to_vec(key, &blob).unwrap(), plaintext);
+                // Whole-blob decryption (the smudge filter) reads streams too
+                assert_eq!(key.decrypt(&blob).unwrap(), plaintext);
+            }
+        }
+        let tagged = encrypt_stream_to_vec(&chacha, b"x");
+        assert_eq!(CryptoKey::generation_of(&tagged), Some(4));
+        assert_eq!(
+            CryptoKey::cipher_of(&tagged),
+            Some(Cipher::ChaCha20Poly1305)
+        );
+    }
+
+    #[test]
+    fn test_stream_detects_dropped_and_reordered_frames() {
+        let key = CryptoKey::generate();
+        let plaintext: Vec<u8> = (0..3 * STREAM_FRAME_SIZE).map(|i| i as u8).collect();
+        let blob = encrypt_stream_to_vec(&key, &plaintext);
+        let (head, frames, tail) = split_stream(&blob);
+        assert_eq!(frames.len(), 3);
+        let assemble = |frames: &[&Vec<u8>]| {
+            let mut blob = head.clone();
+            for frame in frames {
+                blob.extend_from_slice(frame);
+            }
+            blob.extend_from_slice(&tail);
+            blob
+        };
+
+        let dropped_last = assemble(&[&frames[0], &frames[1]]);
+        let err = decrypt_stream_to_vec(&key, &dropped_last).unwrap_err();
+        assert!(err.to_string().contains("Stream MAC mismatch"), "{err}");
+
+        let dropped_middle = assemble(&[&frames[0], &frames[2]]);
+        assert!(decrypt_stream_to_vec(&key, &dropped_middle).is_err());
+
+        let reordered = assemble(&[&frames[1], &frames[0], &frames[2]]);
+        let err = decrypt_stream_to_vec(&key, &reordered).unwrap_err();
+        assert!(
+            err.to_string().contains("frame 0 failed authentication"),
+            "{err}"
+        );
+
+        for tampered in [dropped_last, dropped_middle, reordered] {
+            assert!(key.decrypt(&tampered).is_err());
+        }
+        assert_eq!(
+            decrypt_stream_to_vec(&key, &assemble(&[&frames[0], &frames[1], &frames[2]])).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn test_stream_detects_truncation_and_trailing_data() {
+        let key = CryptoKey::generate();
+        let blob = encrypt_stream_to_vec(&key, b"stream me");
+
+        let err = decrypt_stream_to_vec(&key, &blob[..blob.len() - 1]).unwrap_err();
+        assert!(err.to_string().contains("truncated"), "{err}");
+
+        let mut longer = blob.clone();
+        longer.push(0);
+        assert!(decrypt_stream_to_vec(&key, &longer).is_err());
+
+        let mut forged = blob.clone();
+        let last = forged.len() - 1;
+        forged[last] ^= 1;
+        assert!(decrypt_stream_to_vec(&key, &forged).is_err());
+
+        // Only stream blobs are accepted
+        let other = key.encrypt(b"not a stream").unwrap();
+        assert!(decrypt_stream_to_vec(&key, &other).is_err());
+        assert!(decrypt_stream_to_vec(&CryptoKey::generate(), &blob).is_err());
+    }
 }
@@ -30,11 +30,193 @@ pub enum GitCryptError {
     #[error("Invalid key format")]
     InvalidKeyFormat,
 
+    /// The key bytes are all zero, as read from an empty or zero-filled file
+    #[error("Refusing an all-zero key; the key file is probably truncated or zero-filled")]
+    ZeroKey,
+
+    /// The key file no longer matches the check value recorded when it was
+    /// saved; holds the path of the check value
+    #[error(
+        "Key does not match its check value in {0}; the key file is corrupted or not \
+         this repository's key (import the right one with 'git-crypt import-key')"
+    )]
+    KeyCheckMismatch(String),
+
+    /// Smudge got an encrypted blob in a repository without a key; holds the
+    /// file being checked out
+    #[error("{0} is encrypted but no git-crypt key is available; run 'git-crypt unlock'")]
+    EncryptedWithoutKey(String),
+
     #[error("Not in a git repository")]
     NotInGitRepo,
 
+    #[error("git-crypt cannot be used in a bare repository")]
+    BareRepository,
+
+    /// A request to the sync backend failed; `status` is `None` when no
+    /// HTTP response arrived (connection failure, timeout)
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    #[allow(dead_code)]
+    #[error("Sync error: {message}")]
+    Sync {
+        status: Option<u16>,
+        message: String,
+    },
+
+    /// A configuration file could not be parsed; `location` is the path,
+    /// followed by `:line:column` when the parser reports a position
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    #[allow(dead_code)]
+    #[error("invalid config at {location}: {message}")]
+    Config { location: String, message: String },
+
     #[error("{0}")]
     Other(String),
 }
 
+/// Exit status for command-line usage errors (unknown flags, missing
+/// arguments), kept apart from the codes [`GitCryptError::exit_code`] returns
+pub const EXIT_USAGE: i32 = 64;
+
+impl GitCryptError {
+    /// Process exit status for this error, so scripts can branch on the
+    /// reason a command failed:
+    ///
+    /// | Code | Meaning |
+    /// |------|---------|
+    /// | 1 | Any other failure, including checks such as `verify` or `assert` failing |
+    /// | 2 | Not in a git repository, or in a bare one |
+    /// | 3 | git-crypt is not initialized in this repository |
+    /// | 4 | Encryption or decryption failed (wrong key, corrupted blob) |
+    /// | 5 | The key was not found, or an encrypted file needs a key that isn't installed |
+    /// | 6 | The key file is malformed, all zero, or fails its check value |
+    ///
+    /// Usage errors exit with [`EXIT_USAGE`].
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            GitCryptError::NotInGitRepo | GitCryptError::BareRepository => 2,
+            GitCryptError::NotInitialized => 3,
+            GitCryptError::Crypto(_) => 4,
+            GitCryptError::KeyNotFound(_) | GitCryptError::EncryptedWithoutKey(_) => 5,
+            GitCryptError::InvalidKeyFormat
+            | GitCryptError::ZeroKey
+            | GitCryptError::KeyCheckMismatch(_) => 6,
+            _ => 1,
+        }
+    }
+
+    /// Whether the failure is transient, so retrying the same operation may succeed.
+    ///
+    /// Network trouble, sync throttling/5xx responses and git lock contention are
+    /// retryable; bad keys, missing setup and auth failures are not.
+    #[allow(dead_code)]
+    pub fn is_retryable(&self) -> bool {
+        use std::io::ErrorKind;
+
+        match self {
+            #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+            GitCryptError::Sync { status, .. } => match status {
+                None => true,
+                Some(code) => *code == 408 || *code == 429 || *code >= 500,
+            },
+            GitCryptError::Io(e) => matches!(
+                e.kind(),
+                ErrorKind::TimedOut
+                    | ErrorKind::Interrupted
+                    | ErrorKind::WouldBlock
+                    | ErrorKind::ConnectionRefused
+                    | ErrorKind::ConnectionReset
+                    | ErrorKind::ConnectionAborted
+            ),
+            GitCryptError::Git(e) => e.code() == git2::ErrorCode::Locked,
+            _ => false,
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, GitCryptError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+
+    #[test]
+    fn transient_io_and_git_errors_are_retryable() {
+        assert!(GitCryptError::Io(io::Error::from(io::ErrorKind::TimedOut)).is_retryable());
+        assert!(GitCryptError::Io(io::Error::from(io::ErrorKind::ConnectionReset)).is_retryable());
+        assert!(!GitCryptError::Io(io::Error::from(io::ErrorKind::NotFound)).is_retryable());
+        assert!(
+            !GitCryptError::Io(io::Error::from(io::ErrorKind::PermissionDenied)).is_retryable()
+        );
+
+        let locked = git2::Error::new(git2::ErrorCode::Locked, git2::ErrorClass::Index, "locked");
+        assert!(GitCryptError::Git(locked).is_retryable());
+        let missing = git2::Error::new(git2::ErrorCode::NotFound, git2::ErrorClass::Odb, "gone");
+        assert!(!GitCryptError::Git(missing).is_retryable());
+    }
+
+    #[test]
+    fn deterministic_failures_are_not_retryable() {
+        let errors = [
+            GitCryptError::Crypto("bad tag".into()),
+            GitCryptError::Gpg("no secret key".into()),
+            GitCryptError::NotInitialized,
+            GitCryptError::AlreadyInitialized,
+            GitCryptError::KeyNotFound("default".into()),
+            GitCryptError::InvalidKeyFormat,
+            GitCryptError::ZeroKey,
+            GitCryptError::KeyCheckMismatch("default.kcv".into()),
+            GitCryptError::EncryptedWithoutKey("secret.txt".into()),
+            GitCryptError::NotInGitRepo,
+            GitCryptError::BareRepository,
+            GitCryptError::Other("anything".into()),
+        ];
+        for error in errors {
+            assert!(!error.is_retryable(), "{error}");
+        }
+
+        #[cfg(feature = "ssh")]
+        assert!(!GitCryptError::Age("no identity matched".into()).is_retryable());
+    }
+
+    #[test]
+    fn exit_codes_distinguish_failure_categories() {
+        assert_eq!(GitCryptError::NotInGitRepo.exit_code(), 2);
+        assert_eq!(GitCryptError::BareRepository.exit_code(), 2);
+        assert_eq!(GitCryptError::NotInitialized.exit_code(), 3);
+        assert_eq!(GitCryptError::Crypto("bad tag".into()).exit_code(), 4);
+        assert_eq!(GitCryptError::KeyNotFound("default".into()).exit_code(), 5);
+        assert_eq!(
+            GitCryptError::EncryptedWithoutKey("secret.txt".into()).exit_code(),
+            5
+        );
+        assert_eq!(GitCryptError::InvalidKeyFormat.exit_code(), 6);
+        assert_eq!(GitCryptError::ZeroKey.exit_code(), 6);
+        assert_eq!(
+            GitCryptError::KeyCheckMismatch("default.kcv".into()).exit_code(),
+            6
+        );
+        assert_eq!(GitCryptError::Other("anything".into()).exit_code(), 1);
+        assert_eq!(
+            GitCryptError::Io(io::Error::from(io::ErrorKind::NotFound)).exit_code(),
+            1
+        );
+    }
+
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    #[test]
+    fn sync_errors_classified_by_status() {
+        let sync = |status| GitCryptError::Sync {
+            status,
+            message: "upload failed".into(),
+        };
+        assert!(sync(None).is_retryable());
+        assert!(sync(Some(500)).is_retryable());
+        assert!(sync(Some(503)).is_retryable());
+        assert!(sync(Some(429)).is_retryable());
+        assert!(sync(Some(408)).is_retryable());
+        assert!(!sync(Some(403)).is_retryable());
+        assert!(!sync(Some(404)).is_retryable());
+    }
+}
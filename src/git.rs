@@ -1,18 +1,100 @@
-use crate::crypto::CryptoKey;
+use crate::cache::SmudgeCache;
+use crate::crypto::{Cipher, CryptoKey, FORMAT_STREAM, FORMAT_WRAPPED};
 use crate::error::{GitCryptError, Result};
-use git2::Repository;
+use crate::key;
+use crate::parallel;
+use git2::{
+    AttrCheckFlags, Config, ConfigLevel, ErrorCode, FileMode, ObjectType, Oid, Repository, Sort,
+    Tree, TreeWalkMode, TreeWalkResult,
+};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
 
 pub struct GitRepo {
     repo: Repository,
+    /// Filter driver of the key in use (`--key-name`): `git-crypt`, or
+    /// `git-crypt-<name>` for a named key
+    filter: String,
+}
+
+/// A committed file routed through the git-crypt filter by `.gitattributes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncryptedEntry {
+    pub path: String,
+    pub oid: Oid,
+}
+
+/// Generated attributes file that keeps committed git-crypt metadata
+/// (recipients, encrypted key blobs) out of the filter
+pub const METADATA_ATTRIBUTES_FILE: &str = ".git-crypt/.gitattributes";
+
+const METADATA_ATTRIBUTES: &str = "\
+# Generated by git-crypt. Do not edit: files in .git-crypt/ must never be
+# encrypted, or nobody could read the keys and recipients stored here.
+* -filter -diff
+";
+
+/// The repository-local `filter.git-crypt.*` settings, keyed by the part after
+/// the section (`clean`, `smudge`, `padding`, ...)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigSnapshot {
+    entries: BTreeMap<String, String>,
+}
+
+impl ConfigSnapshot {
+    /// Get a setting's value
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries.get(name).map(String::as_str)
+    }
+
+    /// Set a setting's value
+    pub fn set(&mut self, name: &str, value: impl Into<String>) {
+        self.entries.insert(name.to_string(), value.into());
+    }
+
+    /// Remove a setting, returning its previous value
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        self.entries.remove(name)
+    }
+
+    /// Iterate over all settings in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+/// Encryption format choices for the clean filter
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CleanOptions {
+    /// Pad plaintext to a multiple of this many bytes (`filter.git-crypt.padding`)
+    pub padding: Option<usize>,
+    /// Use a per-file content key wrapped by the repo key (`filter.git-crypt.wrapped`)
+    pub wrapped: bool,
+    /// AEAD for new blobs (`filter.git-crypt.cipher`)
+    pub cipher: Cipher,
+    /// Derive the nonce from the plaintext, so unchanged files re-encrypt to
+    /// the same blob (`filter.git-crypt.deterministic`)
+    pub deterministic: bool,
 }
 
 impl GitRepo {
     /// Open repository at the given path
     pub fn open(path: impl AsRef<Path>) -> Result<Self> {
         let repo = Repository::discover(path).map_err(|_| GitCryptError::NotInGitRepo)?;
-        Ok(Self { repo })
+        Ok(Self::with_repository(repo))
+    }
+
+    fn with_repository(repo: Repository) -> Self {
+        let filter = filter_driver(key::key_name().unwrap_or("default"));
+        Self { repo, filter }
+    }
+
+    /// Full git config name of the filter setting `name` (`clean`,
+    /// `padding`, ...) of the key in use, e.g. `filter.git-crypt.clean`
+    pub fn filter_setting(&self, name: &str) -> String {
+        format!("filter.{}.{name}", self.filter)
     }
 
     /// Get the git directory path
@@ -20,97 +102,1455 @@ impl GitRepo {
         self.repo.path()
     }
 
+    /// Load this repository's key, as the filters would
+    pub fn load_key(&self) -> Result<CryptoKey> {
+        key::KeyManager::new(self.git_dir()).load_key()
+    }
+
     /// Configure git filters for git-crypt
     pub fn configure_filters(&self) -> Result<()> {
-        let mut config = self.repo.config()?;
+        self.write_config_snapshot(&self.configured_snapshot()?)?;
+        Ok(())
+    }
+
+    /// Add whichever filter settings [`Self::configure_filters`] writes are
+    /// missing, keeping any that are set (possibly to a custom command).
+    /// Returns whether anything was added.
+    pub fn repair_filters(&self) -> Result<bool> {
+        let current = self.config_snapshot()?;
+        let mut repaired = current.clone();
+        for (name, value) in self.configured_snapshot()?.iter() {
+            if current.get(name).is_none() {
+                repaired.set(name, value);
+            }
+        }
+        if repaired == current {
+            return Ok(false);
+        }
+        self.write_config_snapshot(&repaired)?;
+        Ok(true)
+    }
 
-        // Set up clean filter (encrypts on add/commit)
-        config.set_str("filter.git-crypt.clean", "git-crypt clean")?;
+    /// The current `filter.git-crypt.*` settings with the filters configured,
+    /// i.e. what [`Self::configure_filters`] would write
+    pub fn configured_snapshot(&self) -> Result<ConfigSnapshot> {
+        let mut snapshot = self.config_snapshot()?;
+
+        // Named keys pass their name on, so the filters load the right key,
+        // and a moved key file or directory its path, so they look in the
+        // same place
+        let mut key_name = match key::key_name() {
+            Some(name) => format!(" --key-name {name}"),
+            None => String::new(),
+        };
+        if let Some(path) = key::key_path_override() {
+            key_name.push_str(&format!(
+                " --repo-key-path {}",
+                shell_quote(&path.display().to_string())
+            ));
+        }
+        if let Some(dir) = key::key_dir_override() {
+            key_name.push_str(&format!(
+                " --keydir {}",
+                shell_quote(&dir.display().to_string())
+            ));
+        }
+
+        // Set up clean filter (encrypts on add/commit); git passes the path
+        // as %f so errors can name the file
+        snapshot.set("clean", format!("git-crypt clean{key_name} -- %f"));
 
         // Set up smudge filter (decrypts on checkout)
-        config.set_str("filter.git-crypt.smudge", "git-crypt smudge")?;
+        snapshot.set("smudge", format!("git-crypt smudge{key_name} -- %f"));
 
         // Don't diff encrypted files
-        config.set_str("filter.git-crypt.diff", "git-crypt diff")?;
+        snapshot.set("diff", format!("git-crypt diff{key_name}"));
 
         // Required attribute
-        config.set_bool("filter.git-crypt.required", true)?;
+        snapshot.set("required", "true");
 
-        Ok(())
+        Ok(snapshot)
     }
 
     /// Remove git-crypt filters
     pub fn remove_filters(&self) -> Result<()> {
-        let mut config = self.repo.config()?;
+        let mut snapshot = self.config_snapshot()?;
 
-        let _ = config.remove("filter.git-crypt.clean");
-        let _ = config.remove("filter.git-crypt.smudge");
-        let _ = config.remove("filter.git-crypt.diff");
-        let _ = config.remove("filter.git-crypt.required");
+        for name in ["clean", "smudge", "diff", "required"] {
+            snapshot.remove(name);
+        }
 
+        self.write_config_snapshot(&snapshot)?;
         Ok(())
     }
 
+    /// Read the repository-local `filter.git-crypt.*` settings
+    pub fn config_snapshot(&self) -> Result<ConfigSnapshot> {
+        read_snapshot(&self.local_config()?, &self.filter)
+    }
+
+    /// Make the repository-local `filter.git-crypt.*` settings match `snapshot`.
+    ///
+    /// Only settings that differ are written. If any write fails, the settings
+    /// present before the call are restored so the repository is never left
+    /// half-configured. Returns those prior settings as a backup.
+    pub fn write_config_snapshot(&self, snapshot: &ConfigSnapshot) -> Result<ConfigSnapshot> {
+        let mut config = self.local_config()?;
+        let previous = read_snapshot(&config, &self.filter)?;
+
+        if let Err(e) = apply_snapshot(&mut config, &self.filter, &previous, snapshot) {
+            // Best effort: put back whatever we managed to change
+            let partial = read_snapshot(&config, &self.filter).unwrap_or_else(|_| snapshot.clone());
+            let _ = apply_snapshot(&mut config, &self.filter, &partial, &previous);
+            return Err(e);
+        }
+
+        Ok(previous)
+    }
+
+    fn local_config(&self) -> Result<Config> {
+        Ok(self.repo.config()?.open_level(ConfigLevel::Local)?)
+    }
+
+    /// Check whether the git-crypt filters are configured
+    pub fn filters_configured(&self) -> Result<bool> {
+        let config = self.repo.config()?;
+        Ok(config.get_string(&self.filter_setting("clean")).is_ok()
+            && config.get_string(&self.filter_setting("smudge")).is_ok())
+    }
+
+    /// Whether clean and smudge filters are configured for the key `name`,
+    /// in the section upstream git-crypt uses for it (see [`filter_section`])
+    pub fn key_filters_configured(&self, name: &str) -> Result<bool> {
+        let config = self.repo.config()?;
+        let section = filter_section(name);
+        Ok(config.get_string(&format!("{section}.clean")).is_ok()
+            && config.get_string(&format!("{section}.smudge")).is_ok())
+    }
+
+    /// Cipher the key `name` encrypts new blobs with: its section's `cipher`
+    /// setting, defaulting to AES-256-GCM
+    pub fn key_cipher(&self, name: &str) -> Result<Cipher> {
+        let key = format!("{}.cipher", filter_section(name));
+        match self.repo.config()?.get_string(&key) {
+            Ok(cipher) => cipher.parse(),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(Cipher::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Read clean filter options from `filter.git-crypt.*` config
+    pub fn clean_options(&self) -> Result<CleanOptions> {
+        let config = self.repo.config()?;
+        let padding_setting = self.filter_setting("padding");
+        let wrapped_setting = self.filter_setting("wrapped");
+
+        let padding = match config.get_i64(&padding_setting) {
+            Ok(size) if size > 0 => Some(size as usize),
+            Ok(size) => {
+                return Err(GitCryptError::Other(format!(
+                    "Invalid {padding_setting} value: {size}"
+                )))
+            }
+            Err(e) if e.code() == ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+
+        let wrapped = match config.get_bool(&wrapped_setting) {
+            Ok(wrapped) => wrapped,
+            Err(e) if e.code() == ErrorCode::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+
+        if wrapped && padding.is_some() {
+            return Err(GitCryptError::Other(format!(
+                "{padding_setting} cannot be combined with {wrapped_setting}"
+            )));
+        }
+
+        let deterministic_setting = self.filter_setting("deterministic");
+        let deterministic = match config.get_bool(&deterministic_setting) {
+            Ok(deterministic) => deterministic,
+            Err(e) if e.code() == ErrorCode::NotFound => false,
+            Err(e) => return Err(e.into()),
+        };
+        // Both other formats seal with randomness of their own
+        if deterministic && (wrapped || padding.is_some()) {
+            let other = if wrapped {
+                wrapped_setting
+            } else {
+                padding_setting
+            };
+            return Err(GitCryptError::Other(format!(
+                "{deterministic_setting} cannot be combined with {other}"
+            )));
+        }
+
+        let cipher = match config.get_string(&self.filter_setting("cipher")) {
+            Ok(cipher) => cipher.parse()?,
+            Err(e) if e.code() == ErrorCode::NotFound => Cipher::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(CleanOptions {
+            padding,
+            wrapped,
+            cipher,
+            deterministic,
+        })
+    }
+
+    /// Whether git config (local, global or system) sets `name`
+    pub fn config_has(&self, name: &str) -> Result<bool> {
+        match self.repo.config()?.get_entry(name) {
+            Ok(_) => Ok(true),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Size cap of the smudge cache from `filter.git-crypt.smudgecache`, or
+    /// `None` when the cache is disabled
+    pub fn smudge_cache_size(&self) -> Result<Option<u64>> {
+        let setting = self.filter_setting("smudgecache");
+        match self.repo.config()?.get_i64(&setting) {
+            Ok(size) if size > 0 => Ok(Some(size as u64)),
+            Ok(0) => Ok(None),
+            Ok(size) => Err(GitCryptError::Other(format!(
+                "Invalid {setting} value: {size}"
+            ))),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether `git-crypt.notify` asks for desktop notifications on lock/unlock
+    #[cfg_attr(not(feature = "notify"), allow(dead_code))]
+    pub fn notify_enabled(&self) -> Result<bool> {
+        match self.repo.config()?.get_bool("git-crypt.notify") {
+            Ok(enabled) => Ok(enabled),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List files in HEAD whose `filter` attribute is `git-crypt`
+    pub fn encrypted_head_entries(&self) -> Result<Vec<EncryptedEntry>> {
+        match self.head_tree()? {
+            Some(tree) => self.encrypted_tree_entries(&tree),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Tree of HEAD, or `None` while HEAD is unborn
+    fn head_tree(&self) -> Result<Option<Tree<'_>>> {
+        match self.repo.head() {
+            Ok(head) => Ok(Some(head.peel_to_tree()?)),
+            Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// List files in `commit` whose `filter` attribute is `git-crypt`.
+    ///
+    /// Attributes come from the current checkout, not from `commit`, so files
+    /// committed before they were marked for encryption are still listed.
+    pub fn encrypted_commit_entries(&self, commit: Oid) -> Result<Vec<EncryptedEntry>> {
+        self.encrypted_tree_entries(&self.repo.find_commit(commit)?.tree()?)
+    }
+
+    /// Commits reachable from HEAD, newest first (empty for an unborn HEAD)
+    pub fn head_history(&self) -> Result<Vec<Oid>> {
+        let mut revwalk = self.repo.revwalk()?;
+        revwalk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+        match revwalk.push_head() {
+            Ok(()) => {}
+            Err(e) if matches!(e.code(), ErrorCode::UnbornBranch | ErrorCode::NotFound) => {
+                return Ok(Vec::new())
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(revwalk.collect::<std::result::Result<_, _>>()?)
+    }
+
+    fn encrypted_tree_entries(&self, tree: &Tree) -> Result<Vec<EncryptedEntry>> {
+        let mut entries = Vec::new();
+        let mut attr_error = None;
+        tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+            // Symlinks never go through the filter, whatever the attributes say
+            if entry.kind() != Some(ObjectType::Blob)
+                || entry.filemode() == i32::from(FileMode::Link)
+            {
+                return TreeWalkResult::Ok;
+            }
+            let Some(name) = entry.name() else {
+                return TreeWalkResult::Ok;
+            };
+
+            let path = format!("{root}{name}");
+            match self.is_filtered(&path) {
+                Ok(true) => entries.push(EncryptedEntry {
+                    path,
+                    oid: entry.id(),
+                }),
+                Ok(false) => {}
+                Err(e) => {
+                    attr_error = Some(e);
+                    return TreeWalkResult::Abort;
+                }
+            }
+            TreeWalkResult::Ok
+        })?;
+
+        match attr_error {
+            Some(e) => Err(e),
+            None => Ok(entries),
+        }
+    }
+
+    /// The checked-out submodule that contains `path` (relative to the
+    /// repository root).
+    ///
+    /// Nested submodules are followed to the innermost one. Returns `None`
+    /// when `path` belongs to this repository or the submodule isn't checked out.
+    pub fn submodule_containing(&self, path: &Path) -> Result<Option<GitRepo>> {
+        // A bare repository has no checked-out submodules
+        if self.is_bare() {
+            return Ok(None);
+        }
+        for submodule in self.repo.submodules()? {
+            let Ok(rest) = path.strip_prefix(submodule.path()) else {
+                continue;
+            };
+            if rest.as_os_str().is_empty() {
+                continue;
+            }
+            let Ok(repo) = submodule.open() else {
+                return Ok(None);
+            };
+
+            let inner = GitRepo::with_repository(repo);
+            return Ok(Some(inner.submodule_containing(rest)?.unwrap_or(inner)));
+        }
+        Ok(None)
+    }
+
+    /// Whether the attributes route `path` through the filter of the key in
+    /// use (`filter=git-crypt`, or `filter=git-crypt-<name>` for a named key)
+    pub fn is_filtered(&self, path: &str) -> Result<bool> {
+        Ok(self
+            .repo
+            .get_attr(Path::new(path), "filter", AttrCheckFlags::default())?
+            == Some(self.filter.as_str()))
+    }
+
+    /// Name of the key whose filter the attributes give `path` (`default`
+    /// for `filter=git-crypt`, `<name>` for `filter=git-crypt-<name>`), or
+    /// `None` if git-crypt doesn't handle it
+    pub fn filter_key_name(&self, path: &str) -> Result<Option<String>> {
+        Ok(self
+            .repo
+            .get_attr(Path::new(path), "filter", AttrCheckFlags::default())?
+            .and_then(key_name_of_driver)
+            .map(str::to_string))
+    }
+
+    /// Every path in the index with the key that encrypts it, as
+    /// [`Self::filter_key_name`] resolves it, whichever key is in use
+    pub fn classify_index(&self) -> Result<Vec<(String, Option<String>)>> {
+        let index = self.repo.index()?;
+        index
+            .iter()
+            .map(|entry| {
+                let path = String::from_utf8_lossy(&entry.path).into_owned();
+                let key_name = self.filter_key_name(&path)?;
+                Ok((path, key_name))
+            })
+            .collect()
+    }
+
+    /// Resolve `path`, given relative to the current directory, to a
+    /// `/`-separated path relative to the repository root
+    pub fn repo_relative(&self, path: &Path) -> Result<String> {
+        let root = self.workdir()?.canonicalize()?;
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            cwd.join(path)
+        };
+
+        // Resolve `.` and `..` lexically: the file may not exist in the worktree
+        let mut normalized = PathBuf::new();
+        for component in absolute.components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    normalized.pop();
+                }
+                other => normalized.push(other),
+            }
+        }
+
+        let relative = normalized.strip_prefix(&root).map_err(|_| {
+            GitCryptError::Other(format!("{} is outside the repository", path.display()))
+        })?;
+        Ok(relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    /// Stored bytes of `path` (relative to the repository root) in HEAD, or
+    /// `None` if HEAD doesn't track a file there
+    pub fn head_blob_for_path(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let Some(tree) = self.head_tree()? else {
+            return Ok(None);
+        };
+        match tree.get_path(Path::new(path)) {
+            Ok(entry) if entry.kind() == Some(ObjectType::Blob) => {
+                self.read_blob(entry.id()).map(Some)
+            }
+            Ok(_) => Ok(None),
+            Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Overwrite the working-tree copies of `paths` with their blobs in HEAD
+    /// exactly as stored, and refresh their index entries to match.
+    ///
+    /// Nothing is filtered, so once the filters are removed this leaves
+    /// encrypted files as ciphertext. Paths HEAD doesn't track are skipped.
+    pub fn checkout_stored(&self, paths: &[String]) -> Result<()> {
+        let workdir = self.workdir()?;
+        let mut index = self.repo.index()?;
+        for path in paths {
+            let Some(blob) = self.head_blob_for_path(path)? else {
+                continue;
+            };
+            fs::write(workdir.join(path), blob)?;
+            index.add_path(Path::new(path))?;
+        }
+        index.write()?;
+        Ok(())
+    }
+
+    /// Blob id of `path` (relative to the repository root) in the index, if staged
+    pub fn index_blob(&self, path: &str) -> Result<Option<Oid>> {
+        Ok(self
+            .repo
+            .index()?
+            .get_path(Path::new(path), 0)
+            .map(|e| e.id))
+    }
+
+    /// Paths and blob ids of every file in the index
+    pub fn index_entries(&self) -> Result<Vec<(String, Oid)>> {
+        let index = self.repo.index()?;
+        Ok(index
+            .iter()
+            .map(|entry| (String::from_utf8_lossy(&entry.path).into_owned(), entry.id))
+            .collect())
+    }
+
+    /// Re-stage tracked `paths` through the configured filters
+    /// (`git add --renormalize`), so plaintext blobs get encrypted
+    pub fn restage(&self, paths: &[String]) -> Result<()> {
+        self.git_add(&["--renormalize"], paths)
+    }
+
+    /// Like [`Self::restage`], but encrypt the working-tree files with `key`
+    /// in-process on up to `threads` workers and write the blobs straight
+    /// into the index, instead of one clean filter process per file.
+    ///
+    /// Paths that aren't staged regular files in the working tree are left
+    /// to [`Self::restage`].
+    pub fn restage_with_key(
+        &self,
+        paths: &[String],
+        key: &CryptoKey,
+        threads: usize,
+    ) -> Result<()> {
+        let options = self.clean_options()?;
+        let workdir = self.workdir()?;
+        let mut index = self.repo.index()?;
+
+        let (direct, fallback): (Vec<&String>, Vec<&String>) = paths.iter().partition(|path| {
+            index.get_path(Path::new(path.as_str()), 0).is_some()
+                && fs::symlink_metadata(workdir.join(path)).is_ok_and(|meta| meta.is_file())
+        });
+
+        // git2 repositories can't be shared across threads, so each worker opens its own
+        let git_dir = self.git_dir();
+        let blobs = parallel::map_bounded(
+            &direct,
+            threads,
+            || Repository::open(git_dir),
+            |worker_repo, path| -> Result<Oid> {
+                let repo = worker_repo
+                    .as_ref()
+                    .map_err(|e| GitCryptError::Other(e.to_string()))?;
+                let mut blob = Vec::new();
+                clean_stream(key, &options, File::open(workdir.join(path))?, &mut blob)?;
+                Ok(repo.blob(&blob)?)
+            },
+        );
+
+        for (path, blob) in direct.iter().zip(blobs) {
+            let Some(mut entry) = index.get_path(Path::new(path.as_str()), 0) else {
+                continue;
+            };
+            entry.id = blob?;
+            index.add(&entry)?;
+        }
+        index.write()?;
+
+        let fallback: Vec<String> = fallback.into_iter().cloned().collect();
+        self.restage(&fallback)
+    }
+
+    /// Stage each of `entries` with its HEAD blob rewrapped from `old_key` to
+    /// `new_key` (see [`CryptoKey::rewrap`]): only the key header changes, the
+    /// content ciphertext is kept byte for byte.
+    ///
+    /// That only applies while new blobs are wrapped too, in the blob's
+    /// cipher, and while the index still holds the HEAD blob. Returns the
+    /// paths it left alone, for [`Self::restage_with_key`].
+    pub fn rewrap_with_key(
+        &self,
+        entries: &[EncryptedEntry],
+        old_key: &CryptoKey,
+        new_key: &CryptoKey,
+        threads: usize,
+    ) -> Result<Vec<String>> {
+        let options = self.clean_options()?;
+        if !options.wrapped {
+            return Ok(entries.iter().map(|entry| entry.path.clone()).collect());
+        }
+        let mut index = self.repo.index()?;
+
+        let git_dir = self.git_dir();
+        let rewrapped = parallel::map_bounded(
+            entries,
+            threads,
+            || Repository::open(git_dir),
+            |worker_repo, entry| -> Result<Option<Oid>> {
+                let repo = worker_repo
+                    .as_ref()
+                    .map_err(|e| GitCryptError::Other(e.to_string()))?;
+                let blob = repo.find_blob(entry.oid)?;
+                let stored = blob.content();
+                if CryptoKey::format_of(stored) != Some(FORMAT_WRAPPED)
+                    || CryptoKey::cipher_of(stored) != Some(options.cipher)
+                {
+                    return Ok(None);
+                }
+                // Under a retired key the header can't be opened; re-encrypt instead
+                match old_key.rewrap(stored, new_key) {
+                    Ok(rewrapped) => Ok(Some(repo.blob(&rewrapped)?)),
+                    Err(_) => Ok(None),
+                }
+            },
+        );
+
+        let mut skipped = Vec::new();
+        for (entry, result) in entries.iter().zip(rewrapped) {
+            let staged = index
+                .get_path(Path::new(&entry.path), 0)
+                .filter(|staged| staged.id == entry.oid);
+            match (staged, result?) {
+                (Some(mut staged), Some(oid)) => {
+                    staged.id = oid;
+                    index.add(&staged)?;
+                }
+                _ => skipped.push(entry.path.clone()),
+            }
+        }
+        index.write()?;
+        Ok(skipped)
+    }
+
+    /// Paths whose staged blob differs from HEAD (all staged paths before
+    /// the first commit)
+    pub fn staged_paths(&self) -> Result<Vec<String>> {
+        let head = match self.repo.head() {
+            Ok(head) => Some(head.peel_to_tree()?),
+            Err(e) if e.code() == ErrorCode::UnbornBranch || e.code() == ErrorCode::NotFound => {
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let diff = self.repo.diff_tree_to_index(head.as_ref(), None, None)?;
+        Ok(diff
+            .deltas()
+            .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect())
+    }
+
+    /// Stage `paths` as they are in the working tree (`git add`)
+    pub fn stage(&self, paths: &[String]) -> Result<()> {
+        self.git_add(&[], paths)
+    }
+
+    fn git_add(&self, options: &[&str], paths: &[String]) -> Result<()> {
+        if paths.is_empty() {
+            return Ok(());
+        }
+
+        let status = std::process::Command::new("git")
+            .current_dir(self.workdir()?)
+            .arg("add")
+            .args(options)
+            .arg("--")
+            .args(paths)
+            .status()?;
+        if !status.success() {
+            let command = ["git", "add"].iter().chain(options).copied();
+            return Err(GitCryptError::Other(format!(
+                "{} failed ({status})",
+                command.collect::<Vec<_>>().join(" ")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Read the raw content of a blob
+    pub fn read_blob(&self, oid: Oid) -> Result<Vec<u8>> {
+        Ok(self.repo.find_blob(oid)?.content().to_vec())
+    }
+
+    /// Write `.git-crypt/.gitattributes` so that broad patterns like
+    /// `* filter=git-crypt` never capture git-crypt's own metadata.
+    ///
+    /// Returns whether the file was created or updated.
+    pub fn write_metadata_attributes(&self) -> Result<bool> {
+        let path = self.workdir()?.join(METADATA_ATTRIBUTES_FILE);
+        if std::fs::read_to_string(&path).is_ok_and(|existing| existing == METADATA_ATTRIBUTES) {
+            return Ok(false);
+        }
+
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, METADATA_ATTRIBUTES)?;
+        Ok(true)
+    }
+
+    /// Add `-text` to rules in the root `.gitattributes` that route files
+    /// through this key's filter without saying whether they are text.
+    ///
+    /// git converts line endings after the clean filter and before the
+    /// smudge filter, so with `core.autocrlf` or a `text`/`eol` attribute an
+    /// encrypted blob can be altered on its way in or out of the repository
+    /// and then fails authentication. Rules that set `text`, `binary` or
+    /// `eol` themselves are left alone.
+    ///
+    /// Returns the patterns of the rules that changed.
+    pub fn ensure_binary_attribute(&self) -> Result<Vec<String>> {
+        let path = self.workdir()?.join(".gitattributes");
+        let existing = match std::fs::read_to_string(&path) {
+            Ok(existing) => existing,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let (updated, patterns) = with_binary_rules(&existing, &self.filter);
+        if !patterns.is_empty() {
+            std::fs::write(&path, updated)?;
+        }
+        Ok(patterns)
+    }
+
+    /// Add git-crypt's block to `.git/hooks/pre-commit`, creating the hook or
+    /// chaining in front of an existing one. `command` runs the staged check.
+    ///
+    /// Returns whether the hook changed; installing twice is a no-op.
+    pub fn install_pre_commit_hook(&self, command: &str) -> Result<bool> {
+        let path = self.pre_commit_hook_path();
+        let existing = std::fs::read_to_string(&path).ok();
+        let updated = with_hook_block(existing.as_deref(), command);
+        if existing.as_deref() == Some(updated.as_str()) {
+            return Ok(false);
+        }
+
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, updated)?;
+        make_executable(&path)?;
+        Ok(true)
+    }
+
+    /// Take git-crypt's block out of the pre-commit hook, deleting the hook
+    /// if nothing else is left in it. Returns whether there was a block.
+    pub fn remove_pre_commit_hook(&self) -> Result<bool> {
+        let path = self.pre_commit_hook_path();
+        let Ok(existing) = std::fs::read_to_string(&path) else {
+            return Ok(false);
+        };
+        match without_hook_block(&existing) {
+            None => Ok(false),
+            Some(rest)
+                if rest
+                    .lines()
+                    .all(|l| l.trim().is_empty() || l.starts_with("#!")) =>
+            {
+                std::fs::remove_file(&path)?;
+                Ok(true)
+            }
+            Some(rest) => {
+                std::fs::write(&path, rest)?;
+                Ok(true)
+            }
+        }
+    }
+
+    fn pre_commit_hook_path(&self) -> std::path::PathBuf {
+        self.git_dir().join("hooks").join("pre-commit")
+    }
+
+    /// Whether `path` (absolute or relative to the current directory) is
+    /// excluded by the repository's ignore rules
+    pub fn is_ignored(&self, path: &Path) -> Result<bool> {
+        let workdir = self.workdir()?.canonicalize()?;
+        let absolute = match path.canonicalize() {
+            Ok(path) => path,
+            Err(_) => std::path::absolute(path)?,
+        };
+        let Ok(relative) = absolute.strip_prefix(&workdir) else {
+            return Ok(false);
+        };
+        Ok(self.repo.is_path_ignored(relative)?)
+    }
+
     /// Get repository root path
-    #[allow(dead_code)]
     pub fn workdir(&self) -> Result<&Path> {
-        self.repo.workdir().ok_or(GitCryptError::Other(
-            "Repository has no working directory".into(),
-        ))
+        self.repo.workdir().ok_or(GitCryptError::BareRepository)
+    }
+
+    /// Whether the repository has no working directory. Filters still run
+    /// there (e.g. for `git cat-file --filters`), but nothing that manages
+    /// checked-out files does.
+    pub fn is_bare(&self) -> bool {
+        self.repo.is_bare()
     }
 }
 
-/// Clean filter: encrypt file content
-pub fn clean_filter(key: &CryptoKey) -> Result<()> {
-    let mut input = Vec::new();
-    io::stdin().read_to_end(&mut input)?;
+/// Markers around git-crypt's lines in a pre-commit hook
+const HOOK_BEGIN: &str = "# >>> git-crypt: block plaintext secrets >>>";
+const HOOK_END: &str = "# <<< git-crypt <<<";
 
-    // Check if already encrypted (has magic header)
-    if CryptoKey::is_encrypted(&input) {
-        io::stdout().write_all(&input)?;
-        return Ok(());
+/// `attributes` with `-text` appended to every rule setting `filter=<filter>`
+/// that has no `text`, `binary` or `eol` setting, and those rules' patterns
+fn with_binary_rules(attributes: &str, filter: &str) -> (String, Vec<String>) {
+    let ours = format!("filter={filter}");
+    let mut patterns = Vec::new();
+    let mut lines = Vec::new();
+    for line in attributes.lines() {
+        let mut fields = line.split_whitespace();
+        let pattern = fields.next().unwrap_or_default();
+        let attrs: Vec<&str> = fields.collect();
+        let decides_text = attrs.iter().any(|attr| {
+            let name = attr.trim_start_matches(['!', '-']);
+            let name = name.split('=').next().unwrap_or(name);
+            matches!(name, "text" | "binary" | "eol")
+        });
+        if pattern.starts_with('#') || !attrs.contains(&ours.as_str()) || decides_text {
+            lines.push(line.to_string());
+            continue;
+        }
+        patterns.push(pattern.to_string());
+        lines.push(format!("{} -text", line.trim_end()));
+    }
+
+    let mut result = lines.join("\n");
+    if attributes.ends_with('\n') {
+        result.push('\n');
+    }
+    (result, patterns)
+}
+
+/// `existing` hook script with git-crypt's block inserted after the shebang
+/// (so it runs first), replacing any earlier copy of the block
+fn with_hook_block(existing: Option<&str>, command: &str) -> String {
+    let block = format!("{HOOK_BEGIN}\n{command} || exit 1\n{HOOK_END}\n");
+    let script = existing
+        .and_then(without_hook_block)
+        .or_else(|| existing.map(str::to_string))
+        .unwrap_or_else(|| "#!/bin/sh\n".to_string());
+
+    match script.split_once('\n') {
+        Some((shebang, rest)) if shebang.starts_with("#!") => format!("{shebang}\n{block}{rest}"),
+        _ => format!("#!/bin/sh\n{block}{script}"),
     }
+}
 
-    let encrypted = key.encrypt(&input)?;
+/// `existing` without git-crypt's block, or `None` if it has none
+fn without_hook_block(existing: &str) -> Option<String> {
+    let start = existing.find(HOOK_BEGIN)?;
+    let end = existing[start..].find(HOOK_END)? + start + HOOK_END.len();
+    let end = if existing[end..].starts_with('\n') {
+        end + 1
+    } else {
+        end
+    };
+    Some(format!("{}{}", &existing[..start], &existing[end..]))
+}
 
-    // Write encrypted data to stdout
-    io::stdout().write_all(&encrypted)?;
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
 
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    std::fs::set_permissions(path, permissions)?;
     Ok(())
 }
 
-/// Smudge filter: decrypt file content
-pub fn smudge_filter(key: &CryptoKey) -> Result<()> {
-    let mut input = Vec::new();
-    io::stdin().read_to_end(&mut input)?;
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<()> {
+    Ok(())
+}
 
-    // Check if encrypted
-    if !CryptoKey::is_encrypted(&input) {
-        io::stdout().write_all(&input)?;
+fn read_snapshot(config: &Config, filter: &str) -> Result<ConfigSnapshot> {
+    let mut snapshot = ConfigSnapshot::default();
+    let prefix = format!("filter.{filter}.");
+    let mut entries = config.entries(Some(&format!("^{}", prefix.replace('.', "\\."))))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        if let (Some(name), Some(value)) = (entry.name(), entry.value()) {
+            if let Some(name) = name.strip_prefix(&prefix) {
+                snapshot.set(name, value);
+            }
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Write the differences between `from` and `to` into the `filter.<filter>`
+/// section of `config`
+fn apply_snapshot(
+    config: &mut Config,
+    filter: &str,
+    from: &ConfigSnapshot,
+    to: &ConfigSnapshot,
+) -> Result<()> {
+    for (name, value) in to.iter() {
+        if from.get(name) != Some(value) {
+            config.set_str(&format!("filter.{filter}.{name}"), value)?;
+        }
+    }
+    for (name, _) in from.iter() {
+        if to.get(name).is_none() {
+            config.remove(&format!("filter.{filter}.{name}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Git config section of the filters for key `name`: `filter.git-crypt` for
+/// the `default` key, `filter.git-crypt-<name>` for named keys
+pub fn filter_section(name: &str) -> String {
+    format!("filter.{}", filter_driver(name))
+}
+
+/// Filter driver (the `filter=` attribute value) of key `name`: `git-crypt`
+/// for the `default` key, `git-crypt-<name>` for named keys
+pub fn filter_driver(name: &str) -> String {
+    match name {
+        "default" => "git-crypt".to_string(),
+        name => format!("git-crypt-{name}"),
+    }
+}
+
+/// Key name of filter driver `driver`, the inverse of [`filter_driver`];
+/// `None` for drivers that aren't git-crypt's
+pub fn key_name_of_driver(driver: &str) -> Option<&str> {
+    match driver.strip_prefix("git-crypt")? {
+        "" => Some("default"),
+        rest => rest.strip_prefix('-').filter(|name| !name.is_empty()),
+    }
+}
+
+/// Inputs larger than this are encrypted and decrypted frame by frame
+/// (format version 3) instead of being held in memory whole
+pub const STREAM_THRESHOLD: usize = 8 * 1024 * 1024;
+
+/// Clean filter: encrypt file content in the format selected by `options`
+pub fn clean_filter(key: &CryptoKey, options: &CleanOptions) -> Result<()> {
+    ignore_broken_pipe(clean_stream(
+        key,
+        options,
+        io::stdin().lock(),
+        io::stdout().lock(),
+    ))
+}
+
+/// Encrypt everything read from `reader` to `writer`, like the clean filter.
+///
+/// Inputs up to [`STREAM_THRESHOLD`] are encrypted by [`clean_content`].
+/// Larger ones are written in the stream format without buffering them,
+/// unless `options` ask for a format that needs the whole file (padding,
+/// wrapped keys, deterministic nonces).
+pub fn clean_stream(
+    key: &CryptoKey,
+    options: &CleanOptions,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<()> {
+    let head = read_head(&mut reader)?;
+
+    // Already encrypted: pass it through unchanged
+    if CryptoKey::is_encrypted(&head) {
+        writer.write_all(&head)?;
+        io::copy(&mut reader, &mut writer)?;
+        writer.flush()?;
         return Ok(());
     }
 
-    let decrypted = key.decrypt(&input)?;
+    let basic = options.padding.is_none() && !options.wrapped && !options.deterministic;
+    if head.len() > STREAM_THRESHOLD && basic {
+        let key = key.clone().with_cipher(options.cipher);
+        return key.encrypt_stream(head.as_slice().chain(reader), writer);
+    }
+
+    let mut content = head;
+    reader.read_to_end(&mut content)?;
+    writer.write_all(&clean_content(key, options, &content)?)?;
+    writer.flush()?;
+    Ok(())
+}
 
-    // Write decrypted data to stdout
-    io::stdout().write_all(&decrypted)?;
+/// Encrypt `content` exactly as the clean filter would store it
+pub fn clean_content(key: &CryptoKey, options: &CleanOptions, content: &[u8]) -> Result<Vec<u8>> {
+    if CryptoKey::is_encrypted(content) {
+        return Ok(content.to_vec());
+    }
 
+    let key = &key.clone().with_cipher(options.cipher);
+    match options.padding {
+        _ if options.wrapped => key.encrypt_wrapped(content),
+        Some(block_size) => key.encrypt_padded(content, block_size),
+        None if options.deterministic => key.encrypt_deterministic(content),
+        None => key.encrypt(content),
+    }
+}
+
+/// Smudge filter: decrypt file content with the first of `keys` that
+/// opens it, through `cache` when given
+pub fn smudge_filter(keys: &[CryptoKey], cache: Option<&SmudgeCache>) -> Result<()> {
+    ignore_broken_pipe(smudge_stream_with_keys(
+        keys,
+        cache,
+        io::stdin().lock(),
+        io::stdout().lock(),
+    ))
+}
+
+/// Decrypt everything read from `reader` to `writer`, like the smudge filter.
+///
+/// Stream-format blobs larger than [`STREAM_THRESHOLD`] are decrypted frame
+/// by frame, bypassing `cache`; everything else goes through
+/// [`smudge_content`]. A streamed blob that fails its trailer check has
+/// already written its frames, but the error still fails the checkout.
+#[allow(dead_code)]
+pub fn smudge_stream(
+    key: &CryptoKey,
+    cache: Option<&SmudgeCache>,
+    reader: impl Read,
+    writer: impl Write,
+) -> Result<()> {
+    smudge_stream_with_keys(std::slice::from_ref(key), cache, reader, writer)
+}
+
+/// Like [`smudge_stream`], but trying each of `keys` in turn, so blobs sealed
+/// before a key rotation still decrypt. AES-GCM and the stream MAC reject a
+/// wrong key, so the first key that opens a blob is the right one. If none
+/// does, the first key's error is reported.
+pub fn smudge_stream_with_keys(
+    keys: &[CryptoKey],
+    cache: Option<&SmudgeCache>,
+    mut reader: impl Read,
+    mut writer: impl Write,
+) -> Result<()> {
+    let mut head = read_head(&mut reader)?;
+
+    if head.len() > STREAM_THRESHOLD && CryptoKey::format_of(&head) == Some(FORMAT_STREAM) {
+        for key in keys {
+            let mut counted = CountingWriter {
+                inner: &mut writer,
+                written: 0,
+            };
+            match key.decrypt_stream(head.as_slice().chain(&mut reader), &mut counted) {
+                Ok(()) => return Ok(()),
+                // The first frame lies within `head`, so if it failed nothing
+                // was consumed past it: try the next key, then the whole
+                // blob, which also covers a legacy nonce that merely looks
+                // like a stream header
+                Err(_) if counted.written == 0 => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    reader.read_to_end(&mut head)?;
+    writer.write_all(&smudge_content_with_keys(keys, cache, &head)?)?;
+    writer.flush()?;
     Ok(())
 }
 
+/// Decrypt `content` as the smudge filter would, passing plaintext through.
+///
+/// The cache is best effort: failing to store an entry never fails the smudge.
+#[allow(dead_code)]
+pub fn smudge_content(
+    key: &CryptoKey,
+    cache: Option<&SmudgeCache>,
+    content: &[u8],
+) -> Result<Vec<u8>> {
+    smudge_content_with_keys(std::slice::from_ref(key), cache, content)
+}
+
+/// Like [`smudge_content`], with the first of `keys` that opens `content`
+/// (see [`smudge_stream_with_keys`])
+pub fn smudge_content_with_keys(
+    keys: &[CryptoKey],
+    cache: Option<&SmudgeCache>,
+    content: &[u8],
+) -> Result<Vec<u8>> {
+    if !CryptoKey::is_encrypted(content) {
+        return Ok(content.to_vec());
+    }
+
+    if let Some(plaintext) = cache.and_then(|cache| cache.get(content)) {
+        return Ok(plaintext);
+    }
+
+    let mut first_err = None;
+    for key in keys {
+        match key.decrypt(content) {
+            Ok(decrypted) => {
+                if let Some(cache) = cache {
+                    let _ = cache.put(content, &decrypted);
+                }
+                return Ok(decrypted);
+            }
+            Err(err) => {
+                first_err.get_or_insert(err);
+            }
+        }
+    }
+    Err(first_err.unwrap_or_else(|| GitCryptError::Crypto("No key to decrypt with".into())))
+}
+
 /// Diff filter: show that file is encrypted
+///
+/// Unencrypted text passes through; unencrypted binary data is replaced by a
+/// marker, as git does for binary files, instead of dumping raw bytes.
 pub fn diff_filter() -> Result<()> {
-    let mut input = Vec::new();
-    io::stdin().read_to_end(&mut input)?;
+    let input = read_input()?;
 
     if CryptoKey::is_encrypted(&input) {
-        writeln!(
-            io::stdout(),
-            "*** This file is encrypted with git-crypt ***"
-        )?;
+        write_output(b"*** This file is encrypted with git-crypt ***\n")
+    } else if is_binary(&input) {
+        write_output(b"Binary file (not encrypted)\n")
     } else {
-        io::stdout().write_all(&input)?;
+        write_output(&input)
     }
+}
 
-    Ok(())
+/// Whether `content` is binary: it has a NUL byte or isn't valid UTF-8
+fn is_binary(content: &[u8]) -> bool {
+    content.contains(&0) || std::str::from_utf8(content).is_err()
+}
+
+/// Read up to [`STREAM_THRESHOLD`] + 1 bytes, enough to tell whether the
+/// input is too large to buffer
+fn read_head(reader: &mut impl Read) -> Result<Vec<u8>> {
+    let mut head = Vec::new();
+    reader
+        .take(STREAM_THRESHOLD as u64 + 1)
+        .read_to_end(&mut head)?;
+    Ok(head)
+}
+
+/// Counts the bytes written through it
+struct CountingWriter<W> {
+    inner: W,
+    written: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Quote `value` for a POSIX shell when it isn't a plain word
+pub fn shell_quote(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:@".contains(c));
+    if plain {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+/// A reader that goes away early (git cancelled the operation) is not an
+/// error: like other Unix filters we simply stop writing.
+pub(crate) fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(GitCryptError::Io(e)) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => result,
+    }
+}
+
+/// Read all of stdin until EOF.
+///
+/// `read_to_end` keeps going across short reads and retries reads interrupted
+/// by signals, so input arriving in pieces is assembled in full.
+pub(crate) fn read_input() -> Result<Vec<u8>> {
+    let mut input = Vec::new();
+    io::stdin().lock().read_to_end(&mut input)?;
+    Ok(input)
+}
+
+/// Write filter output to stdout.
+///
+/// A reader that goes away early (git cancelled the operation) is not an
+/// error: like other Unix filters we simply stop writing.
+pub(crate) fn write_output(data: &[u8]) -> Result<()> {
+    let mut stdout = io::stdout().lock();
+    match stdout.write_all(data).and_then(|()| stdout.flush()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn create_repo() -> (TempDir, GitRepo) {
+        let temp = TempDir::new().unwrap();
+        Repository::init(temp.path()).unwrap();
+        let repo = GitRepo::open(temp.path()).unwrap();
+        (temp, repo)
+    }
+
+    fn commit_files(temp: &TempDir, files: &[(&str, &[u8])]) {
+        let repo = Repository::open(temp.path()).unwrap();
+        let mut index = repo.index().unwrap();
+        for (path, content) in files {
+            let full = temp.path().join(path);
+            std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+            std::fs::write(full, content).unwrap();
+            index.add_path(Path::new(path)).unwrap();
+        }
+        index.write().unwrap();
+        let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = git2::Signature::now("Test", "test@example.com").unwrap();
+        repo.commit(Some("HEAD"), &sig, &sig, "test", &tree, &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn is_bare_tells_bare_repositories_apart() {
+        let (_temp, repo) = create_repo();
+        assert!(!repo.is_bare());
+        assert!(repo.workdir().is_ok());
+
+        let temp = TempDir::new().unwrap();
+        Repository::init_bare(temp.path()).unwrap();
+        let bare = GitRepo::open(temp.path()).unwrap();
+        assert!(bare.is_bare());
+        assert!(matches!(bare.workdir(), Err(GitCryptError::BareRepository)));
+    }
+
+    #[test]
+    fn key_name_of_driver_inverts_filter_driver() {
+        for name in ["default", "team-b"] {
+            assert_eq!(key_name_of_driver(&filter_driver(name)), Some(name));
+        }
+        assert_eq!(key_name_of_driver("lfs"), None);
+        assert_eq!(key_name_of_driver("git-crypt-"), None);
+        assert_eq!(key_name_of_driver("git-cryptx"), None);
+    }
+
+    #[test]
+    fn classify_index_resolves_each_path_to_its_key() {
+        let (temp, repo) = create_repo();
+        commit_files(
+            &temp,
+            &[
+                (
+                    ".gitattributes",
+                    b"*.secret filter=git-crypt diff=git-crypt\n\
+                      team-b/** filter=git-crypt-teamb\n\
+                      team-b/open.txt -filter\n\
+                      *.bin filter=lfs\n",
+                ),
+                ("a.secret", b"a"),
+                ("docs/nested/b.secret", b"b"),
+                ("team-b/c.txt", b"c"),
+                ("team-b/open.txt", b"d"),
+                ("e.bin", b"e"),
+                ("readme.md", b"f"),
+            ],
+        );
+
+        let classified = repo.classify_index().unwrap();
+        let key_of = |path: &str| {
+            classified
+                .iter()
+                .find(|(p, _)| p == path)
+                .unwrap_or_else(|| panic!("{path} not in index"))
+                .1
+                .as_deref()
+        };
+        assert_eq!(classified.len(), 7);
+        assert_eq!(key_of("a.secret"), Some("default"));
+        assert_eq!(key_of("docs/nested/b.secret"), Some("default"));
+        assert_eq!(key_of("team-b/c.txt"), Some("teamb"));
+        assert_eq!(key_of("team-b/open.txt"), None);
+        assert_eq!(key_of("e.bin"), None);
+        assert_eq!(key_of("readme.md"), None);
+        assert_eq!(key_of(".gitattributes"), None);
+
+        // Paths needn't be tracked, and named keys don't count as the default
+        assert_eq!(
+            repo.filter_key_name("new/file.secret").unwrap().as_deref(),
+            Some("default")
+        );
+        assert!(!repo.is_filtered("team-b/c.txt").unwrap());
+    }
+
+    #[test]
+    fn load_key_reads_the_repository_key() {
+        let (_temp, repo) = create_repo();
+        assert!(repo.load_key().is_err());
+
+        let key = key::KeyManager::new(repo.git_dir()).generate_key().unwrap();
+        assert_eq!(repo.load_key().unwrap().fingerprint(), key.fingerprint());
+    }
+
+    #[test]
+    fn streams_round_trip_through_in_memory_buffers() {
+        let key = CryptoKey::generate();
+        let mut encrypted = Vec::new();
+        clean_stream(
+            &key,
+            &CleanOptions::default(),
+            &b"secret"[..],
+            &mut encrypted,
+        )
+        .unwrap();
+        assert!(CryptoKey::is_encrypted(&encrypted));
+
+        let mut decrypted = Vec::new();
+        smudge_stream(&key, None, encrypted.as_slice(), &mut decrypted).unwrap();
+        assert_eq!(decrypted, b"secret");
+    }
+
+    #[test]
+    fn smudge_tries_each_key_in_turn() {
+        let old = CryptoKey::generate();
+        let new = CryptoKey::generate();
+        let keys = [new.clone(), old.clone()];
+
+        let small = old.encrypt(b"before the rotation").unwrap();
+        assert!(smudge_content(&new, None, &small).is_err());
+        assert_eq!(
+            smudge_content_with_keys(&keys, None, &small).unwrap(),
+            b"before the rotation"
+        );
+
+        // Large blobs take the streaming path
+        let plaintext = vec![7u8; STREAM_THRESHOLD + 1];
+        let mut large = Vec::new();
+        clean_stream(
+            &old,
+            &CleanOptions::default(),
+            plaintext.as_slice(),
+            &mut large,
+        )
+        .unwrap();
+        let mut decrypted = Vec::new();
+        smudge_stream_with_keys(&keys, None, large.as_slice(), &mut decrypted).unwrap();
+        assert!(decrypted == plaintext);
+
+        // No key opens it: the current key's error is reported
+        let other = CryptoKey::generate().encrypt(b"elsewhere").unwrap();
+        assert!(smudge_content_with_keys(&keys, None, &other).is_err());
+    }
+
+    #[test]
+    fn head_blob_for_path_reads_tracked_files() {
+        let (temp, repo) = create_repo();
+        assert_eq!(repo.head_blob_for_path("top.txt").unwrap(), None);
+
+        commit_files(&temp, &[("top.txt", b"top"), ("a/b/nested.txt", b"nested")]);
+        assert_eq!(
+            repo.head_blob_for_path("top.txt").unwrap().as_deref(),
+            Some(&b"top"[..])
+        );
+        assert_eq!(
+            repo.head_blob_for_path("a/b/nested.txt")
+                .unwrap()
+                .as_deref(),
+            Some(&b"nested"[..])
+        );
+    }
+
+    #[test]
+    fn head_blob_for_path_ignores_untracked_paths_and_directories() {
+        let (temp, repo) = create_repo();
+        commit_files(&temp, &[("a/b/nested.txt", b"nested")]);
+        std::fs::write(temp.path().join("untracked.txt"), "x").unwrap();
+
+        assert_eq!(repo.head_blob_for_path("untracked.txt").unwrap(), None);
+        assert_eq!(repo.head_blob_for_path("a/b").unwrap(), None);
+        assert_eq!(repo.head_blob_for_path("a/b/missing.txt").unwrap(), None);
+    }
+
+    #[test]
+    fn binary_rules_mark_only_undecided_git_crypt_rules() {
+        let attributes = "# secrets filter=git-crypt\n\
+                          *.secret filter=git-crypt diff=git-crypt\n\
+                          *.pem filter=git-crypt -text\n\
+                          *.env filter=git-crypt eol=lf\n\
+                          *.bin binary filter=git-crypt\n\
+                          *.named filter=git-crypt-other\n\
+                          *.txt text\n";
+        let (updated, patterns) = with_binary_rules(attributes, "git-crypt");
+        assert_eq!(patterns, ["*.secret"]);
+        assert_eq!(
+            updated,
+            attributes.replace("diff=git-crypt\n", "diff=git-crypt -text\n")
+        );
+
+        let (updated, patterns) =
+            with_binary_rules("*.named filter=git-crypt-other", "git-crypt-other");
+        assert_eq!(patterns, ["*.named"]);
+        assert_eq!(updated, "*.named filter=git-crypt-other -text");
+    }
+
+    #[test]
+    fn hook_block_is_idempotent_and_chains_existing_hooks() {
+        let fresh = with_hook_block(None, "git-crypt check-staged");
+        assert_eq!(
+            fresh,
+            format!("#!/bin/sh\n{HOOK_BEGIN}\ngit-crypt check-staged || exit 1\n{HOOK_END}\n")
+        );
+        assert_eq!(
+            with_hook_block(Some(&fresh), "git-crypt check-staged"),
+            fresh
+        );
+
+        let existing = "#!/bin/bash\nrun-linters\nexit 0\n";
+        let chained = with_hook_block(Some(existing), "git-crypt check-staged");
+        assert!(chained.starts_with("#!/bin/bash\n# >>> git-crypt"));
+        assert!(chained.ends_with("run-linters\nexit 0\n"));
+        assert_eq!(without_hook_block(&chained).unwrap(), existing);
+        assert_eq!(without_hook_block(existing), None);
+    }
+
+    #[test]
+    fn remove_pre_commit_hook_keeps_other_hooks() {
+        let (_temp, repo) = create_repo();
+        let path = repo.pre_commit_hook_path();
+
+        assert!(repo
+            .install_pre_commit_hook("git-crypt check-staged")
+            .unwrap());
+        assert!(!repo
+            .install_pre_commit_hook("git-crypt check-staged")
+            .unwrap());
+        assert!(repo.remove_pre_commit_hook().unwrap());
+        assert!(!path.exists());
+        assert!(!repo.remove_pre_commit_hook().unwrap());
+
+        std::fs::write(&path, "#!/bin/sh\nrun-linters\n").unwrap();
+        repo.install_pre_commit_hook("git-crypt check-staged")
+            .unwrap();
+        assert!(repo.remove_pre_commit_hook().unwrap());
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "#!/bin/sh\nrun-linters\n"
+        );
+    }
+
+    #[test]
+    fn config_snapshot_round_trip() {
+        let (_temp, repo) = create_repo();
+        assert_eq!(repo.config_snapshot().unwrap(), ConfigSnapshot::default());
+
+        repo.configure_filters().unwrap();
+        let mut snapshot = repo.config_snapshot().unwrap();
+        assert_eq!(snapshot.get("clean"), Some("git-crypt clean -- %f"));
+        assert_eq!(snapshot.get("required"), Some("true"));
+
+        snapshot.set("padding", "64");
+        snapshot.remove("diff");
+        let previous = repo.write_config_snapshot(&snapshot).unwrap();
+
+        assert_eq!(repo.config_snapshot().unwrap(), snapshot);
+        assert_eq!(previous.get("diff"), Some("git-crypt diff"));
+        assert_eq!(repo.clean_options().unwrap().padding, Some(64));
+
+        // The returned backup restores the earlier settings
+        repo.write_config_snapshot(&previous).unwrap();
+        assert_eq!(repo.config_snapshot().unwrap(), previous);
+    }
+
+    #[test]
+    fn write_config_snapshot_rolls_back_on_error() {
+        let (_temp, repo) = create_repo();
+        repo.configure_filters().unwrap();
+        let before = repo.config_snapshot().unwrap();
+
+        let mut snapshot = before.clone();
+        snapshot.set("clean", "/elsewhere/git-crypt clean");
+        snapshot.remove("diff");
+        // Not a valid config variable name; written after the changes above
+        snapshot.set("zz invalid", "value");
+
+        assert!(repo.write_config_snapshot(&snapshot).is_err());
+        assert_eq!(repo.config_snapshot().unwrap(), before);
+    }
+
+    #[test]
+    fn clean_content_passes_ciphertext_through() {
+        let key = CryptoKey::generate();
+        let options = CleanOptions::default();
+
+        let first = clean_content(&key, &options, b"secret").unwrap();
+        let second = clean_content(&key, &options, b"secret").unwrap();
+        assert_ne!(first, second, "random nonces make each clean unique");
+
+        // A locked checkout holds ciphertext, which is stored unchanged
+        assert_eq!(clean_content(&key, &options, &first).unwrap(), first);
+    }
+
+    #[test]
+    fn remove_filters_keeps_format_settings() {
+        let (_temp, repo) = create_repo();
+        repo.configure_filters().unwrap();
+        let mut snapshot = repo.config_snapshot().unwrap();
+        snapshot.set("wrapped", "true");
+        repo.write_config_snapshot(&snapshot).unwrap();
+
+        repo.remove_filters().unwrap();
+        assert!(!repo.filters_configured().unwrap());
+        assert!(repo.clean_options().unwrap().wrapped);
+    }
 }
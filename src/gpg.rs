@@ -1,48 +1,65 @@
 #[cfg(feature = "gpg")]
 use pgp::{
-    composed::{MessageBuilder, SignedPublicKey, SignedPublicSubKey},
+    composed::{ArmorOptions, MessageBuilder, SignedPublicKey, SignedPublicSubKey},
     crypto::sym::SymmetricKeyAlgorithm,
     errors::Error as PgpError,
     packet::{KeyFlags, PublicKey, PublicSubkey},
 };
 #[cfg(feature = "gpg")]
 use rand::rngs::OsRng;
+#[cfg(feature = "gpg")]
+use std::io::Write;
+#[cfg(feature = "gpg")]
+use std::process::{Command, Stdio};
+#[cfg(feature = "gpg")]
+use zeroize::Zeroizing;
 
 use crate::crypto::CryptoKey;
 use crate::error::{GitCryptError, Result};
 
 pub struct GpgManager;
 
+/// A repository key decrypted from a GPG blob
+pub struct GpgDecrypted {
+    pub key: CryptoKey,
+    /// Fingerprint of the primary key whose secret key decrypted the blob,
+    /// as gpg reported it
+    pub fingerprint: Option<String>,
+}
+
+/// A public key found in the local GPG keyring
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpgKeyInfo {
+    pub fingerprint: String,
+    pub uid: String,
+}
+
 impl GpgManager {
     /// Encrypt a key for a GPG recipient using rPGP.
+    ///
+    /// By default the key is encrypted to the first encryption-capable subkey.
+    /// With `all_subkeys` it is encrypted to every one, so the recipient can
+    /// still decrypt after rotating to a newer subkey.
     #[cfg(feature = "gpg")]
     pub fn encrypt_key_for_recipient(
         key: &CryptoKey,
         recipient_fingerprint: &str,
+        all_subkeys: bool,
     ) -> Result<Vec<u8>> {
         let signed_key = Self::get_public_key_from_keyring(recipient_fingerprint)?;
-        let recipient = select_recipient_key(&signed_key).ok_or_else(|| {
-            GitCryptError::Gpg(format!(
-                "No encryption-capable keys found for recipient: {recipient_fingerprint}"
-            ))
-        })?;
-
-        let mut rng = OsRng;
-        let mut builder = MessageBuilder::from_bytes("", key.as_bytes().to_vec())
-            .seipd_v1(&mut rng, SymmetricKeyAlgorithm::AES256);
-
-        match recipient {
-            RecipientKey::Primary(pk) => builder.encrypt_to_key(&mut rng, pk),
-            RecipientKey::Subkey(subkey) => builder.encrypt_to_key(&mut rng, subkey),
-        }
-        .map_err(map_pgp_err)?;
-
-        let mut encrypted = Vec::new();
-        builder
-            .to_writer(&mut rng, &mut encrypted)
-            .map_err(map_pgp_err)?;
+        encrypt_key_to_public_key(key, &signed_key, all_subkeys, false)
+    }
 
-        Ok(encrypted)
+    /// Like [`Self::encrypt_key_for_recipient`], but as an ASCII-armored
+    /// message that can be mailed or pasted
+    #[cfg(feature = "gpg")]
+    pub fn encrypt_key_for_recipient_armored(
+        key: &CryptoKey,
+        recipient_fingerprint: &str,
+        all_subkeys: bool,
+    ) -> Result<Vec<u8>> {
+        let signed_key = Self::get_public_key_from_keyring(recipient_fingerprint)?;
+        encrypt_key_to_public_key(key, &signed_key, all_subkeys, true)
     }
 
     /// Encrypt a key for a GPG recipient (no GPG support compiled in)
@@ -50,29 +67,71 @@ impl GpgManager {
     pub fn encrypt_key_for_recipient(
         _key: &CryptoKey,
         _recipient_fingerprint: &str,
+        _all_subkeys: bool,
     ) -> Result<Vec<u8>> {
         Err(GitCryptError::Gpg(
             "GPG support not enabled. Rebuild with --features gpg".into(),
         ))
     }
 
-    /// Decrypt a GPG-encrypted key
-    #[cfg(feature = "gpg")]
-    #[allow(dead_code)]
-    pub fn decrypt_key(_encrypted_data: &[u8]) -> Result<CryptoKey> {
-        // This is a placeholder - actual implementation would need:
-        // 1. Access to private key material
-        // 2. Proper decryption with rPGP
-        // 3. Password handling for encrypted private keys
-
+    /// Encrypt a key for a GPG recipient, armored (no GPG support compiled in)
+    #[cfg(not(feature = "gpg"))]
+    pub fn encrypt_key_for_recipient_armored(
+        _key: &CryptoKey,
+        _recipient_fingerprint: &str,
+        _all_subkeys: bool,
+    ) -> Result<Vec<u8>> {
         Err(GitCryptError::Gpg(
-            "GPG decryption via rPGP requires private key access - to be implemented".into(),
+            "GPG support not enabled. Rebuild with --features gpg".into(),
         ))
     }
 
+    /// Decrypt a GPG-encrypted key with whichever secret key in the local
+    /// keyring can open it.
+    ///
+    /// Shells out to `gpg --decrypt`, so gpg-agent handles passphrases and
+    /// smartcards as it would for any other message.
+    #[cfg(feature = "gpg")]
+    pub fn decrypt_key(encrypted_data: &[u8]) -> Result<GpgDecrypted> {
+        let mut child = Command::new("gpg")
+            .args(["--batch", "--quiet", "--status-fd", "2", "--decrypt"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => GitCryptError::Gpg(
+                    "gpg executable not found. Install GnuPG to decrypt keys".into(),
+                ),
+                _ => GitCryptError::Gpg(format!("Failed to run gpg: {e}")),
+            })?;
+        // Key blobs are a few hundred bytes, well within the pipe buffer
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(encrypted_data)?;
+        }
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if !output.status.success() {
+            let reason = stderr
+                .lines()
+                .rfind(|line| !line.starts_with("[GNUPG:]"))
+                .unwrap_or("no usable secret key");
+            return Err(GitCryptError::Gpg(format!(
+                "gpg --decrypt failed: {}",
+                reason.trim()
+            )));
+        }
+
+        Ok(GpgDecrypted {
+            key: crate::key::decode_key(&Zeroizing::new(output.stdout))?,
+            fingerprint: parse_decryption_key(&stderr),
+        })
+    }
+
     /// Decrypt a GPG-encrypted key (no GPG support compiled in)
     #[cfg(not(feature = "gpg"))]
-    pub fn decrypt_key(_encrypted_data: &[u8]) -> Result<CryptoKey> {
+    pub fn decrypt_key(_encrypted_data: &[u8]) -> Result<GpgDecrypted> {
         Err(GitCryptError::Gpg(
             "GPG support not enabled. Rebuild with --features gpg".into(),
         ))
@@ -91,17 +150,37 @@ impl GpgManager {
         )))
     }
 
-    /// List available GPG keys
+    /// List public keys in the local GPG keyring.
+    ///
+    /// Shells out to `gpg --list-keys --with-colons` and returns one entry per
+    /// primary key, using its first user id.
     #[cfg(feature = "gpg")]
-    #[allow(dead_code)]
-    pub fn list_keys() -> Result<Vec<String>> {
-        // Placeholder for listing available GPG keys
-        Err(GitCryptError::Gpg("Key listing not yet implemented".into()))
+    pub fn list_keys() -> Result<Vec<GpgKeyInfo>> {
+        let output = Command::new("gpg")
+            .args(["--batch", "--list-keys", "--with-colons"])
+            .output()
+            .map_err(|e| match e.kind() {
+                std::io::ErrorKind::NotFound => GitCryptError::Gpg(
+                    "gpg executable not found. Install GnuPG to list keys".into(),
+                ),
+                _ => GitCryptError::Gpg(format!("Failed to run gpg: {e}")),
+            })?;
+
+        if !output.status.success() {
+            return Err(GitCryptError::Gpg(format!(
+                "gpg --list-keys failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(parse_colon_listing(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
     }
 
     /// List available GPG keys (no GPG support compiled in)
     #[cfg(not(feature = "gpg"))]
-    pub fn list_keys() -> Result<Vec<String>> {
+    pub fn list_keys() -> Result<Vec<GpgKeyInfo>> {
         Err(GitCryptError::Gpg(
             "GPG support not enabled. Rebuild with --features gpg".into(),
         ))
@@ -115,13 +194,58 @@ enum RecipientKey<'a> {
 }
 
 #[cfg(feature = "gpg")]
-fn select_recipient_key(signed_key: &SignedPublicKey) -> Option<RecipientKey<'_>> {
-    signed_key
+fn encrypt_key_to_public_key(
+    key: &CryptoKey,
+    signed_key: &SignedPublicKey,
+    all_subkeys: bool,
+    armor: bool,
+) -> Result<Vec<u8>> {
+    let recipients = select_recipient_keys(signed_key, all_subkeys);
+
+    let mut rng = OsRng;
+    let mut builder = MessageBuilder::from_bytes("", key.as_bytes().to_vec())
+        .seipd_v1(&mut rng, SymmetricKeyAlgorithm::AES256);
+
+    for recipient in recipients {
+        match recipient {
+            RecipientKey::Primary(pk) => builder.encrypt_to_key(&mut rng, pk),
+            RecipientKey::Subkey(subkey) => builder.encrypt_to_key(&mut rng, subkey),
+        }
+        .map_err(map_pgp_err)?;
+    }
+
+    let mut encrypted = Vec::new();
+    if armor {
+        builder.to_armored_writer(rng, ArmorOptions::default(), &mut encrypted)
+    } else {
+        builder.to_writer(rng, &mut encrypted)
+    }
+    .map_err(map_pgp_err)?;
+
+    Ok(encrypted)
+}
+
+/// Pick the keys to encrypt to: the first encryption subkey, or all of them
+/// with `all_subkeys`. Falls back to the primary key when no subkey can encrypt.
+#[cfg(feature = "gpg")]
+fn select_recipient_keys(signed_key: &SignedPublicKey, all_subkeys: bool) -> Vec<RecipientKey<'_>> {
+    let subkeys = signed_key
         .public_subkeys
         .iter()
-        .find(|subkey| subkey_supports_encryption(subkey))
-        .map(|subkey| RecipientKey::Subkey(&subkey.key))
-        .or_else(|| Some(RecipientKey::Primary(&signed_key.primary_key)))
+        .filter(|subkey| subkey_supports_encryption(subkey))
+        .map(|subkey| RecipientKey::Subkey(&subkey.key));
+
+    let selected: Vec<_> = if all_subkeys {
+        subkeys.collect()
+    } else {
+        subkeys.take(1).collect()
+    };
+
+    if selected.is_empty() {
+        vec![RecipientKey::Primary(&signed_key.primary_key)]
+    } else {
+        selected
+    }
 }
 
 #[cfg(feature = "gpg")]
@@ -141,3 +265,180 @@ fn key_flags_allow_encryption(flags: &KeyFlags) -> bool {
 fn map_pgp_err(err: PgpError) -> GitCryptError {
     GitCryptError::Gpg(err.to_string())
 }
+
+/// Primary key fingerprint from gpg's `DECRYPTION_KEY` status line (falling
+/// back to the subkey's), if gpg printed one
+#[cfg(feature = "gpg")]
+fn parse_decryption_key(status: &str) -> Option<String> {
+    status.lines().find_map(|line| {
+        let mut fields = line.strip_prefix("[GNUPG:] DECRYPTION_KEY ")?.split(' ');
+        let subkey = fields.next()?;
+        Some(fields.next().unwrap_or(subkey).to_string())
+    })
+}
+
+/// Parse `gpg --with-colons` output into fingerprint/uid pairs.
+#[cfg(feature = "gpg")]
+fn parse_colon_listing(listing: &str) -> Vec<GpgKeyInfo> {
+    let mut keys = Vec::new();
+    // (fingerprint, uid) of the primary key currently being parsed
+    let mut current: Option<(Option<String>, Option<String>)> = None;
+
+    let mut flush = |current: &mut Option<(Option<String>, Option<String>)>| {
+        if let Some((Some(fingerprint), uid)) = current.take() {
+            keys.push(GpgKeyInfo {
+                fingerprint,
+                uid: uid.unwrap_or_default(),
+            });
+        }
+    };
+
+    for line in listing.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        let field = |idx: usize| fields.get(idx).copied().unwrap_or("");
+
+        match field(0) {
+            "pub" => {
+                flush(&mut current);
+                current = Some((None, None));
+            }
+            // Subkeys carry their own fpr records; stop collecting for the primary key.
+            "sub" => flush(&mut current),
+            "fpr" => {
+                if let Some((fingerprint @ None, _)) = current.as_mut() {
+                    *fingerprint = Some(field(9).to_string());
+                }
+            }
+            "uid" => {
+                if let Some((_, uid @ None)) = current.as_mut() {
+                    *uid = Some(unescape_colon_field(field(9)));
+                }
+            }
+            _ => {}
+        }
+    }
+    flush(&mut current);
+
+    keys
+}
+
+/// Undo the `\xNN` escaping gpg applies to colon-listing fields.
+#[cfg(feature = "gpg")]
+fn unescape_colon_field(field: &str) -> String {
+    let mut bytes = Vec::with_capacity(field.len());
+    let raw = field.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'\\' && raw.get(i + 1) == Some(&b'x') {
+            if let Some(byte) = field
+                .get(i + 2..i + 4)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                bytes.push(byte);
+                i += 4;
+                continue;
+            }
+        }
+        bytes.push(raw[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(all(test, feature = "gpg"))]
+mod tests {
+    use super::*;
+
+    const COLON_LISTING: &str = "\
+tru::1:1700000000:0:3:1:5
+pub:u:255:22:A1B2C3D4E5F60718:1700000000:::u:::scESC:::::ed25519:::0:
+fpr:::::::::0123456789ABCDEF0123456789ABCDEFA1B2C3D4:
+grp:::::::::AAAABBBBCCCCDDDDEEEEFFFF0000111122223333:
+uid:u::::1700000000::HASH1::Alice Example <alice@example.com>::::::::::0:
+uid:u::::1700000000::HASH2::Alice Work <alice@work.example>::::::::::0:
+sub:u:255:18:1122334455667788:1700000000::::::e:::::cv25519::
+fpr:::::::::FEDCBA9876543210FEDCBA98765432101122334455667788:
+pub:e:3072:1:0011223344556677:1600000000:1650000000::u:::sc:::::::0:
+fpr:::::::::99999999999999999999999999999999:
+uid:e::::1600000000::HASH3::Bob\\x3a Ops <bob@example.com>::::::::::0:
+";
+
+    #[test]
+    fn parse_colon_listing_extracts_primary_keys() {
+        let keys = parse_colon_listing(COLON_LISTING);
+        assert_eq!(
+            keys,
+            vec![
+                GpgKeyInfo {
+                    fingerprint: "0123456789ABCDEF0123456789ABCDEFA1B2C3D4".into(),
+                    uid: "Alice Example <alice@example.com>".into(),
+                },
+                GpgKeyInfo {
+                    fingerprint: "99999999999999999999999999999999".into(),
+                    uid: "Bob: Ops <bob@example.com>".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_decryption_key_prefers_primary_fingerprint() {
+        let status = "[GNUPG:] ENC_TO 1122334455667788 18 0\n\
+                      [GNUPG:] DECRYPTION_KEY FEDCBA98 0123ABCD u\n\
+                      [GNUPG:] DECRYPTION_OKAY\n";
+        assert_eq!(parse_decryption_key(status).as_deref(), Some("0123ABCD"));
+        assert_eq!(
+            parse_decryption_key("[GNUPG:] DECRYPTION_KEY FEDCBA98\n").as_deref(),
+            Some("FEDCBA98")
+        );
+        assert_eq!(parse_decryption_key("gpg: decryption failed\n"), None);
+    }
+
+    #[test]
+    fn parse_colon_listing_handles_empty_output() {
+        assert!(parse_colon_listing("").is_empty());
+    }
+
+    /// Public key with an ed25519 primary and two cv25519 encryption subkeys
+    const TWO_ENCRYPTION_SUBKEYS: &str =
+        include_str!("../tests/fixtures/gpg_two_encryption_subkeys.asc");
+
+    fn session_key_packets(message: &[u8]) -> usize {
+        use pgp::packet::{Packet, PacketParser};
+
+        PacketParser::new(message)
+            .filter(|packet| matches!(packet, Ok(Packet::PublicKeyEncryptedSessionKey(_))))
+            .count()
+    }
+
+    #[test]
+    fn encrypts_to_one_or_all_encryption_subkeys() {
+        use pgp::composed::Deserializable;
+
+        let (signed_key, _) = SignedPublicKey::from_string(TWO_ENCRYPTION_SUBKEYS).unwrap();
+        assert_eq!(select_recipient_keys(&signed_key, false).len(), 1);
+        assert_eq!(select_recipient_keys(&signed_key, true).len(), 2);
+        assert!(select_recipient_keys(&signed_key, true)
+            .iter()
+            .all(|k| matches!(k, RecipientKey::Subkey(_))));
+
+        let key = CryptoKey::generate();
+        let single = encrypt_key_to_public_key(&key, &signed_key, false, false).unwrap();
+        let all = encrypt_key_to_public_key(&key, &signed_key, true, false).unwrap();
+        assert_eq!(session_key_packets(&single), 1);
+        assert_eq!(session_key_packets(&all), 2);
+    }
+
+    #[test]
+    fn armored_key_blob_dearmors_to_the_same_message() {
+        use pgp::composed::{Deserializable, Message};
+
+        let (signed_key, _) = SignedPublicKey::from_string(TWO_ENCRYPTION_SUBKEYS).unwrap();
+        let armored =
+            encrypt_key_to_public_key(&CryptoKey::generate(), &signed_key, true, true).unwrap();
+        let text = String::from_utf8(armored).unwrap();
+        assert!(text.starts_with("-----BEGIN PGP MESSAGE-----"));
+        assert!(text.trim_end().ends_with("-----END PGP MESSAGE-----"));
+        assert!(Message::from_string(&text).is_ok());
+    }
+}
@@ -5,22 +5,66 @@
 //! ## Key Storage
 //!
 //! Keys are stored in the git repository's internal directory:
-//! - **Default key path**: `.git/git-crypt/keys/default`
+//! - **Default key path**: `.git/git-crypt/keys/default`, or the file given
+//!   with the global `--repo-key-path` flag ([`KeyManager::with_key_path`])
+//! - **Named keys**: `.git/git-crypt/keys/<name>` with the global `--key-name`
+//!   flag ([`KeyManager::with_key_name`]), each with its own side files and
+//!   lock state (`.git/git-crypt/state-<name>`)
 //! - **Format**: Raw 32-byte binary data
-//! - **Permissions**: 0600 on Unix (owner read/write only)
+//! - **Permissions**: 0600 on Unix, owner-only ACL on Windows; a warning is
+//!   printed when the filesystem can't enforce either
 //! - **Never committed**: Keys stay in `.git/` directory
+//! - **Lock state**: `.git/git-crypt/state` records whether filters are active
+//! - **Import log**: `.git/git-crypt/keys/import.log` records each imported key's
+//!   source and fingerprint (never the key)
+//! - **KDF metadata**: `.git/git-crypt/keys/default.kdf` holds the Argon2id
+//!   salt and parameters when the key file is wrapped under a passphrase
+//!   (`init --passphrase`); its presence marks the repository as
+//!   passphrase-protected. See [`KdfParams`]
+//! - **Session key**: once a passphrase-protected key has been unwrapped, it
+//!   is kept in `$XDG_RUNTIME_DIR/git-crypt/` until `lock` or logout, so the
+//!   filters don't prompt for every file
+//! - **Creation time**: `.git/git-crypt/keys/default.created` holds when the key
+//!   was generated (Unix seconds), for `verify --max-key-age`; see [`KeyAge`]
+//! - **Key check value**: `.git/git-crypt/keys/default.kcv` holds
+//!   [`CryptoKey::check_value`] of the saved key; loading a key that doesn't
+//!   match it fails with [`GitCryptError::KeyCheckMismatch`] instead of
+//!   decrypting garbage later. Keys saved before check values existed have
+//!   none and load unchecked
+//!
+//! ## Key File Formats
+//!
+//! [`probe_format`] is the single place that decides how a key file is
+//! encoded; every command that reads a key file goes through [`decode_key`],
+//! which is built on it. A file of exactly 32 bytes is always a raw key, so a
+//! raw key that happens to look like text is never misread. One trailing
+//! newline after those 32 bytes is tolerated and trimmed.
+//!
+//! ## Key Shares
+//!
+//! [`split_key`] splits the key into N Shamir shares over GF(256), any
+//! `threshold` of which rebuild it with [`combine_shares`]. Each share
+//! records the threshold and a short key fingerprint, so combining too few
+//! shares, or shares of different keys, fails instead of yielding a wrong key.
+//! The field is the AES one (polynomial 0x11b); the tests pin it to FIPS-197
+//! products and a share set worked out by hand.
 //!
 //! ## Key Operations
 //!
-//! - **Generate**: Create new random 256-bit key
-//! - **Save/Load**: Persist keys to/from filesystem
-//! - **Export**: Save key to file for sharing
-//! - **Import**: Load key from shared file
+//! - **Generate**: Create new random 256-bit key, written under a temporary
+//!   name and hard-linked into place, so it is never partial and never
+//!   replaces an existing key
+//! - **Save/Load**: Persist keys to/from filesystem; saves write a `.tmp`
+//!   file and rename it over the key, so an interrupted save can't truncate it
+//! - **Export**: Save key to file for sharing, whole or as shares, written the
+//!   same way
+//! - **Import**: Load key from shared file, or combine shares
+//! - **Purge**: Overwrite and delete all key material (best effort)
 //!
 //! ## Security Considerations
 //!
 //! - Keys are stored unencrypted in `.git/git-crypt/`
-//! - File permissions are restricted to owner only (Unix)
+//! - File permissions are restricted to owner only (Unix modes, Windows ACLs)
 //! - Exported key files must be shared securely
 //! - Consider using GPG for team key distribution
 //!
@@ -38,33 +82,479 @@
 //! - Export and import workflows
 //! - File permissions (Unix)
 //! - Error handling for missing files
+//! - Key file format detection, including ambiguous inputs
+//! - Share splitting and reconstruction at and below the threshold, and
+//!   known-answer vectors for the field arithmetic and reconstruction
 
-use crate::crypto::CryptoKey;
+use crate::crypto::{CryptoKey, KEY_SIZE, UPSTREAM_KEY_MAGIC};
 use crate::error::{GitCryptError, Result};
-use std::fs::{self, File};
-use std::io::{Read, Write};
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::Engine;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use zeroize::Zeroizing;
+
+/// Whether git-crypt filters are active for the repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockState {
+    Locked,
+    Unlocked,
+}
+
+impl LockState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LockState::Locked => "locked",
+            LockState::Unlocked => "unlocked",
+        }
+    }
+}
+
+// Key path from `--repo-key-path`, used by every KeyManager made with `new`
+static KEY_PATH_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Read and write the default key at `path` for the rest of the process
+/// (the `--repo-key-path` flag). Relative paths are resolved now.
+pub fn set_key_path_override(path: &Path) {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let _ = KEY_PATH_OVERRIDE.set(path);
+}
+
+/// The `--repo-key-path` override, if one was given
+pub fn key_path_override() -> Option<&'static Path> {
+    KEY_PATH_OVERRIDE.get().map(PathBuf::as_path)
+}
+
+/// Environment variable that moves the git-crypt directory, like `--keydir`
+pub const KEY_DIR_ENV: &str = "GIT_CRYPT_KEYDIR";
+
+// Directory from `--keydir`, used by every KeyManager made with `new`
+static KEY_DIR_FLAG: OnceLock<PathBuf> = OnceLock::new();
+
+/// Keep keys and git-crypt state in `dir` instead of `.git/git-crypt` for
+/// the rest of the process (the `--keydir` flag). Relative paths are
+/// resolved now.
+pub fn set_key_dir_override(dir: &Path) {
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let _ = KEY_DIR_FLAG.set(dir);
+}
+
+/// The `--keydir` flag, if one was given
+pub fn key_dir_flag() -> Option<&'static Path> {
+    KEY_DIR_FLAG.get().map(PathBuf::as_path)
+}
+
+/// Directory replacing `.git/git-crypt`: `--keydir`, else a non-empty
+/// `GIT_CRYPT_KEYDIR`, else `None`
+pub fn key_dir_override() -> Option<PathBuf> {
+    if let Some(dir) = key_dir_flag() {
+        return Some(dir.to_path_buf());
+    }
+    let dir = std::env::var_os(KEY_DIR_ENV).filter(|dir| !dir.is_empty())?;
+    let dir = PathBuf::from(dir);
+    Some(std::path::absolute(&dir).unwrap_or(dir))
+}
+
+// Key name from `--key-name`, used by every KeyManager made with `new` and
+// by the filter section every GitRepo reads
+static KEY_NAME: OnceLock<String> = OnceLock::new();
+
+/// Use the named key `name` for the rest of the process (the `--key-name`
+/// flag), as upstream git-crypt does
+pub fn set_key_name(name: &str) {
+    let _ = KEY_NAME.set(name.to_string());
+}
+
+/// The `--key-name` in use, if one was given
+pub fn key_name() -> Option<&'static str> {
+    KEY_NAME.get().map(String::as_str)
+}
+
+/// Check a key name the way upstream git-crypt does: ASCII letters, digits,
+/// `-` and `_` only, and not `default`, which is the unnamed key
+pub fn validate_key_name(name: &str) -> Result<String> {
+    if name.is_empty() || name.len() > 128 {
+        return Err(GitCryptError::Other(
+            "Key name must be 1 to 128 characters".into(),
+        ));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(GitCryptError::Other(format!(
+            "Invalid key name '{name}': use only letters, digits, '-' and '_'"
+        )));
+    }
+    if name == "default" {
+        return Err(GitCryptError::Other(
+            "'default' is reserved for the unnamed key; omit --key-name".into(),
+        ));
+    }
+    Ok(name.to_string())
+}
+
+// Set by `--follow-key-symlink`, used by every KeyManager made with `new`
+static FOLLOW_KEY_SYMLINK: AtomicBool = AtomicBool::new(false);
+
+/// Let [`KeyManager::save_key`] write through a symlinked key path for the
+/// rest of the process (the `--follow-key-symlink` flag)
+pub fn set_follow_key_symlink(follow: bool) {
+    FOLLOW_KEY_SYMLINK.store(follow, Ordering::Relaxed);
+}
+
+/// Whether `--follow-key-symlink` was given
+pub fn follows_key_symlink() -> bool {
+    FOLLOW_KEY_SYMLINK.load(Ordering::Relaxed)
+}
 
 /// Key storage and management
 pub struct KeyManager {
     git_dir: PathBuf,
+    /// Replaces `<git dir>/git-crypt` as the home of keys and state
+    key_dir: Option<PathBuf>,
+    /// Replaces `keys/default` as the location of the default key
+    key_path: Option<PathBuf>,
+    /// Named key (`keys/<name>`) managed instead of `keys/default`
+    key_name: Option<String>,
+    /// Write through a symlinked key path instead of refusing
+    follow_symlink: bool,
 }
 
 impl KeyManager {
     pub fn new(git_dir: impl AsRef<Path>) -> Self {
+        let manager = match KEY_PATH_OVERRIDE.get() {
+            Some(key_path) => Self::with_key_path(git_dir, key_path),
+            None => Self {
+                git_dir: git_dir.as_ref().to_path_buf(),
+                key_dir: None,
+                key_path: None,
+                key_name: None,
+                follow_symlink: false,
+            },
+        };
+        let manager = match KEY_NAME.get() {
+            Some(name) => manager.with_key_name(name),
+            None => manager,
+        };
+        let manager = match key_dir_override() {
+            Some(dir) => manager.with_key_dir(dir),
+            None => manager,
+        };
+        manager.follow_key_symlink(FOLLOW_KEY_SYMLINK.load(Ordering::Relaxed))
+    }
+
+    /// Choose what [`Self::save_key`] does when the key path is a symlink:
+    /// write into the link's target (`true`) or refuse (`false`, the default)
+    pub fn follow_key_symlink(mut self, follow: bool) -> Self {
+        self.follow_symlink = follow;
+        self
+    }
+
+    /// Keep keys and state in `dir` instead of `<git dir>/git-crypt`, for
+    /// a `.git` on a filesystem that shouldn't hold them
+    pub fn with_key_dir(mut self, dir: impl AsRef<Path>) -> Self {
+        self.key_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Manage the named key `name` (`keys/<name>`, with its own lock state)
+    /// instead of the repository's default key; `default` is the default key
+    pub fn with_key_name(mut self, name: &str) -> Self {
+        self.key_name = (name != "default").then(|| name.to_string());
+        self
+    }
+
+    /// Name of the managed key: `default` unless a named key was chosen
+    pub fn key_name(&self) -> &str {
+        self.key_name.as_deref().unwrap_or("default")
+    }
+
+    /// Like [`Self::new`], but with the default key stored at `key_path`.
+    ///
+    /// Repository state (lock state, key blobs, caches) stays under the git
+    /// directory.
+    pub fn with_key_path(git_dir: impl AsRef<Path>, key_path: impl AsRef<Path>) -> Self {
         Self {
             git_dir: git_dir.as_ref().to_path_buf(),
+            key_dir: None,
+            key_path: Some(key_path.as_ref().to_path_buf()),
+            key_name: None,
+            follow_symlink: false,
         }
     }
 
-    /// Get the path to the git-crypt directory
+    /// Get the path to the git-crypt directory: `<git dir>/git-crypt`
+    /// unless moved with [`Self::with_key_dir`]
     pub fn git_crypt_dir(&self) -> PathBuf {
-        self.git_dir.join("git-crypt")
+        match &self.key_dir {
+            Some(dir) => dir.clone(),
+            None => self.git_dir.join("git-crypt"),
+        }
     }
 
     /// Get the path to the default key file
     pub fn default_key_path(&self) -> PathBuf {
-        self.git_crypt_dir().join("keys").join("default")
+        match &self.key_path {
+            Some(key_path) => key_path.clone(),
+            None => self.keys_dir().join(self.key_name()),
+        }
+    }
+
+    /// Directory holding the repository key and any named keys
+    pub fn keys_dir(&self) -> PathBuf {
+        self.git_crypt_dir().join("keys")
+    }
+
+    /// Names of the keys in [`Self::keys_dir`], sorted: every file without an
+    /// extension. Side files (`.kdf`, `.kcv`, `.generation`, `.created`, retired
+    /// `.gen<N>`), `import.log` and the `gpg`/`age` directories are skipped.
+    pub fn key_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        let entries = match fs::read_dir(self.keys_dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(names),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if !name.contains('.') {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// Manager for the key called `name` in [`Self::keys_dir`]; `default` is
+    /// the repository key
+    pub fn named_key(&self, name: &str) -> KeyManager {
+        KeyManager {
+            key_dir: self.key_dir.clone(),
+            ..KeyManager::with_key_path(&self.git_dir, self.keys_dir().join(name))
+                .with_key_name(name)
+        }
+    }
+
+    /// Get the path to the KDF salt/parameters of a passphrase-protected
+    /// key, next to the key itself
+    pub fn kdf_metadata_path(&self) -> PathBuf {
+        self.default_key_path().with_extension("kdf")
+    }
+
+    /// Get the path to the check value of the default key, next to the key
+    /// itself
+    pub fn check_value_path(&self) -> PathBuf {
+        self.default_key_path().with_extension("kcv")
+    }
+
+    /// Record the check value of `key` as the one the default key must match
+    fn record_check_value(&self, key: &CryptoKey) -> Result<()> {
+        let path = self.check_value_path();
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, format!("{}\n", key.check_value()))?;
+        Ok(())
+    }
+
+    /// Check `key` against the recorded check value, if there is one
+    pub fn verify_check_value(&self, key: &CryptoKey) -> Result<()> {
+        let path = self.check_value_path();
+        let recorded = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+        if recorded.trim() != key.check_value() {
+            return Err(GitCryptError::KeyCheckMismatch(path.display().to_string()));
+        }
+        Ok(())
+    }
+
+    /// Get the path to the generation counter of the default key, next to
+    /// the key itself
+    pub fn generation_path(&self) -> PathBuf {
+        self.default_key_path().with_extension("generation")
+    }
+
+    /// Generation of the default key, or `None` if it was never rotated.
+    ///
+    /// The first key counts as generation 1; each `rotate-key` adds one.
+    pub fn key_generation(&self) -> Result<Option<u32>> {
+        let path = self.generation_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => text.trim().parse().map(Some).map_err(|_| {
+                GitCryptError::Other(format!("Invalid key generation in {}", path.display()))
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Record the generation of the default key
+    pub fn set_key_generation(&self, generation: u32) -> Result<()> {
+        let path = self.generation_path();
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, format!("{generation}\n"))?;
+        Ok(())
+    }
+
+    /// Get the path to the creation time of the default key, next to the
+    /// key itself
+    pub fn created_path(&self) -> PathBuf {
+        self.default_key_path().with_extension("created")
+    }
+
+    /// Record that the default key was created now
+    pub fn record_key_created(&self) -> Result<()> {
+        let path = self.created_path();
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, format!("{}\n", unix_now()))?;
+        Ok(())
+    }
+
+    /// Age of the default key, from its recorded creation time.
+    ///
+    /// A missing record means the age is unknown (the key predates the record,
+    /// or was imported). A record that can't be trusted never fails: see
+    /// [`KeyAge::from_record`].
+    pub fn key_age(&self) -> Result<KeyAge> {
+        let path = self.created_path();
+        match fs::read_to_string(&path) {
+            Ok(text) => Ok(KeyAge::from_record(&path, &text, unix_now())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(KeyAge::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check whether the key file is wrapped under a passphrase
+    pub fn is_passphrase_protected(&self) -> bool {
+        self.kdf_metadata_path().exists()
+    }
+
+    /// Where the unwrapped key of a passphrase-protected repository is kept
+    /// for the login session: `$XDG_RUNTIME_DIR/git-crypt/<hash of the key
+    /// path>`. `None` without a runtime directory, in which case every
+    /// filter run asks for the passphrase.
+    pub fn session_key_path(&self) -> Option<PathBuf> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())?;
+        let key_path = std::path::absolute(self.default_key_path()).ok()?;
+        let id = Sha256::digest(key_path.as_os_str().as_encoded_bytes());
+        Some(runtime_dir.join("git-crypt").join(hex::encode(&id[..16])))
+    }
+
+    /// Keep `key` in the session cache, if there is one
+    pub fn cache_session_key(&self, key: &CryptoKey) -> Result<()> {
+        let Some(path) = self.session_key_path() else {
+            return Ok(());
+        };
+        let dir = path.parent().unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::DirBuilderExt;
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)?;
+        }
+        #[cfg(not(unix))]
+        fs::create_dir_all(dir)?;
+        write_key_atomically(&path, &key.to_key_file())
+    }
+
+    /// Drop the session copy of the key, so the next use asks for the
+    /// passphrase again
+    pub fn forget_session_key(&self) -> Result<()> {
+        let Some(path) = self.session_key_path() else {
+            return Ok(());
+        };
+        match fs::remove_file(path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Get the path to the key import provenance log
+    pub fn import_log_path(&self) -> PathBuf {
+        self.git_crypt_dir().join("keys").join("import.log")
+    }
+
+    /// Append a provenance entry for an installed key.
+    ///
+    /// Records the time, where the key came from and its fingerprint; the key
+    /// itself is never written. When the imported key was created is unknown,
+    /// so the creation record of the key it replaced is dropped.
+    pub fn record_import(&self, source: &str, key: &CryptoKey) -> Result<()> {
+        let log_path = self.import_log_path();
+        fs::create_dir_all(log_path.parent().unwrap())?;
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&log_path)?;
+
+        // The imported key was created elsewhere, at a time we don't know
+        if let Err(e) = fs::remove_file(self.created_path()) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                return Err(e.into());
+            }
+        }
+
+        let timestamp = unix_now();
+        writeln!(
+            file,
+            "{timestamp}\tsource={}\tfingerprint={}",
+            source.replace(['\n', '\t'], " "),
+            key.fingerprint()
+        )?;
+
+        Ok(())
+    }
+
+    /// Get the path to the lock state file; named keys record their own
+    pub fn state_path(&self) -> PathBuf {
+        match &self.key_name {
+            Some(name) => self.git_crypt_dir().join(format!("state-{name}")),
+            None => self.git_crypt_dir().join("state"),
+        }
+    }
+
+    /// Read the recorded lock state, if any
+    pub fn lock_state(&self) -> Result<Option<LockState>> {
+        let path = self.state_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        Ok(match fs::read_to_string(path)?.trim() {
+            "locked" => Some(LockState::Locked),
+            "unlocked" => Some(LockState::Unlocked),
+            _ => None,
+        })
+    }
+
+    /// Record the lock state
+    pub fn set_lock_state(&self, state: LockState) -> Result<()> {
+        fs::write(self.state_path(), format!("{}\n", state.as_str()))?;
+        Ok(())
     }
 
     /// Initialize the git-crypt directory structure
@@ -80,83 +570,1101 @@ impl KeyManager {
         Ok(())
     }
 
-    /// Check if repository is initialized
+    /// Remove all git-crypt data from the git directory.
+    ///
+    /// Every file under `keys/` is overwritten with zeros and synced before it
+    /// is deleted. This is best effort: copy-on-write and journaling
+    /// filesystems or SSDs may still retain the old blocks. Returns the number
+    /// of key files erased.
+    pub fn purge(&self) -> Result<usize> {
+        let git_crypt_dir = self.git_crypt_dir();
+        if !git_crypt_dir.exists() {
+            return Ok(0);
+        }
+
+        self.forget_session_key()?;
+        let erased = erase_dir(&git_crypt_dir.join("keys"))?;
+        // Cached plaintext is erased too, but isn't key material to report
+        erase_dir(&git_crypt_dir.join(crate::cache::CACHE_DIR))?;
+        fs::remove_dir_all(&git_crypt_dir)?;
+        Ok(erased)
+    }
+
+    /// Check if repository is initialized, or a key exists at an explicit key path
     pub fn is_initialized(&self) -> bool {
-        self.git_crypt_dir().exists()
+        self.git_crypt_dir().exists() || self.key_path.as_ref().is_some_and(|p| p.exists())
+    }
+
+    /// Check if the repository key file exists
+    pub fn has_key(&self) -> bool {
+        self.default_key_path().exists()
     }
 
-    /// Generate and save a new key
+    /// Generate and save a new key.
+    ///
+    /// Never replaces an existing key file: if one appears first (for example
+    /// written by a concurrent `init`), this fails with `AlreadyInitialized`.
     pub fn generate_key(&self) -> Result<CryptoKey> {
+        self.create_key(None)
+    }
+
+    /// Like [`Self::generate_key`], but with the key file wrapped under
+    /// `passphrase` (see [`Self::save_key_encrypted`])
+    pub fn generate_key_encrypted(&self, passphrase: &str) -> Result<CryptoKey> {
+        self.create_key(Some(passphrase))
+    }
+
+    fn create_key(&self, passphrase: Option<&str>) -> Result<CryptoKey> {
         let key = CryptoKey::generate();
-        self.save_key(&key)?;
+        let (contents, kdf) = match passphrase {
+            Some(passphrase) => {
+                let kdf = KdfParams::generate();
+                (kdf.wrap(&key, passphrase)?, Some(kdf))
+            }
+            None => (key.to_key_file(), None),
+        };
+        let key_path = self.default_key_path();
+        fs::create_dir_all(key_path.parent().unwrap())?;
+        if fs::symlink_metadata(&key_path).is_ok() {
+            return Err(GitCryptError::AlreadyInitialized);
+        }
+
+        // Write the whole key under a temporary name, then publish it with a
+        // hard link, which fails instead of replacing a key that appeared in
+        // the meantime. A killed init leaves at worst a stray temp file.
+        let tmp = sibling_path(&key_path, &format!(".{}.tmp", std::process::id()));
+        write_key_tmp(&tmp, &contents)?;
+        let published = (|| -> Result<()> {
+            if let Some(kdf) = &kdf {
+                write_key_atomically(&self.kdf_metadata_path(), kdf.to_line().as_bytes())?;
+            }
+            match fs::hard_link(&tmp, &key_path) {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    Err(GitCryptError::AlreadyInitialized)
+                }
+                Err(e) => Err(e.into()),
+            }
+        })();
+        let _ = fs::remove_file(&tmp);
+        published?;
+        sync_parent_dir(&key_path);
+        self.record_check_value(&key)?;
+        self.record_key_created()?;
+
         Ok(key)
     }
 
-    /// Save a key to disk
+    /// Save a key to disk.
+    ///
+    /// The key is written to a temporary file that replaces the old one only
+    /// once complete, so an interrupted save never leaves a truncated key
+    /// (see [`write_key_atomically`]).
+    ///
+    /// A symlinked key path (say, into a secrets mount) is never replaced. It
+    /// is refused unless [`Self::follow_key_symlink`] is set, in which case
+    /// the link's target is replaced the same way, as an owner-only file.
+    ///
+    /// A passphrase-protected key stays protected: the passphrase to wrap
+    /// the new key under is asked for (see [`read_passphrase`]).
     pub fn save_key(&self, key: &CryptoKey) -> Result<()> {
+        if self.is_passphrase_protected() {
+            let passphrase = read_passphrase(
+                &format!("New passphrase for git-crypt key '{}'", self.key_name()),
+                true,
+            )?;
+            self.save_key_encrypted(key, &passphrase)?;
+            return self.forget_session_key();
+        }
+        self.write_key_file(&key.to_key_file())?;
+        self.record_check_value(key)
+    }
+
+    /// Save `key` wrapped under `passphrase`: Argon2id turns the passphrase
+    /// into a key that seals the key file, and the salt and cost go to
+    /// [`Self::kdf_metadata_path`].
+    ///
+    /// The salt of an existing protected key is kept, so only the key file
+    /// changes and the save stays atomic.
+    pub fn save_key_encrypted(&self, key: &CryptoKey, passphrase: &str) -> Result<()> {
+        let kdf = match self.kdf_params() {
+            Ok(kdf) => kdf,
+            Err(_) => {
+                let kdf = KdfParams::generate();
+                fs::create_dir_all(self.kdf_metadata_path().parent().unwrap())?;
+                write_key_atomically(&self.kdf_metadata_path(), kdf.to_line().as_bytes())?;
+                kdf
+            }
+        };
+        self.write_key_file(&kdf.wrap(key, passphrase)?)?;
+        self.record_check_value(key)
+    }
+
+    /// Load a passphrase-protected key, unwrapping it with `passphrase`
+    pub fn load_key_encrypted(&self, passphrase: &str) -> Result<CryptoKey> {
         let key_path = self.default_key_path();
-        fs::create_dir_all(key_path.parent().unwrap())?;
+        if !key_path.exists() {
+            return Err(GitCryptError::KeyNotFound(self.key_name().into()));
+        }
+        let key = self
+            .kdf_params()?
+            .unwrap(&fs::read(&key_path)?, passphrase)?;
+        self.verify_check_value(&key)?;
+        self.tag_generation(key)
+    }
 
-        let mut file = File::create(&key_path)?;
-        file.write_all(key.as_bytes())?;
+    /// Salt and cost of the passphrase wrapping the key file
+    fn kdf_params(&self) -> Result<KdfParams> {
+        let path = self.kdf_metadata_path();
+        let text = fs::read_to_string(&path)?;
+        KdfParams::parse(&text).ok_or_else(|| {
+            GitCryptError::Other(format!("Invalid KDF parameters in {}", path.display()))
+        })
+    }
 
-        // Set restrictive permissions (Unix only)
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(&key_path)?.permissions();
-            perms.set_mode(0o600);
-            fs::set_permissions(&key_path, perms)?;
+    /// Write key file `contents` to the default key path
+    fn write_key_file(&self, contents: &[u8]) -> Result<()> {
+        let key_path = self.default_key_path();
+
+        let is_symlink = fs::symlink_metadata(&key_path).is_ok_and(|m| m.file_type().is_symlink());
+        if is_symlink {
+            if !self.follow_symlink {
+                return Err(GitCryptError::Other(format!(
+                    "{} is a symlink; refusing to overwrite the key it points to \
+                     (pass --follow-key-symlink to write through it)",
+                    key_path.display()
+                )));
+            }
+            let target = fs::canonicalize(&key_path).map_err(|e| {
+                GitCryptError::Other(format!(
+                    "Cannot resolve key symlink {}: {e}",
+                    key_path.display()
+                ))
+            })?;
+            return write_key_atomically(&target, contents);
         }
+        fs::create_dir_all(key_path.parent().unwrap())?;
+        write_key_atomically(&key_path, contents)
+    }
 
-        Ok(())
+    /// Keep `key`, of `generation`, next to the default key after it has
+    /// been replaced, returning where it was written
+    pub fn retire_key(&self, key: &CryptoKey, generation: u32) -> Result<PathBuf> {
+        let path = self
+            .default_key_path()
+            .with_extension(format!("gen{generation}"));
+        write_key_atomically(&path, &key.to_key_file())?;
+        Ok(path)
     }
 
-    /// Load the key from disk
+    /// Load the key from disk, tagged with its generation once rotated.
+    ///
+    /// Fails with [`GitCryptError::KeyCheckMismatch`] when the key doesn't
+    /// match its recorded check value.
     pub fn load_key(&self) -> Result<CryptoKey> {
         let key_path = self.default_key_path();
 
         if !key_path.exists() {
-            return Err(GitCryptError::KeyNotFound("default".into()));
+            return Err(GitCryptError::KeyNotFound(self.key_name().into()));
         }
 
-        let mut file = File::open(&key_path)?;
-        let mut key_bytes = Vec::new();
-        file.read_to_end(&mut key_bytes)?;
+        if self.is_passphrase_protected() {
+            return self.load_protected_key();
+        }
+        let key = read_key_file(&key_path)?;
+        self.verify_check_value(&key)?;
+        self.tag_generation(key)
+    }
 
-        CryptoKey::from_bytes(&key_bytes)
+    /// The key from [`Self::load_key`], followed by every other key in
+    /// [`Self::keys_dir`] that may have sealed older blobs: its retired
+    /// generations, newest first, then the other named keys and theirs.
+    /// A named key only falls back to its own generations, so its files stay
+    /// isolated from the repository key.
+    ///
+    /// Only the current key has to load. The others are best effort, and
+    /// passphrase-protected named keys are skipped rather than prompted for.
+    pub fn load_all_keys(&self) -> Result<Vec<CryptoKey>> {
+        let mut keys = vec![self.load_key()?];
+        keys.extend(self.retired_keys());
+        if self.key_name.is_some() {
+            return Ok(keys);
+        }
+        for name in self.key_names()? {
+            let named = self.named_key(&name);
+            if named.default_key_path() == self.default_key_path() {
+                continue;
+            }
+            if !named.is_passphrase_protected() {
+                keys.extend(named.load_key().ok());
+            }
+            keys.extend(named.retired_keys());
+        }
+        Ok(keys)
     }
 
-    /// Export key to a file
-    pub fn export_key(&self, output_path: impl AsRef<Path>) -> Result<()> {
-        let key = self.load_key()?;
-        let mut file = File::create(output_path.as_ref())?;
-        file.write_all(key.as_bytes())?;
+    /// Keys kept by [`Self::retire_key`] (`<key>.gen<N>`), newest first;
+    /// unreadable ones are skipped
+    fn retired_keys(&self) -> Vec<CryptoKey> {
+        let key_path = self.default_key_path();
+        let (Some(dir), Some(name)) = (
+            key_path.parent(),
+            key_path.file_name().and_then(|name| name.to_str()),
+        ) else {
+            return Vec::new();
+        };
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Vec::new();
+        };
+
+        let prefix = format!("{name}.gen");
+        let mut retired: Vec<(u32, PathBuf)> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let file_name = entry.file_name();
+                let generation = file_name.to_str()?.strip_prefix(&prefix)?.parse().ok()?;
+                Some((generation, entry.path()))
+            })
+            .collect();
+        retired.sort_by_key(|(generation, _)| std::cmp::Reverse(*generation));
+        retired
+            .into_iter()
+            .filter_map(|(generation, path)| {
+                read_key_file(&path)
+                    .ok()
+                    .map(|key| key.with_generation(generation))
+            })
+            .collect()
+    }
 
-        #[cfg(unix)]
+    /// Load a passphrase-protected key from the session cache, or unwrap it
+    /// with a passphrase from [`PASSPHRASE_ENV`] or the terminal and cache it.
+    ///
+    /// A cached key that no longer matches the check value (the key was
+    /// replaced since) is ignored.
+    fn load_protected_key(&self) -> Result<CryptoKey> {
+        if let Some(key) = self
+            .session_key_path()
+            .and_then(|path| read_key_file(&path).ok())
+            .filter(|key| self.verify_check_value(key).is_ok())
         {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = fs::metadata(output_path.as_ref())?.permissions();
-            perms.set_mode(0o600);
-            fs::set_permissions(output_path.as_ref(), perms)?;
+            return self.tag_generation(key);
         }
 
+        let passphrase = read_passphrase(
+            &format!("Passphrase for git-crypt key '{}'", self.key_name()),
+            false,
+        )
+        .map_err(|e| {
+            GitCryptError::Other(format!(
+                "The key is protected by a passphrase and none could be read ({e}); \
+                 run 'git-crypt unlock' in a terminal or set {PASSPHRASE_ENV}"
+            ))
+        })?;
+        let key = self.load_key_encrypted(&passphrase)?;
+        // The cache only saves prompts; failing to write it is not an error
+        let _ = self.cache_session_key(&key);
+        Ok(key)
+    }
+
+    /// `key`, tagged with the generation recorded for the default key
+    fn tag_generation(&self, key: CryptoKey) -> Result<CryptoKey> {
+        Ok(match self.key_generation()? {
+            Some(generation) => key.with_generation(generation),
+            None => key,
+        })
+    }
+
+    /// Export key to a file
+    pub fn export_key(&self, output_path: impl AsRef<Path>) -> Result<()> {
+        let key = self.load_key()?;
+        write_key_atomically(output_path.as_ref(), &key.to_key_file())
+    }
+
+    /// Write the 32 raw key bytes to `writer`, e.g. stdout for piping into a
+    /// secret store
+    pub fn export_key_to_writer(&self, writer: &mut impl Write) -> Result<()> {
+        let key = self.load_key()?;
+        writer.write_all(key.as_bytes())?;
         Ok(())
     }
 
-    /// Import key from a file
+    /// Export the key as base64 text between `-----BEGIN GIT-CRYPT KEY-----`
+    /// armor lines, for pasting into a password manager or chat.
+    /// [`Self::import_key`] reads it back.
+    pub fn export_key_armored(&self, output_path: impl AsRef<Path>) -> Result<()> {
+        let key = self.load_key()?;
+        let armored = Zeroizing::new(armor_key(&key));
+        write_key_atomically(output_path.as_ref(), armored.as_bytes())
+    }
+
+    /// Import key from a file, or from stdin when the path is `-`
     pub fn import_key(&self, input_path: impl AsRef<Path>) -> Result<()> {
-        let mut file = File::open(input_path)?;
-        let mut key_bytes = Vec::new();
-        file.read_to_end(&mut key_bytes)?;
+        let input_path = input_path.as_ref();
+        let key = read_key_input(input_path)?;
+        self.save_key(&key)?;
+        let source = if input_path == Path::new(STDIN_KEY_PATH) {
+            "stdin".to_string()
+        } else {
+            input_path.display().to_string()
+        };
+        self.record_import(&source, &key)?;
+
+        Ok(())
+    }
+
+    /// Split the key into `shares` share files in `output_dir`, any
+    /// `threshold` of which reconstruct it. Returns the written paths.
+    pub fn export_key_shares(
+        &self,
+        output_dir: impl AsRef<Path>,
+        shares: u8,
+        threshold: u8,
+    ) -> Result<Vec<PathBuf>> {
+        let key = self.load_key()?;
+        let output_dir = output_dir.as_ref();
+        fs::create_dir_all(output_dir)?;
+
+        let mut paths = Vec::new();
+        for share in split_key(&key, shares, threshold)? {
+            let path = output_dir.join(format!("share-{}-of-{shares}.key", share.index));
+            write_key_atomically(&path, share.to_armored().as_bytes())?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
 
-        let key = CryptoKey::from_bytes(&key_bytes)?;
+    /// Rebuild the key from share files and install it
+    pub fn import_key_shares(&self, input_paths: &[PathBuf]) -> Result<()> {
+        let shares = input_paths
+            .iter()
+            .map(|path| KeyShare::parse(&fs::read(path)?))
+            .collect::<Result<Vec<_>>>()?;
+        let key = combine_shares(&shares)?;
         self.save_key(&key)?;
+        self.record_import(&format!("{} shares", shares.len()), &key)?;
 
         Ok(())
     }
 }
 
+/// Clock skew tolerated before a creation time counts as "in the future"
+const CLOCK_SKEW_TOLERANCE_SECS: u64 = 5 * 60;
+
+/// Creation times before this (2020-01-01) predate git-crypt-rs and can only
+/// come from a wrong clock
+const EARLIEST_KEY_CREATION: u64 = 1_577_836_800;
+
+/// How old the repository key is, as far as its creation record can tell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyAge {
+    /// Age of the key, or `None` if unknown
+    pub age: Option<Duration>,
+    /// Why the record was distrusted, when it was
+    pub warning: Option<String>,
+}
+
+impl KeyAge {
+    /// Evaluate the creation record at `path` holding `text`, at `now`
+    /// (seconds since the Unix epoch).
+    ///
+    /// A record written by a machine with a wrong clock is never trusted
+    /// silently: a malformed or implausibly old time leaves the age unknown,
+    /// and a time in the future counts as a brand-new key. Both come with a
+    /// warning.
+    pub fn from_record(path: &Path, text: &str, now: u64) -> Self {
+        let Ok(created) = text.trim().parse::<u64>() else {
+            return KeyAge {
+                age: None,
+                warning: Some(format!(
+                    "Ignoring malformed key creation time in {}",
+                    path.display()
+                )),
+            };
+        };
+
+        if created < EARLIEST_KEY_CREATION {
+            return KeyAge {
+                age: None,
+                warning: Some(format!(
+                    "Ignoring implausible key creation time {created} in {} (was the clock wrong?)",
+                    path.display()
+                )),
+            };
+        }
+
+        match now.checked_sub(created) {
+            Some(age) => KeyAge {
+                age: Some(Duration::from_secs(age)),
+                warning: None,
+            },
+            None => KeyAge {
+                age: Some(Duration::ZERO),
+                warning: (created - now > CLOCK_SKEW_TOLERANCE_SECS).then(|| {
+                    format!(
+                        "Key creation time in {} is {}s in the future (clock skew?); treating the key as new",
+                        path.display(),
+                        created - now
+                    )
+                }),
+            },
+        }
+    }
+
+    /// Age in whole days, if known
+    pub fn days(&self) -> Option<u64> {
+        self.age.map(|age| age.as_secs() / 86_400)
+    }
+}
+
+/// Seconds since the Unix epoch; 0 on a clock set before it
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Environment variable that supplies passphrases instead of prompting
+/// (for scripts and tests; prefer the prompt interactively)
+pub const PASSPHRASE_ENV: &str = "GIT_CRYPT_PASSPHRASE";
+
+/// Read a passphrase from [`PASSPHRASE_ENV`], or prompt for it on the terminal.
+///
+/// With `confirm`, an interactive passphrase must be typed twice.
+pub fn read_passphrase(prompt: &str, confirm: bool) -> Result<Zeroizing<String>> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(Zeroizing::new(passphrase));
+    }
+
+    let passphrase = Zeroizing::new(rpassword::prompt_password(format!("{prompt}: "))?);
+    if confirm
+        && *Zeroizing::new(rpassword::prompt_password("Confirm passphrase: ")?) != *passphrase
+    {
+        return Err(GitCryptError::Other("Passphrases do not match".into()));
+    }
+    if passphrase.is_empty() {
+        return Err(GitCryptError::Other("Passphrase must not be empty".into()));
+    }
+    Ok(passphrase)
+}
+
+/// Argon2id cost of new passphrase-protected keys: OWASP's recommended
+/// minimum of 19 MiB, 2 passes and 1 lane
+const KDF_MEMORY_KIB: u32 = 19 * 1024;
+const KDF_PASSES: u32 = 2;
+const KDF_LANES: u32 = 1;
+
+/// Size of the random salt in the KDF metadata
+const KDF_SALT_SIZE: usize = 16;
+
+/// How a passphrase becomes the key that wraps a protected key file.
+///
+/// Stored as one line in the `.kdf` file next to the key:
+///
+/// ```text
+/// argon2id m=19456 t=2 p=1 salt=<32 hex digits>
+/// ```
+///
+/// The key file then holds the usual key file contents (raw, or upstream
+/// git-crypt format) encrypted with [`CryptoKey::encrypt`] under the derived
+/// key, so a wrong passphrase fails authentication instead of yielding a
+/// wrong key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KdfParams {
+    memory_kib: u32,
+    passes: u32,
+    lanes: u32,
+    salt: [u8; KDF_SALT_SIZE],
+}
+
+impl KdfParams {
+    /// Default cost with a fresh random salt
+    fn generate() -> Self {
+        let mut salt = [0u8; KDF_SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            memory_kib: KDF_MEMORY_KIB,
+            passes: KDF_PASSES,
+            lanes: KDF_LANES,
+            salt,
+        }
+    }
+
+    /// Parse the `.kdf` line; `None` if it is malformed
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut fields = text.split_whitespace();
+        if fields.next()? != "argon2id" {
+            return None;
+        }
+        let (mut memory_kib, mut passes, mut lanes, mut salt) = (None, None, None, None);
+        for field in fields {
+            match field.split_once('=')? {
+                ("m", value) => memory_kib = Some(value.parse().ok()?),
+                ("t", value) => passes = Some(value.parse().ok()?),
+                ("p", value) => lanes = Some(value.parse().ok()?),
+                ("salt", value) => salt = Some(hex::decode(value).ok()?.try_into().ok()?),
+                _ => return None,
+            }
+        }
+        Some(KdfParams {
+            memory_kib: memory_kib?,
+            passes: passes?,
+            lanes: lanes?,
+            salt: salt?,
+        })
+    }
+
+    /// The `.kdf` line
+    pub fn to_line(&self) -> String {
+        format!(
+            "argon2id m={} t={} p={} salt={}\n",
+            self.memory_kib,
+            self.passes,
+            self.lanes,
+            hex::encode(self.salt)
+        )
+    }
+
+    /// The key that wraps the key file, derived from `passphrase`
+    fn derive(&self, passphrase: &str) -> Result<CryptoKey> {
+        let kdf_error =
+            |e: argon2::Error| GitCryptError::Crypto(format!("Key derivation failed: {e}"));
+        let params = Params::new(self.memory_kib, self.passes, self.lanes, Some(KEY_SIZE))
+            .map_err(kdf_error)?;
+        let mut derived = Zeroizing::new([0u8; KEY_SIZE]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(passphrase.as_bytes(), &self.salt, &mut *derived)
+            .map_err(kdf_error)?;
+        CryptoKey::from_bytes(&*derived)
+    }
+
+    /// Key file contents for `key`, sealed under `passphrase`
+    fn wrap(&self, key: &CryptoKey, passphrase: &str) -> Result<Zeroizing<Vec<u8>>> {
+        let wrapped = self.derive(passphrase)?.encrypt(&key.to_key_file())?;
+        Ok(Zeroizing::new(wrapped))
+    }
+
+    /// The key sealed in `wrapped` under `passphrase`
+    fn unwrap(&self, wrapped: &[u8], passphrase: &str) -> Result<CryptoKey> {
+        let contents = self
+            .derive(passphrase)?
+            .decrypt(wrapped)
+            .map(Zeroizing::new)
+            .map_err(|_| GitCryptError::Other("Wrong passphrase for the git-crypt key".into()))?;
+        decode_key(&contents)
+    }
+}
+
+/// Armor lines around a base64-encoded key
+const ARMOR_BEGIN: &str = "-----BEGIN GIT-CRYPT KEY-----";
+const ARMOR_END: &str = "-----END GIT-CRYPT KEY-----";
+
+/// Armor lines around a key share
+const SHARE_BEGIN: &str = "-----BEGIN GIT-CRYPT KEY SHARE-----";
+const SHARE_END: &str = "-----END GIT-CRYPT KEY SHARE-----";
+
+/// Hex digits of the key fingerprint stored in each share
+const SHARE_FINGERPRINT_LEN: usize = 16;
+
+/// Headers of key files encrypted for a recipient (age binary, age armor, OpenPGP armor)
+const WRAPPED_HEADERS: &[&str] = &[
+    "age-encryption.org/v1\n",
+    "-----BEGIN AGE ENCRYPTED FILE-----",
+    "-----BEGIN PGP MESSAGE-----",
+];
+
+/// Word counts of BIP39-style mnemonics (128 to 256 bits of entropy)
+const MNEMONIC_WORD_COUNTS: &[usize] = &[12, 15, 18, 21, 24];
+
+/// How a key file is encoded on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFileFormat {
+    /// Exactly 32 key bytes, as written by `export-key`
+    Raw,
+    /// 64 hex digits, optionally surrounded by whitespace
+    Hex,
+    /// Base64 key between `-----BEGIN GIT-CRYPT KEY-----` armor lines
+    Armored,
+    /// Key encrypted for an age or OpenPGP recipient; needs an identity to unwrap
+    Wrapped,
+    /// Whitespace-separated list of lowercase words
+    Mnemonic,
+    /// Key file written by upstream git-crypt (`\0GITCRYPTKEY` header)
+    Upstream,
+    /// One Shamir share of a key; needs enough other shares to rebuild it
+    Share,
+    /// None of the above
+    Unknown,
+}
+
+impl KeyFileFormat {
+    /// Human-readable name used in error messages
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            KeyFileFormat::Raw => "raw",
+            KeyFileFormat::Hex => "hex",
+            KeyFileFormat::Armored => "armored",
+            KeyFileFormat::Wrapped => "wrapped",
+            KeyFileFormat::Mnemonic => "mnemonic",
+            KeyFileFormat::Upstream => "upstream git-crypt",
+            KeyFileFormat::Share => "key share",
+            KeyFileFormat::Unknown => "unknown",
+        }
+    }
+}
+
+/// Detect the encoding of a key file from its contents.
+///
+/// Only the shape of the data is checked: an `Armored` or `Upstream` file may
+/// still fail to decode.
+pub fn probe_format(bytes: &[u8]) -> KeyFileFormat {
+    // Any 32 bytes are a valid raw key, so length wins over content
+    if raw_key_bytes(bytes).is_some() {
+        return KeyFileFormat::Raw;
+    }
+    if bytes.starts_with(UPSTREAM_KEY_MAGIC) {
+        return KeyFileFormat::Upstream;
+    }
+
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return KeyFileFormat::Unknown;
+    };
+    if WRAPPED_HEADERS
+        .iter()
+        .any(|header| text.starts_with(header))
+    {
+        return KeyFileFormat::Wrapped;
+    }
+
+    let text = text.trim();
+    if text.starts_with(SHARE_BEGIN) && text.ends_with(SHARE_END) {
+        return KeyFileFormat::Share;
+    }
+    if text.starts_with(ARMOR_BEGIN) && text.ends_with(ARMOR_END) {
+        return KeyFileFormat::Armored;
+    }
+    if text.len() == KEY_SIZE * 2 && text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return KeyFileFormat::Hex;
+    }
+
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if MNEMONIC_WORD_COUNTS.contains(&words.len())
+        && words
+            .iter()
+            .all(|word| word.bytes().all(|b| b.is_ascii_lowercase()))
+    {
+        return KeyFileFormat::Mnemonic;
+    }
+
+    KeyFileFormat::Unknown
+}
+
+/// The key bytes of a raw key file, allowing one trailing `\n` or `\r\n`
+/// (as left by `echo "$KEY" > key.bin` or an editor)
+fn raw_key_bytes(bytes: &[u8]) -> Option<&[u8]> {
+    let trimmed = bytes
+        .strip_suffix(b"\r\n")
+        .or_else(|| bytes.strip_suffix(b"\n"))
+        .filter(|trimmed| trimmed.len() == KEY_SIZE);
+    match bytes.len() {
+        KEY_SIZE => Some(bytes),
+        _ => trimmed,
+    }
+}
+
+/// Encode `key` as armored base64 text, the [`KeyFileFormat::Armored`] format
+pub fn armor_key(key: &CryptoKey) -> String {
+    let body = Zeroizing::new(base64::engine::general_purpose::STANDARD.encode(key.as_bytes()));
+    format!("{ARMOR_BEGIN}\n{}\n{ARMOR_END}\n", body.as_str())
+}
+
+/// Read and decode the key file at `path`; the file contents are wiped from
+/// memory once decoded
+pub fn read_key_file(path: &Path) -> Result<CryptoKey> {
+    decode_key(&Zeroizing::new(fs::read(path)?))
+}
+
+/// Key path that stands for stdin (`import-key -`, `unlock --key-file -`)
+pub const STDIN_KEY_PATH: &str = "-";
+
+/// Most bytes read from stdin for a key; every format is far smaller
+const MAX_KEY_INPUT: u64 = 64 * 1024;
+
+/// Read a key from `path`, or from stdin when it is [`STDIN_KEY_PATH`], so
+/// a key held in a CI secret never has to be written to disk
+pub fn read_key_input(path: &Path) -> Result<CryptoKey> {
+    if path != Path::new(STDIN_KEY_PATH) {
+        return read_key_file(path);
+    }
+    read_key_from(io::stdin().lock())
+}
+
+/// Read a key in any format [`decode_key`] accepts from `reader`
+pub fn read_key_from(reader: impl Read) -> Result<CryptoKey> {
+    let mut bytes = Zeroizing::new(Vec::new());
+    reader.take(MAX_KEY_INPUT + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > MAX_KEY_INPUT {
+        return Err(GitCryptError::InvalidKeyFormat);
+    }
+    decode_key(&bytes)
+}
+
+/// Decode a key file in any format that holds the key in the clear
+pub fn decode_key(bytes: &[u8]) -> Result<CryptoKey> {
+    let format = probe_format(bytes);
+    let unsupported = |hint: &str| {
+        GitCryptError::Other(format!(
+            "Key file is in {} format, which can't be read directly ({hint})",
+            format.as_str()
+        ))
+    };
+
+    match format {
+        KeyFileFormat::Raw => CryptoKey::from_bytes(raw_key_bytes(bytes).unwrap_or(bytes)),
+        KeyFileFormat::Hex => {
+            let text = std::str::from_utf8(bytes).map_err(|_| GitCryptError::InvalidKeyFormat)?;
+            let decoded = hex::decode(text.trim())
+                .map(Zeroizing::new)
+                .map_err(|_| GitCryptError::InvalidKeyFormat)?;
+            CryptoKey::from_bytes(&decoded)
+        }
+        KeyFileFormat::Armored => {
+            let text = std::str::from_utf8(bytes).map_err(|_| GitCryptError::InvalidKeyFormat)?;
+            let body: Zeroizing<String> = text
+                .trim()
+                .trim_start_matches(ARMOR_BEGIN)
+                .trim_end_matches(ARMOR_END)
+                .split_whitespace()
+                .collect::<String>()
+                .into();
+            let decoded = base64::engine::general_purpose::STANDARD
+                .decode(body.as_bytes())
+                .map(Zeroizing::new)
+                .map_err(|_| GitCryptError::InvalidKeyFormat)?;
+            CryptoKey::from_bytes(&decoded)
+        }
+        KeyFileFormat::Wrapped => Err(unsupported(
+            "decrypt it with import-age-key or your GPG identity first",
+        )),
+        KeyFileFormat::Mnemonic => Err(unsupported("mnemonic keys are not supported yet")),
+        KeyFileFormat::Upstream => CryptoKey::from_git_crypt_key_file(bytes),
+        KeyFileFormat::Share => Err(unsupported(
+            "combine enough shares with import-key --combine",
+        )),
+        KeyFileFormat::Unknown => Err(GitCryptError::InvalidKeyFormat),
+    }
+}
+
+/// One Shamir share of a key, as written by `export-key --shares`
+#[derive(Clone, PartialEq, Eq)]
+pub struct KeyShare {
+    /// Evaluation point, 1 to 255; unique within a split
+    pub index: u8,
+    /// Number of shares needed to rebuild the key
+    pub threshold: u8,
+    /// Prefix of the key fingerprint, to detect mixed or missing shares
+    pub fingerprint: String,
+    pub data: [u8; KEY_SIZE],
+}
+
+impl KeyShare {
+    /// Encode the share as armored text
+    pub fn to_armored(&self) -> String {
+        format!(
+            "{SHARE_BEGIN}\nIndex: {}\nThreshold: {}\nKey: {}\n\n{}\n{SHARE_END}\n",
+            self.index,
+            self.threshold,
+            self.fingerprint,
+            base64::engine::general_purpose::STANDARD.encode(self.data)
+        )
+    }
+
+    /// Parse a share written by [`Self::to_armored`]
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let invalid = |what: &str| GitCryptError::Other(format!("Invalid key share: {what}"));
+        if probe_format(bytes) != KeyFileFormat::Share {
+            return Err(invalid("missing share armor"));
+        }
+        let text = std::str::from_utf8(bytes).map_err(|_| invalid("not UTF-8"))?;
+
+        let (mut index, mut threshold, mut fingerprint) = (None, None, None);
+        let mut body = String::new();
+        for line in text.lines().map(str::trim) {
+            if line == SHARE_BEGIN || line == SHARE_END || line.is_empty() {
+                continue;
+            }
+            match line.split_once(": ") {
+                Some(("Index", value)) => index = value.parse::<u8>().ok(),
+                Some(("Threshold", value)) => threshold = value.parse::<u8>().ok(),
+                Some(("Key", value)) => fingerprint = Some(value.to_string()),
+                _ => body.push_str(line),
+            }
+        }
+
+        let index = index
+            .filter(|&i| i > 0)
+            .ok_or_else(|| invalid("bad index"))?;
+        let threshold = threshold
+            .filter(|&t| t >= 2)
+            .ok_or_else(|| invalid("bad threshold"))?;
+        let fingerprint = fingerprint.ok_or_else(|| invalid("missing key fingerprint"))?;
+        let data = base64::engine::general_purpose::STANDARD
+            .decode(body)
+            .ok()
+            .and_then(|data| <[u8; KEY_SIZE]>::try_from(data).ok())
+            .ok_or_else(|| invalid("bad share data"))?;
+
+        Ok(Self {
+            index,
+            threshold,
+            fingerprint,
+            data,
+        })
+    }
+}
+
+/// Split `key` into `shares` Shamir shares, any `threshold` of which rebuild it
+pub fn split_key(key: &CryptoKey, shares: u8, threshold: u8) -> Result<Vec<KeyShare>> {
+    if threshold < 2 || threshold > shares {
+        return Err(GitCryptError::Other(format!(
+            "Invalid share split {threshold}-of-{shares}: need 2 <= threshold <= shares"
+        )));
+    }
+
+    // One random polynomial per key byte, with the byte as constant term
+    let mut coefficients = vec![[0u8; KEY_SIZE]; threshold as usize];
+    coefficients[0].copy_from_slice(key.as_bytes());
+    for row in &mut coefficients[1..] {
+        OsRng.fill_bytes(row);
+    }
+
+    let fingerprint = key.fingerprint()[..SHARE_FINGERPRINT_LEN].to_string();
+    Ok((1..=shares)
+        .map(|x| {
+            let mut data = [0u8; KEY_SIZE];
+            for (i, byte) in data.iter_mut().enumerate() {
+                // Horner's rule, highest coefficient first
+                *byte = coefficients
+                    .iter()
+                    .rev()
+                    .fold(0, |acc, row| gf_mul(acc, x) ^ row[i]);
+            }
+            KeyShare {
+                index: x,
+                threshold,
+                fingerprint: fingerprint.clone(),
+                data,
+            }
+        })
+        .collect())
+}
+
+/// Rebuild a key from at least `threshold` shares of the same split
+pub fn combine_shares(shares: &[KeyShare]) -> Result<CryptoKey> {
+    let Some(first) = shares.first() else {
+        return Err(GitCryptError::Other("No key shares given".into()));
+    };
+    if shares.iter().any(|s| s.fingerprint != first.fingerprint) {
+        return Err(GitCryptError::Other(
+            "Key shares belong to different keys".into(),
+        ));
+    }
+    for (i, share) in shares.iter().enumerate() {
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(GitCryptError::Other(format!(
+                "Key share {} was given more than once",
+                share.index
+            )));
+        }
+    }
+    if shares.len() < first.threshold as usize {
+        return Err(GitCryptError::Other(format!(
+            "Not enough key shares: {} given, {} needed",
+            shares.len(),
+            first.threshold
+        )));
+    }
+
+    // Lagrange interpolation at x = 0 (subtraction is XOR in GF(256))
+    let mut secret = Zeroizing::new([0u8; KEY_SIZE]);
+    for share in shares {
+        let basis = shares
+            .iter()
+            .filter(|other| other.index != share.index)
+            .fold(1, |acc, other| {
+                gf_mul(acc, gf_div(other.index, other.index ^ share.index))
+            });
+        for (byte, y) in secret.iter_mut().zip(share.data) {
+            *byte ^= gf_mul(y, basis);
+        }
+    }
+
+    let key = CryptoKey::from_bytes(secret.as_slice())?;
+    if key.fingerprint()[..SHARE_FINGERPRINT_LEN] != first.fingerprint {
+        return Err(GitCryptError::Other(
+            "Key shares don't reconstruct the key (corrupted share?)".into(),
+        ));
+    }
+    Ok(key)
+}
+
+/// Multiply in GF(256) with the AES polynomial, without secret-dependent branches
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    for _ in 0..8 {
+        product ^= a & (b & 1).wrapping_neg();
+        let carry = (a >> 7).wrapping_neg();
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Divide in GF(256); `b` must be non-zero
+fn gf_div(a: u8, b: u8) -> u8 {
+    // b^254 is the inverse of b
+    let mut inverse = 1;
+    for _ in 0..254 {
+        inverse = gf_mul(inverse, b);
+    }
+    gf_mul(a, inverse)
+}
+
+/// Overwrite and delete every file below `dir`, returning how many were erased
+fn erase_dir(dir: &Path) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+
+    let mut erased = 0;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && !path.is_symlink() {
+            erased += erase_dir(&path)?;
+            continue;
+        }
+
+        if !path.is_symlink() {
+            let len = fs::metadata(&path)?.len() as usize;
+            let mut file = OpenOptions::new().write(true).open(&path)?;
+            file.write_all(&vec![0u8; len])?;
+            file.sync_all()?;
+        }
+        fs::remove_file(&path)?;
+        erased += 1;
+    }
+    Ok(erased)
+}
+
+/// Write `contents` to the key file at `path` so that it is either fully
+/// written or untouched.
+///
+/// The key goes to `<path>.tmp`, restricted to its owner before any key
+/// byte is written, synced, then renamed over `path`. A crash or full disk
+/// leaves at worst a stale `.tmp` beside an intact key, which the next write
+/// replaces. A `path` that exists but isn't a regular file (`/dev/stdout`, a
+/// FIFO) is written directly, since renaming over it would replace it.
+fn write_key_atomically(path: &Path, contents: &[u8]) -> Result<()> {
+    if fs::metadata(path).is_ok_and(|m| !m.is_file()) {
+        File::create(path)?.write_all(contents)?;
+        return Ok(());
+    }
+
+    let tmp = sibling_path(path, ".tmp");
+    write_key_tmp(&tmp, contents)?;
+    if let Err(e) = fs::rename(&tmp, path) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    sync_parent_dir(path);
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Write `contents` to the temporary key file `tmp`, restricted to its owner
+/// before any key byte is written, and sync it. Removes `tmp` on failure.
+fn write_key_tmp(tmp: &Path, contents: &[u8]) -> Result<()> {
+    let written = (|| -> Result<()> {
+        let mut file = File::create(tmp)?;
+        restrict_key_file(tmp);
+        file.write_all(contents)?;
+        file.sync_all()?;
+        Ok(())
+    })();
+    if written.is_err() {
+        let _ = fs::remove_file(tmp);
+    }
+    written
+}
+
+/// Make a rename or link in `path`'s directory durable; not every platform
+/// can sync a directory
+fn sync_parent_dir(path: &Path) {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        let _ = File::open(dir).and_then(|dir| dir.sync_all());
+    }
+}
+
+/// Restrict a key file to its owner, warning when that can't be enforced
+/// (no Unix modes, or a FAT/network filesystem that ignores them)
+fn restrict_key_file(path: &Path) {
+    if !restrict_to_owner(path) {
+        eprintln!("{}", unrestricted_warning(path));
+    }
+}
+
+#[cfg(unix)]
+fn restrict_to_owner(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    if fs::set_permissions(path, fs::Permissions::from_mode(0o600)).is_err() {
+        return false;
+    }
+    // Some filesystems accept chmod without storing the mode; check what stuck
+    fs::metadata(path).is_ok_and(|m| is_owner_only(m.permissions().mode()))
+}
+
+#[cfg(windows)]
+fn restrict_to_owner(path: &Path) -> bool {
+    use std::process::{Command, Stdio};
+
+    // Drop inherited ACL entries and grant only the current user access
+    let Ok(user) = std::env::var("USERNAME") else {
+        return false;
+    };
+    Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r", &format!("{user}:F")])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn restrict_to_owner(_path: &Path) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn is_owner_only(mode: u32) -> bool {
+    mode & 0o077 == 0
+}
+
+fn unrestricted_warning(path: &Path) -> String {
+    format!(
+        "Warning: could not restrict permissions on {}; other users may be able to read the key",
+        path.display()
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,21 +1675,169 @@ mod tests {
     }
 
     #[test]
-    fn test_git_crypt_dir_path() {
-        let temp = create_test_git_dir();
-        let key_manager = KeyManager::new(temp.path());
-
-        let expected = temp.path().join("git-crypt");
-        assert_eq!(key_manager.git_crypt_dir(), expected);
+    fn test_git_crypt_dir_path() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+
+        let expected = temp.path().join("git-crypt");
+        assert_eq!(key_manager.git_crypt_dir(), expected);
+    }
+
+    #[test]
+    fn test_default_key_path() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+
+        let expected = temp.path().join("git-crypt").join("keys").join("default");
+        assert_eq!(key_manager.default_key_path(), expected);
+    }
+
+    #[test]
+    fn test_key_dir_replaces_git_crypt_dir() {
+        let temp = create_test_git_dir();
+        let key_dir = temp.path().join("elsewhere");
+        let key_manager = KeyManager::new(temp.path()).with_key_dir(&key_dir);
+
+        assert_eq!(key_manager.git_crypt_dir(), key_dir);
+        assert_eq!(
+            key_manager.default_key_path(),
+            key_dir.join("keys").join("default")
+        );
+        assert_eq!(
+            key_manager.named_key("ci").default_key_path(),
+            key_dir.join("keys").join("ci")
+        );
+        assert_eq!(
+            key_manager.named_key("ci").state_path().parent(),
+            Some(key_dir.as_path())
+        );
+    }
+
+    #[test]
+    fn test_interrupted_save_leaves_key_intact() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let original = CryptoKey::generate();
+        key_manager.save_key(&original).unwrap();
+        let key_path = key_manager.default_key_path();
+        let tmp = key_path.with_file_name("default.tmp");
+        assert!(!tmp.exists());
+
+        // A save killed mid-write leaves only a short temp file behind
+        fs::write(&tmp, &CryptoKey::generate().as_bytes()[..10]).unwrap();
+        assert_eq!(
+            key_manager.load_key().unwrap().as_bytes(),
+            original.as_bytes()
+        );
+        assert_eq!(key_manager.key_names().unwrap(), ["default"]);
+
+        // The next save replaces it
+        let replacement = CryptoKey::generate();
+        key_manager.save_key(&replacement).unwrap();
+        assert!(!tmp.exists());
+        assert_eq!(
+            key_manager.load_key().unwrap().as_bytes(),
+            replacement.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_interrupted_generate_leaves_no_key() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key_path = key_manager.default_key_path();
+        let own_tmp = key_path.with_file_name(format!("default.{}.tmp", std::process::id()));
+
+        // An init killed mid-write leaves only a short temp file behind,
+        // which doesn't count as a key
+        let stale = key_path.with_file_name("default.1.tmp");
+        fs::write(&stale, &CryptoKey::generate().as_bytes()[..10]).unwrap();
+        assert!(!key_manager.has_key());
+        assert!(key_manager.key_names().unwrap().is_empty());
+
+        let key = key_manager.generate_key().unwrap();
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+        assert!(!own_tmp.exists());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        // A second generate is refused and cleans up after itself
+        assert!(matches!(
+            key_manager.generate_key(),
+            Err(GitCryptError::AlreadyInitialized)
+        ));
+        assert!(!own_tmp.exists());
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_export_key_writes_atomically() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key = key_manager.generate_key().unwrap();
+
+        let output = temp.path().join("exported.key");
+        fs::write(&output, b"old contents").unwrap();
+        key_manager.export_key(&output).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), key.as_bytes());
+        assert!(!temp.path().join("exported.key.tmp").exists());
+
+        // A write that can't start leaves no temp file behind
+        let missing_dir = temp.path().join("missing").join("exported.key");
+        assert!(key_manager.export_key(&missing_dir).is_err());
+        assert!(!temp.path().join("missing").exists());
+    }
+
+    #[test]
+    fn test_kdf_params_round_trip_and_reject_garbage() {
+        let kdf = KdfParams::generate();
+        assert_eq!(KdfParams::parse(&kdf.to_line()), Some(kdf));
+        assert_eq!(KdfParams::parse("salt"), None);
+        assert_eq!(KdfParams::parse("argon2id m=1 t=1 p=1"), None);
+        assert_eq!(KdfParams::parse("argon2id m=1 t=1 p=1 salt=00 x=1"), None);
+        assert_eq!(KdfParams::parse("scrypt m=1 t=1 p=1 salt=00"), None);
     }
 
     #[test]
-    fn test_default_key_path() {
+    fn test_save_and_load_key_encrypted() {
         let temp = create_test_git_dir();
         let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key = CryptoKey::generate();
 
-        let expected = temp.path().join("git-crypt").join("keys").join("default");
-        assert_eq!(key_manager.default_key_path(), expected);
+        key_manager.save_key_encrypted(&key, "hunter2").unwrap();
+        assert!(key_manager.is_passphrase_protected());
+        let on_disk = fs::read(key_manager.default_key_path()).unwrap();
+        assert!(!on_disk
+            .windows(KEY_SIZE)
+            .any(|window| window == key.as_bytes()));
+
+        let loaded = key_manager.load_key_encrypted("hunter2").unwrap();
+        assert_eq!(loaded.as_bytes(), key.as_bytes());
+        let err = key_manager.load_key_encrypted("hunter3").err().unwrap();
+        assert!(err.to_string().contains("Wrong passphrase"), "{err}");
+
+        // Re-wrapping keeps the salt, so only the key file changes
+        let kdf = fs::read(key_manager.kdf_metadata_path()).unwrap();
+        let replacement = CryptoKey::generate();
+        key_manager
+            .save_key_encrypted(&replacement, "new passphrase")
+            .unwrap();
+        assert_eq!(fs::read(key_manager.kdf_metadata_path()).unwrap(), kdf);
+        assert_eq!(
+            key_manager
+                .load_key_encrypted("new passphrase")
+                .unwrap()
+                .as_bytes(),
+            replacement.as_bytes()
+        );
     }
 
     #[test]
@@ -232,6 +1888,53 @@ mod tests {
         assert_eq!(key1.as_bytes(), key2.as_bytes());
     }
 
+    #[test]
+    fn test_load_key_rejects_key_not_matching_check_value() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key = key_manager.generate_key().unwrap();
+        assert_eq!(
+            fs::read_to_string(key_manager.check_value_path()).unwrap(),
+            format!("{}\n", key.check_value())
+        );
+
+        // Swapped behind git-crypt's back
+        let other = CryptoKey::generate();
+        fs::write(key_manager.default_key_path(), other.as_bytes()).unwrap();
+        let err = key_manager.load_key().err().unwrap();
+        assert!(matches!(err, GitCryptError::KeyCheckMismatch(_)), "{err}");
+
+        // Saving through the manager records the new check value
+        key_manager.save_key(&other).unwrap();
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), other.as_bytes());
+
+        // Keys saved before check values existed load unchecked
+        fs::remove_file(key_manager.check_value_path()).unwrap();
+        fs::write(key_manager.default_key_path(), key.as_bytes()).unwrap();
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_generate_key_never_overwrites() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+
+        key_manager.init_dirs().unwrap();
+        assert!(!key_manager.has_key());
+        let original = key_manager.generate_key().unwrap();
+        assert!(key_manager.has_key());
+
+        let Err(err) = key_manager.generate_key() else {
+            panic!("generate_key replaced an existing key");
+        };
+        assert!(matches!(err, GitCryptError::AlreadyInitialized));
+        assert_eq!(
+            key_manager.load_key().unwrap().as_bytes(),
+            original.as_bytes()
+        );
+    }
+
     #[test]
     fn test_load_key_before_init_fails() {
         let temp = create_test_git_dir();
@@ -282,6 +1985,67 @@ mod tests {
         assert_eq!(original_key.as_bytes(), imported_key.as_bytes());
     }
 
+    #[test]
+    fn test_read_key_from_validates_like_a_file() {
+        let key = CryptoKey::generate();
+        assert_eq!(
+            read_key_from(key.as_bytes()).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+        assert_eq!(
+            read_key_from(armor_key(&key).as_bytes())
+                .unwrap()
+                .as_bytes(),
+            key.as_bytes()
+        );
+        assert!(read_key_from(&key.as_bytes()[..31]).is_err());
+        let oversized = vec![b'a'; MAX_KEY_INPUT as usize + 1];
+        assert!(matches!(
+            read_key_from(oversized.as_slice()),
+            Err(GitCryptError::InvalidKeyFormat)
+        ));
+    }
+
+    #[test]
+    fn test_armored_export_round_trips() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let original_key = key_manager.generate_key().unwrap();
+
+        let export_path = temp.path().join("exported.asc");
+        key_manager.export_key_armored(&export_path).unwrap();
+        let armored = fs::read(&export_path).unwrap();
+        assert!(armored.starts_with(ARMOR_BEGIN.as_bytes()));
+        assert_eq!(probe_format(&armored), KeyFileFormat::Armored);
+
+        let temp2 = create_test_git_dir();
+        let key_manager2 = KeyManager::new(temp2.path());
+        key_manager2.init_dirs().unwrap();
+        key_manager2.import_key(&export_path).unwrap();
+
+        assert_eq!(
+            key_manager2.load_key().unwrap().as_bytes(),
+            original_key.as_bytes()
+        );
+        assert_eq!(
+            fs::read(key_manager2.default_key_path()).unwrap(),
+            original_key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_export_key_to_writer_writes_raw_bytes() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key = key_manager.generate_key().unwrap();
+
+        let mut out = Vec::new();
+        key_manager.export_key_to_writer(&mut out).unwrap();
+        assert_eq!(out, key.as_bytes());
+    }
+
     #[test]
     fn test_export_key_without_init_fails() {
         let temp = create_test_git_dir();
@@ -293,6 +2057,35 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_import_records_provenance() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+
+        let key = CryptoKey::generate();
+        let key_path = temp.path().join("shared.key");
+        fs::write(&key_path, key.as_bytes()).unwrap();
+        key_manager.import_key(&key_path).unwrap();
+
+        let log = fs::read_to_string(key_manager.import_log_path()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].contains(&format!("source={}", key_path.display())));
+        assert!(lines[0].contains(&format!("fingerprint={}", key.fingerprint())));
+        assert!(!log.contains(&hex::encode(key.as_bytes())));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(key_manager.import_log_path())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
     #[test]
     fn test_import_invalid_key_file() {
         let temp = create_test_git_dir();
@@ -307,6 +2100,262 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_key_age_from_creation_record() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        km.init_dirs().unwrap();
+        assert_eq!(km.key_age().unwrap(), KeyAge::default());
+
+        km.generate_key().unwrap();
+        let age = km.key_age().unwrap();
+        assert!(age.age.unwrap() < Duration::from_secs(60));
+        assert_eq!(age.warning, None);
+
+        // Imported keys have no known creation time
+        let exported = temp.path().join("exported.key");
+        km.export_key(&exported).unwrap();
+        km.import_key(&exported).unwrap();
+        assert_eq!(km.key_age().unwrap(), KeyAge::default());
+    }
+
+    #[test]
+    fn test_key_age_tolerates_bad_clocks() {
+        let path = Path::new("keys/default.created");
+        let now = 1_700_000_000;
+
+        let age = KeyAge::from_record(path, "1699913600\n", now);
+        assert_eq!(age.days(), Some(1));
+        assert_eq!(age.warning, None);
+
+        // Slightly ahead: clock skew within tolerance, no warning
+        let age = KeyAge::from_record(path, &(now + 60).to_string(), now);
+        assert_eq!(age.age, Some(Duration::ZERO));
+        assert_eq!(age.warning, None);
+
+        // Far in the future: treated as new, with a warning
+        let age = KeyAge::from_record(path, &(now + 86_400 * 365).to_string(), now);
+        assert_eq!(age.age, Some(Duration::ZERO));
+        assert!(age.warning.unwrap().contains("in the future"));
+
+        // Before git-crypt-rs existed: unknown
+        let age = KeyAge::from_record(path, "86400", now);
+        assert_eq!(age.age, None);
+        assert!(age.warning.unwrap().contains("implausible"));
+
+        for malformed in ["", "yesterday", "-5", "1.5e9"] {
+            let age = KeyAge::from_record(path, malformed, now);
+            assert_eq!(age.age, None);
+            assert!(age.warning.unwrap().contains("malformed"), "{malformed}");
+        }
+    }
+
+    #[test]
+    fn test_key_names_skip_side_files() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        assert!(km.key_names().unwrap().is_empty());
+
+        km.init_dirs().unwrap();
+        km.generate_key().unwrap();
+        km.set_key_generation(2).unwrap();
+        km.retire_key(&CryptoKey::generate(), 1).unwrap();
+        fs::write(km.keys_dir().join("staging"), [1u8; 32]).unwrap();
+        fs::create_dir(km.keys_dir().join("gpg")).unwrap();
+        km.record_import("test", &CryptoKey::generate()).unwrap();
+
+        assert_eq!(km.key_names().unwrap(), ["default", "staging"]);
+        let staging = km.named_key("staging");
+        assert_eq!(staging.load_key().unwrap().as_bytes(), [1u8; 32]);
+        assert_eq!(staging.key_generation().unwrap(), None);
+    }
+
+    #[test]
+    fn test_load_all_keys_puts_current_key_first() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        km.init_dirs().unwrap();
+        let current = km.generate_key().unwrap();
+        km.set_key_generation(3).unwrap();
+        let gen1 = CryptoKey::generate();
+        let gen2 = CryptoKey::generate();
+        km.retire_key(&gen1, 1).unwrap();
+        km.retire_key(&gen2, 2).unwrap();
+        fs::write(km.keys_dir().join("staging"), [1u8; 32]).unwrap();
+        fs::write(km.keys_dir().join("default.gen9"), b"not a key").unwrap();
+
+        let keys: Vec<_> = km
+            .load_all_keys()
+            .unwrap()
+            .iter()
+            .map(|key| (key.as_bytes().to_vec(), key.generation()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                (current.as_bytes().to_vec(), Some(3)),
+                (gen2.as_bytes().to_vec(), Some(2)),
+                (gen1.as_bytes().to_vec(), Some(1)),
+                (vec![1u8; 32], None),
+            ]
+        );
+
+        // A named key doesn't fall back to the repository key
+        let staging = km.named_key("staging").load_all_keys().unwrap();
+        assert_eq!(staging.len(), 1);
+        assert_eq!(staging[0].as_bytes(), [1u8; 32]);
+    }
+
+    #[test]
+    fn test_with_key_name_isolates_key_and_lock_state() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        let team = KeyManager::new(temp.path()).with_key_name("team-a");
+        assert_eq!(team.default_key_path(), km.keys_dir().join("team-a"));
+        assert_ne!(team.state_path(), km.state_path());
+        assert_eq!(
+            KeyManager::new(temp.path())
+                .with_key_name("default")
+                .state_path(),
+            km.state_path()
+        );
+
+        km.init_dirs().unwrap();
+        team.generate_key().unwrap();
+        assert!(team.has_key() && !km.has_key());
+        assert!(matches!(
+            km.load_key(),
+            Err(GitCryptError::KeyNotFound(name)) if name == "default"
+        ));
+
+        assert!(validate_key_name("team_b-2").is_ok());
+        for bad in ["", "default", "a/b", "a.b", "with space"] {
+            assert!(validate_key_name(bad).is_err(), "{bad:?} accepted");
+        }
+    }
+
+    #[test]
+    fn test_key_generation_tags_loaded_key() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        key_manager.generate_key().unwrap();
+
+        assert_eq!(key_manager.key_generation().unwrap(), None);
+        assert_eq!(key_manager.load_key().unwrap().generation(), None);
+
+        key_manager.set_key_generation(3).unwrap();
+        assert_eq!(
+            key_manager.generation_path(),
+            temp.path().join("git-crypt/keys/default.generation")
+        );
+        assert_eq!(key_manager.key_generation().unwrap(), Some(3));
+        assert_eq!(key_manager.load_key().unwrap().generation(), Some(3));
+
+        fs::write(key_manager.generation_path(), "three\n").unwrap();
+        assert!(key_manager.key_generation().is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_save_key_through_symlinked_key_path() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp = create_test_git_dir();
+        let mount = TempDir::new().unwrap();
+        let target = mount.path().join("repo.key");
+        let original = CryptoKey::generate();
+        fs::write(&target, original.as_bytes()).unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o640)).unwrap();
+
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let link = key_manager.default_key_path();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        assert_eq!(
+            key_manager.load_key().unwrap().as_bytes(),
+            original.as_bytes()
+        );
+
+        // Refused by default, leaving the target alone
+        let replacement = CryptoKey::generate();
+        let Err(err) = key_manager.save_key(&replacement) else {
+            panic!("saving through a symlink should be refused");
+        };
+        assert!(err.to_string().contains("is a symlink"));
+        assert_eq!(fs::read(&target).unwrap(), original.as_bytes());
+
+        // Followed on request: the link survives and the target is replaced
+        // whole, owner-only like any other key file
+        let key_manager = key_manager.follow_key_symlink(true);
+        key_manager.save_key(&replacement).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), replacement.as_bytes());
+        assert_eq!(
+            fs::metadata(&target).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert!(!mount.path().join("repo.key.tmp").exists());
+    }
+
+    #[test]
+    fn test_with_key_path_stores_key_outside_git_dir() {
+        let temp = create_test_git_dir();
+        let elsewhere = TempDir::new().unwrap();
+        let key_path = elsewhere.path().join("mounted").join("repo.key");
+        let key_manager = KeyManager::with_key_path(temp.path(), &key_path);
+
+        assert_eq!(key_manager.default_key_path(), key_path);
+        assert_eq!(
+            key_manager.kdf_metadata_path(),
+            elsewhere.path().join("mounted").join("repo.kdf")
+        );
+        assert!(!key_manager.is_initialized());
+
+        let key = key_manager.generate_key().unwrap();
+        assert!(key_path.exists());
+        assert!(key_manager.is_initialized());
+        assert!(!temp.path().join("git-crypt/keys/default").exists());
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_import_raw_key_with_trailing_newline() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+
+        let key = CryptoKey::generate();
+        for suffix in [&b""[..], b"\n", b"\r\n"] {
+            let path = temp.path().join("raw.key");
+            fs::write(&path, [key.as_bytes(), suffix].concat()).unwrap();
+
+            key_manager.import_key(&path).unwrap();
+            assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_wrong_sized_raw_keys() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+
+        // Only a single trailing newline is trimmed
+        let path = temp.path().join("raw.key");
+        fs::write(&path, [&[7u8; 32][..], b"\n\n"].concat()).unwrap();
+        assert!(key_manager.import_key(&path).is_err());
+
+        fs::write(&path, [7u8; 40]).unwrap();
+        assert!(key_manager.import_key(&path).is_err());
+
+        fs::write(&path, [&[7u8; 31][..], b"\r\n"].concat()).unwrap();
+        assert!(key_manager.import_key(&path).is_err());
+    }
+
     #[test]
     fn test_import_nonexistent_file() {
         let temp = create_test_git_dir();
@@ -338,6 +2387,43 @@ mod tests {
         }
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_owner_only_mode_detection() {
+        assert!(is_owner_only(0o100600));
+        assert!(is_owner_only(0o100400));
+        assert!(!is_owner_only(0o100644));
+        assert!(!is_owner_only(0o100660));
+    }
+
+    #[test]
+    fn test_unrestricted_key_warning_names_file() {
+        let warning = unrestricted_warning(Path::new("/mnt/fat/keys/default"));
+        assert!(warning.starts_with("Warning:"));
+        assert!(warning.contains("/mnt/fat/keys/default"));
+        assert!(warning.contains("other users may be able to read the key"));
+    }
+
+    #[test]
+    fn test_purge_removes_all_key_material() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        km.init_dirs().unwrap();
+        km.generate_key().unwrap();
+        km.set_lock_state(LockState::Unlocked).unwrap();
+        let age_dir = km.git_crypt_dir().join("keys").join("age");
+        fs::create_dir_all(&age_dir).unwrap();
+        fs::write(age_dir.join("alice.age"), b"blob").unwrap();
+
+        // The key, its check value, its creation record and the age blob
+        assert_eq!(km.purge().unwrap(), 4);
+        assert!(!km.git_crypt_dir().exists());
+        assert!(!km.is_initialized());
+
+        // Nothing left to purge
+        assert_eq!(km.purge().unwrap(), 0);
+    }
+
     #[test]
     fn test_multiple_save_overwrites() {
         let temp = create_test_git_dir();
@@ -357,6 +2443,36 @@ mod tests {
         assert_ne!(key1.as_bytes(), loaded.as_bytes());
     }
 
+    #[test]
+    fn test_passphrase_protection_detected_from_kdf_metadata() {
+        let temp = create_test_git_dir();
+        let km = KeyManager::new(temp.path());
+        km.init_dirs().unwrap();
+        assert!(!km.is_passphrase_protected());
+
+        fs::write(km.kdf_metadata_path(), b"salt").unwrap();
+        assert!(km.is_passphrase_protected());
+    }
+
+    #[test]
+    fn test_lock_state_round_trip() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+
+        assert_eq!(key_manager.lock_state().unwrap(), None);
+
+        key_manager.set_lock_state(LockState::Locked).unwrap();
+        assert_eq!(key_manager.lock_state().unwrap(), Some(LockState::Locked));
+
+        key_manager.set_lock_state(LockState::Unlocked).unwrap();
+        assert_eq!(key_manager.lock_state().unwrap(), Some(LockState::Unlocked));
+
+        // Unrecognised contents are treated as unknown
+        fs::write(key_manager.state_path(), "garbage").unwrap();
+        assert_eq!(key_manager.lock_state().unwrap(), None);
+    }
+
     #[test]
     fn test_key_survives_encrypt_decrypt() {
         let temp = create_test_git_dir();
@@ -374,4 +2490,342 @@ mod tests {
 
         assert_eq!(plaintext.as_slice(), &decrypted[..]);
     }
+
+    fn decode_error(bytes: &[u8]) -> String {
+        match decode_key(bytes) {
+            Ok(_) => panic!("expected decoding to fail"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    const MNEMONIC_24: &str = "abandon ability able about above absent absorb abstract absurd \
+        abuse access accident account accuse achieve acid acoustic acquire across act action \
+        actor actress actual";
+
+    #[test]
+    fn test_probe_raw_key() {
+        let key = CryptoKey::generate();
+        assert_eq!(probe_format(key.as_bytes()), KeyFileFormat::Raw);
+    }
+
+    #[test]
+    fn test_probe_raw_key_that_looks_like_text() {
+        // 32 hex digits are a raw key, not a truncated hex key
+        assert_eq!(probe_format(&[b'a'; 32]), KeyFileFormat::Raw);
+        assert_eq!(
+            probe_format(b"0123456789abcdef0123456789abcdef"),
+            KeyFileFormat::Raw
+        );
+        // Nor is a raw key that starts with another format's header
+        let mut bytes = [0u8; 32];
+        bytes[..UPSTREAM_KEY_MAGIC.len()].copy_from_slice(UPSTREAM_KEY_MAGIC);
+        assert_eq!(probe_format(&bytes), KeyFileFormat::Raw);
+        let mut bytes = [b' '; 32];
+        bytes[..22].copy_from_slice(b"age-encryption.org/v1\n");
+        assert_eq!(probe_format(&bytes), KeyFileFormat::Raw);
+    }
+
+    #[test]
+    fn test_probe_hex_key() {
+        let hex_key = hex::encode(CryptoKey::generate().as_bytes());
+        assert_eq!(probe_format(hex_key.as_bytes()), KeyFileFormat::Hex);
+        assert_eq!(
+            probe_format(format!("{hex_key}\n").as_bytes()),
+            KeyFileFormat::Hex
+        );
+        assert_eq!(
+            probe_format(format!("  {}\r\n", hex_key.to_uppercase()).as_bytes()),
+            KeyFileFormat::Hex
+        );
+    }
+
+    #[test]
+    fn test_probe_near_miss_hex_is_unknown() {
+        let hex_key = hex::encode(CryptoKey::generate().as_bytes());
+        assert_eq!(
+            probe_format(&hex_key.as_bytes()[..63]),
+            KeyFileFormat::Unknown
+        );
+        assert_eq!(
+            probe_format(format!("{hex_key}0").as_bytes()),
+            KeyFileFormat::Unknown
+        );
+        let not_hex = format!("g{}", &hex_key[1..]);
+        assert_eq!(probe_format(not_hex.as_bytes()), KeyFileFormat::Unknown);
+        let split = format!("{} {}", &hex_key[..32], &hex_key[32..]);
+        assert_eq!(probe_format(split.as_bytes()), KeyFileFormat::Unknown);
+    }
+
+    #[test]
+    fn test_probe_armored_key() {
+        let key = CryptoKey::generate();
+        let body = base64::engine::general_purpose::STANDARD.encode(key.as_bytes());
+        let armored = format!("{ARMOR_BEGIN}\n{body}\n{ARMOR_END}\n");
+        assert_eq!(probe_format(armored.as_bytes()), KeyFileFormat::Armored);
+        assert_eq!(
+            decode_key(armored.as_bytes()).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+
+        // Missing end line is not armor
+        let truncated = format!("{ARMOR_BEGIN}\n{body}\n");
+        assert_eq!(probe_format(truncated.as_bytes()), KeyFileFormat::Unknown);
+    }
+
+    #[test]
+    fn test_probe_wrapped_keys() {
+        assert_eq!(
+            probe_format(b"age-encryption.org/v1\n-> X25519 abc\n---\x00\x01binary"),
+            KeyFileFormat::Wrapped
+        );
+        assert_eq!(
+            probe_format(
+                b"-----BEGIN AGE ENCRYPTED FILE-----\nYWdl\n-----END AGE ENCRYPTED FILE-----\n"
+            ),
+            KeyFileFormat::Wrapped
+        );
+        assert_eq!(
+            probe_format(b"-----BEGIN PGP MESSAGE-----\n\nwcBM\n-----END PGP MESSAGE-----\n"),
+            KeyFileFormat::Wrapped
+        );
+    }
+
+    #[test]
+    fn test_probe_mnemonic() {
+        assert_eq!(
+            probe_format(MNEMONIC_24.as_bytes()),
+            KeyFileFormat::Mnemonic
+        );
+        let twelve: Vec<&str> = MNEMONIC_24.split_whitespace().take(12).collect();
+        assert_eq!(
+            probe_format(twelve.join("\n").as_bytes()),
+            KeyFileFormat::Mnemonic
+        );
+
+        // Wrong word count, capitals or digits are not a mnemonic
+        let thirteen: Vec<&str> = MNEMONIC_24.split_whitespace().take(13).collect();
+        assert_eq!(
+            probe_format(thirteen.join(" ").as_bytes()),
+            KeyFileFormat::Unknown
+        );
+        let shouty = MNEMONIC_24.replace("abandon", "Abandon");
+        assert_eq!(probe_format(shouty.as_bytes()), KeyFileFormat::Unknown);
+        let digits = MNEMONIC_24.replace("abandon", "abandon1");
+        assert_eq!(probe_format(digits.as_bytes()), KeyFileFormat::Unknown);
+    }
+
+    #[test]
+    fn test_probe_upstream_key() {
+        let mut upstream = UPSTREAM_KEY_MAGIC.to_vec();
+        upstream.extend_from_slice(&[0, 0, 0, 2]);
+        upstream.resize(148, 0);
+        assert_eq!(probe_format(&upstream), KeyFileFormat::Upstream);
+        let err = decode_error(&upstream);
+        assert!(err.contains("upstream git-crypt key file"), "{err}");
+
+        // A well-formed one decodes, and is saved back in the same format
+        let bytes = CryptoKey::from_git_crypt_key_file(&upstream_key_file())
+            .unwrap()
+            .to_key_file();
+        assert_eq!(*bytes, upstream_key_file());
+        let key = decode_key(&bytes).unwrap();
+        assert!(key.is_upstream());
+        assert_eq!(
+            key.as_bytes(),
+            &std::array::from_fn::<u8, 32, _>(|i| i as u8)
+        );
+    }
+
+    /// Upstream key file with AES key bytes 0..32 and HMAC key bytes 64..128,
+    /// as `git-crypt export-key` writes it
+    fn upstream_key_file() -> Vec<u8> {
+        let mut bytes = UPSTREAM_KEY_MAGIC.to_vec();
+        bytes.extend_from_slice(&[0, 0, 0, 2, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 32]);
+        bytes.extend(0u8..32);
+        bytes.extend_from_slice(&[0, 0, 0, 5, 0, 0, 0, 64]);
+        bytes.extend(64u8..128);
+        bytes.extend_from_slice(&[0, 0, 0, 0]);
+        bytes
+    }
+
+    #[test]
+    fn test_probe_unknown_inputs() {
+        assert_eq!(probe_format(b""), KeyFileFormat::Unknown);
+        assert_eq!(probe_format(b"too short"), KeyFileFormat::Unknown);
+        assert_eq!(probe_format(&[0xff; 33]), KeyFileFormat::Unknown);
+        assert_eq!(probe_format(&[0u8; 31]), KeyFileFormat::Unknown);
+        assert_eq!(probe_format(b"\n\n\n"), KeyFileFormat::Unknown);
+    }
+
+    #[test]
+    fn test_decode_key_accepts_hex_and_rejects_unreadable_formats() {
+        let key = CryptoKey::generate();
+        let hex_key = format!("{}\n", hex::encode(key.as_bytes()));
+        assert_eq!(
+            decode_key(hex_key.as_bytes()).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+        assert_eq!(
+            decode_key(key.as_bytes()).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+
+        let err = decode_error(MNEMONIC_24.as_bytes());
+        assert!(err.contains("mnemonic format"), "{err}");
+        let err = decode_error(b"age-encryption.org/v1\n...");
+        assert!(err.contains("import-age-key"), "{err}");
+        assert!(matches!(
+            decode_key(b"garbage"),
+            Err(GitCryptError::InvalidKeyFormat)
+        ));
+    }
+
+    #[test]
+    fn test_import_hex_key_file() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+
+        let key = CryptoKey::generate();
+        let hex_path = temp.path().join("shared.hex");
+        fs::write(&hex_path, format!("{}\n", hex::encode(key.as_bytes()))).unwrap();
+
+        key_manager.import_key(&hex_path).unwrap();
+        assert_eq!(key_manager.load_key().unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_gf_arithmetic() {
+        // FIPS-197 4.2: {57}*{83} = {c1}; 4.2.1: {57}*{13} = {fe} via xtime
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        assert_eq!(gf_mul(0x57, 0x13), 0xfe);
+        for (b, product) in [(0x02, 0xae), (0x04, 0x47), (0x08, 0x8e), (0x10, 0x07)] {
+            assert_eq!(gf_mul(0x57, b), product);
+        }
+        // {53} and {ca} are inverses (the usual Rijndael inverse example)
+        assert_eq!(gf_mul(0x53, 0xca), 0x01);
+        assert_eq!(gf_div(0x01, 0x53), 0xca);
+        // Division undoes multiplication
+        for a in [1u8, 2, 0x53, 0xff] {
+            for b in [1u8, 3, 0xca, 0xfe] {
+                assert_eq!(gf_div(gf_mul(a, b), b), a);
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_key_reconstructs_at_threshold() {
+        let key = CryptoKey::generate();
+        let shares = split_key(&key, 5, 3).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Every 3-share subset rebuilds the key, in any order
+        for a in 0..5 {
+            for b in a + 1..5 {
+                for c in b + 1..5 {
+                    let subset = [shares[c].clone(), shares[a].clone(), shares[b].clone()];
+                    let rebuilt = combine_shares(&subset).unwrap();
+                    assert_eq!(rebuilt.as_bytes(), key.as_bytes());
+                }
+            }
+        }
+        assert_eq!(combine_shares(&shares).unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_combine_shares_known_answer() {
+        // f(x) = {53} + {ca}x for every byte: f(1) = {99}, f(2) = {53} + {8f}
+        // = {dc}, f(3) = {53} + {45} = {16}
+        let key = CryptoKey::from_bytes(&[0x53; KEY_SIZE]).unwrap();
+        let fingerprint = key.fingerprint()[..SHARE_FINGERPRINT_LEN].to_string();
+        let share = |index, byte| KeyShare {
+            index,
+            threshold: 2,
+            fingerprint: fingerprint.clone(),
+            data: [byte; KEY_SIZE],
+        };
+        let shares = [share(1, 0x99), share(2, 0xdc), share(3, 0x16)];
+
+        for (a, b) in [(0, 1), (0, 2), (1, 2), (2, 0)] {
+            let rebuilt = combine_shares(&[shares[a].clone(), shares[b].clone()]).unwrap();
+            assert_eq!(rebuilt.as_bytes(), key.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_combine_shares_fails_below_threshold() {
+        let key = CryptoKey::generate();
+        let shares = split_key(&key, 5, 3).unwrap();
+
+        let Err(err) = combine_shares(&shares[..2]) else {
+            panic!("two of three shares rebuilt a key");
+        };
+        assert!(err.to_string().contains("2 given, 3 needed"));
+
+        // Lying about the threshold still can't produce the key
+        let mut forged = shares[..2].to_vec();
+        for share in &mut forged {
+            share.threshold = 2;
+        }
+        assert!(combine_shares(&forged).is_err());
+    }
+
+    #[test]
+    fn test_combine_shares_rejects_mixed_or_duplicate_shares() {
+        let key = CryptoKey::generate();
+        let other = CryptoKey::generate();
+        let shares = split_key(&key, 3, 2).unwrap();
+        let other_shares = split_key(&other, 3, 2).unwrap();
+
+        let mixed = [shares[0].clone(), other_shares[1].clone()];
+        assert!(combine_shares(&mixed).is_err());
+        let duplicate = [shares[0].clone(), shares[0].clone()];
+        assert!(combine_shares(&duplicate).is_err());
+    }
+
+    #[test]
+    fn test_split_key_rejects_invalid_parameters() {
+        let key = CryptoKey::generate();
+        assert!(split_key(&key, 5, 1).is_err());
+        assert!(split_key(&key, 3, 4).is_err());
+    }
+
+    #[test]
+    fn test_key_share_armor_round_trip() {
+        let key = CryptoKey::generate();
+        let share = split_key(&key, 3, 2).unwrap().remove(1);
+        let armored = share.to_armored();
+
+        assert_eq!(probe_format(armored.as_bytes()), KeyFileFormat::Share);
+        assert!(KeyShare::parse(armored.as_bytes()).unwrap() == share);
+        assert!(decode_error(armored.as_bytes()).contains("import-key --combine"));
+    }
+
+    #[test]
+    fn test_export_and_import_key_shares() {
+        let temp = create_test_git_dir();
+        let key_manager = KeyManager::new(temp.path());
+        key_manager.init_dirs().unwrap();
+        let key = key_manager.generate_key().unwrap();
+
+        let out = temp.path().join("shares");
+        let paths = key_manager.export_key_shares(&out, 3, 2).unwrap();
+        assert_eq!(paths.len(), 3);
+        #[cfg(unix)]
+        for path in &paths {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(path).unwrap().permissions().mode() & 0o777,
+                0o600
+            );
+        }
+
+        let other = TempDir::new().unwrap();
+        let importer = KeyManager::new(other.path());
+        importer.init_dirs().unwrap();
+        importer.import_key_shares(&paths[1..]).unwrap();
+        assert_eq!(importer.load_key().unwrap().as_bytes(), key.as_bytes());
+    }
 }
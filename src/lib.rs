@@ -34,7 +34,7 @@
 //! git-crypt init
 //!
 //! # Configure which files to encrypt in .gitattributes
-//! echo "*.secret filter=git-crypt diff=git-crypt" >> .gitattributes
+//! echo "*.secret filter=git-crypt diff=git-crypt -text" >> .gitattributes
 //! git add .gitattributes
 //! git commit -m "Configure git-crypt"
 //!
@@ -69,26 +69,89 @@
 //! Encrypted data in .git → smudge filter → decrypt → working directory
 //! ```
 //!
+//! ## Library Usage
+//!
+//! The filters work on any reader and writer, so blobs can be encrypted and
+//! decrypted without a working tree or stdin/stdout:
+//!
+//! ```
+//! use git_crypt::git::{clean_stream, smudge_stream, CleanOptions};
+//! use git_crypt::CryptoKey;
+//!
+//! # fn main() -> git_crypt::Result<()> {
+//! // Or `GitRepo::open(path)?.load_key()?` for a repository's key
+//! let key = CryptoKey::generate();
+//!
+//! let mut blob = Vec::new();
+//! clean_stream(&key, &CleanOptions::default(), &b"my secret data"[..], &mut blob)?;
+//! assert!(CryptoKey::is_encrypted(&blob));
+//!
+//! let mut plaintext = Vec::new();
+//! smudge_stream(&key, None, blob.as_slice(), &mut plaintext)?;
+//! assert_eq!(plaintext, b"my secret data");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`git::clean_content`] and [`git::smudge_content`] do the same for byte
+//! slices held in memory.
+//!
 //! ## Module Overview
 //!
 //! - [`crypto`] - Core AES-256-GCM encryption/decryption operations
 //! - [`key`] - Key management, storage, export/import
 //! - [`git`] - Git filter integration and repository operations
+//! - [`cache`] - Optional cache of decrypted blobs for the smudge filter
 //! - [`gpg`] - Optional GPG support for key sharing (requires `gpg` feature)
 //! - [`rage`] - Optional age/rage-based SSH key sharing (requires `ssh` feature)
+//! - [`parallel`] - Bounded worker pool for bulk file operations
+//! - [`recipients`] - Committed `.git-crypt/recipients` list of who holds the key
+//! - [`attributes`] - Which `.gitattributes` rule marks a file for encryption
+//! - [`settings`] - Effective settings and their sources, for `config --list`
+//! - `names` - Opaque file names and their encrypted manifest, a proof of concept (requires `filename-encryption` feature)
 //! - [`error`] - Error types and unified error handling
 //!
 //! ## Commands
 //!
-//! - `init` - Initialize git-crypt in the current repository
-//! - `lock` - Lock the repository (remove filters, show encrypted content)
-//! - `unlock [--key-file PATH]` - Unlock the repository
-//! - `export-key OUTPUT` - Export the symmetric key to a file
-//! - `import-key INPUT` - Import a symmetric key from a file
-//! - `add-gpg-user GPG_ID` - Grant access to a GPG user (requires `gpg` feature)
-//! - `add-ssh-user --ssh-key PATH` - Encrypt the key for an SSH recipient via age/rage (requires `ssh` feature)
-//! - `import-age-key --input FILE --identity SSH_KEY` - Decrypt an age/rage key blob with your SSH key (requires `ssh` feature)
-//! - `status` - Show status of encrypted files (not yet implemented)
+//! - `--repo-key-path PATH` (global) - Use the key at `PATH` instead of `.git/git-crypt/keys/default`
+//! - `--keydir DIR` (global) - Keep keys and state in `DIR` instead of `.git/git-crypt` (also `GIT_CRYPT_KEYDIR`)
+//! - `--follow-key-symlink` (global) - Write new keys through a symlinked key file instead of refusing
+//! - `--key-name NAME` (global) - Use the named key `.git/git-crypt/keys/NAME` and its `filter=git-crypt-NAME` filters
+//! - `init [--strict [--restage]] [--install-hook] [--cipher CIPHER] [--passphrase]` - Initialize git-crypt in the current repository, optionally sealing new blobs with `chacha20-poly1305` or wrapping the key file under a passphrase; also writes `.git-crypt/.gitattributes` so git-crypt's own metadata is never encrypted. `--strict` refuses while tracked files marked for encryption (or named like secrets, e.g. `.env`, `*.pem`) are stored in plaintext; `--restage` re-adds the marked ones through the filter instead; `--install-hook` adds a pre-commit hook (chained before any existing one) that runs `check-staged`
+//! - `lock [--force]` - Lock the repository (remove filters, show encrypted content)
+//! - `unlock [--key-file PATH | --gpg | --identity PATH] [--force] [--print-commands]` - Unlock the repository with the installed key, a key file, or the first GPG/age key blob you can decrypt, or with `--print-commands` only show what it would change
+//! - `purge [--lock] [--yes]` - Remove the key, key blobs, filter config and pre-commit hook from this clone; key files are overwritten before deletion
+//! - `check-staged` - Fail if a staged file marked for encryption is stored in plaintext (what the pre-commit hook runs)
+//! - `export-key OUTPUT [--raw | --armor] [--allow-raw]` - Export the symmetric key to a file, with `--armor` as copy-pasteable base64; passphrase-protected keys require `--allow-raw`. With `--raw`, only the key bytes reach stdout and `-` writes them there (e.g. `git-crypt export-key --raw - | age -r age1... > key.age`)
+//! - `encrypt-file --sidecar PATH` - Encrypt `PATH` into a committable `PATH.enc` (left untouched if it already holds the same content), for files that live outside the git filters
+//! - `decrypt-file --sidecar PATH.enc` - Decrypt a sidecar back into the plaintext file next to it
+//! - `sync-sidecars` - Update every pair listed in `.git-crypt/sidecars` (one plaintext path per line); when both files changed, the newer one wins
+//! - `watch [--idle DURATION]` - Lock the repository after a period of inactivity
+//! - `export-key OUTDIR --shares N --threshold T` - Split the key into N Shamir shares, any T of which rebuild it
+//! - `import-key INPUT` - Import a symmetric key from a file, or from stdin with `-`
+//! - `import-key --combine SHARE...` - Rebuild the key from share files
+//! - `add-gpg-user GPG_ID [--all-subkeys]` - Grant access to a GPG user, optionally to all of their encryption subkeys (requires `gpg` feature)
+//! - `gpg-keys` - List keyring fingerprints usable with `add-gpg-user` (requires `gpg` feature)
+//! - `add-ssh-user --ssh-key PATH...` - Encrypt the key for one or more SSH recipients via age/rage (requires `ssh` feature)
+//! - `list-users [--json]` - List the GPG ids and SSH aliases with access, and when each was granted
+//! - `remove-user (--alias ALIAS | --gpg-id ID) [--rotate]` - Delete a collaborator's key blob; `--rotate` also rotates the key they still hold
+//! - `add-user --passphrase --label NAME` - Wrap the key with an age passphrase recipient (requires `ssh` feature)
+//! - `import-age-key --input FILE (--identity SSH_KEY [--verify-only] | --passphrase)` - Decrypt an age/rage key blob with your SSH key or a passphrase (requires `ssh` feature)
+//! - `download-age-key --alias NAME [--output PATH]` - Fetch an age key blob uploaded by S3 or GCS sync, for `import-age-key` (requires `sync-s3` or `sync-gcs` feature)
+//! - `sync-recipients` - Grant/revoke access to match the committed `.git-crypt/recipients` file
+//! - `rotate-key [--cipher CIPHER] [--force] [--jobs N]` - Replace the key with a new generation, re-stage encrypted files under it on N threads and rewrap it for collaborators; refuses with uncommitted changes unless `--force`
+//! - `rewrap` - Regenerate recipient blobs that are not in the current format for the current key
+//! - `verify [--threads N] [--format-check [--min-format V]] [--max-key-age DAYS]` - Check that committed files matching the attributes are encrypted and decrypt with the key, reporting key generation coverage after a rotation; `--max-key-age` also fails on an overdue key
+//! - `check-reproducible` - Clean every encrypted file twice and fail if the stored blob would change on re-staging
+//! - `assert PATH (--encrypted | --plaintext) [--index]` - Check a file's stored state in HEAD or the index
+//! - `check [--all-commits]` - Fail if HEAD holds plaintext copies of files marked for encryption
+//! - `audit [--fail-fast] [--max-commits N]` - Scan history for files that were committed in plaintext
+//! - `status [--scan DIR] [--json]` - Show lock state and whether the filters run this binary, for one repository or every git-crypt repository under `DIR`
+//! - `status --files [--encrypted-only | --unencrypted-only] [--exit-code] [--json]` - List files marked for encryption, their key and pattern and whether they are stored encrypted; `--exit-code` fails on plaintext
+//! - `encrypt-path` / `decrypt-path` - Switch files in or out of encryption via the root `.gitattributes` and re-stage them
+//! - `key-fingerprint` - Print a short fingerprint of the key (grouped hex) to compare with teammates out of band
+//! - `keys list` - List the repository key and named keys with fingerprint, cipher, generation and filter status
+//! - `config --list` - Print every effective setting and the layer it came from
 //!
 //! ## Examples
 //!
@@ -105,15 +168,15 @@
 //! # 3. Configure encryption patterns in .gitattributes
 //! cat > .gitattributes << 'EOF'
 //! # Encrypt all files in the secrets/ directory
-//! secrets/** filter=git-crypt diff=git-crypt
+//! secrets/** filter=git-crypt diff=git-crypt -text
 //!
 //! # Encrypt specific file types
-//! *.key filter=git-crypt diff=git-crypt
-//! *.secret filter=git-crypt diff=git-crypt
+//! *.key filter=git-crypt diff=git-crypt -text
+//! *.secret filter=git-crypt diff=git-crypt -text
 //!
 //! # Encrypt specific config files
-//! config/database.yml filter=git-crypt diff=git-crypt
-//! .env.production filter=git-crypt diff=git-crypt
+//! config/database.yml filter=git-crypt diff=git-crypt -text
+//! .env.production filter=git-crypt diff=git-crypt -text
 //! EOF
 //!
 //! git add .gitattributes
@@ -173,13 +236,14 @@
 //! **Not protected against:**
 //! - Attacks on the working directory (files are plaintext there)
 //! - Compromised git client or filters
-//! - Key extraction from `.git` directory
+//! - Key extraction from `.git` directory, unless the key is passphrase-protected
+//!   (`init --passphrase`)
 //! - Side-channel attacks
 //!
 //! ### Best Practices
 //!
 //! 1. Keep `.git/git-crypt/` directory secure
-//! 2. Use restrictive file permissions (automatic on Unix)
+//! 2. Use restrictive file permissions (automatic on Unix and Windows; git-crypt warns when the filesystem can't enforce them)
 //! 3. Never commit key files to the repository
 //! 4. Use GPG for team key distribution when possible
 //! 5. Rotate keys if compromised
@@ -202,6 +266,36 @@
 //! The magic header ensures reliable detection of encrypted data and provides
 //! versioning capability for future format changes.
 //!
+//! Setting `git config filter.git-crypt.padding 256` pads plaintext to a multiple
+//! of 256 bytes before encryption (format version 1), so empty and small files no
+//! longer reveal their size. See [`crypto`] for the versioned layout.
+//!
+//! Setting `git config filter.git-crypt.wrapped true` encrypts each file under its
+//! own content key wrapped by the repository key (format version 2), so rotating
+//! the repository key only rewrites small per-file headers.
+//!
+//! Files over 8 MiB are encrypted by the clean filter in the stream format
+//! (format version 3, [`CryptoKey::encrypt_stream`]): fixed-size frames under an
+//! HMAC trailer that detects dropped or reordered frames. Clean and smudge then
+//! process them frame by frame, so memory use stays flat however large the
+//! file. Padding, wrapped keys and deterministic nonces need the whole file and
+//! keep buffering it.
+//!
+//! After `git-crypt rotate-key`, every blob also records the key generation that
+//! encrypted it, authenticated with the ciphertext, so `verify` can show how far a
+//! rotation has propagated.
+//!
+//! Setting `git config filter.git-crypt.cipher chacha20-poly1305` (or running
+//! `git-crypt init --cipher chacha20-poly1305` or `git-crypt rotate-key --cipher chacha20`) encrypts new blobs with
+//! ChaCha20-Poly1305 instead of AES-256-GCM, which is faster on CPUs without AES
+//! instructions. The choice is flagged in each blob's header, so both ciphers can
+//! be read side by side.
+//!
+//! Setting `git config filter.git-crypt.deterministic true` derives each blob's
+//! nonce from its plaintext (HMAC-SHA256 under the key), like upstream git-crypt:
+//! re-staging an unchanged file stores the same blob, at the cost of revealing
+//! which blobs hold identical content. It applies to the basic format only.
+//!
 //! ## GPG Support (Optional)
 //!
 //! To enable GPG support, install system dependencies and build with the `gpg` feature:
@@ -244,6 +338,10 @@
 //! - Different key storage location
 //! - Different filter commands
 //!
+//! Blobs written by original git-crypt can still be read: install its key
+//! file with `import-key` (see [`crypto::CryptoKey::from_git_crypt_key_file`])
+//! and they check out as usual, while re-staged files move to this format.
+//!
 //! This is a complete reimplementation focusing on:
 //! - Memory safety (Rust)
 //! - Modern cryptography practices
@@ -321,18 +419,25 @@
 //! Tests verify security properties:
 //! - ✅ Authentication (wrong key fails decryption)
 //! - ✅ Tamper detection (corrupted data rejected)
-//! - ✅ File permissions (0600 on Unix)
+//! - ✅ File permissions (0600 on Unix, owner-only ACL on Windows)
 //! - ✅ Key isolation (different repos use different keys)
 //! - ✅ Nonce uniqueness (no nonce reuse)
 
 // Library exports for testing
+pub mod attributes;
+pub mod cache;
 pub mod crypto;
 pub mod error;
 pub mod git;
 pub mod gpg;
 pub mod key;
+#[cfg(feature = "filename-encryption")]
+pub mod names;
+pub mod parallel;
 #[cfg(feature = "ssh")]
 pub mod rage;
+pub mod recipients;
+pub mod settings;
 pub mod sync;
 
 // Re-export commonly used types
@@ -1,21 +1,78 @@
+mod cache;
 mod commands;
 mod crypto;
 mod error;
 mod git;
 mod gpg;
 mod key;
+mod notify;
+mod output;
+mod parallel;
 #[cfg(feature = "ssh")]
 mod rage;
+mod recipients;
 
 use clap::{Parser, Subcommand};
+use commands::UnlockMethod;
 use error::Result;
+use std::num::NonZeroUsize;
 use std::path::PathBuf;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+/// `--version` output: the version and the optional features compiled in,
+/// e.g. `0.1.0 (features: gpg, ssh)`
+static VERSION: LazyLock<String> = LazyLock::new(|| {
+    let features = [
+        ("gpg", cfg!(feature = "gpg")),
+        ("age", cfg!(feature = "age")),
+        ("ssh", cfg!(feature = "ssh")),
+        ("sync-s3", cfg!(feature = "sync-s3")),
+        ("sync-gcs", cfg!(feature = "sync-gcs")),
+        ("notify", cfg!(feature = "notify")),
+        ("serde", cfg!(feature = "serde")),
+    ];
+    let enabled: Vec<&str> = features
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect();
+    let enabled = if enabled.is_empty() {
+        "none".to_string()
+    } else {
+        enabled.join(", ")
+    };
+    format!("0.1.0 (features: {enabled})")
+});
 
 #[derive(Parser)]
 #[command(name = "git-crypt")]
-#[command(version = "0.1.0")]
+#[command(version = VERSION.as_str())]
 #[command(about = "Transparent file encryption in git", long_about = None)]
 struct Cli {
+    /// Disable colored output (also honors NO_COLOR)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Read and write the repository key at PATH instead of .git/git-crypt/keys/default
+    #[arg(long, global = true, value_name = "PATH")]
+    repo_key_path: Option<PathBuf>,
+
+    /// Keep keys and git-crypt state in DIR instead of .git/git-crypt (also
+    /// GIT_CRYPT_KEYDIR)
+    #[arg(long, global = true, value_name = "DIR")]
+    keydir: Option<PathBuf>,
+
+    /// When the key file is a symlink, write new keys into its target instead
+    /// of refusing
+    #[arg(long, global = true)]
+    follow_key_symlink: bool,
+
+    /// Use the named key .git/git-crypt/keys/NAME and its filter
+    /// `git-crypt-NAME` (`filter=git-crypt-NAME` in .gitattributes)
+    #[arg(long, global = true, value_name = "NAME", value_parser = key::validate_key_name, conflicts_with = "repo_key_path")]
+    key_name: Option<String>,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -23,100 +80,632 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize git-crypt in the current repository
-    Init,
+    Init {
+        /// Refuse to initialize while tracked secrets are stored in plaintext
+        #[arg(long)]
+        strict: bool,
+
+        /// With --strict, re-stage plaintext files that .gitattributes marks for encryption
+        #[arg(long, requires = "strict")]
+        restage: bool,
+
+        /// Install a pre-commit hook that blocks committing plaintext files
+        /// marked for encryption
+        #[arg(long)]
+        install_hook: bool,
+
+        /// Cipher for new blobs (aes-256-gcm or chacha20-poly1305)
+        #[arg(long)]
+        cipher: Option<crypto::Cipher>,
+
+        /// Protect the key file with a passphrase (prompted twice, or
+        /// GIT_CRYPT_PASSPHRASE)
+        #[arg(long)]
+        passphrase: bool,
+    },
 
     /// Unlock the repository (decrypt files)
     Unlock {
-        /// Path to key file (optional)
+        /// Path to key file (optional), or `-` to read the key from stdin
         #[arg(short, long)]
         key_file: Option<PathBuf>,
+        /// Install the key from the first GPG key blob in
+        /// .git/git-crypt/keys/gpg that a local secret key decrypts (the
+        /// default when no key is installed and such blobs exist)
+        #[arg(long, conflicts_with = "key_file")]
+        gpg: bool,
+        /// Install the key from the first age key blob in
+        /// .git/git-crypt/keys/age that this SSH private key decrypts
+        #[cfg(feature = "ssh")]
+        #[arg(long, value_name = "PATH", conflicts_with_all = ["key_file", "gpg"])]
+        identity: Option<PathBuf>,
+        /// Unlock even if the repository is already unlocked
+        #[arg(long)]
+        force: bool,
+        /// Print the config changes and commands unlock would run, then exit
+        /// without changing anything
+        #[arg(long)]
+        print_commands: bool,
     },
 
     /// Lock the repository (show encrypted content)
-    Lock,
+    Lock {
+        /// Lock even if the repository is already locked
+        #[arg(long)]
+        force: bool,
+        /// Also replace decrypted files in the working tree with their
+        /// encrypted content from HEAD
+        #[arg(long)]
+        reset_working_tree: bool,
+    },
+
+    /// Remove the key, key blobs and filter configuration from this clone
+    Purge {
+        /// Lock the repository and replace decrypted files with ciphertext first
+        #[arg(long)]
+        lock: bool,
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
 
     /// Grant access to a GPG user
     AddGpgUser {
         /// GPG key ID or fingerprint
         gpg_id: String,
+        /// Encrypt to every encryption-capable subkey, not just the first
+        #[arg(long)]
+        all_subkeys: bool,
+    },
+
+    /// List GPG keys available for add-gpg-user
+    GpgKeys,
+
+    /// List the GPG and SSH users granted access to the repository key
+    ListUsers {
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Revoke a collaborator's stored key blob
+    RemoveUser {
+        /// Alias of an SSH/age user (keys/age/ALIAS.age)
+        #[arg(long, required_unless_present = "gpg_id", conflicts_with = "gpg_id")]
+        alias: Option<String>,
+        /// GPG id of a GPG user (keys/gpg/GPG_ID.key)
+        #[arg(long)]
+        gpg_id: Option<String>,
+        /// Rotate the repository key afterwards, so the user's copy stops working
+        #[arg(long)]
+        rotate: bool,
     },
 
     /// Grant access to an SSH user using age/rage
     #[cfg(feature = "ssh")]
     AddSshUser {
-        /// Path to the SSH public key
-        #[arg(long = "ssh-key", value_name = "SSH_KEY")]
-        ssh_key: PathBuf,
-        /// Optional alias used when storing the encrypted key
+        /// Path to an SSH public key (repeat to grant several users at once)
+        #[arg(long = "ssh-key", value_name = "SSH_KEY", required = true)]
+        ssh_key: Vec<PathBuf>,
+        /// Optional alias used when storing the encrypted key (single key only)
         #[arg(short, long)]
         alias: Option<String>,
+        /// Replace the key blob of a user who already has one
+        #[arg(long)]
+        force: bool,
+        /// Re-upload to the sync backend even if the remote blob is unchanged
+        #[arg(long)]
+        force_upload: bool,
+    },
+
+    /// Share the key under a passphrase (age scrypt) instead of a public key
+    #[cfg(feature = "ssh")]
+    AddUser {
+        /// Wrap the key with a passphrase (prompted, or GIT_CRYPT_PASSPHRASE)
+        #[arg(long, required = true)]
+        passphrase: bool,
+        /// Name of the blob under keys/passphrase/
+        #[arg(long)]
+        label: String,
+        /// Replace an existing blob with the same label
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Regenerate encrypted key blobs from the committed recipients file
+    SyncRecipients {
+        /// Re-upload to the sync backend even if the remote blobs are unchanged
+        #[arg(long)]
+        force_upload: bool,
+    },
+
+    /// Regenerate recipient blobs written in an older format or for another key
+    Rewrap,
+
+    /// Replace the repository key with a new generation, re-stage encrypted
+    /// files and rewrap recipient blobs
+    RotateKey {
+        /// Also switch new blobs to this cipher (aes-256-gcm or chacha20-poly1305)
+        #[arg(long)]
+        cipher: Option<crypto::Cipher>,
+        /// Rotate even with uncommitted changes, which get re-staged too
+        #[arg(long)]
+        force: bool,
+        /// Number of worker threads (defaults to available parallelism)
+        #[arg(long, visible_alias = "jobs", value_name = "N")]
+        threads: Option<NonZeroUsize>,
     },
 
     /// Export the repository's symmetric key
     ExportKey {
-        /// Output file path
+        /// Output file path, `-` for the raw key on stdout, or the share
+        /// directory with --shares
         output: PathBuf,
+        /// Write only the raw key bytes; all messages go to stderr
+        #[arg(long, conflicts_with = "shares")]
+        raw: bool,
+        /// Write the key as base64 text between BEGIN/END GIT-CRYPT KEY lines
+        /// (import-key detects it)
+        #[arg(long, conflicts_with_all = ["raw", "shares"])]
+        armor: bool,
+        /// Export the raw key even if it is protected by a passphrase
+        #[arg(long)]
+        allow_raw: bool,
+        /// Split the key into this many Shamir shares
+        #[arg(long, requires = "threshold", value_parser = clap::value_parser!(u8).range(2..))]
+        shares: Option<u8>,
+        /// Number of shares needed to rebuild the key
+        #[arg(long, requires = "shares", value_parser = clap::value_parser!(u8).range(2..))]
+        threshold: Option<u8>,
+        /// Write the key GPG-encrypted (armored) for this recipient instead
+        #[arg(long, value_name = "RECIPIENT", conflicts_with_all = ["raw", "armor", "shares"])]
+        gpg: Option<String>,
     },
 
-    /// Import a symmetric key
+    /// Import a symmetric key, or rebuild it from key shares
+    #[command(group(clap::ArgGroup::new("source").required(true).args(["input", "combine"])))]
     ImportKey {
-        /// Input file path
-        input: PathBuf,
+        /// Input file path, or `-` to read the key from stdin
+        input: Option<PathBuf>,
+        /// Share files written by `export-key --shares`
+        #[arg(long, num_args = 1.., value_name = "SHARE")]
+        combine: Vec<PathBuf>,
     },
 
-    /// Import an age/rage-encrypted key using your SSH identity
+    /// Import an age/rage-encrypted key using your SSH identity or a passphrase
     #[cfg(feature = "ssh")]
+    #[command(group(clap::ArgGroup::new("unwrap").required(true).args(["identity", "passphrase"])))]
     ImportAgeKey {
         /// Path to the age-encrypted key blob
         #[arg(long = "input", value_name = "AGE_FILE")]
         input: PathBuf,
         /// Path to your SSH private key (identity)
         #[arg(long = "identity", value_name = "SSH_KEY")]
-        identity: PathBuf,
+        identity: Option<PathBuf>,
+        /// Unwrap a blob made with `add-user --passphrase`
+        #[arg(long)]
+        passphrase: bool,
+        /// Only check that the identity decrypts the blob; install nothing
+        #[arg(long, requires = "identity")]
+        verify_only: bool,
+    },
+
+    /// Download a collaborator's age key blob from the sync backend
+    #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+    DownloadAgeKey {
+        /// Alias the blob was uploaded under (the name used by add-ssh-user)
+        #[arg(short, long)]
+        alias: String,
+        /// Where to write the blob (defaults to .git/git-crypt/keys/age/ALIAS.age)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
     },
 
     /// Clean filter (used internally by git)
-    Clean,
+    Clean {
+        /// Read the key from this file instead of the repository
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Read content from this file instead of stdin
+        #[arg(long)]
+        input: Option<PathBuf>,
+        /// Write the result to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Report progress on stderr for large files, when it is a terminal
+        /// (also GIT_CRYPT_PROGRESS=1)
+        #[arg(long)]
+        progress: bool,
+        /// Path of the file being filtered (`%f`), named in errors and used to
+        /// pick a submodule's key
+        path: Option<PathBuf>,
+    },
 
     /// Smudge filter (used internally by git)
-    Smudge,
+    Smudge {
+        /// Read the key from this file instead of the repository
+        #[arg(long)]
+        key_file: Option<PathBuf>,
+        /// Read content from this file instead of stdin
+        #[arg(long)]
+        input: Option<PathBuf>,
+        /// Write the result to this file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Report progress on stderr for large files, when it is a terminal
+        /// (also GIT_CRYPT_PROGRESS=1)
+        #[arg(long)]
+        progress: bool,
+        /// Path of the file being filtered (`%f`), named in errors and used to
+        /// pick a submodule's key
+        path: Option<PathBuf>,
+    },
 
     /// Diff filter (used internally by git)
     Diff,
 
-    /// Show status of encrypted files
-    Status,
+    /// Check that committed files matching git-crypt attributes are encrypted and decryptable
+    Verify {
+        /// Number of worker threads (defaults to available parallelism)
+        #[arg(long)]
+        threads: Option<NonZeroUsize>,
+        /// Report each file's format version and cipher
+        #[arg(long)]
+        format_check: bool,
+        /// With --format-check, fail for files below this format version
+        #[arg(long, value_name = "VERSION", requires = "format_check")]
+        min_format: Option<u8>,
+        /// Fail if the repository key is older than this many days
+        #[arg(long, value_name = "DAYS")]
+        max_key_age: Option<u64>,
+    },
+
+    /// Check that a file is stored encrypted (or plaintext), for CI gates
+    #[command(group(clap::ArgGroup::new("expect").required(true).args(["encrypted", "plaintext"])))]
+    Assert {
+        /// File to check
+        path: PathBuf,
+
+        /// Expect the stored blob to be encrypted
+        #[arg(long)]
+        encrypted: bool,
+
+        /// Expect the stored blob to be plaintext
+        #[arg(long)]
+        plaintext: bool,
+
+        /// Check the staged blob instead of HEAD
+        #[arg(long)]
+        index: bool,
+    },
+
+    /// Scan history for files that were committed in plaintext
+    Audit {
+        /// Stop at the first plaintext file found
+        #[arg(long)]
+        fail_fast: bool,
+
+        /// Only scan the N most recent commits
+        #[arg(long, value_name = "N")]
+        max_commits: Option<NonZeroUsize>,
+    },
+
+    /// Check HEAD for files marked for encryption that were committed in
+    /// plaintext
+    Check {
+        /// Check every commit reachable from HEAD, like audit
+        #[arg(long)]
+        all_commits: bool,
+    },
+
+    /// Check that re-staging unchanged encrypted files reproduces identical blobs
+    CheckReproducible,
+
+    /// Fail if a staged file marked for encryption is plaintext (run by the pre-commit hook)
+    CheckStaged,
+
+    /// Encrypt a file into a `.enc` sidecar next to it
+    EncryptFile {
+        /// Plaintext file; the sidecar is written to PATH.enc
+        #[arg(long, value_name = "PATH")]
+        sidecar: PathBuf,
+    },
+
+    /// Decrypt a `.enc` sidecar into the plaintext file next to it
+    DecryptFile {
+        /// Sidecar file ending in .enc
+        #[arg(long, value_name = "PATH")]
+        sidecar: PathBuf,
+    },
+
+    /// Bring every sidecar pair listed in .git-crypt/sidecars up to date
+    SyncSidecars,
+
+    /// Lock the repository after a period without activity
+    Watch {
+        /// Inactivity before locking, e.g. 90s, 30m or 2h
+        #[arg(long, value_name = "DURATION", default_value = "30m", value_parser = commands::watch::parse_duration)]
+        idle: Duration,
+    },
+
+    /// Start encrypting files: add them to .gitattributes and re-stage them
+    EncryptPath {
+        /// Files to encrypt from the next commit on
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Stop encrypting files: exclude them in .gitattributes and stage the
+    /// plaintext
+    DecryptPath {
+        /// Files to store in plaintext from the next commit on
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Print a short fingerprint of the key, to compare with teammates
+    KeyFingerprint,
+
+    /// Inspect the repository key and named keys
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+
+    /// Show effective git-crypt settings and where each one comes from
+    Config {
+        /// List every setting with its source (default, file, git-config, env, cli)
+        #[arg(long, required = true)]
+        list: bool,
+    },
+
+    /// Show the lock state and filter setup of this repository, or of every
+    /// git-crypt repository under a directory
+    Status {
+        /// Report every git-crypt repository found under DIR
+        #[arg(long, value_name = "DIR")]
+        scan: Option<PathBuf>,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+        /// List tracked files marked for encryption and whether their staged
+        /// blobs are encrypted
+        #[arg(long, conflicts_with = "scan")]
+        files: bool,
+        /// With --files, show only encrypted files
+        #[arg(long, conflicts_with_all = ["scan", "unencrypted_only"])]
+        encrypted_only: bool,
+        /// With --files, show only files stored in plaintext
+        #[arg(long, conflicts_with = "scan")]
+        unencrypted_only: bool,
+        /// With --files, exit with status 1 if any file marked for encryption
+        /// is stored in plaintext
+        #[arg(long, conflicts_with = "scan")]
+        exit_code: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// List keys in .git/git-crypt/keys with fingerprint, cipher, generation
+    /// and whether their filters are configured
+    List,
 }
 
 fn main() {
     if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+        eprintln!("{} {}", output::stderr("Error:", output::Style::Fail), e);
+        std::process::exit(e.exit_code());
     }
 }
 
 fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let cli = Cli::try_parse().unwrap_or_else(|e| {
+        // --help and --version also arrive here and exit 0
+        if e.use_stderr() {
+            let _ = e.print();
+            std::process::exit(error::EXIT_USAGE);
+        }
+        e.exit()
+    });
+    output::set_no_color(cli.no_color);
+    if let Some(path) = &cli.repo_key_path {
+        key::set_key_path_override(path);
+    }
+    if let Some(dir) = &cli.keydir {
+        key::set_key_dir_override(dir);
+    }
+    key::set_follow_key_symlink(cli.follow_key_symlink);
+    if let Some(name) = &cli.key_name {
+        key::set_key_name(name);
+    }
 
     match cli.command {
-        Commands::Init => commands::init(),
-        Commands::Unlock { key_file } => commands::unlock(key_file.as_deref()),
-        Commands::Lock => commands::lock(),
-        Commands::AddGpgUser { gpg_id } => commands::add_gpg_user(&gpg_id),
-        #[cfg(feature = "ssh")]
-        Commands::AddSshUser { ssh_key, alias } => {
-            commands::add_ssh_user(&ssh_key, alias.as_deref())
+        Commands::Init {
+            strict,
+            restage,
+            install_hook,
+            cipher,
+            passphrase,
+        } => commands::init(strict, restage, install_hook, cipher, passphrase),
+        Commands::Unlock {
+            key_file,
+            gpg,
+            #[cfg(feature = "ssh")]
+            identity,
+            force,
+            print_commands,
+        } => {
+            let method = match key_file {
+                Some(key_file) => UnlockMethod::KeyFile(key_file),
+                None if gpg => UnlockMethod::Gpg,
+                None => UnlockMethod::Auto,
+            };
+            #[cfg(feature = "ssh")]
+            let method = match identity {
+                Some(identity) => UnlockMethod::Age(identity),
+                None => method,
+            };
+            commands::unlock(method, force, print_commands)
+        }
+        Commands::Lock {
+            force,
+            reset_working_tree,
+        } => commands::lock(force, reset_working_tree),
+        Commands::Purge { lock, yes } => commands::purge(lock, yes),
+        Commands::AddGpgUser {
+            gpg_id,
+            all_subkeys,
+        } => commands::add_gpg_user(&gpg_id, all_subkeys),
+        Commands::GpgKeys => commands::gpg_keys(),
+        Commands::ListUsers { json } => commands::list_users(json),
+        Commands::RemoveUser {
+            alias,
+            gpg_id,
+            rotate,
+        } => {
+            let user = match (&alias, &gpg_id) {
+                (Some(alias), _) => commands::RemovedUser::Alias(alias),
+                (_, Some(gpg_id)) => commands::RemovedUser::GpgId(gpg_id),
+                (None, None) => unreachable!("clap requires --alias or --gpg-id"),
+            };
+            commands::remove_user(user, rotate)
         }
-        Commands::ExportKey { output } => commands::export_key(&output),
-        Commands::ImportKey { input } => commands::import_key(&input),
         #[cfg(feature = "ssh")]
-        Commands::ImportAgeKey { input, identity } => commands::import_age_key(&input, &identity),
-        Commands::Clean => commands::clean(),
-        Commands::Smudge => commands::smudge(),
+        Commands::AddSshUser {
+            ssh_key,
+            alias,
+            force,
+            force_upload,
+        } => commands::add_ssh_user(&ssh_key, alias.as_deref(), force, force_upload),
+        #[cfg(feature = "ssh")]
+        Commands::AddUser {
+            passphrase: _,
+            label,
+            force,
+        } => commands::add_passphrase_user(&label, force),
+        Commands::SyncRecipients { force_upload } => commands::sync_recipients(force_upload),
+        Commands::Rewrap => commands::rewrap(),
+        Commands::RotateKey {
+            cipher,
+            force,
+            threads,
+        } => commands::rotate_key(cipher, force, threads.map(NonZeroUsize::get)),
+        Commands::ExportKey {
+            output,
+            raw,
+            armor,
+            allow_raw,
+            shares,
+            threshold,
+            gpg,
+        } => commands::export_key(
+            &output,
+            raw,
+            armor,
+            allow_raw,
+            shares.zip(threshold),
+            gpg.as_deref(),
+        ),
+        Commands::ImportKey { input, combine } => match input {
+            Some(input) => commands::import_key(&input),
+            None => commands::import_key_shares(&combine),
+        },
+        #[cfg(feature = "ssh")]
+        Commands::ImportAgeKey {
+            input,
+            identity,
+            passphrase: _,
+            verify_only,
+        } => match identity {
+            Some(identity) => commands::import_age_key(&input, &identity, verify_only),
+            None => commands::import_age_key_with_passphrase(&input),
+        },
+        #[cfg(any(feature = "sync-s3", feature = "sync-gcs"))]
+        Commands::DownloadAgeKey { alias, output } => {
+            commands::download_age_key(&alias, output.as_deref())
+        }
+        Commands::Clean {
+            key_file,
+            input,
+            output,
+            progress,
+            path,
+        } => commands::clean(
+            key_file.as_deref(),
+            path.as_deref(),
+            input.as_deref(),
+            output.as_deref(),
+            progress,
+        ),
+        Commands::Smudge {
+            key_file,
+            input,
+            output,
+            progress,
+            path,
+        } => commands::smudge(
+            key_file.as_deref(),
+            path.as_deref(),
+            input.as_deref(),
+            output.as_deref(),
+            progress,
+        ),
         Commands::Diff => commands::diff(),
-        Commands::Status => {
-            println!("Status command not yet implemented");
-            Ok(())
+        Commands::Verify {
+            threads,
+            format_check,
+            min_format,
+            max_key_age,
+        } => commands::verify(
+            threads.map(NonZeroUsize::get),
+            format_check,
+            min_format,
+            max_key_age,
+        ),
+        Commands::Assert {
+            path,
+            encrypted,
+            plaintext: _,
+            index,
+        } => commands::assert_stored(&path, encrypted, index),
+        Commands::Audit {
+            fail_fast,
+            max_commits,
+        } => commands::audit(fail_fast, max_commits.map(NonZeroUsize::get)),
+        Commands::Check { all_commits } => commands::check(all_commits),
+        Commands::CheckReproducible => commands::check_reproducible(),
+        Commands::CheckStaged => commands::check_staged(),
+        Commands::EncryptFile { sidecar } => commands::encrypt_file(&sidecar),
+        Commands::DecryptFile { sidecar } => commands::decrypt_file(&sidecar),
+        Commands::SyncSidecars => commands::sync_sidecars(),
+        Commands::Watch { idle } => commands::watch(idle),
+        Commands::Status {
+            scan,
+            json,
+            files,
+            encrypted_only,
+            unencrypted_only,
+            exit_code,
+        } => {
+            // The filters and --exit-code only make sense per file
+            if files || encrypted_only || unencrypted_only || exit_code {
+                let encrypted = match (encrypted_only, unencrypted_only) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                };
+                commands::status_files(json, encrypted, exit_code)
+            } else {
+                commands::status(scan.as_deref(), json)
+            }
         }
+        Commands::Config { list: _ } => commands::config_list(),
+        Commands::EncryptPath { paths } => commands::encrypt_path(&paths),
+        Commands::DecryptPath { paths } => commands::decrypt_path(&paths),
+        Commands::KeyFingerprint => commands::key_fingerprint(),
+        Commands::Keys {
+            command: KeysCommand::List,
+        } => commands::keys_list(),
     }
 }
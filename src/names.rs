@@ -0,0 +1,295 @@
+//! # File Name Encryption (proof of concept)
+//!
+//! Encrypted files still leak their paths: `secrets/prod-db-password.txt`
+//! tells a reader what the blob is. This module stores such files under
+//! opaque names instead, with an encrypted manifest mapping the names back.
+//!
+//! Git filters only ever see file content, never rename paths, so this can't
+//! ride on the clean/smudge filters. Instead, a file is kept at
+//! `.git-crypt/objects/<name>` (its content encrypted as usual) and only the
+//! manifest knows where it belongs in the working tree. Wiring that into
+//! commands is left for later; this module fixes the naming scheme and the
+//! manifest format.
+//!
+//! ## Names
+//!
+//! A path's name is the first 32 hex digits (128 bits) of
+//! `HMAC-SHA256(key, "git-crypt file name\0" || path)`. Names are stable for
+//! a key, so re-adding a file reuses its object, and reveal nothing about the
+//! path without the key.
+//!
+//! Should two paths ever share a 32-digit name, the later one gets the full
+//! 64 digits; [`Manifest::add`] fails if even those are taken.
+//!
+//! ## Manifest Format
+//!
+//! The manifest is committed as `.git-crypt/manifest.enc`, sealed with
+//! [`CryptoKey::encrypt`]. Its plaintext is a version line followed by one
+//! line per file, sorted by name:
+//!
+//! ```text
+//! git-crypt-manifest 1
+//! <name>\t<path>
+//! ```
+//!
+//! Paths are `/`-separated and relative to the repository root; they can't
+//! contain tabs or newlines.
+
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::BTreeMap;
+
+/// Manifest committed in the working tree, relative to the repository root
+pub const MANIFEST_FILE: &str = ".git-crypt/manifest.enc";
+
+/// Directory holding files under their opaque names
+pub const OBJECTS_DIR: &str = ".git-crypt/objects";
+
+/// First line of the manifest plaintext
+const MANIFEST_HEADER: &str = "git-crypt-manifest 1";
+
+/// Domain separation for name derivation
+const NAME_LABEL: &[u8] = b"git-crypt file name\0";
+
+/// Hex digits in a name, unless it collided
+const NAME_LEN: usize = 32;
+
+/// The opaque name of `path` under `key`, before collision handling: all 64
+/// hex digits, of which names normally use the first [`NAME_LEN`]
+fn full_name(key: &CryptoKey, path: &str) -> String {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(NAME_LABEL);
+    mac.update(path.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Mapping from opaque names to the paths they stand for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    entries: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Record `path` and return its name; a path already recorded keeps the
+    /// name it has
+    pub fn add(&mut self, key: &CryptoKey, path: &str) -> Result<String> {
+        if path.is_empty() || path.contains(['\t', '\n', '\r']) {
+            return Err(GitCryptError::Other(format!(
+                "Path {path:?} can't be stored in the file name manifest"
+            )));
+        }
+        if let Some(name) = self.name_of(path) {
+            return Ok(name.to_string());
+        }
+
+        let full = full_name(key, path);
+        for name in [&full[..NAME_LEN], &full[..]] {
+            if !self.entries.contains_key(name) {
+                self.entries.insert(name.to_string(), path.to_string());
+                return Ok(name.to_string());
+            }
+        }
+        Err(GitCryptError::Other(format!(
+            "Name of {path} collides with another file in the manifest"
+        )))
+    }
+
+    /// Forget `path`, returning the name it had
+    pub fn remove(&mut self, path: &str) -> Option<String> {
+        let name = self.name_of(path)?.to_string();
+        self.entries.remove(&name);
+        Some(name)
+    }
+
+    /// The path stored under `name`
+    pub fn path_of(&self, name: &str) -> Option<&str> {
+        self.entries.get(name).map(String::as_str)
+    }
+
+    /// The name `path` is stored under
+    pub fn name_of(&self, path: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, p)| *p == path)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// `(name, path)` pairs in name order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(n, p)| (n.as_str(), p.as_str()))
+    }
+
+    /// Number of files recorded
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether no files are recorded
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The manifest encrypted for committing as [`MANIFEST_FILE`]
+    pub fn seal(&self, key: &CryptoKey) -> Result<Vec<u8>> {
+        key.encrypt(self.to_text().as_bytes())
+    }
+
+    /// Decrypt and parse a manifest written by [`Self::seal`]
+    pub fn open(key: &CryptoKey, sealed: &[u8]) -> Result<Self> {
+        let plaintext = key.decrypt(sealed)?;
+        let text = String::from_utf8(plaintext)
+            .map_err(|_| GitCryptError::Other("File name manifest is not UTF-8".into()))?;
+        Self::parse(&text)
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{MANIFEST_HEADER}\n");
+        for (name, path) in &self.entries {
+            text.push_str(&format!("{name}\t{path}\n"));
+        }
+        text
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let invalid =
+            |what: &str| GitCryptError::Other(format!("Invalid file name manifest: {what}"));
+
+        let mut lines = text.lines();
+        if lines.next() != Some(MANIFEST_HEADER) {
+            return Err(invalid("unknown version"));
+        }
+        let mut manifest = Manifest::default();
+        for line in lines {
+            let (name, path) = line.split_once('\t').ok_or_else(|| invalid(line))?;
+            let well_formed = matches!(name.len(), NAME_LEN | 64)
+                && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+                && !path.is_empty();
+            if !well_formed || manifest.name_of(path).is_some() {
+                return Err(invalid(line));
+            }
+            if manifest
+                .entries
+                .insert(name.to_string(), path.to_string())
+                .is_some()
+            {
+                return Err(invalid(line));
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_stable_opaque_and_key_specific() {
+        let key = CryptoKey::generate();
+        let mut manifest = Manifest::default();
+        let name = manifest.add(&key, "secrets/prod-db-password.txt").unwrap();
+
+        assert_eq!(name.len(), NAME_LEN);
+        assert!(!name.contains("secrets"));
+        assert_eq!(
+            manifest.add(&key, "secrets/prod-db-password.txt").unwrap(),
+            name
+        );
+        assert_eq!(manifest.len(), 1);
+
+        let other_key = CryptoKey::generate();
+        let other = Manifest::default()
+            .add(&other_key, "secrets/prod-db-password.txt")
+            .unwrap();
+        assert_ne!(other, name);
+    }
+
+    #[test]
+    fn manifest_round_trips_through_encryption() {
+        let key = CryptoKey::generate();
+        let mut manifest = Manifest::default();
+        for path in ["a.secret", "dir/b.secret", "dir/sub dir/c é.secret"] {
+            manifest.add(&key, path).unwrap();
+        }
+
+        let sealed = manifest.seal(&key).unwrap();
+        assert!(CryptoKey::is_encrypted(&sealed));
+        assert!(!sealed.windows(8).any(|w| w == b"a.secret"));
+
+        let opened = Manifest::open(&key, &sealed).unwrap();
+        assert_eq!(opened, manifest);
+        let name = opened.name_of("dir/b.secret").unwrap();
+        assert_eq!(opened.path_of(name), Some("dir/b.secret"));
+
+        assert!(Manifest::open(&CryptoKey::generate(), &sealed).is_err());
+    }
+
+    #[test]
+    fn colliding_name_falls_back_to_the_full_digest() {
+        let key = CryptoKey::generate();
+        let full = full_name(&key, "b.secret");
+
+        // Another file already holds the short name
+        let mut manifest = Manifest::default();
+        manifest
+            .entries
+            .insert(full[..NAME_LEN].to_string(), "a.secret".to_string());
+
+        let name = manifest.add(&key, "b.secret").unwrap();
+        assert_eq!(name, full);
+        assert_eq!(manifest.path_of(&full[..NAME_LEN]), Some("a.secret"));
+        assert_eq!(manifest.path_of(&full), Some("b.secret"));
+
+        let reopened = Manifest::open(&key, &manifest.seal(&key).unwrap()).unwrap();
+        assert_eq!(reopened, manifest);
+
+        // Both taken: refuse rather than overwrite
+        let mut full_collision = Manifest::default();
+        full_collision
+            .entries
+            .insert(full[..NAME_LEN].to_string(), "a.secret".to_string());
+        full_collision
+            .entries
+            .insert(full.clone(), "c.secret".to_string());
+        assert!(full_collision.add(&key, "b.secret").is_err());
+    }
+
+    #[test]
+    fn remove_frees_the_name() {
+        let key = CryptoKey::generate();
+        let mut manifest = Manifest::default();
+        let name = manifest.add(&key, "a.secret").unwrap();
+
+        assert_eq!(manifest.remove("a.secret"), Some(name.clone()));
+        assert!(manifest.is_empty());
+        assert_eq!(manifest.remove("a.secret"), None);
+        assert_eq!(manifest.add(&key, "a.secret").unwrap(), name);
+    }
+
+    #[test]
+    fn rejects_unstorable_paths_and_malformed_manifests() {
+        let key = CryptoKey::generate();
+        let mut manifest = Manifest::default();
+        assert!(manifest.add(&key, "tab\there").is_err());
+        assert!(manifest.add(&key, "new\nline").is_err());
+        assert!(manifest.add(&key, "").is_err());
+
+        let name = "0".repeat(NAME_LEN);
+        for text in [
+            "git-crypt-manifest 2\n".to_string(),
+            format!("{MANIFEST_HEADER}\nno tab here\n"),
+            format!("{MANIFEST_HEADER}\nXYZ\ta.secret\n"),
+            format!("{MANIFEST_HEADER}\n{name}\ta.secret\n{name}\tb.secret\n"),
+            format!(
+                "{MANIFEST_HEADER}\n{name}\ta.secret\n{}\ta.secret\n",
+                "1".repeat(NAME_LEN)
+            ),
+        ] {
+            let sealed = key.encrypt(text.as_bytes()).unwrap();
+            assert!(Manifest::open(&key, &sealed).is_err(), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Optional desktop notifications for lock/unlock.
+//!
+//! Compiled in with the `notify` feature and enabled per user or per repository
+//! with `git config git-crypt.notify true`. Notifications go through the
+//! platform notifier (`notify-send` on Linux and BSD, `osascript` on macOS);
+//! when none is available, as on headless machines and CI, nothing happens.
+//! Windows has no notifier yet. The notifier is started and left running, so
+//! a slow or hung one never holds up `lock` or `unlock`.
+
+use crate::git::GitRepo;
+use crate::key::LockState;
+
+/// Notify the desktop that the repository's lock state changed.
+///
+/// Never fails: a missing notifier or unreadable config only skips the
+/// notification.
+#[cfg(feature = "notify")]
+pub fn lock_state_changed(repo: &GitRepo, state: LockState) {
+    if !matches!(repo.notify_enabled(), Ok(true)) {
+        return;
+    }
+    let name = repo_name(repo);
+    let body = match state {
+        LockState::Locked => format!("repo {name} locked"),
+        LockState::Unlocked => {
+            format!("repo {name} unlocked \u{2014} secrets are now in plaintext")
+        }
+    };
+    platform::show("git-crypt", &body);
+}
+
+#[cfg(not(feature = "notify"))]
+pub fn lock_state_changed(_repo: &GitRepo, _state: LockState) {}
+
+#[cfg(feature = "notify")]
+fn repo_name(repo: &GitRepo) -> String {
+    repo.workdir()
+        .ok()
+        .and_then(|dir| dir.file_name())
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| repo.git_dir().display().to_string())
+}
+
+#[cfg(feature = "notify")]
+mod platform {
+    #[cfg(unix)]
+    use std::process::{Command, Stdio};
+
+    #[cfg(target_os = "macos")]
+    pub fn show(title: &str, body: &str) {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(body),
+            applescript_string(title)
+        );
+        spawn(Command::new("osascript").args(["-e", &script]));
+    }
+
+    #[cfg(target_os = "macos")]
+    fn applescript_string(text: &str) -> String {
+        format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    pub fn show(title: &str, body: &str) {
+        // Without a display server there is nobody to notify
+        if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+            return;
+        }
+        spawn(Command::new("notify-send").args(["--app-name=git-crypt", title, body]));
+    }
+
+    #[cfg(not(unix))]
+    pub fn show(_title: &str, _body: &str) {}
+
+    /// Start the notifier without waiting for it to finish
+    #[cfg(unix)]
+    fn spawn(command: &mut Command) {
+        let _ = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+    }
+}
@@ -0,0 +1,84 @@
+//! Color-aware output for human-readable commands.
+//!
+//! Whether a stream gets color is decided by `anstream`: only on a terminal,
+//! honoring `NO_COLOR`, `CLICOLOR`/`CLICOLOR_FORCE` and `TERM=dumb`, and
+//! never after `--no-color`. Filters never go through here: their stdout is
+//! file content.
+
+use anstream::{AutoStream, ColorChoice};
+use anstyle::AnsiColor;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NO_COLOR_FLAG: AtomicBool = AtomicBool::new(false);
+
+/// Semantic styles for diagnostic output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Ok,
+    Warn,
+    Fail,
+}
+
+impl Style {
+    fn ansi(&self) -> anstyle::Style {
+        let color = match self {
+            Style::Ok => AnsiColor::Green,
+            Style::Warn => AnsiColor::Yellow,
+            Style::Fail => AnsiColor::Red,
+        };
+        anstyle::Style::new().fg_color(Some(color.into()))
+    }
+}
+
+/// Disable color for the rest of the process (the `--no-color` flag)
+pub fn set_no_color(no_color: bool) {
+    NO_COLOR_FLAG.store(no_color, Ordering::Relaxed);
+    if no_color {
+        ColorChoice::Never.write_global();
+    }
+}
+
+/// Whether `--no-color` was given
+pub fn no_color_flag() -> bool {
+    NO_COLOR_FLAG.load(Ordering::Relaxed)
+}
+
+/// Style `text` for printing to stdout
+pub fn stdout(text: &str, style: Style) -> String {
+    paint(text, style, AutoStream::choice(&std::io::stdout()))
+}
+
+/// Style `text` for printing to stderr
+pub fn stderr(text: &str, style: Style) -> String {
+    paint(text, style, AutoStream::choice(&std::io::stderr()))
+}
+
+fn paint(text: &str, style: Style, choice: ColorChoice) -> String {
+    if choice == ColorChoice::Never {
+        return text.to_string();
+    }
+    let style = style.ansi();
+    format!("{style}{text}{style:#}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paint_is_plain_without_color() {
+        assert_eq!(paint("OK", Style::Ok, ColorChoice::Never), "OK");
+    }
+
+    #[test]
+    fn paint_wraps_text_in_ansi_codes() {
+        assert_eq!(
+            paint("FAIL", Style::Fail, ColorChoice::Always),
+            "\x1b[31mFAIL\x1b[0m"
+        );
+        assert_eq!(
+            paint("WARN", Style::Warn, ColorChoice::AlwaysAnsi),
+            "\x1b[33mWARN\x1b[0m"
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! Bounded parallel execution for bulk operations over many files.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Default worker count: the available parallelism, or 1 if unknown
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Apply `f` to every item using at most `threads` worker threads.
+///
+/// Each worker builds its own state with `init` (e.g. a repository handle that
+/// can't be shared across threads). Results are returned in input order
+/// regardless of which worker handled which item.
+pub fn map_bounded<T, S, R>(
+    items: &[T],
+    threads: usize,
+    init: impl Fn() -> S + Sync,
+    f: impl Fn(&mut S, &T) -> R + Sync,
+) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+{
+    let workers = threads.clamp(1, items.len().max(1));
+    let next = AtomicUsize::new(0);
+    let slots: Vec<Mutex<Option<R>>> = items.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                let mut state = init();
+                loop {
+                    let idx = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(idx) else {
+                        break;
+                    };
+                    let result = f(&mut state, item);
+                    *slots[idx].lock().unwrap() = Some(result);
+                }
+            });
+        }
+    });
+
+    slots
+        .into_iter()
+        .map(|slot| {
+            slot.into_inner()
+                .unwrap()
+                .expect("every item is processed by a worker")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn results_keep_input_order() {
+        let items: Vec<u32> = (0..500).collect();
+        let expected: Vec<u32> = items.iter().map(|n| n * 2).collect();
+
+        for threads in [1, 2, 7, 64] {
+            let results = map_bounded(&items, threads, || (), |_, n| n * 2);
+            assert_eq!(results, expected, "threads = {threads}");
+        }
+    }
+
+    #[test]
+    fn worker_count_is_bounded() {
+        let items: Vec<u32> = (0..200).collect();
+        let seen = Mutex::new(HashSet::new());
+
+        map_bounded(
+            &items,
+            3,
+            || thread::current().id(),
+            |id, _| {
+                seen.lock().unwrap().insert(*id);
+            },
+        );
+
+        assert!(seen.lock().unwrap().len() <= 3);
+    }
+
+    #[test]
+    fn empty_input_and_zero_threads() {
+        let empty: Vec<u32> = Vec::new();
+        assert!(map_bounded(&empty, 4, || (), |_, n| *n).is_empty());
+        assert_eq!(map_bounded(&[1, 2, 3], 0, || (), |_, n| *n), vec![1, 2, 3]);
+    }
+}
@@ -5,10 +5,19 @@ use crate::error::{GitCryptError, Result};
 
 use age::secrecy::SecretString;
 use age::{
+    scrypt,
     ssh::{Identity as SshIdentity, Recipient as SshRecipient},
-    Callbacks, DecryptError, Decryptor, EncryptError, Encryptor,
+    x25519, Callbacks, DecryptError, Decryptor, EncryptError, Encryptor,
 };
 use rpassword::prompt_password;
+use zeroize::Zeroizing;
+
+/// Read a passphrase like [`crate::key::read_passphrase`] (from
+/// `GIT_CRYPT_PASSPHRASE` or the terminal), for age
+pub fn read_passphrase(prompt: &str, confirm: bool) -> Result<SecretString> {
+    let passphrase = crate::key::read_passphrase(prompt, confirm)?;
+    Ok(SecretString::new(passphrase.as_str().into()))
+}
 
 pub struct RageManager;
 
@@ -20,8 +29,30 @@ impl RageManager {
             .parse()
             .map_err(|e| GitCryptError::Age(format!("Invalid SSH recipient: {e:?}")))?;
 
-        let encryptor = Encryptor::with_recipients(std::iter::once(&recipient as _))
-            .map_err(map_encrypt_err)?;
+        Self::encrypt_key_to(key, &recipient)
+    }
+
+    /// Encrypt the repo's symmetric key for a native age (`age1...`) recipient.
+    pub fn encrypt_key_for_age_recipient(key: &CryptoKey, recipient: &str) -> Result<Vec<u8>> {
+        let recipient: x25519::Recipient = recipient
+            .trim()
+            .parse()
+            .map_err(|e| GitCryptError::Age(format!("Invalid age recipient: {e}")))?;
+
+        Self::encrypt_key_to(key, &recipient)
+    }
+
+    /// Encrypt the repo's symmetric key to an age scrypt passphrase recipient.
+    pub fn encrypt_key_with_passphrase(
+        key: &CryptoKey,
+        passphrase: SecretString,
+    ) -> Result<Vec<u8>> {
+        Self::encrypt_key_to(key, &scrypt::Recipient::new(passphrase))
+    }
+
+    fn encrypt_key_to(key: &CryptoKey, recipient: &dyn age::Recipient) -> Result<Vec<u8>> {
+        let encryptor =
+            Encryptor::with_recipients(std::iter::once(recipient)).map_err(map_encrypt_err)?;
 
         let mut ciphertext = Vec::new();
         let mut writer = encryptor
@@ -51,7 +82,7 @@ impl RageManager {
         let mut reader = decryptor
             .decrypt(std::iter::once(&identity as &dyn age::Identity))
             .map_err(map_decrypt_err)?;
-        let mut plaintext = Vec::new();
+        let mut plaintext = Zeroizing::new(Vec::new());
         reader
             .read_to_end(&mut plaintext)
             .map_err(|e| GitCryptError::Io(e))?;
@@ -60,6 +91,30 @@ impl RageManager {
     }
 }
 
+impl RageManager {
+    /// Decrypt an age-encrypted key blob made with [`Self::encrypt_key_with_passphrase`].
+    pub fn decrypt_key_with_passphrase(
+        encrypted: &[u8],
+        passphrase: SecretString,
+    ) -> Result<CryptoKey> {
+        let identity = scrypt::Identity::new(passphrase);
+        let decryptor = Decryptor::new_buffered(Cursor::new(encrypted)).map_err(map_decrypt_err)?;
+        if !decryptor.is_scrypt() {
+            return Err(GitCryptError::Age(
+                "Key blob is not passphrase-encrypted; use --identity".into(),
+            ));
+        }
+
+        let mut reader = decryptor
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .map_err(map_decrypt_err)?;
+        let mut plaintext = Zeroizing::new(Vec::new());
+        reader.read_to_end(&mut plaintext)?;
+
+        CryptoKey::from_bytes(&plaintext)
+    }
+}
+
 fn map_encrypt_err(err: EncryptError) -> GitCryptError {
     GitCryptError::Age(format!("age encryption failed: {err}"))
 }
@@ -112,6 +167,50 @@ AAAEADBJvjZT8X6JRJI8xVq/1aU8nMVgOtVnmdwqWwrSlXG3sKLqeplhpW+uObz5dvMgjz
         assert_eq!(decrypted.as_bytes(), key.as_bytes());
     }
 
+    #[test]
+    fn age_recipient_round_trip() {
+        let key = deterministic_key(0x5A);
+        let identity = x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+
+        let ciphertext = RageManager::encrypt_key_for_age_recipient(&key, &recipient).unwrap();
+        let mut reader = Decryptor::new_buffered(Cursor::new(ciphertext))
+            .unwrap()
+            .decrypt(std::iter::once(&identity as &dyn age::Identity))
+            .unwrap();
+        let mut plaintext = Vec::new();
+        reader.read_to_end(&mut plaintext).unwrap();
+        assert_eq!(plaintext, key.as_bytes());
+    }
+
+    #[test]
+    fn passphrase_round_trip() {
+        let key = deterministic_key(0x3C);
+        let passphrase = || SecretString::new("correct horse battery staple".into());
+
+        let ciphertext = RageManager::encrypt_key_with_passphrase(&key, passphrase()).unwrap();
+        let decrypted =
+            RageManager::decrypt_key_with_passphrase(&ciphertext, passphrase()).unwrap();
+        assert_eq!(decrypted.as_bytes(), key.as_bytes());
+
+        let wrong = SecretString::new("wrong".into());
+        assert!(RageManager::decrypt_key_with_passphrase(&ciphertext, wrong).is_err());
+    }
+
+    #[test]
+    fn passphrase_decrypt_rejects_recipient_blobs() {
+        let key = deterministic_key(0x3D);
+        let ciphertext =
+            RageManager::encrypt_key_for_ssh_recipient(&key, TEST_SSH_ED25519_PUB).unwrap();
+        let Err(err) = RageManager::decrypt_key_with_passphrase(
+            &ciphertext,
+            SecretString::new("anything".into()),
+        ) else {
+            panic!("recipient blob decrypted with a passphrase");
+        };
+        assert!(err.to_string().contains("not passphrase-encrypted"));
+    }
+
     #[test]
     fn invalid_recipient_is_rejected() {
         let key = deterministic_key(0x11);
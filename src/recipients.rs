@@ -0,0 +1,431 @@
+//! # Recipients File
+//!
+//! A committed list of everyone who should hold the repository key, kept at
+//! `.git-crypt/recipients` in the working tree so access changes are reviewable.
+//!
+//! ## Format
+//!
+//! One recipient per line as `<kind> <name> <recipient>`. Blank lines and lines
+//! starting with `#` are ignored.
+//!
+//! ```text
+//! # kind  name   recipient
+//! ssh     alice  ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAA... alice@example.com
+//! age     bob    age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p
+//! gpg     carol  0123456789ABCDEF0123456789ABCDEF01234567
+//! ```
+//!
+//! - `ssh` and `age` recipients are stored as `.git/git-crypt/keys/age/<name>.age`
+//! - `gpg` recipients are stored as `.git/git-crypt/keys/gpg/<name>.key`
+//!
+//! `git-crypt sync-recipients` regenerates those blobs for exactly the listed
+//! recipients and removes blobs for anyone no longer listed.
+//!
+//! ## Blob Manifest
+//!
+//! Every grant also records how the blob was made in
+//! `.git/git-crypt/keys/blobs.manifest`, one line per blob:
+//!
+//! ```text
+//! <format version> <key fingerprint> <kind> <name> <recipient>
+//! ```
+//!
+//! `git-crypt rewrap` uses it to find blobs written in an older
+//! [`BLOB_FORMAT_VERSION`], for another key, or for a changed recipient.
+
+use crate::crypto::CryptoKey;
+use crate::error::{GitCryptError, Result};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Location of the recipients file relative to the repository root
+pub const RECIPIENTS_FILE: &str = ".git-crypt/recipients";
+
+/// Version of the recipient blob format written by this build. Bump it when
+/// the way blobs are encrypted changes so `rewrap` regenerates older ones.
+pub const BLOB_FORMAT_VERSION: u32 = 1;
+
+/// Blob manifest file name, relative to `.git/git-crypt/keys`
+pub const BLOB_MANIFEST_FILE: &str = "blobs.manifest";
+
+/// How a recipient's copy of the key is encrypted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipientKind {
+    Ssh,
+    Age,
+    Gpg,
+}
+
+impl RecipientKind {
+    /// Directory under `keys/` holding blobs for this kind
+    pub fn key_dir(&self) -> &'static str {
+        match self {
+            RecipientKind::Ssh | RecipientKind::Age => "age",
+            RecipientKind::Gpg => "gpg",
+        }
+    }
+
+    /// Name used for this kind in the recipients file
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecipientKind::Ssh => "ssh",
+            RecipientKind::Age => "age",
+            RecipientKind::Gpg => "gpg",
+        }
+    }
+
+    /// Parse a kind as written in the recipients file
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "ssh" => Some(RecipientKind::Ssh),
+            "age" => Some(RecipientKind::Age),
+            "gpg" => Some(RecipientKind::Gpg),
+            _ => None,
+        }
+    }
+
+    /// File extension of blobs for this kind
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RecipientKind::Ssh | RecipientKind::Age => "age",
+            RecipientKind::Gpg => "key",
+        }
+    }
+}
+
+/// A single entry of the recipients file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recipient {
+    pub kind: RecipientKind,
+    pub name: String,
+    pub recipient: String,
+}
+
+/// Load the recipients file from the repository root
+pub fn load_recipients(repo_root: &Path) -> Result<Vec<Recipient>> {
+    let path = repo_root.join(RECIPIENTS_FILE);
+    if !path.exists() {
+        return Err(GitCryptError::Other(format!(
+            "Recipients file not found: {RECIPIENTS_FILE}"
+        )));
+    }
+    parse_recipients(&fs::read_to_string(path)?)
+}
+
+/// Parse the contents of a recipients file
+pub fn parse_recipients(content: &str) -> Result<Vec<Recipient>> {
+    let mut recipients = Vec::new();
+    let mut seen = HashSet::new();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid =
+            |reason: &str| GitCryptError::Other(format!("{RECIPIENTS_FILE}:{line_no}: {reason}"));
+
+        let (kind, rest) = split_field(line);
+        let (name, recipient) = split_field(rest);
+
+        let kind = match RecipientKind::parse(kind) {
+            Some(kind) => kind,
+            None => return Err(invalid(&format!("unknown recipient kind '{kind}'"))),
+        };
+        if name.is_empty() {
+            return Err(invalid("missing recipient name"));
+        }
+        if recipient.is_empty() {
+            return Err(invalid("missing recipient"));
+        }
+
+        if !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            return Err(invalid(&format!("invalid recipient name '{name}'")));
+        }
+        if !seen.insert((kind.key_dir(), name)) {
+            return Err(invalid(&format!("duplicate recipient name '{name}'")));
+        }
+
+        recipients.push(Recipient {
+            kind,
+            name: name.to_string(),
+            recipient: recipient.to_string(),
+        });
+    }
+
+    Ok(recipients)
+}
+
+/// How and for whom a recipient blob was written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobRecord {
+    pub format: u32,
+    pub key_fingerprint: String,
+    pub recipient: Recipient,
+}
+
+impl BlobRecord {
+    /// Record a blob written now, in the current format, of `key` for `recipient`
+    pub fn new(key: &CryptoKey, recipient: Recipient) -> Self {
+        Self {
+            format: BLOB_FORMAT_VERSION,
+            key_fingerprint: key.fingerprint(),
+            recipient,
+        }
+    }
+
+    /// Whether the blob is what granting `recipient` with `key` would write today
+    pub fn is_current(&self, key: &CryptoKey, recipient: &Recipient) -> bool {
+        self.format == BLOB_FORMAT_VERSION
+            && self.key_fingerprint == key.fingerprint()
+            && &self.recipient == recipient
+    }
+}
+
+/// The blobs recorded in `.git/git-crypt/keys/blobs.manifest`
+#[derive(Debug, Default)]
+pub struct BlobManifest {
+    records: Vec<BlobRecord>,
+}
+
+impl BlobManifest {
+    /// Load the manifest from `keys_dir`; a missing manifest is empty
+    pub fn load(keys_dir: &Path) -> Result<Self> {
+        let path = keys_dir.join(BLOB_MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let mut records = Vec::new();
+        for (idx, line) in fs::read_to_string(&path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let invalid = || {
+                GitCryptError::Other(format!(
+                    "{}:{}: malformed blob manifest entry",
+                    path.display(),
+                    idx + 1
+                ))
+            };
+
+            let (format, rest) = split_field(line);
+            let (key_fingerprint, rest) = split_field(rest);
+            let (kind, rest) = split_field(rest);
+            let (name, recipient) = split_field(rest);
+            if key_fingerprint.is_empty() || name.is_empty() || recipient.is_empty() {
+                return Err(invalid());
+            }
+
+            records.push(BlobRecord {
+                format: format.parse().map_err(|_| invalid())?,
+                key_fingerprint: key_fingerprint.to_string(),
+                recipient: Recipient {
+                    kind: RecipientKind::parse(kind).ok_or_else(invalid)?,
+                    name: name.to_string(),
+                    recipient: recipient.to_string(),
+                },
+            });
+        }
+        Ok(Self { records })
+    }
+
+    /// Write the manifest to `keys_dir`
+    pub fn save(&self, keys_dir: &Path) -> Result<()> {
+        let mut content = String::new();
+        for record in &self.records {
+            let r = &record.recipient;
+            content.push_str(&format!(
+                "{} {} {} {} {}\n",
+                record.format,
+                record.key_fingerprint,
+                r.kind.as_str(),
+                r.name,
+                r.recipient
+            ));
+        }
+        fs::create_dir_all(keys_dir)?;
+        fs::write(keys_dir.join(BLOB_MANIFEST_FILE), content)?;
+        Ok(())
+    }
+
+    /// The record for the blob `name` of `kind`, if any
+    pub fn get(&self, kind: RecipientKind, name: &str) -> Option<&BlobRecord> {
+        self.records
+            .iter()
+            .find(|r| r.recipient.kind.key_dir() == kind.key_dir() && r.recipient.name == name)
+    }
+
+    /// Add `record`, replacing any earlier record for the same blob
+    pub fn record(&mut self, record: BlobRecord) {
+        let recipient = &record.recipient;
+        self.remove(recipient.kind, &recipient.name.clone());
+        self.records.push(record);
+    }
+
+    /// Forget the blob `name` of `kind`
+    pub fn remove(&mut self, kind: RecipientKind, name: &str) {
+        self.records.retain(|r| {
+            !(r.recipient.kind.key_dir() == kind.key_dir() && r.recipient.name == name)
+        });
+    }
+
+    pub fn records(&self) -> &[BlobRecord] {
+        &self.records
+    }
+}
+
+/// Split off the first whitespace-delimited field, returning it and the trimmed rest
+fn split_field(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim()),
+        None => (line, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn parse_recipients_reads_all_kinds() {
+        let content = "\
+# team access
+ssh alice ssh-ed25519 AAAAC3 alice@example.com
+
+age   bob   age1qyqszqgpqyqszqgpqyqszqgpqyqszqgp
+gpg carol 0123456789ABCDEF
+";
+        let recipients = parse_recipients(content).unwrap();
+        assert_eq!(
+            recipients,
+            vec![
+                Recipient {
+                    kind: RecipientKind::Ssh,
+                    name: "alice".into(),
+                    recipient: "ssh-ed25519 AAAAC3 alice@example.com".into(),
+                },
+                Recipient {
+                    kind: RecipientKind::Age,
+                    name: "bob".into(),
+                    recipient: "age1qyqszqgpqyqszqgpqyqszqgpqyqszqgp".into(),
+                },
+                Recipient {
+                    kind: RecipientKind::Gpg,
+                    name: "carol".into(),
+                    recipient: "0123456789ABCDEF".into(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_recipients_reports_line_numbers() {
+        let err = parse_recipients("# header\nsmtp dave dave@example.com\n").unwrap_err();
+        assert!(err.to_string().contains(":2:"));
+        assert!(err.to_string().contains("unknown recipient kind"));
+    }
+
+    #[test]
+    fn parse_recipients_rejects_bad_entries() {
+        assert!(parse_recipients("ssh alice\n").is_err());
+        assert!(parse_recipients("ssh ../alice ssh-ed25519 AAAA\n").is_err());
+        assert!(parse_recipients("ssh alice ssh-ed25519 A\nage alice age1xyz\n").is_err());
+        // Same name is fine across separate key directories
+        assert!(parse_recipients("ssh alice ssh-ed25519 A\ngpg alice ABCDEF\n").is_ok());
+    }
+
+    #[test]
+    fn load_recipients_requires_file() {
+        let temp = TempDir::new().unwrap();
+        assert!(load_recipients(temp.path()).is_err());
+
+        fs::create_dir(temp.path().join(".git-crypt")).unwrap();
+        fs::write(temp.path().join(RECIPIENTS_FILE), "# nobody yet\n").unwrap();
+        assert!(load_recipients(temp.path()).unwrap().is_empty());
+    }
+
+    fn alice() -> Recipient {
+        Recipient {
+            kind: RecipientKind::Ssh,
+            name: "alice".into(),
+            recipient: "ssh-ed25519 AAAAC3 alice@example.com".into(),
+        }
+    }
+
+    #[test]
+    fn blob_manifest_round_trips() {
+        let temp = TempDir::new().unwrap();
+        let key = CryptoKey::generate();
+
+        let mut manifest = BlobManifest::load(temp.path()).unwrap();
+        assert!(manifest.records().is_empty());
+        manifest.record(BlobRecord::new(&key, alice()));
+        manifest.record(BlobRecord::new(
+            &key,
+            Recipient {
+                kind: RecipientKind::Gpg,
+                name: "carol".into(),
+                recipient: "0123456789ABCDEF".into(),
+            },
+        ));
+        manifest.save(temp.path()).unwrap();
+
+        let loaded = BlobManifest::load(temp.path()).unwrap();
+        assert_eq!(loaded.records(), manifest.records());
+        assert_eq!(
+            loaded.get(RecipientKind::Ssh, "alice").unwrap().recipient,
+            alice()
+        );
+        // ssh and age blobs share a directory, so they share manifest entries
+        assert!(loaded.get(RecipientKind::Age, "alice").is_some());
+        assert!(loaded.get(RecipientKind::Gpg, "alice").is_none());
+    }
+
+    #[test]
+    fn blob_manifest_replaces_and_removes_records() {
+        let key = CryptoKey::generate();
+        let mut manifest = BlobManifest::default();
+        manifest.record(BlobRecord::new(&key, alice()));
+        manifest.record(BlobRecord::new(&key, alice()));
+        assert_eq!(manifest.records().len(), 1);
+
+        manifest.remove(RecipientKind::Age, "alice");
+        assert!(manifest.records().is_empty());
+    }
+
+    #[test]
+    fn blob_manifest_rejects_malformed_lines() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join(BLOB_MANIFEST_FILE), "1 abcd ssh alice\n").unwrap();
+        let err = BlobManifest::load(temp.path()).unwrap_err().to_string();
+        assert!(err.contains(":1: malformed"), "{err}");
+    }
+
+    #[test]
+    fn blob_record_is_current_only_for_same_format_key_and_recipient() {
+        let key = CryptoKey::generate();
+        let record = BlobRecord::new(&key, alice());
+        assert!(record.is_current(&key, &alice()));
+
+        assert!(!record.is_current(&CryptoKey::generate(), &alice()));
+
+        let mut moved = alice();
+        moved.recipient = "ssh-ed25519 BBBBC3 alice@example.com".into();
+        assert!(!record.is_current(&key, &moved));
+
+        let old = BlobRecord {
+            format: BLOB_FORMAT_VERSION - 1,
+            ..record
+        };
+        assert!(!old.is_current(&key, &alice()));
+    }
+}
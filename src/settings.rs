@@ -0,0 +1,94 @@
+//! # Settings
+//!
+//! Effective git-crypt settings together with where each one came from, so
+//! `git-crypt config --list` can explain why git-crypt behaves the way it does.
+//!
+//! Settings are layered: a built-in default, then `.git-crypt.toml`, then git
+//! config, then the environment, then command-line flags. Each later layer
+//! overrides the earlier ones, and [`layered`] resolves a value by that rule.
+
+use std::fmt;
+
+/// The layer a setting's effective value came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    /// Built-in default
+    Default,
+    /// `.git-crypt.toml` in the working tree
+    File,
+    /// git config (local, global or system)
+    GitConfig,
+    /// An environment variable
+    Env,
+    /// A command-line flag
+    Cli,
+}
+
+impl Source {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Source::Default => "default",
+            Source::File => "file",
+            Source::GitConfig => "git-config",
+            Source::Env => "env",
+            Source::Cli => "cli",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One effective setting
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Setting {
+    pub name: String,
+    pub value: String,
+    pub source: Source,
+}
+
+impl Setting {
+    pub fn new(name: impl Into<String>, value: impl ToString, source: Source) -> Self {
+        Setting {
+            name: name.into(),
+            value: value.to_string(),
+            source,
+        }
+    }
+}
+
+/// Resolve a value from `layers`, falling back to `default`.
+///
+/// The highest-precedence layer that sets the value wins, whatever order the
+/// layers are given in.
+pub fn layered<T>(
+    default: T,
+    layers: impl IntoIterator<Item = (Source, Option<T>)>,
+) -> (T, Source) {
+    layers
+        .into_iter()
+        .filter_map(|(source, value)| value.map(|value| (value, source)))
+        .max_by_key(|(_, source)| *source)
+        .unwrap_or((default, Source::Default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layered_prefers_higher_sources() {
+        assert_eq!(
+            layered(0, [(Source::Env, Some(3)), (Source::File, Some(1))]),
+            (3, Source::Env)
+        );
+        assert_eq!(
+            layered(0, [(Source::Cli, None), (Source::GitConfig, Some(2))]),
+            (2, Source::GitConfig)
+        );
+        assert_eq!(layered(7, [(Source::File, None)]), (7, Source::Default));
+    }
+}
@@ -1,4 +1,5 @@
 use assert_cmd::{cargo::cargo_bin_cmd, Command};
+use std::path::Path;
 use std::process::Command as StdCommand;
 use tempfile::TempDir;
 
@@ -38,3 +39,23 @@ pub fn git_crypt_cmd() -> Command {
 pub fn git_crypt_bin() -> &'static str {
     env!("CARGO_BIN_EXE_git-crypt")
 }
+
+/// Point the repository's git-crypt filters at the test binary so that real
+/// `git add`/`git checkout` run clean and smudge without it being on PATH.
+#[allow(dead_code)]
+pub fn use_test_binary_filters(dir: &Path) {
+    for filter in ["clean", "smudge", "diff"] {
+        StdCommand::new("git")
+            .args([
+                "config",
+                &format!("filter.git-crypt.{filter}"),
+                &match filter {
+                    "diff" => format!("'{}' diff", git_crypt_bin()),
+                    _ => format!("'{}' {filter} -- %f", git_crypt_bin()),
+                },
+            ])
+            .current_dir(dir)
+            .output()
+            .expect("failed to configure git-crypt filter");
+    }
+}
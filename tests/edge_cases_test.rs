@@ -4,7 +4,8 @@
 //!
 //! ## Test Coverage
 //!
-//! - **Large files**: 10MB file encryption/decryption
+//! - **Large files**: 10MB file encryption/decryption through the stream format,
+//!   and bounded memory on a 200MB input (ignored by default; slow in debug builds)
 //! - **Empty files**: Zero-byte file handling
 //! - **Binary data**: Files with null bytes and all byte values
 //! - **Unicode**: International characters in filenames and content
@@ -48,44 +49,114 @@ fn test_very_large_file_encryption() {
         .assert()
         .success();
 
-    // Create 10MB file
+    // Create 10MB file, above the size the filters stream instead of buffering
     let large_data = vec![0x42u8; 10 * 1024 * 1024];
 
+    let encrypted = git_crypt_cmd()
+        .arg("clean")
+        .current_dir(temp.path())
+        .write_stdin(large_data.clone())
+        .output()
+        .unwrap();
+    assert!(encrypted.status.success());
+    // Stored in the stream format (version 3)
+    assert_eq!(&encrypted.stdout[..10], b"GITCRYPT\x00\x03");
+
+    // Decrypt back
+    let decrypted = git_crypt_cmd()
+        .arg("smudge")
+        .current_dir(temp.path())
+        .write_stdin(encrypted.stdout)
+        .output()
+        .unwrap();
+    assert!(decrypted.status.success());
+    assert_eq!(decrypted.stdout.len(), large_data.len());
+    assert!(decrypted.stdout == large_data);
+}
+
+/// Peak memory of clean and smudge on a 200 MB input stays far below its size.
+///
+/// Slow in debug builds; run with
+/// `cargo test --release --test edge_cases_test -- --ignored`.
+#[cfg(unix)]
+#[test]
+#[ignore]
+fn test_streaming_filters_peak_rss() {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    const SIZE: usize = 200 * 1024 * 1024;
+    const MAX_RSS: i64 = 64 * 1024 * 1024;
+
+    // Peak RSS of any child this process has waited for
+    fn children_max_rss() -> i64 {
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        assert_eq!(
+            unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) },
+            0
+        );
+        // Kilobytes on Linux, bytes on macOS
+        if cfg!(target_os = "macos") {
+            usage.ru_maxrss
+        } else {
+            usage.ru_maxrss * 1024
+        }
+    }
+
+    let temp = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(temp.path())
+        .assert()
+        .success();
+
+    let chunk: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let mut expected = Sha256::new();
+    for _ in 0..SIZE / chunk.len() {
+        expected.update(&chunk);
+    }
+
+    let encrypted_path = temp.path().join("large.enc");
     let mut clean = StdCommand::new(git_crypt_bin())
         .arg("clean")
         .current_dir(temp.path())
         .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
+        .stdout(fs::File::create(&encrypted_path).unwrap())
         .spawn()
         .unwrap();
+    let mut stdin = clean.stdin.take().unwrap();
+    let feeder = std::thread::spawn(move || {
+        for _ in 0..SIZE / chunk.len() {
+            stdin.write_all(&chunk).unwrap();
+        }
+    });
+    feeder.join().unwrap();
+    assert!(clean.wait().unwrap().success());
+    let clean_rss = children_max_rss();
+    assert!(clean_rss < MAX_RSS, "clean peaked at {clean_rss} bytes");
 
-    clean
-        .stdin
-        .as_mut()
-        .unwrap()
-        .write_all(&large_data)
-        .unwrap();
-    let encrypted = clean.wait_with_output().unwrap();
-    assert!(encrypted.status.success());
-
-    // Decrypt back
     let mut smudge = StdCommand::new(git_crypt_bin())
         .arg("smudge")
         .current_dir(temp.path())
-        .stdin(Stdio::piped())
+        .stdin(fs::File::open(&encrypted_path).unwrap())
         .stdout(Stdio::piped())
         .spawn()
         .unwrap();
+    let mut stdout = smudge.stdout.take().unwrap();
+    let mut actual = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    loop {
+        let n = stdout.read(&mut buffer).unwrap();
+        if n == 0 {
+            break;
+        }
+        actual.update(&buffer[..n]);
+    }
+    assert!(smudge.wait().unwrap().success());
+    let smudge_rss = children_max_rss();
+    assert!(smudge_rss < MAX_RSS, "smudge peaked at {smudge_rss} bytes");
 
-    smudge
-        .stdin
-        .as_mut()
-        .unwrap()
-        .write_all(&encrypted.stdout)
-        .unwrap();
-    let decrypted = smudge.wait_with_output().unwrap();
-    assert!(decrypted.status.success());
-    assert_eq!(decrypted.stdout.len(), large_data.len());
+    assert_eq!(actual.finalize(), expected.finalize());
 }
 
 #[test]
@@ -498,3 +569,38 @@ fn test_repeated_key_operations() {
     let encrypted = clean.wait_with_output().unwrap();
     assert!(encrypted.status.success());
 }
+
+#[test]
+fn test_filters_tolerate_closed_reader() {
+    let temp = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(temp.path())
+        .assert()
+        .success();
+
+    for filter in ["clean", "smudge", "diff"] {
+        let mut child = StdCommand::new(git_crypt_bin())
+            .arg(filter)
+            .current_dir(temp.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        // Close the read end of stdout before the filter writes anything
+        drop(child.stdout.take());
+
+        let mut stdin = child.stdin.take().unwrap();
+        stdin.write_all(&vec![0x42u8; 256 * 1024]).unwrap();
+        drop(stdin);
+
+        let output = child.wait_with_output().unwrap();
+        assert!(
+            output.status.success(),
+            "{filter} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+}
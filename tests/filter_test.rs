@@ -10,13 +10,21 @@
 //! - **Round-trip encryption**: Multiple content types (text, binary, Unicode)
 //! - **Nonce uniqueness**: Ensures different ciphertext for same plaintext
 //! - **Error handling**: Uninitialized repository detection
+//! - **Standalone mode**: `--key-file` outside any repository
+//! - **Diff filter on unencrypted data**: Text passes through, binary is summarized
+//! - **Smudge cache**: Cache hits, and clearing on lock
+//! - **File mode**: `--input`/`--output` instead of stdin/stdout
+//! - **Error context**: failures name the file passed as `%f`
+//! - **Upstream git-crypt**: blobs and key files written by the C++ tool
+//! - **Progress**: never written when stderr is not a terminal
+//! - **Symlinks**: a symlink's target passes through the clean filter unencrypted
 //!
 //! ## How Git Filters Work
 //!
 //! Git filters are configured in `.git/config`:
 //! ```text
-//! filter.git-crypt.clean = git-crypt clean
-//! filter.git-crypt.smudge = git-crypt smudge
+//! filter.git-crypt.clean = git-crypt clean -- %f
+//! filter.git-crypt.smudge = git-crypt smudge -- %f
 //! filter.git-crypt.diff = git-crypt diff
 //! ```
 //!
@@ -243,6 +251,44 @@ fn test_clean_filter_without_init_fails() {
     assert!(!output.status.success());
 }
 
+#[test]
+fn test_smudge_without_key_refuses_encrypted_blobs() {
+    let source = create_git_repo();
+    init_git_crypt(source.path());
+    let blob = run_filter(source.path(), "clean", b"top secret");
+
+    let temp = create_git_repo();
+    let output = filter_output(temp.path(), &["smudge", "a.secret"], &blob);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(
+        "a.secret is encrypted but no git-crypt key is available; run 'git-crypt unlock'"
+    ));
+
+    // Plaintext is still checked out as is
+    assert_eq!(run_filter(temp.path(), "smudge", b"plain"), b"plain");
+}
+
+#[cfg(unix)]
+#[test]
+fn test_clean_passes_symlink_targets_through() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    std::os::unix::fs::symlink("target.txt", temp.path().join("link.secret")).unwrap();
+
+    let output = filter_output(temp.path(), &["clean", "link.secret"], b"target.txt");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"target.txt");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("link.secret is not a regular file; storing it unencrypted"));
+
+    // A regular file at the path is encrypted as usual
+    std::fs::remove_file(temp.path().join("link.secret")).unwrap();
+    std::fs::write(temp.path().join("link.secret"), "data").unwrap();
+    let encrypted = run_filter_args(temp.path(), &["clean", "link.secret"], b"data");
+    assert!(encrypted.starts_with(b"GITCRYPT"));
+}
+
 #[test]
 fn test_multiple_encryptions_different_output() {
     let temp = create_git_repo();
@@ -289,3 +335,542 @@ fn test_multiple_encryptions_different_output() {
         assert_eq!(&decrypted.stdout[..], plaintext);
     }
 }
+
+#[test]
+fn test_padding_normalizes_small_file_sizes() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    let status = Command::new("git")
+        .args(["config", "filter.git-crypt.padding", "256"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let mut sizes = Vec::new();
+    for plaintext in [&b""[..], b"short secret", &[0x42u8; 200][..]] {
+        let mut clean = Command::new(git_crypt_bin())
+            .arg("clean")
+            .current_dir(temp.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        clean.stdin.as_mut().unwrap().write_all(plaintext).unwrap();
+        let encrypted = clean.wait_with_output().unwrap();
+        assert!(encrypted.status.success());
+        sizes.push(encrypted.stdout.len());
+
+        let mut smudge = Command::new(git_crypt_bin())
+            .arg("smudge")
+            .current_dir(temp.path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        smudge
+            .stdin
+            .as_mut()
+            .unwrap()
+            .write_all(&encrypted.stdout)
+            .unwrap();
+        let decrypted = smudge.wait_with_output().unwrap();
+        assert!(decrypted.status.success());
+        assert_eq!(decrypted.stdout, plaintext);
+    }
+
+    assert!(sizes.iter().all(|&size| size == sizes[0]));
+}
+
+#[test]
+fn test_wrapped_key_format_round_trip() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    let status = Command::new("git")
+        .args(["config", "filter.git-crypt.wrapped", "true"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let plaintext = b"wrapped secret";
+
+    let mut clean = Command::new(git_crypt_bin())
+        .arg("clean")
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    clean.stdin.as_mut().unwrap().write_all(plaintext).unwrap();
+    let encrypted = clean.wait_with_output().unwrap();
+    assert!(encrypted.status.success());
+
+    // Versioned header: magic, marker, wrapped-key format version
+    assert_eq!(&encrypted.stdout[..10], b"GITCRYPT\x00\x02");
+
+    let mut smudge = Command::new(git_crypt_bin())
+        .arg("smudge")
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    smudge
+        .stdin
+        .as_mut()
+        .unwrap()
+        .write_all(&encrypted.stdout)
+        .unwrap();
+    let decrypted = smudge.wait_with_output().unwrap();
+    assert!(decrypted.status.success());
+    assert_eq!(&decrypted.stdout[..], plaintext);
+}
+
+#[test]
+fn test_filter_outside_repo_hints_at_key_file() {
+    let temp = tempfile::TempDir::new().unwrap();
+
+    let mut child = Command::new(git_crypt_bin())
+        .arg("clean")
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn clean filter");
+    child.stdin.take().unwrap().write_all(b"data").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Not in a git repository"));
+    assert!(stderr.contains("pass --key-file"));
+}
+
+#[test]
+fn test_filters_with_key_file_work_outside_repo() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let key_file = temp.path().join("key.bin");
+    std::fs::write(&key_file, [7u8; 32]).unwrap();
+    let plaintext = b"standalone secret";
+
+    let mut clean = Command::new(git_crypt_bin())
+        .args(["clean", "--key-file", key_file.to_str().unwrap()])
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    clean.stdin.take().unwrap().write_all(plaintext).unwrap();
+    let encrypted = clean.wait_with_output().unwrap();
+    assert!(encrypted.status.success());
+    assert!(encrypted.stdout.starts_with(b"GITCRYPT"));
+
+    let mut smudge = Command::new(git_crypt_bin())
+        .args(["smudge", "--key-file", key_file.to_str().unwrap()])
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    smudge
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&encrypted.stdout)
+        .unwrap();
+    let decrypted = smudge.wait_with_output().unwrap();
+    assert!(decrypted.status.success());
+    assert_eq!(decrypted.stdout, plaintext);
+}
+
+fn run_filter(repo_path: &std::path::Path, filter: &str, input: &[u8]) -> Vec<u8> {
+    run_filter_args(repo_path, &[filter], input)
+}
+
+fn run_filter_args(repo_path: &std::path::Path, args: &[&str], input: &[u8]) -> Vec<u8> {
+    let output = filter_output(repo_path, args, input);
+    assert!(output.status.success());
+    output.stdout
+}
+
+fn filter_output(repo_path: &std::path::Path, args: &[&str], input: &[u8]) -> std::process::Output {
+    let mut child = Command::new(git_crypt_bin())
+        .args(args)
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn filter");
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().expect("Failed to read output")
+}
+
+#[test]
+fn test_smudge_cache_hit_and_lock_clears_it() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let status = Command::new("git")
+        .args(["config", "filter.git-crypt.smudgecache", "1m"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let ciphertext = run_filter(temp.path(), "clean", b"cached secret");
+    assert_eq!(
+        run_filter(temp.path(), "smudge", &ciphertext),
+        b"cached secret"
+    );
+
+    let cache_root = temp.path().join(".git/git-crypt/cache");
+    let key_dirs: Vec<_> = std::fs::read_dir(&cache_root).unwrap().collect();
+    assert_eq!(key_dirs.len(), 1);
+    let key_dir = key_dirs.into_iter().next().unwrap().unwrap().path();
+    let entries: Vec<_> = std::fs::read_dir(&key_dir).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+
+    // A second smudge is served from the cache without decrypting
+    let entry = entries.into_iter().next().unwrap().unwrap().path();
+    std::fs::write(&entry, b"served from cache").unwrap();
+    assert_eq!(
+        run_filter(temp.path(), "smudge", &ciphertext),
+        b"served from cache"
+    );
+
+    // Plaintext must not outlive the unlocked state
+    let status = Command::new(git_crypt_bin())
+        .arg("lock")
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(!cache_root.exists());
+}
+
+#[test]
+fn test_smudge_without_cache_config_writes_nothing() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    let ciphertext = run_filter(temp.path(), "clean", b"secret");
+    assert_eq!(run_filter(temp.path(), "smudge", &ciphertext), b"secret");
+    assert!(!temp.path().join(".git/git-crypt/cache").exists());
+}
+
+#[test]
+fn test_diff_filter_summarizes_unencrypted_binary() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    // NUL bytes
+    assert_eq!(
+        run_filter(temp.path(), "diff", b"PNG\x00\x01\x02"),
+        b"Binary file (not encrypted)\n"
+    );
+    // Invalid UTF-8 without any NUL
+    assert_eq!(
+        run_filter(temp.path(), "diff", &[0xff, 0xfe, b'a', b'b']),
+        b"Binary file (not encrypted)\n"
+    );
+}
+
+#[test]
+fn test_diff_filter_passes_unencrypted_text_through() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    let text = "plain text, caf\u{e9}\n".as_bytes();
+    assert_eq!(run_filter(temp.path(), "diff", text), text);
+    assert_eq!(run_filter(temp.path(), "diff", b""), b"");
+}
+
+#[test]
+fn test_repo_key_path_overrides_default_key() {
+    let temp = create_git_repo();
+    let key_dir = tempfile::TempDir::new().unwrap();
+    let key_path = key_dir.path().join("mounted.key");
+    let key_arg = key_path.to_str().unwrap();
+
+    let status = Command::new(git_crypt_bin())
+        .args(["--repo-key-path", key_arg, "init"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(key_path.exists());
+    assert!(!temp.path().join(".git/git-crypt/keys/default").exists());
+
+    let ciphertext = run_filter_args(
+        temp.path(),
+        &["--repo-key-path", key_arg, "clean"],
+        b"mounted secret",
+    );
+    assert!(ciphertext.starts_with(b"GITCRYPT"));
+    let plaintext = run_filter_args(
+        temp.path(),
+        &["smudge", "--repo-key-path", key_arg],
+        &ciphertext,
+    );
+    assert_eq!(plaintext, b"mounted secret");
+
+    // Without the flag the repository has no key of its own
+    let status = Command::new(git_crypt_bin())
+        .arg("clean")
+        .current_dir(temp.path())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
+
+#[test]
+fn test_filter_path_in_git_crypt_submodule_uses_submodule_key() {
+    let sub_origin = create_git_repo();
+    std::fs::write(sub_origin.path().join("README"), "sub").unwrap();
+    for args in [&["add", "README"][..], &["commit", "-m", "init"]] {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(sub_origin.path())
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    let parent = create_git_repo();
+    init_git_crypt(parent.path());
+    let status = Command::new("git")
+        .args(["-c", "protocol.file.allow=always", "submodule", "add"])
+        .arg(sub_origin.path())
+        .arg("sub")
+        .current_dir(parent.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let sub = parent.path().join("sub");
+    init_git_crypt(&sub);
+
+    // The submodule's key encrypts its files, not the parent's
+    let ciphertext = run_filter_args(
+        parent.path(),
+        &["clean", "sub/secret.txt"],
+        b"submodule secret",
+    );
+    assert_eq!(run_filter(&sub, "smudge", &ciphertext), b"submodule secret");
+    assert_eq!(
+        run_filter_args(parent.path(), &["smudge", "sub/secret.txt"], &ciphertext),
+        b"submodule secret"
+    );
+    let parent_smudge = filter_output(parent.path(), &["smudge"], &ciphertext);
+    assert!(!parent_smudge.status.success());
+
+    // Paths outside the submodule keep using the parent's key
+    let parent_ciphertext = run_filter_args(parent.path(), &["clean", "top.txt"], b"top");
+    assert_eq!(
+        run_filter(parent.path(), "smudge", &parent_ciphertext),
+        b"top"
+    );
+
+    // A locked-out submodule is an error, not a fallback to the parent's key
+    std::fs::remove_file(
+        parent
+            .path()
+            .join(".git/modules/sub/git-crypt/keys/default"),
+    )
+    .unwrap();
+    let output = filter_output(parent.path(), &["clean", "sub/secret.txt"], b"x");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("belongs to a submodule"));
+}
+
+#[test]
+fn test_filters_read_and_write_files() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let plaintext = b"file mode secret";
+    std::fs::write(temp.path().join("a"), plaintext).unwrap();
+
+    let status = Command::new(git_crypt_bin())
+        .args(["clean", "--input", "a", "--output", "a.enc"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let encrypted = std::fs::read(temp.path().join("a.enc")).unwrap();
+    assert!(encrypted.starts_with(b"GITCRYPT"));
+
+    let status = Command::new(git_crypt_bin())
+        .args(["smudge", "--input", "a.enc", "--output", "a.dec"])
+        .current_dir(temp.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(std::fs::read(temp.path().join("a.dec")).unwrap(), plaintext);
+}
+
+#[test]
+fn test_filters_mix_files_and_stdio() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let plaintext = b"half file, half pipe";
+
+    // stdin in, file out
+    let output = filter_output(temp.path(), &["clean", "--output", "b.enc"], plaintext);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+    let encrypted = std::fs::read(temp.path().join("b.enc")).unwrap();
+    assert!(encrypted.starts_with(b"GITCRYPT"));
+
+    // file in, stdout out; stdin is ignored
+    let decrypted = run_filter_args(temp.path(), &["smudge", "--input", "b.enc"], b"ignored");
+    assert_eq!(decrypted, plaintext);
+}
+
+#[test]
+fn test_filter_refuses_same_input_and_output() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    std::fs::write(temp.path().join("a"), b"in place").unwrap();
+
+    let output = filter_output(
+        temp.path(),
+        &["clean", "--input", "a", "--output", "./a"],
+        b"",
+    );
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("same file"));
+    assert_eq!(std::fs::read(temp.path().join("a")).unwrap(), b"in place");
+}
+
+#[test]
+fn test_filter_missing_input_file_fails() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+
+    let output = filter_output(temp.path(), &["clean", "--input", "missing"], b"");
+    assert!(!output.status.success());
+    assert!(!temp.path().join("missing").exists());
+}
+
+#[test]
+fn test_smudge_error_names_the_file() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let encrypted = filter_output(temp.path(), &["clean"], b"secret").stdout;
+    let mut tampered = encrypted.clone();
+    *tampered.last_mut().unwrap() ^= 1;
+
+    let output = filter_output(temp.path(), &["smudge", "--", "secrets/api.txt"], &tampered);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Failed to decrypt secrets/api.txt: "),
+        "{stderr}"
+    );
+
+    // Without a path the error is unchanged
+    let output = filter_output(temp.path(), &["smudge"], &tampered);
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Failed to decrypt"));
+}
+
+/// Upstream git-crypt key file with AES key bytes 0..32 and HMAC key bytes 64..128
+fn upstream_key_file() -> Vec<u8> {
+    let mut bytes = b"\0GITCRYPTKEY\0\0\0\x02\0\0\0\0".to_vec();
+    bytes.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, 4, 0, 0, 0, 0]);
+    bytes.extend_from_slice(&[0, 0, 0, 3, 0, 0, 0, 32]);
+    bytes.extend(0u8..32);
+    bytes.extend_from_slice(&[0, 0, 0, 5, 0, 0, 0, 64]);
+    bytes.extend(64u8..128);
+    bytes.extend_from_slice(&[0, 0, 0, 0]);
+    bytes
+}
+
+#[test]
+fn test_smudge_reads_upstream_git_crypt_blob() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let key_file = temp.path().join("upstream.key");
+    std::fs::write(&key_file, upstream_key_file()).unwrap();
+
+    // `hello from upstream git-crypt\n` as upstream git-crypt stores it
+    let mut blob = b"\0GITCRYPT\0".to_vec();
+    blob.extend(hex::decode("c5e82db533e03f4ba70fc9f5").unwrap());
+    blob.extend(
+        hex::decode("6250b099abbb9863b6cca6d9f71517403735ed744d627dd3fb143d9fc119").unwrap(),
+    );
+
+    // The repository key can't read it
+    let output = filter_output(temp.path(), &["smudge"], &blob);
+    assert!(!output.status.success());
+
+    let key_arg = key_file.to_str().unwrap();
+    let decrypted = run_filter_args(temp.path(), &["smudge", "--key-file", key_arg], &blob);
+    assert_eq!(decrypted, b"hello from upstream git-crypt\n");
+
+    let import = Command::new(git_crypt_bin())
+        .args(["import-key", key_arg])
+        .current_dir(temp.path())
+        .output()
+        .unwrap();
+    assert!(import.status.success());
+    assert!(String::from_utf8_lossy(&import.stdout).contains("upstream git-crypt key"));
+    let decrypted = run_filter(temp.path(), "smudge", &blob);
+    assert_eq!(decrypted, b"hello from upstream git-crypt\n");
+
+    // Re-encrypting writes our own format
+    let encrypted = run_filter(temp.path(), "clean", &decrypted);
+    assert!(encrypted.starts_with(b"GITCRYPT"));
+    assert_eq!(run_filter(temp.path(), "smudge", &encrypted), decrypted);
+}
+
+#[test]
+fn test_progress_is_silent_without_a_terminal() {
+    let temp = create_git_repo();
+    init_git_crypt(temp.path());
+    let plaintext = vec![b'x'; 9 * 1024 * 1024];
+
+    let encrypted = large_filter_output(
+        temp.path(),
+        &["clean", "--progress", "--", "big.bin"],
+        &plaintext,
+    );
+    assert!(encrypted.status.success());
+    assert!(encrypted.stderr.is_empty());
+
+    let output = large_filter_output(temp.path(), &["smudge", "--progress"], &encrypted.stdout);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(output.stdout, plaintext);
+}
+
+/// Like [`filter_output`] with `GIT_CRYPT_PROGRESS=1`, feeding `input` from
+/// another thread: streamed output fills the pipe before input is consumed
+fn large_filter_output(
+    repo_path: &std::path::Path,
+    args: &[&str],
+    input: &[u8],
+) -> std::process::Output {
+    let mut child = Command::new(git_crypt_bin())
+        .args(args)
+        .env("GIT_CRYPT_PROGRESS", "1")
+        .current_dir(repo_path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn filter");
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input).unwrap());
+    let output = child.wait_with_output().expect("Failed to read output");
+    writer.join().unwrap();
+    output
+}
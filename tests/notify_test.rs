@@ -0,0 +1,171 @@
+#![cfg(all(feature = "notify", unix, not(target_os = "macos")))]
+
+mod common;
+
+use common::{create_git_repo, git_crypt_cmd};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+
+/// Install a fake `notify-send` that appends its arguments to `log`
+fn fake_notifier(log: &Path) -> TempDir {
+    let bin = TempDir::new().unwrap();
+    let script = bin.path().join("notify-send");
+    fs::write(
+        &script,
+        format!("#!/bin/sh\necho \"$@\" >> '{}'\n", log.display()),
+    )
+    .unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+    bin
+}
+
+fn path_with(dir: &Path) -> String {
+    format!(
+        "{}:{}",
+        dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    )
+}
+
+/// The notifier runs in the background: wait for it to log `lines` entries
+fn wait_for_log(log: &Path, lines: usize) -> String {
+    for _ in 0..100 {
+        let logged = fs::read_to_string(log).unwrap_or_default();
+        if logged.lines().count() >= lines {
+            return logged;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("notifier logged fewer than {lines} line(s)");
+}
+
+fn enable_notify(repo: &Path) {
+    Command::new("git")
+        .args(["config", "git-crypt.notify", "true"])
+        .current_dir(repo)
+        .output()
+        .unwrap();
+}
+
+#[test]
+fn lock_and_unlock_notify_when_enabled() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+    enable_notify(repo.path());
+
+    let log = repo.path().join("notifications.log");
+    let bin = fake_notifier(&log);
+
+    for command in ["lock", "unlock"] {
+        git_crypt_cmd()
+            .arg(command)
+            .env("PATH", path_with(bin.path()))
+            .env("DISPLAY", ":0")
+            .current_dir(repo.path())
+            .assert()
+            .success();
+    }
+
+    let logged = wait_for_log(&log, 2);
+    let name = repo.path().file_name().unwrap().to_string_lossy();
+    assert!(logged.contains(&format!("repo {name} locked")), "{logged}");
+    assert!(
+        logged.contains(&format!(
+            "repo {name} unlocked \u{2014} secrets are now in plaintext"
+        )),
+        "{logged}"
+    );
+}
+
+#[test]
+fn notifications_are_off_by_default() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let log = repo.path().join("notifications.log");
+    let bin = fake_notifier(&log);
+
+    git_crypt_cmd()
+        .arg("lock")
+        .env("PATH", path_with(bin.path()))
+        .env("DISPLAY", ":0")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    assert!(!log.exists());
+}
+
+#[test]
+fn headless_lock_and_unlock_succeed_silently() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+    enable_notify(repo.path());
+
+    let log = repo.path().join("notifications.log");
+    let bin = fake_notifier(&log);
+
+    // No display server: the notifier must not even be tried
+    for command in ["lock", "unlock"] {
+        git_crypt_cmd()
+            .arg(command)
+            .env("PATH", path_with(bin.path()))
+            .env_remove("DISPLAY")
+            .env_remove("WAYLAND_DISPLAY")
+            .current_dir(repo.path())
+            .assert()
+            .success();
+    }
+    assert!(!log.exists());
+
+    // A display without any notifier installed is not an error either
+    git_crypt_cmd()
+        .arg("lock")
+        .env("PATH", "/nonexistent")
+        .env("DISPLAY", ":0")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+}
+
+#[test]
+fn hung_notifier_does_not_block_lock() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+    enable_notify(repo.path());
+
+    let bin = TempDir::new().unwrap();
+    let script = bin.path().join("notify-send");
+    fs::write(&script, "#!/bin/sh\nsleep 30\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let started = Instant::now();
+    git_crypt_cmd()
+        .arg("lock")
+        .env("PATH", path_with(bin.path()))
+        .env("DISPLAY", ":0")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+    assert!(started.elapsed() < Duration::from_secs(20));
+}
@@ -4,6 +4,7 @@ mod common;
 
 use common::{create_git_repo, git_crypt_cmd};
 use predicates::prelude::*;
+use sha2::{Digest, Sha256};
 use std::fs;
 
 const TEST_SSH_ED25519_PUB: &str =
@@ -74,6 +75,75 @@ fn add_ssh_user_and_import_age_key_round_trip() {
     assert!(consumer.path().join(".git/git-crypt/keys/default").exists());
 }
 
+#[test]
+fn import_age_key_verify_only_installs_nothing() {
+    let producer = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(producer.path())
+        .assert()
+        .success();
+    let pub_path = producer.path().join("alice.pub");
+    fs::write(&pub_path, TEST_SSH_ED25519_PUB).unwrap();
+    git_crypt_cmd()
+        .args([
+            "add-ssh-user",
+            "--ssh-key",
+            pub_path.to_str().unwrap(),
+            "--alias",
+            "alice",
+        ])
+        .current_dir(producer.path())
+        .assert()
+        .success();
+    let producer_key = fs::read(producer.path().join(".git/git-crypt/keys/default")).unwrap();
+    let fingerprint = hex::encode(Sha256::digest(&producer_key));
+
+    // The consumer has not run init: verify-only needs no repository setup
+    let consumer = create_git_repo();
+    let age_copy = consumer.path().join("alice.age");
+    fs::copy(
+        producer.path().join(".git/git-crypt/keys/age/alice.age"),
+        &age_copy,
+    )
+    .unwrap();
+    let identity_path = consumer.path().join("alice");
+    fs::write(&identity_path, TEST_SSH_ED25519_SK).unwrap();
+
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            age_copy.to_str().unwrap(),
+            "--identity",
+            identity_path.to_str().unwrap(),
+            "--verify-only",
+        ])
+        .current_dir(consumer.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Key fingerprint: {fingerprint}"
+        )));
+    assert!(!consumer.path().join(".git/git-crypt/keys/default").exists());
+
+    // A blob the identity can't open fails the check
+    fs::write(&age_copy, b"not an age file").unwrap();
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            age_copy.to_str().unwrap(),
+            "--identity",
+            identity_path.to_str().unwrap(),
+            "--verify-only",
+        ])
+        .current_dir(consumer.path())
+        .assert()
+        .failure();
+    assert!(!consumer.path().join(".git/git-crypt").exists());
+}
+
 #[test]
 fn add_ssh_user_requires_ssh_key_argument() {
     let repo = create_git_repo();
@@ -116,3 +186,364 @@ fn add_ssh_user_rejects_invalid_ssh_key() {
         .failure()
         .stderr(predicate::str::contains("Invalid SSH recipient"));
 }
+
+#[test]
+fn sync_recipients_adds_and_removes_ssh_recipients() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let recipients_dir = repo.path().join(".git-crypt");
+    fs::create_dir_all(&recipients_dir).unwrap();
+    let recipients_file = recipients_dir.join("recipients");
+    let age_dir = repo.path().join(".git/git-crypt/keys/age");
+
+    // Add alice via the recipients file
+    fs::write(
+        &recipients_file,
+        format!("ssh alice {TEST_SSH_ED25519_PUB}\n"),
+    )
+    .unwrap();
+    git_crypt_cmd()
+        .arg("sync-recipients")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 granted, 0 revoked"));
+    assert!(age_dir.join("alice.age").exists());
+
+    // The blob decrypts with alice's identity
+    let identity_path = repo.path().join("alice");
+    fs::write(&identity_path, TEST_SSH_ED25519_SK).unwrap();
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            age_dir.join("alice.age").to_str().unwrap(),
+            "--identity",
+            identity_path.to_str().unwrap(),
+        ])
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    // Removing alice from the file revokes her blob
+    fs::write(&recipients_file, "# alice left the team\n").unwrap();
+    git_crypt_cmd()
+        .arg("sync-recipients")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 granted, 1 revoked"));
+    assert!(!age_dir.join("alice.age").exists());
+}
+
+const TEST_SSH_ED25519_PUB_BOB: &str =
+    "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIPIL/FF+auRs6qMzTVyPe9fx619BxtFhHHHqLmbyBzbp bob@rust";
+
+#[test]
+fn add_ssh_user_bulk_reports_summary_counts() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let alice = repo.path().join("alice.pub");
+    let bob = repo.path().join("bob.pub");
+    let bogus = repo.path().join("bogus.pub");
+    fs::write(&alice, TEST_SSH_ED25519_PUB).unwrap();
+    fs::write(&bob, TEST_SSH_ED25519_PUB_BOB).unwrap();
+    fs::write(&bogus, "this is not an ssh key").unwrap();
+
+    git_crypt_cmd()
+        .args(["add-ssh-user", "--ssh-key", alice.to_str().unwrap()])
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "granted 1 recipient, 0 skipped (already present), 0 failed",
+        ));
+
+    // alice already has a blob, bob is new, bogus fails without stopping the batch
+    git_crypt_cmd()
+        .args([
+            "add-ssh-user",
+            "--ssh-key",
+            alice.to_str().unwrap(),
+            "--ssh-key",
+            bob.to_str().unwrap(),
+            "--ssh-key",
+            bogus.to_str().unwrap(),
+        ])
+        .current_dir(repo.path())
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "granted 1 recipient, 1 skipped (already present), 1 failed",
+        ))
+        .stderr(predicate::str::contains(
+            "1 of 3 SSH key(s) could not be granted",
+        ));
+
+    let age_dir = repo.path().join(".git/git-crypt/keys/age");
+    assert!(age_dir.join("alicerust.age").exists());
+    assert!(age_dir.join("bobrust.age").exists());
+
+    // --force re-encrypts for users who already have a blob
+    git_crypt_cmd()
+        .args([
+            "add-ssh-user",
+            "--ssh-key",
+            alice.to_str().unwrap(),
+            "--force",
+        ])
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("granted 1 recipient, 0 skipped"));
+}
+
+#[test]
+fn rewrap_regenerates_blobs_after_format_bump() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let recipients_dir = repo.path().join(".git-crypt");
+    fs::create_dir_all(&recipients_dir).unwrap();
+    fs::write(
+        recipients_dir.join("recipients"),
+        format!("ssh alice {TEST_SSH_ED25519_PUB}\n"),
+    )
+    .unwrap();
+    git_crypt_cmd()
+        .arg("sync-recipients")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let keys_dir = repo.path().join(".git/git-crypt/keys");
+    let blob_path = keys_dir.join("age/alice.age");
+    // A blob nobody is recorded for is reported, not touched
+    fs::write(keys_dir.join("age/legacy.age"), b"old blob").unwrap();
+
+    git_crypt_cmd()
+        .arg("rewrap")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Current    alice"))
+        .stdout(predicate::str::contains("legacy.age: recipient unknown"))
+        .stdout(predicate::str::contains(
+            "0 rewrapped, 1 already current, 1 skipped",
+        ));
+
+    // Simulate blobs written by a build with an older blob format
+    let manifest_path = keys_dir.join("blobs.manifest");
+    let manifest = fs::read_to_string(&manifest_path).unwrap();
+    let (_, rest) = manifest.split_once(' ').unwrap();
+    fs::write(&manifest_path, format!("0 {rest}")).unwrap();
+    let before = fs::read(&blob_path).unwrap();
+
+    git_crypt_cmd()
+        .arg("rewrap")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rewrapped  alice"))
+        .stdout(predicate::str::contains(
+            "1 rewrapped, 0 already current, 1 skipped",
+        ));
+    assert_ne!(fs::read(&blob_path).unwrap(), before);
+    assert_eq!(
+        fs::read(keys_dir.join("age/legacy.age")).unwrap(),
+        b"old blob"
+    );
+
+    // The rewrapped blob still opens with alice's identity
+    let identity_path = repo.path().join("alice");
+    fs::write(&identity_path, TEST_SSH_ED25519_SK).unwrap();
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            blob_path.to_str().unwrap(),
+            "--identity",
+            identity_path.to_str().unwrap(),
+        ])
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    git_crypt_cmd()
+        .arg("rewrap")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("0 rewrapped, 1 already current"));
+}
+
+#[test]
+fn add_ssh_user_records_recipient_for_rewrap() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    let pub_path = repo.path().join("alice.pub");
+    fs::write(&pub_path, format!("{TEST_SSH_ED25519_PUB}\n")).unwrap();
+    git_crypt_cmd()
+        .args(["add-ssh-user", "--ssh-key", pub_path.to_str().unwrap()])
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    // No recipients file: rewrap works from the manifest alone
+    git_crypt_cmd()
+        .arg("rewrap")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "0 rewrapped, 1 already current, 0 skipped",
+        ));
+}
+
+#[test]
+fn add_user_passphrase_and_import_round_trip() {
+    let producer = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(producer.path())
+        .assert()
+        .success();
+
+    git_crypt_cmd()
+        .args(["add-user", "--passphrase", "--label", "contractors"])
+        .env("GIT_CRYPT_PASSPHRASE", "correct horse battery staple")
+        .current_dir(producer.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Encrypted key saved"));
+
+    let blob = producer
+        .path()
+        .join(".git/git-crypt/keys/passphrase/contractors.age");
+    assert!(blob.exists());
+
+    let consumer = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(consumer.path())
+        .assert()
+        .success();
+
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            blob.to_str().unwrap(),
+            "--passphrase",
+        ])
+        .env("GIT_CRYPT_PASSPHRASE", "wrong passphrase")
+        .current_dir(consumer.path())
+        .assert()
+        .failure();
+
+    git_crypt_cmd()
+        .args([
+            "import-age-key",
+            "--input",
+            blob.to_str().unwrap(),
+            "--passphrase",
+        ])
+        .env("GIT_CRYPT_PASSPHRASE", "correct horse battery staple")
+        .current_dir(consumer.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "imported successfully using passphrase",
+        ));
+
+    let default_key = ".git/git-crypt/keys/default";
+    assert_eq!(
+        fs::read(producer.path().join(default_key)).unwrap(),
+        fs::read(consumer.path().join(default_key)).unwrap()
+    );
+}
+
+#[test]
+fn add_user_rejects_invalid_label() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    git_crypt_cmd()
+        .args(["add-user", "--passphrase", "--label", "../escape"])
+        .env("GIT_CRYPT_PASSPHRASE", "secret")
+        .current_dir(repo.path())
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid label"));
+}
+
+#[test]
+fn rotate_key_rewraps_recipient_blobs() {
+    let repo = create_git_repo();
+    git_crypt_cmd()
+        .arg("init")
+        .current_dir(repo.path())
+        .assert()
+        .success();
+    let pub_path = repo.path().join("alice.pub");
+    fs::write(&pub_path, TEST_SSH_ED25519_PUB).unwrap();
+    git_crypt_cmd()
+        .args([
+            "add-ssh-user",
+            "--ssh-key",
+            pub_path.to_str().unwrap(),
+            "--alias",
+            "alice",
+        ])
+        .current_dir(repo.path())
+        .assert()
+        .success();
+
+    git_crypt_cmd()
+        .args(["rotate-key", "--cipher", "chacha20-poly1305"])
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 rewrapped"));
+
+    // Alice's blob now unwraps to the rotated key
+    let key = fs::read(repo.path().join(".git/git-crypt/keys/default")).unwrap();
+    let identity_path = repo.path().join("alice");
+    fs::write(&identity_path, TEST_SSH_ED25519_SK).unwrap();
+    git_crypt_cmd()
+        .args(["import-age-key", "--input"])
+        .arg(repo.path().join(".git/git-crypt/keys/age/alice.age"))
+        .arg("--identity")
+        .arg(&identity_path)
+        .arg("--verify-only")
+        .current_dir(repo.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "Key fingerprint: {}",
+            hex::encode(Sha256::digest(&key))
+        )));
+}
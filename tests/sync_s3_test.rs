@@ -1,6 +1,6 @@
 #![cfg(feature = "sync-s3")]
 
-use git_crypt::sync::maybe_sync_age_key;
+use git_crypt::sync::{download_age_key, maybe_sync_age_key, maybe_sync_gpg_key, SyncOutcome};
 use s3::{
     bucket::Bucket, bucket_ops::BucketConfiguration, creds::Credentials, error::S3Error,
     region::Region,
@@ -61,16 +61,60 @@ fn syncs_age_key_to_minio() {
         ],
     );
 
-    maybe_sync_age_key(temp_repo.path().join(".git").as_path(), &age_file, "alice")
-        .expect("sync should succeed");
+    let git_dir = temp_repo.path().join(".git");
+    let outcome =
+        maybe_sync_age_key(&git_dir, &age_file, "alice", false).expect("sync should succeed");
+    assert_eq!(outcome, SyncOutcome::Uploaded);
+
+    // Identical content is not uploaded again unless forced
+    let outcome = maybe_sync_age_key(&git_dir, &age_file, "alice", false).unwrap();
+    assert_eq!(
+        outcome,
+        SyncOutcome::Unchanged,
+        "second identical sync should be a no-op"
+    );
+    let outcome = maybe_sync_age_key(&git_dir, &age_file, "alice", true).unwrap();
+    assert_eq!(
+        outcome,
+        SyncOutcome::Uploaded,
+        "--force-upload should re-upload"
+    );
+
+    fs::write(&age_file, b"rotated-age-data").unwrap();
+    let outcome = maybe_sync_age_key(&git_dir, &age_file, "alice", false).unwrap();
+    assert_eq!(
+        outcome,
+        SyncOutcome::Uploaded,
+        "changed content should be uploaded"
+    );
 
     let bucket = build_bucket(&bucket_name).unwrap();
     let remote_path = "team-alpha/demo-repo/keys/age/alice.age";
     let response = bucket
         .get_object_blocking(remote_path)
         .expect("object should exist");
-    assert_eq!(response.bytes().as_ref(), contents);
+    assert_eq!(response.bytes().as_ref(), b"rotated-age-data");
+
+    let downloaded = download_age_key(&git_dir, "alice").expect("download should succeed");
+    assert_eq!(downloaded, b"rotated-age-data");
+    let err = download_age_key(&git_dir, "bob").expect_err("missing blob should fail");
+    assert!(err.to_string().contains("bob"), "{err}");
+
+    // GPG blobs land next to the age ones under keys/gpg
+    let gpg_file = temp_repo
+        .path()
+        .join(".git/git-crypt/keys/gpg/ABCD1234.key");
+    fs::create_dir_all(gpg_file.parent().unwrap()).unwrap();
+    fs::write(&gpg_file, b"test-gpg-data").unwrap();
+    let outcome = maybe_sync_gpg_key(&git_dir, &gpg_file, "ABCD1234", false).unwrap();
+    assert_eq!(outcome, SyncOutcome::Uploaded);
+    let gpg_path = "team-alpha/demo-repo/keys/gpg/ABCD1234.key";
+    let response = bucket
+        .get_object_blocking(gpg_path)
+        .expect("object should exist");
+    assert_eq!(response.bytes().as_ref(), b"test-gpg-data");
 
+    cleanup_bucket(&bucket, gpg_path);
     cleanup_bucket(&bucket, remote_path);
     run_docker_compose(["compose", "down", "-v"]);
 }